@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Loading must fail or succeed, but never panic.
+    // Anything that loads must also dump without panicking.
+    if let Ok(value_arena) = ruby_marshal::load(data) {
+        let _ = ruby_marshal::dump(&mut Vec::new(), &value_arena);
+    }
+});
@@ -1 +1,3 @@
+pub mod hexdump;
 pub mod marshal2json;
+pub mod marshal2yaml;
@@ -0,0 +1,128 @@
+use anyhow::Context;
+use ruby_marshal::DisplayByteString;
+use ruby_marshal::FixnumUsage;
+use ruby_marshal::Token;
+use ruby_marshal::TokenKind;
+use std::path::PathBuf;
+
+/// The number of bytes to print on each line.
+const BYTES_PER_LINE: usize = 8;
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "hexdump",
+    description = "print the bytes of a Ruby Marshal file, annotated with what they are"
+)]
+pub struct Options {
+    #[argh(positional, description = "the input file path")]
+    pub input: PathBuf,
+}
+
+/// Get a name for a value kind byte.
+fn value_kind_name(kind: u8) -> &'static str {
+    match kind {
+        b'0' => "nil",
+        b'T' => "true",
+        b'F' => "false",
+        b'i' => "fixnum",
+        b':' => "symbol",
+        b';' => "symbol link",
+        b'@' => "object link",
+        b'I' => "instance variables",
+        b'[' => "array",
+        b'{' => "hash",
+        b'}' => "hash with default",
+        b'o' => "object",
+        b'"' => "string",
+        b'u' => "user defined",
+        b'M' => "old module",
+        b'f' => "float",
+        b'l' => "bignum",
+        b'/' => "regexp",
+        b'S' => "struct",
+        b'c' => "class",
+        b'm' => "module",
+        b'U' => "user marshal",
+        _ => "unknown",
+    }
+}
+
+/// Describe a token.
+fn annotate(token: &Token, data: &[u8]) -> String {
+    match token.kind {
+        TokenKind::Header { major, minor } => format!("version {major}.{minor}"),
+        TokenKind::ValueKind { kind } => value_kind_name(kind).to_string(),
+        TokenKind::Fixnum { value, usage } => match usage {
+            FixnumUsage::Value => format!("fixnum {value}"),
+            FixnumUsage::SymbolLink => format!("symbol link index {value}"),
+            FixnumUsage::ObjectLink => format!("object link index {value}"),
+            FixnumUsage::Length => format!("length {value}"),
+        },
+        TokenKind::BignumSign { is_negative } => {
+            if is_negative { "negative" } else { "positive" }.to_string()
+        }
+        TokenKind::RegexpOptions { options } => format!("options {options}"),
+        TokenKind::Bytes => {
+            let bytes = &data[token.offset..][..token.len];
+            format!(
+                "\"{}\"",
+                DisplayByteString(bytes).to_string().escape_debug()
+            )
+        }
+    }
+}
+
+/// Print a span of bytes, with an annotation on the first line.
+fn print_span(offset: usize, bytes: &[u8], annotation: &str) {
+    for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let annotation = if i == 0 { annotation } else { "" };
+        let line = format!(
+            "{:08X}  {hex:<width$}  {annotation}",
+            offset + (i * BYTES_PER_LINE),
+            width = (BYTES_PER_LINE * 3) - 1,
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+pub fn exec(options: Options) -> anyhow::Result<()> {
+    let file = std::fs::read(&options.input)
+        .with_context(|| format!("failed to read file at \"{}\"", options.input.display()))?;
+
+    let mut end = 0;
+    let result =
+        ruby_marshal::load_with_tokens(&*file, ruby_marshal::LoaderOptions::default(), |token| {
+            if token.len > 0 {
+                print_span(
+                    token.offset,
+                    &file[token.offset..][..token.len],
+                    &annotate(&token, &file),
+                );
+            }
+            end = token.offset + token.len;
+        });
+
+    if let Err(error) = result {
+        if end < file.len() {
+            print_span(end, &file[end..], "unparsed");
+        }
+        return Err(error).with_context(|| {
+            format!(
+                "failed to parse file at \"{}\" after offset {end:08X}",
+                options.input.display()
+            )
+        });
+    }
+
+    if end < file.len() {
+        print_span(end, &file[end..], "trailing data");
+    }
+
+    Ok(())
+}
@@ -1,7 +1,5 @@
 use anyhow::bail;
-use anyhow::ensure;
 use anyhow::Context;
-use base64::Engine;
 use std::path::PathBuf;
 
 #[derive(Debug, argh::FromArgs)]
@@ -23,72 +21,48 @@ pub struct Options {
         description = "convert binary strings to base64"
     )]
     pub convert_binary_strings_to_base64: bool,
-}
-
-struct ConvertOptions {
-    convert_binary_strings_to_base64: bool,
-}
 
-fn ruby2json_value(
-    arena: &ruby_marshal::ValueArena,
-    handle: ruby_marshal::ValueHandle,
-    options: &ConvertOptions,
-) -> anyhow::Result<serde_json::Value> {
-    let value = arena.get(handle).context("missing handle")?;
-    match value {
-        ruby_marshal::Value::Nil(_) => Ok(serde_json::Value::Null),
-        ruby_marshal::Value::Bool(value) => Ok(serde_json::Value::Bool(value.value())),
-        ruby_marshal::Value::Symbol(_value) => bail!("cannot convert a Symbol to Json"),
-        ruby_marshal::Value::Fixnum(value) => Ok(serde_json::Value::Number(value.value().into())),
-        ruby_marshal::Value::Array(value) => {
-            let value = value.value();
+    #[argh(
+        switch,
+        long = "symbols-as-strings",
+        description = "convert symbols to strings of their names"
+    )]
+    pub symbols_as_strings: bool,
 
-            let mut array = Vec::with_capacity(value.len());
-            for handle in value {
-                array.push(ruby2json_value(arena, *handle, options)?);
-            }
+    #[argh(
+        switch,
+        long = "tag-objects",
+        description = "convert objects and user defined values to json objects with a \"__class__\" key"
+    )]
+    pub tag_objects: bool,
 
-            Ok(serde_json::Value::Array(array))
-        }
-        ruby_marshal::Value::Hash(_value) => {
-            // TODO: This is possible if the has only has string keys
-            bail!("cannot convert a Hash to Json")
-        }
-        ruby_marshal::Value::Object(_value) => {
-            bail!("cannot convert an Object to Json")
-        }
-        ruby_marshal::Value::String(value) => {
-            let instance_variables = value.instance_variables();
-            let encoding = instance_variables.and_then(|instance_variables| {
-                instance_variables.iter().find_map(|(key, value)| {
-                    let name = arena.get_symbol(*key)?.value();
-                    let value = arena.get(*value)?;
+    #[argh(
+        switch,
+        long = "normalize-keys",
+        description = "convert symbol and string hash keys to json strings, so hashes always become json objects"
+    )]
+    pub normalize_keys: bool,
 
-                    if name == b"encoding" || name == b"E" {
-                        Some(value)
-                    } else {
-                        None
-                    }
-                })
-            });
+    #[argh(
+        switch,
+        long = "stringify-keys",
+        description = "like \"--normalize-keys\", but convert other hash keys to strings of their json"
+    )]
+    pub stringify_keys: bool,
 
-            match encoding {
-                Some(_encoding) => {
-                    bail!("cannot convert a String to Json")
-                }
-                None => {
-                    ensure!(options.convert_binary_strings_to_base64, "cannot convert a binary String to Json. Consider using the \"--convert-binary-strings-to-base64\" switch.");
+    #[argh(
+        option,
+        long = "max-depth",
+        description = "the maximum depth of nested arrays, hashes, and objects to render, replacing deeper ones with a placeholder"
+    )]
+    pub max_depth: Option<usize>,
 
-                    Ok(serde_json::Value::String(
-                        base64::engine::general_purpose::STANDARD.encode(value.value()),
-                    ))
-                }
-            }
-        }
-        ruby_marshal::Value::UserDefined(_value) => {
-            bail!("cannot convert an UserDefined to Json")
-        }
-    }
+    #[argh(
+        option,
+        long = "max-array",
+        description = "the maximum number of array items to render, replacing the rest with a marker"
+    )]
+    pub max_array: Option<usize>,
 }
 
 pub fn exec(options: Options) -> anyhow::Result<()> {
@@ -98,14 +72,48 @@ pub fn exec(options: Options) -> anyhow::Result<()> {
         .with_context(|| format!("failed to parse file at \"{}\"", options.input.display()))?;
 
     // TODO: Should this conversion be lossy or lossless?
-    let json_value = ruby2json_value(
+    let symbols = if options.symbols_as_strings {
+        ruby_marshal::JsonSymbolMode::String
+    } else {
+        ruby_marshal::JsonSymbolMode::Error
+    };
+    let objects = if options.tag_objects {
+        ruby_marshal::JsonObjectMode::Tagged
+    } else {
+        ruby_marshal::JsonObjectMode::Error
+    };
+    let keys = if options.stringify_keys {
+        ruby_marshal::JsonKeyMode::Stringify
+    } else if options.normalize_keys {
+        ruby_marshal::JsonKeyMode::Normalize
+    } else {
+        ruby_marshal::JsonKeyMode::Auto
+    };
+    let json_options = ruby_marshal::JsonOptions {
+        symbols,
+        objects,
+        keys,
+        binary_strings_to_base64: options.convert_binary_strings_to_base64,
+        max_depth: options.max_depth,
+        max_array: options.max_array,
+    };
+    let json_value = match ruby_marshal::to_json_value(
         &value_arena,
         value_arena.root(),
-        &ConvertOptions {
-            convert_binary_strings_to_base64: options.convert_binary_strings_to_base64,
-        },
-    )
-    .context("failed to convert to json")?;
+        &json_options,
+    ) {
+        Err(ruby_marshal::Error::Json {
+            error: ruby_marshal::JsonError::BinaryString,
+        }) => {
+            bail!("cannot convert a binary String to Json. Consider using the \"--convert-binary-strings-to-base64\" switch.")
+        }
+        Err(ruby_marshal::Error::Json {
+            error: ruby_marshal::JsonError::NonStringKey { kind },
+        }) => {
+            bail!("cannot convert a {kind:?} hash key to a Json string. Consider using the \"--stringify-keys\" switch.")
+        }
+        result => result.context("failed to convert to json")?,
+    };
     let output_data = serde_json::to_string(&json_value)?;
 
     let output_tmp = nd_util::with_push_extension(&options.output, "tmp");
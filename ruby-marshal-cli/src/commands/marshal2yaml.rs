@@ -0,0 +1,352 @@
+use anyhow::bail;
+use anyhow::Context;
+use base64::Engine;
+use ruby_marshal::SymbolValue;
+use ruby_marshal::TypedValueHandle;
+use ruby_marshal::Value;
+use ruby_marshal::ValueArena;
+use ruby_marshal::ValueHandle;
+use serde_yaml::value::Tag;
+use serde_yaml::value::TaggedValue;
+use serde_yaml::Mapping;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "marshal2yaml",
+    description = "turn a Ruby Marshal file into yaml, with tags like Ruby's psych"
+)]
+pub struct Options {
+    #[argh(positional, description = "the input file path")]
+    pub input: PathBuf,
+
+    #[argh(positional, description = "the output file path")]
+    pub output: PathBuf,
+}
+
+pub fn exec(options: Options) -> anyhow::Result<()> {
+    let file = std::fs::read(&options.input)
+        .with_context(|| format!("failed to read file at \"{}\"", options.input.display()))?;
+    let value_arena = ruby_marshal::load(&*file)
+        .with_context(|| format!("failed to parse file at \"{}\"", options.input.display()))?;
+
+    let yaml_value = YamlConverter::new(&value_arena)
+        .convert(value_arena.root())
+        .context("failed to convert to yaml")?;
+    let output_data = serde_yaml::to_string(&yaml_value)?;
+
+    let output_tmp = nd_util::with_push_extension(&options.output, "tmp");
+    std::fs::write(&output_tmp, output_data.as_bytes())?;
+    std::fs::rename(&output_tmp, &options.output)?;
+
+    Ok(())
+}
+
+/// The deepest values can be nested.
+///
+/// Both the conversion and the yaml serializer recurse once for each level,
+/// so deeper input is an error instead of a stack overflow.
+const MAX_DEPTH: usize = 128;
+
+/// A converter from Ruby values into yaml, using the tags that psych uses where it has them.
+///
+/// Yaml anchors are not supported by the yaml library,
+/// so shared values are written once for each reference, and cycles are an error.
+///
+/// This does not reuse the JSON conversion of `ruby_marshal::to_json_value`,
+/// as that only supports values with a JSON equivalent and has no way to attach tags.
+struct YamlConverter<'a> {
+    arena: &'a ValueArena,
+
+    /// The values that are currently being converted, to detect cycles.
+    ancestors: HashSet<ValueHandle>,
+}
+
+impl<'a> YamlConverter<'a> {
+    fn new(arena: &'a ValueArena) -> Self {
+        Self {
+            arena,
+            ancestors: HashSet::new(),
+        }
+    }
+
+    fn convert(&mut self, handle: ValueHandle) -> anyhow::Result<serde_yaml::Value> {
+        let value = self
+            .arena
+            .get(handle)
+            .with_context(|| format!("invalid value handle {handle:?}"))?;
+
+        if self.ancestors.len() >= MAX_DEPTH {
+            bail!("cannot convert values nested more than {MAX_DEPTH} deep to yaml");
+        }
+        if !self.ancestors.insert(handle) {
+            bail!("cannot convert a cyclic value to yaml");
+        }
+        let result = self.convert_value(value);
+        self.ancestors.remove(&handle);
+
+        result
+    }
+
+    fn convert_value(&mut self, value: &'a Value) -> anyhow::Result<serde_yaml::Value> {
+        let yaml_value = match value {
+            Value::Nil(_) => serde_yaml::Value::Null,
+            Value::Bool(value) => serde_yaml::Value::Bool(value.value()),
+            Value::Fixnum(value) => serde_yaml::Value::Number(value.value().into()),
+            Value::Float(value) => serde_yaml::Value::Number(value.value().into()),
+            Value::Bignum(value) => {
+                // The magnitude is little endian.
+                let mut magnitude: u64 = 0;
+                for (i, byte) in value.value().iter().copied().enumerate() {
+                    if byte == 0 {
+                        continue;
+                    }
+                    if i >= 8 {
+                        bail!("cannot convert a Bignum larger than 64 bits to yaml");
+                    }
+                    magnitude |= u64::from(byte) << (i * 8);
+                }
+
+                if value.is_negative() {
+                    let value = 0_i64
+                        .checked_sub_unsigned(magnitude)
+                        .context("cannot convert a Bignum larger than 64 bits to yaml")?;
+                    serde_yaml::Value::Number(value.into())
+                } else {
+                    serde_yaml::Value::Number(magnitude.into())
+                }
+            }
+            Value::Symbol(value) => tagged(
+                "ruby/symbol",
+                serde_yaml::Value::String(utf8_name(value.value())?.into()),
+            ),
+            Value::String(value) => match value.as_str() {
+                Ok(value) => serde_yaml::Value::String(value.into()),
+                Err(_) => tagged(
+                    "binary",
+                    serde_yaml::Value::String(
+                        base64::engine::general_purpose::STANDARD.encode(value.value()),
+                    ),
+                ),
+            },
+            Value::Array(value) => serde_yaml::Value::Sequence(
+                value
+                    .value()
+                    .iter()
+                    .map(|handle| self.convert(*handle))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            Value::Hash(value) => {
+                if value.default_value().is_some() {
+                    bail!("cannot convert a Hash with a default value to yaml");
+                }
+
+                let mut mapping = Mapping::with_capacity(value.len());
+                for (key, value) in value.value().iter().copied() {
+                    let key = self.convert(key)?;
+                    let value = self.convert(value)?;
+                    mapping.insert(key, value);
+                }
+
+                serde_yaml::Value::Mapping(mapping)
+            }
+            Value::Object(value) => {
+                let name = self.symbol_name(value.name())?;
+
+                // Psych leaves out the `@` of instance variable names.
+                let mut mapping = Mapping::with_capacity(value.instance_variable_count());
+                for (key, value) in value.instance_variables().iter().copied() {
+                    let key = self.symbol_name(key)?;
+                    let key = key.strip_prefix('@').unwrap_or(key);
+                    let value = self.convert(value)?;
+                    mapping.insert(serde_yaml::Value::String(key.into()), value);
+                }
+
+                tagged(
+                    &format!("ruby/object:{name}"),
+                    serde_yaml::Value::Mapping(mapping),
+                )
+            }
+            Value::Struct(value) => {
+                let name = self.symbol_name(value.name())?;
+
+                let mut mapping = Mapping::with_capacity(value.members().len());
+                for (key, value) in value.members().iter().copied() {
+                    let key = self.symbol_name(key)?;
+                    let value = self.convert(value)?;
+                    mapping.insert(serde_yaml::Value::String(key.into()), value);
+                }
+
+                tagged(
+                    &format!("ruby/struct:{name}"),
+                    serde_yaml::Value::Mapping(mapping),
+                )
+            }
+            Value::UserMarshal(value) => {
+                let name = self.symbol_name(value.name())?;
+                let value = self.convert(value.value())?;
+
+                tagged(&format!("ruby/object:{name}"), value)
+            }
+            Value::UserDefined(value) => {
+                // Psych has no tag for these, as the data is only meaningful to the class's `_load`.
+                let name = self.symbol_name(value.name())?;
+
+                tagged(
+                    &format!("ruby/marshalable:{name}"),
+                    serde_yaml::Value::String(
+                        base64::engine::general_purpose::STANDARD.encode(value.value()),
+                    ),
+                )
+            }
+            Value::Regexp(value) => {
+                let source = std::str::from_utf8(value.source())
+                    .context("cannot convert a Regexp that is not UTF-8 to yaml")?;
+
+                let mut regexp = format!("/{source}/");
+                for (bit, flag) in [(1, 'i'), (2, 'x'), (4, 'm')] {
+                    if value.options() & bit != 0 {
+                        regexp.push(flag);
+                    }
+                }
+
+                tagged("ruby/regexp", serde_yaml::Value::String(regexp))
+            }
+            Value::Class(value) => tagged(
+                "ruby/class",
+                serde_yaml::Value::String(utf8_name(value.name())?.into()),
+            ),
+            Value::Module(value) => tagged(
+                "ruby/module",
+                serde_yaml::Value::String(utf8_name(value.name())?.into()),
+            ),
+            Value::OldModule(value) => tagged(
+                "ruby/module",
+                serde_yaml::Value::String(utf8_name(value.name())?.into()),
+            ),
+        };
+
+        Ok(yaml_value)
+    }
+
+    /// Get the name of a symbol, which must be UTF-8.
+    fn symbol_name(&self, handle: TypedValueHandle<SymbolValue>) -> anyhow::Result<&'a str> {
+        let symbol = self
+            .arena
+            .get_symbol(handle)
+            .with_context(|| format!("invalid symbol handle {handle:?}"))?;
+
+        utf8_name(symbol.value())
+    }
+}
+
+/// Get a name as a str, which must be UTF-8.
+fn utf8_name(name: &[u8]) -> anyhow::Result<&str> {
+    std::str::from_utf8(name).with_context(|| {
+        format!(
+            "cannot convert the name \"{}\" to yaml, as it is not UTF-8",
+            ruby_marshal::DisplayByteString(name)
+        )
+    })
+}
+
+/// Make a value with a local tag, like `!ruby/object:Foo`.
+fn tagged(tag: &str, value: serde_yaml::Value) -> serde_yaml::Value {
+    serde_yaml::Value::Tagged(Box::new(TaggedValue {
+        tag: Tag::new(tag),
+        value,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_yaml(arena: &ValueArena) -> String {
+        let yaml_value = YamlConverter::new(arena)
+            .convert(arena.root())
+            .expect("failed to convert");
+        serde_yaml::to_string(&yaml_value).expect("failed to serialize")
+    }
+
+    #[test]
+    fn tags() {
+        let mut arena = ValueArena::new();
+        let one = arena.create_fixnum(1).into();
+        let point = arena.create_symbol("Point".into());
+        let x = arena.create_symbol("@x".into());
+        let object = arena.create_object(point, vec![(x, one)]).into();
+        let member = arena.create_symbol("x".into());
+        let ruby_struct = arena.create_struct(point, vec![(member, one)]).into();
+        let binary = arena.create_string(b"\xFF\x00".into()).into();
+        let regexp = arena.create_regexp("a+".into(), 5).into();
+        let class = arena.create_class("Point".into()).into();
+        let user_defined = arena.create_user_defined(point, b"\xFF".into()).into();
+        let root = arena.create_array(vec![
+            object,
+            ruby_struct,
+            member.into(),
+            binary,
+            regexp,
+            class,
+            user_defined,
+        ]);
+        arena.replace_root(root);
+
+        let yaml = to_yaml(&arena);
+        assert!(
+            yaml == "\
+- !ruby/object:Point
+  x: 1
+- !ruby/struct:Point
+  x: 1
+- !ruby/symbol x
+- !binary /wA=
+- !ruby/regexp /a+/im
+- !ruby/class Point
+- !ruby/marshalable:Point /w==
+",
+            "{yaml}"
+        );
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut arena = ValueArena::new();
+        let mut handle = arena.create_nil().into();
+        for _ in 0..100_000 {
+            handle = arena.create_array(vec![handle]).into();
+        }
+        arena.replace_root(handle);
+
+        let error = YamlConverter::new(&arena)
+            .convert(arena.root())
+            .expect_err("conversion should fail");
+        assert!(error.to_string().contains("nested"), "{error}");
+
+        let mut arena = ValueArena::new();
+        let mut handle = arena.create_nil().into();
+        for _ in 0..MAX_DEPTH - 1 {
+            handle = arena.create_array(vec![handle]).into();
+        }
+        arena.replace_root(handle);
+        to_yaml(&arena);
+    }
+
+    #[test]
+    fn cycle() {
+        let mut arena = ValueArena::new();
+        let array = arena.create_array(Vec::new());
+        arena
+            .push_array_item(array, array.into())
+            .expect("failed to push");
+        arena.replace_root(array);
+
+        let error = YamlConverter::new(&arena)
+            .convert(arena.root())
+            .expect_err("conversion should fail");
+        assert!(error.to_string().contains("cyclic"), "{error}");
+    }
+}
@@ -11,12 +11,16 @@ struct Options {
 #[argh(subcommand)]
 enum Subcommand {
     Marshal2Json(self::commands::marshal2json::Options),
+    Marshal2Yaml(self::commands::marshal2yaml::Options),
+    Hexdump(self::commands::hexdump::Options),
 }
 
 fn main() -> anyhow::Result<()> {
     let options: Options = argh::from_env();
     match options.subcommand {
         Subcommand::Marshal2Json(options) => self::commands::marshal2json::exec(options)?,
+        Subcommand::Marshal2Yaml(options) => self::commands::marshal2yaml::exec(options)?,
+        Subcommand::Hexdump(options) => self::commands::hexdump::exec(options)?,
     }
     Ok(())
 }
@@ -1,12 +1,17 @@
 use crate::parse_container_attributes;
 use crate::parse_field_attributes;
+use crate::parse_symbol_enum;
+use proc_macro2::Span;
 use quote::format_ident;
 use quote::quote;
 use quote::quote_spanned;
 use syn::parse_macro_input;
 use syn::spanned::Spanned;
+use syn::DataEnum;
 use syn::DeriveInput;
+use syn::GenericParam;
 use syn::Ident;
+use syn::Lifetime;
 use syn::LitByteStr;
 use syn::Type;
 
@@ -14,20 +19,23 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let input_data = match &input.data {
         syn::Data::Struct(data) => data,
+        syn::Data::Enum(data) => return derive_symbol_enum(&input, data),
         _ => {
             return quote_spanned! {
                 input.span() =>
-                compile_error!("only structs are supported");
+                compile_error!("only structs and enums are supported");
             }
             .into();
         }
     };
-    let input_fields = match &input_data.fields {
-        syn::Fields::Named(fields) => fields,
+    // Unit structs are treated like structs with no fields.
+    let (input_fields, is_unit) = match &input_data.fields {
+        syn::Fields::Named(fields) => (fields.named.iter().collect::<Vec<_>>(), false),
+        syn::Fields::Unit => (Vec::new(), true),
         _ => {
             return quote_spanned! {
                 input.span() =>
-                compile_error!("only named field structs are supported");
+                compile_error!("only named field structs and unit structs are supported");
             }
             .into();
         }
@@ -41,10 +49,21 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
         };
 
-    let object_name = container_attributes;
+    let object_name = container_attributes.object_name;
+    let is_hash = container_attributes.hash;
+    let is_flatten_only = container_attributes.flatten_only;
 
-    let mut fields = Vec::with_capacity(input_fields.named.len());
-    for field in input_fields.named.iter() {
+    // Borrowed fields borrow from the arena, so the struct lifetime is the arena lifetime.
+    let (lifetime, ty_generics) = match parse_lifetime(&input) {
+        Ok(Some(lifetime)) => (lifetime.clone(), quote! { <#lifetime> }),
+        Ok(None) => (Lifetime::new("'a", Span::call_site()), quote! {}),
+        Err(error) => {
+            return error.into_compile_error().into();
+        }
+    };
+
+    let mut fields = Vec::with_capacity(input_fields.len());
+    for field in input_fields.iter() {
         let field_attributes =
             match parse_field_attributes(field).map_err(syn::Error::into_compile_error) {
                 Ok(value) => value,
@@ -58,8 +77,16 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             .as_ref()
             .expect("named field structs should have named fields");
 
+        if is_hash && field_attributes.flatten {
+            return syn::Error::new_spanned(field, "hash structs do not support flattened fields")
+                .into_compile_error()
+                .into();
+        }
+
+        // Hash keys are plain symbols, while instance variables start with '@'.
         let name_str = match field_attributes.name {
             Some(name) => name,
+            None if is_hash => LitByteStr::new(name.to_string().as_bytes(), name.span()),
             None => LitByteStr::new(format!("@{name}").as_bytes(), name.span()),
         };
         fields.push(FromValueField {
@@ -67,70 +94,106 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             name_str,
             ty: &field.ty,
             from_value: field_attributes.from_value,
+            flatten: field_attributes.flatten,
         });
     }
 
-    let option_fields = fields.iter().enumerate().map(|(i, _field)| {
-        let ident = format_ident!("option_field_{i}");
+    let mut flatten_fields = fields.iter().filter(|field| field.flatten);
+    let flatten_field = flatten_fields.next();
+    if let Some(field) = flatten_fields.next() {
+        return syn::Error::new_spanned(field.name, "only one field may be flattened")
+            .into_compile_error()
+            .into();
+    }
+
+    let (path_segment, duplicate_error) = if is_hash {
+        (
+            quote! { ::ruby_marshal::PathSegment::HashKey(key_handle) },
+            quote! { ::ruby_marshal::FromValueError::DuplicateHashKey { key: key_handle } },
+        )
+    } else {
+        (
+            quote! { ::ruby_marshal::PathSegment::InstanceVariable(key_handle) },
+            quote! { ::ruby_marshal::FromValueError::DuplicateInstanceVariable { name: key.into() } },
+        )
+    };
 
-        quote! {
-            let mut #ident = None;
-        }
-    });
-    let match_arms = fields.iter().enumerate().map(|(i, field)| {
-        let ident = format_ident!("option_field_{i}");
-        let field_name = &field.name_str;
-        let ty = &field.ty;
-        let ty_span = ty.span();
-
-        let get_value = match field.from_value.as_ref() {
-            Some(from_value) => {
-                quote_spanned! {from_value.span()=>
-                    let value = {
-                        struct Wrapper(#ty);
-
-                        impl<'a> ::ruby_marshal::FromValue<'a> for Wrapper {
-                            fn from_value(
-                                ctx: &::ruby_marshal::FromValueContext,
-                                value: &'a::ruby_marshal::Value
-                            ) -> Result<Self, ::ruby_marshal::FromValueError> {
-                                let value = #from_value(ctx, value)?;
-
-                                Ok(Self(value))
+    let option_fields = fields
+        .iter()
+        .enumerate()
+        .filter(|(_i, field)| !field.flatten)
+        .map(|(i, _field)| {
+            let ident = format_ident!("option_field_{i}");
+
+            quote! {
+                let mut #ident = None;
+            }
+        });
+    let match_arms = fields
+        .iter()
+        .enumerate()
+        .filter(|(_i, field)| !field.flatten)
+        .map(|(i, field)| {
+            let ident = format_ident!("option_field_{i}");
+            let field_name = &field.name_str;
+            let ty = &field.ty;
+            let ty_span = ty.span();
+
+            let get_value = match field.from_value.as_ref() {
+                Some(from_value) => {
+                    quote_spanned! {from_value.span()=>
+                        let value = {
+                            struct Wrapper<#lifetime>(#ty, ::core::marker::PhantomData<&#lifetime ()>);
+
+                            impl<#lifetime> ::ruby_marshal::FromValue<#lifetime> for Wrapper<#lifetime> {
+                                fn from_value(
+                                    ctx: &::ruby_marshal::FromValueContext<#lifetime>,
+                                    value: &#lifetime ::ruby_marshal::Value
+                                ) -> Result<Self, ::ruby_marshal::FromValueError> {
+                                    let value = #from_value(ctx, value)?;
+
+                                    Ok(Self(value, ::core::marker::PhantomData))
+                                }
                             }
-                        }
 
-                        let value: Wrapper = ctx.from_value(value)?;
-                        value.0
-                    };
+                            let value: Wrapper = ctx.from_value_at(#path_segment, value)?;
+                            value.0
+                        };
+                    }
                 }
-            }
-            None => {
-                quote_spanned! {ty_span=>
-                    let value = ctx.from_value(value)?;
+                None => {
+                    quote_spanned! {ty_span=>
+                        let value = ctx.from_value_at(#path_segment, value)?;
+                    }
                 }
-            }
-        };
+            };
 
-        quote! {
-            #field_name => {
-                if #ident.is_some() {
-                    return Err(
-                        ::ruby_marshal::FromValueError::DuplicateInstanceVariable {
-                            name: key.into()
-                        }
-                    );
-                }
+            quote! {
+                #field_name => {
+                    if #ident.is_some() {
+                        return Err(#duplicate_error);
+                    }
 
-                #get_value
-                #ident = Some(value);
+                    #get_value
+                    #ident = Some(value);
+                }
             }
-        }
-    });
+        });
     let unpack_option_fields = fields.iter().enumerate().map(|(i, field)| {
         let option_field_ident = format_ident!("option_field_{i}");
         let field_ident = format_ident!("field_{i}");
         let field_name = &field.name_str;
+
+        if field.flatten {
+            let ty = &field.ty;
+            let cast_type = quote_spanned! {ty.span()=>
+                <#ty as ::ruby_marshal::FromInstanceVariables>
+            };
+            return quote! {
+                let #field_ident = #cast_type::from_instance_variables(ctx, &flatten_instance_variables)?;
+            };
+        }
+
         quote! {
             let #field_ident = #option_field_ident.ok_or_else(|| ::ruby_marshal::FromValueError::MissingInstanceVariable {
                 name: #field_name.into(),
@@ -145,34 +208,129 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         }
     });
 
+    let (init_flatten_instance_variables, unknown_instance_variable) = match flatten_field {
+        Some(_) => (
+            quote! {
+                let mut flatten_instance_variables = ::ruby_marshal::__private::Vec::new();
+            },
+            quote! {
+                flatten_instance_variables.push((key_handle, value));
+            },
+        ),
+        None => (
+            quote! {},
+            quote! {
+                return Err(::ruby_marshal::FromValueError::UnknownInstanceVariable { name: key.into() });
+            },
+        ),
+    };
+
     let input_name = &input.ident;
-    let tokens = quote! {
-        impl<'a> ::ruby_marshal::FromValue<'a> for #input_name {
-            fn from_value(
-                ctx: &::ruby_marshal::FromValueContext,
-                value: &'a::ruby_marshal::Value
-            ) -> Result<Self, ::ruby_marshal::FromValueError> {
-                let value: &::ruby_marshal::ObjectValue = ::ruby_marshal::FromValue::from_value(ctx, value)?;
-                {
-                    let name = value.name();
-                    let name: &::ruby_marshal::SymbolValue = ctx.from_value(name.into())?;
-                    let name = name.value();
-
-                    if name != #object_name {
-                        return Err(::ruby_marshal::FromValueError::UnexpectedObjectName { name: name.into() });
+    if is_hash {
+        let tokens = quote! {
+            impl<#lifetime> ::ruby_marshal::FromValue<#lifetime> for #input_name #ty_generics {
+                fn from_value(
+                    ctx: &::ruby_marshal::FromValueContext<#lifetime>,
+                    value: &#lifetime ::ruby_marshal::Value
+                ) -> Result<Self, ::ruby_marshal::FromValueError> {
+                    let value: &::ruby_marshal::HashValue = ::ruby_marshal::FromValue::from_value(ctx, value)?;
+
+                    #(#option_fields)*
+
+                    for (key_handle, value) in value.value().iter().copied() {
+                        let key: &::ruby_marshal::SymbolValue = ctx.from_value_at(#path_segment, key_handle)?;
+                        let key = key.value();
+
+                        match key {
+                            #(#match_arms)*
+                            _ => {
+                                return Err(::ruby_marshal::FromValueError::UnknownInstanceVariable { name: key.into() });
+                            }
+                        }
                     }
+
+                    #(#unpack_option_fields)*
+
+                    Ok(Self {
+                        #(#init_struct_fields)*
+                    })
                 }
+            }
+        };
 
+        return proc_macro::TokenStream::from(tokens);
+    }
+
+    let from_value_impl = object_name.map(|object_name| {
+        quote! {
+            impl<#lifetime> ::ruby_marshal::FromValue<#lifetime> for #input_name #ty_generics {
+                fn from_value(
+                    ctx: &::ruby_marshal::FromValueContext<#lifetime>,
+                    value: &#lifetime ::ruby_marshal::Value
+                ) -> Result<Self, ::ruby_marshal::FromValueError> {
+                    let value: &::ruby_marshal::ObjectValue = ::ruby_marshal::FromValue::from_value(ctx, value)?;
+                    {
+                        let name = value.name();
+                        let name: &::ruby_marshal::SymbolValue = ctx.from_value(name.into())?;
+                        let name = name.value();
+
+                        if name != #object_name {
+                            return Err(::ruby_marshal::FromValueError::UnexpectedObjectName { name: name.into() });
+                        }
+                    }
+
+                    ::ruby_marshal::FromInstanceVariables::from_instance_variables(
+                        ctx,
+                        value.instance_variables(),
+                    )
+                }
+            }
+        }
+    })
+    .or_else(|| {
+        // Unit structs without an object name are empty arrays,
+        // unless they are only flattened.
+        (is_unit && !is_flatten_only).then(|| {
+            quote! {
+                impl<#lifetime> ::ruby_marshal::FromValue<#lifetime> for #input_name #ty_generics {
+                    fn from_value(
+                        ctx: &::ruby_marshal::FromValueContext<#lifetime>,
+                        value: &#lifetime ::ruby_marshal::Value
+                    ) -> Result<Self, ::ruby_marshal::FromValueError> {
+                        let value: &::ruby_marshal::ArrayValue = ::ruby_marshal::FromValue::from_value(ctx, value)?;
+                        if !value.is_empty() {
+                            return Err(::ruby_marshal::FromValueError::UnexpectedArrayLength {
+                                expected: 0,
+                                actual: value.len(),
+                            });
+                        }
+
+                        Ok(Self)
+                    }
+                }
+            }
+        })
+    });
+    let tokens = quote! {
+        impl<#lifetime> ::ruby_marshal::FromInstanceVariables<#lifetime> for #input_name #ty_generics {
+            fn from_instance_variables(
+                ctx: &::ruby_marshal::FromValueContext<#lifetime>,
+                instance_variables: &[(
+                    ::ruby_marshal::TypedValueHandle<::ruby_marshal::SymbolValue>,
+                    ::ruby_marshal::ValueHandle,
+                )],
+            ) -> Result<Self, ::ruby_marshal::FromValueError> {
                 #(#option_fields)*
+                #init_flatten_instance_variables
 
-                for (key, value) in value.instance_variables().iter().copied() {
-                    let key: &::ruby_marshal::SymbolValue = ctx.from_value(key.into())?;
+                for (key_handle, value) in instance_variables.iter().copied() {
+                    let key: &::ruby_marshal::SymbolValue = ctx.from_value(key_handle.into())?;
                     let key = key.value();
 
                     match key {
                         #(#match_arms)*
                         _ => {
-                            return Err(::ruby_marshal::FromValueError::UnknownInstanceVariable { name: key.into() });
+                            #unknown_instance_variable
                         }
                     }
                 }
@@ -184,14 +342,95 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 })
             }
         }
+
+        #from_value_impl
     };
 
     proc_macro::TokenStream::from(tokens)
 }
 
+fn derive_symbol_enum(input: &DeriveInput, data: &DataEnum) -> proc_macro::TokenStream {
+    let variants = match parse_symbol_enum(input, data).map_err(syn::Error::into_compile_error) {
+        Ok(value) => value,
+        Err(error) => {
+            return error.into();
+        }
+    };
+
+    let match_arms = variants.iter().map(|variant| {
+        let variant_name = variant.name;
+        let name_str = &variant.name_str;
+
+        quote! {
+            #name_str => Ok(Self::#variant_name),
+        }
+    });
+
+    let input_name = &input.ident;
+    let tokens = quote! {
+        impl<'a> ::ruby_marshal::FromValue<'a> for #input_name {
+            fn from_value(
+                ctx: &::ruby_marshal::FromValueContext,
+                value: &'a::ruby_marshal::Value
+            ) -> Result<Self, ::ruby_marshal::FromValueError> {
+                let value: &::ruby_marshal::SymbolValue = ::ruby_marshal::FromValue::from_value(ctx, value)?;
+                let value = value.value();
+
+                match value {
+                    #(#match_arms)*
+                    _ => Err(::ruby_marshal::FromValueError::UnexpectedSymbol { name: value.into() }),
+                }
+            }
+        }
+    };
+
+    proc_macro::TokenStream::from(tokens)
+}
+
+/// Get the lifetime parameter of a struct, if it has one.
+fn parse_lifetime(input: &DeriveInput) -> syn::Result<Option<&Lifetime>> {
+    let mut lifetime = None;
+    for param in input.generics.params.iter() {
+        match param {
+            GenericParam::Lifetime(param) if lifetime.is_none() => {
+                if !param.bounds.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        &param.bounds,
+                        "lifetime bounds are not supported",
+                    ));
+                }
+
+                lifetime = Some(&param.lifetime);
+            }
+            GenericParam::Lifetime(param) => {
+                return Err(syn::Error::new_spanned(
+                    param,
+                    "only one lifetime parameter is supported",
+                ));
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    param,
+                    "only lifetime parameters are supported",
+                ));
+            }
+        }
+    }
+
+    if let Some(where_clause) = input.generics.where_clause.as_ref() {
+        return Err(syn::Error::new_spanned(
+            where_clause,
+            "where clauses are not supported",
+        ));
+    }
+
+    Ok(lifetime)
+}
+
 struct FromValueField<'a> {
     name: &'a Ident,
     name_str: LitByteStr,
     ty: &'a Type,
     from_value: Option<syn::Path>,
+    flatten: bool,
 }
@@ -1,10 +1,11 @@
 use crate::parse_container_attributes;
 use crate::parse_field_attributes;
-use quote::format_ident;
+use crate::parse_symbol_enum;
 use quote::quote;
 use quote::quote_spanned;
 use syn::parse_macro_input;
 use syn::spanned::Spanned;
+use syn::DataEnum;
 use syn::DeriveInput;
 use syn::Ident;
 use syn::LitByteStr;
@@ -14,20 +15,23 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let input_data = match &input.data {
         syn::Data::Struct(data) => data,
+        syn::Data::Enum(data) => return derive_symbol_enum(&input, data),
         _ => {
             return quote_spanned! {
                 input.span() =>
-                compile_error!("only structs are supported");
+                compile_error!("only structs and enums are supported");
             }
             .into();
         }
     };
-    let input_fields = match &input_data.fields {
-        syn::Fields::Named(fields) => fields,
+    // Unit structs are treated like structs with no fields.
+    let (input_fields, is_unit) = match &input_data.fields {
+        syn::Fields::Named(fields) => (fields.named.iter().collect::<Vec<_>>(), false),
+        syn::Fields::Unit => (Vec::new(), true),
         _ => {
             return quote_spanned! {
                 input.span() =>
-                compile_error!("only named field structs are supported");
+                compile_error!("only named field structs and unit structs are supported");
             }
             .into();
         }
@@ -40,10 +44,12 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 return error.into();
             }
         };
-    let object_name = container_attributes;
+    let object_name = container_attributes.object_name;
+    let is_hash = container_attributes.hash;
+    let is_flatten_only = container_attributes.flatten_only;
 
-    let mut fields = Vec::with_capacity(input_fields.named.len());
-    for field in input_fields.named.iter() {
+    let mut fields = Vec::with_capacity(input_fields.len());
+    for field in input_fields.iter() {
         let field_attributes =
             match parse_field_attributes(field).map_err(syn::Error::into_compile_error) {
                 Ok(value) => value,
@@ -57,8 +63,16 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             .as_ref()
             .expect("named field structs should have named fields");
 
+        if is_hash && field_attributes.flatten {
+            return syn::Error::new_spanned(field, "hash structs do not support flattened fields")
+                .into_compile_error()
+                .into();
+        }
+
+        // Hash keys are plain symbols, while instance variables start with '@'.
         let name_str = match field_attributes.name {
             Some(name) => name,
+            None if is_hash => LitByteStr::new(name.to_string().as_bytes(), name.span()),
             None => LitByteStr::new(format!("@{name}").as_bytes(), name.span()),
         };
         fields.push(IntoValueField {
@@ -66,27 +80,35 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             name_str,
             ty: &field.ty,
             into_value: field_attributes.into_value,
+            flatten: field_attributes.flatten,
         });
     }
 
-    let create_field_keys = fields.iter().enumerate().map(|(i, field)| {
-        let ident = format_ident!("field_{i}_key");
-        let name = &field.name_str;
-        quote! {
-            let #ident = arena.create_symbol(#name.into());;
-        }
-    });
+    if let Some(field) = fields.iter().filter(|field| field.flatten).nth(1) {
+        return syn::Error::new_spanned(field.name, "only one field may be flattened")
+            .into_compile_error()
+            .into();
+    }
 
-    let create_field_values = fields.iter().enumerate().map(|(i, field)| {
-        let ident = format_ident!("field_{i}_value");
+    let push_fields = fields.iter().map(|field| {
         let field_name = &field.name;
+        let name = &field.name_str;
         let ty = &field.ty;
         let ty_span = ty.span();
 
-        match field.into_value.as_ref() {
+        if field.flatten {
+            let cast_type = quote_spanned! {ty_span=>
+                <#ty as ::ruby_marshal::IntoInstanceVariables>
+            };
+            return quote! {
+                #cast_type::into_instance_variables(self.#field_name, arena, instance_variables)?;
+            };
+        }
+
+        let create_value = match field.into_value.as_ref() {
             Some(into_value) => {
                 quote_spanned! {into_value.span()=>
-                    let #ident = #into_value(self.#field_name, arena)?;
+                    let value = #into_value(self.#field_name, arena)?;
                 }
             }
             None => {
@@ -94,18 +116,126 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                     <#ty as ::ruby_marshal::IntoValue>
                 };
                 quote! {
-                    let #ident = #cast_type::into_value(self.#field_name, arena)?;
+                    let value = #cast_type::into_value(self.#field_name, arena)?;
                 }
             }
+        };
+
+        let push = if is_hash {
+            quote! { pairs.push((key.into(), value)); }
+        } else {
+            quote! { instance_variables.push((key, value)); }
+        };
+
+        quote! {
+            {
+                let key = arena.create_symbol_static(#name);
+                #create_value
+                #push
+            }
         }
     });
 
-    let field_vec_entries = fields.iter().enumerate().map(|(i, _field)| {
-        let key_ident = format_ident!("field_{i}_key");
-        let value_ident = format_ident!("field_{i}_value");
+    let input_name = &input.ident;
+    if is_hash {
+        let num_fields = fields.len();
+        let tokens = quote! {
+            impl ::ruby_marshal::IntoValue for #input_name {
+                fn into_value(
+                    self,
+                    arena: &mut ::ruby_marshal::ValueArena
+                ) -> Result<::ruby_marshal::ValueHandle, ::ruby_marshal::IntoValueError> {
+                    let mut pairs = ::ruby_marshal::__private::Vec::with_capacity(#num_fields);
+                    #(#push_fields)*
+
+                    let hash = arena.create_hash(pairs, None);
+
+                    Ok(hash.into())
+                }
+            }
+        };
+
+        return proc_macro::TokenStream::from(tokens);
+    }
+
+    let into_value_impl = object_name
+        .map(|object_name| {
+            quote! {
+                impl ::ruby_marshal::IntoValue for #input_name {
+                    fn into_value(
+                        self,
+                        arena: &mut ::ruby_marshal::ValueArena
+                    ) -> Result<::ruby_marshal::ValueHandle, ::ruby_marshal::IntoValueError> {
+                        let object_name = arena.create_symbol_static(#object_name);
+
+                        let mut instance_variables = ::ruby_marshal::__private::Vec::new();
+                        ::ruby_marshal::IntoInstanceVariables::into_instance_variables(
+                            self,
+                            arena,
+                            &mut instance_variables,
+                        )?;
+
+                        let object = arena.create_object(object_name, instance_variables);
+
+                        Ok(object.into())
+                    }
+                }
+            }
+        })
+        .or_else(|| {
+            // Unit structs without an object name are empty arrays,
+            // unless they are only flattened.
+            (is_unit && !is_flatten_only).then(|| {
+                quote! {
+                    impl ::ruby_marshal::IntoValue for #input_name {
+                        fn into_value(
+                            self,
+                            arena: &mut ::ruby_marshal::ValueArena
+                        ) -> Result<::ruby_marshal::ValueHandle, ::ruby_marshal::IntoValueError> {
+                            let array = arena.create_array(::ruby_marshal::__private::Vec::new());
+
+                            Ok(array.into())
+                        }
+                    }
+                }
+            })
+        });
+    let tokens = quote! {
+        impl ::ruby_marshal::IntoInstanceVariables for #input_name {
+            fn into_instance_variables(
+                self,
+                arena: &mut ::ruby_marshal::ValueArena,
+                instance_variables: &mut ::ruby_marshal::__private::Vec<(
+                    ::ruby_marshal::TypedValueHandle<::ruby_marshal::SymbolValue>,
+                    ::ruby_marshal::ValueHandle,
+                )>,
+            ) -> Result<(), ::ruby_marshal::IntoValueError> {
+                #(#push_fields)*
+
+                Ok(())
+            }
+        }
+
+        #into_value_impl
+    };
+
+    proc_macro::TokenStream::from(tokens)
+}
+
+fn derive_symbol_enum(input: &DeriveInput, data: &DataEnum) -> proc_macro::TokenStream {
+    let variants = match parse_symbol_enum(input, data).map_err(syn::Error::into_compile_error) {
+        Ok(value) => value,
+        Err(error) => {
+            return error.into();
+        }
+    };
+
+    let match_arms = variants.iter().map(|variant| {
+        let variant_name = variant.name;
+        let name_str = &variant.name_str;
 
         quote! {
-            (#key_ident, #value_ident),
+            Self::#variant_name => #name_str,
         }
     });
 
@@ -116,19 +246,11 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 self,
                 arena: &mut ::ruby_marshal::ValueArena
             ) -> Result<::ruby_marshal::ValueHandle, ::ruby_marshal::IntoValueError> {
-                let object_name = arena.create_symbol(#object_name.into());
-
-                #(#create_field_keys)*
-
-                #(#create_field_values)*
-
-                let fields = vec![
-                    #(#field_vec_entries)*
-                ];
-
-                let object = arena.create_object(object_name, fields);
+                let name: &'static [u8] = match self {
+                    #(#match_arms)*
+                };
 
-                Ok(object.into())
+                Ok(arena.create_symbol_static(name).into())
             }
         }
     };
@@ -141,4 +263,5 @@ struct IntoValueField<'a> {
     name_str: LitByteStr,
     ty: &'a Type,
     into_value: Option<syn::Path>,
+    flatten: bool,
 }
@@ -3,26 +3,58 @@ mod into_value;
 
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
+use syn::DataEnum;
 use syn::DeriveInput;
 use syn::Expr;
 use syn::Field;
+use syn::Fields;
+use syn::Ident;
 use syn::Lit;
 use syn::LitByteStr;
 use syn::Meta;
 use syn::Token;
+use syn::Variant;
 
+/// Derive `FromValue` for a struct, or a unit-only enum.
+///
+/// Named field structs are read from an object, and need an `object` attribute.
+/// Structs that are only flattened into other structs can instead use a `flatten_only` attribute,
+/// which only reads them from instance variables.
+/// Unit structs are read from an empty array,
+/// or from an object without instance variables if they have an `object` attribute.
+/// Unit-only enums are read from symbols.
+///
+/// Structs with a `hash` attribute are instead read from a hash with symbol keys.
+/// Keys default to the field names, without a leading `@`.
+/// Missing, unknown, and duplicate keys are reported like instance variables.
+/// Flattened fields are not supported in this mode.
+///
+/// Structs may have one lifetime parameter, which is used as the lifetime of the arena.
+/// This allows fields like `&'a str` and `&'a [u8]` to borrow from the arena instead of allocating.
 #[proc_macro_derive(FromValue, attributes(ruby_marshal))]
 pub fn derive_from_value(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     from_value::derive(input)
 }
 
+/// Derive `IntoValue` for a struct, or a unit-only enum.
+///
+/// This uses the same mapping as the `FromValue` derive.
+/// Note that this maps unit structs to empty arrays, while `()` is mapped to nil.
 #[proc_macro_derive(IntoValue, attributes(ruby_marshal))]
 pub fn derive_into_value(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     into_value::derive(input)
 }
 
-pub(crate) fn parse_container_attributes(input: &DeriveInput) -> syn::Result<LitByteStr> {
+pub(crate) struct ContainerAttributes {
+    pub object_name: Option<LitByteStr>,
+    pub hash: bool,
+    pub flatten_only: bool,
+}
+
+pub(crate) fn parse_container_attributes(input: &DeriveInput) -> syn::Result<ContainerAttributes> {
     let mut object_name = None;
+    let mut hash = false;
+    let mut flatten_only = false;
     for attr in input.attrs.iter() {
         if attr.path().is_ident("ruby_marshal") {
             let nested = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
@@ -57,6 +89,23 @@ pub(crate) fn parse_container_attributes(input: &DeriveInput) -> syn::Result<Lit
 
                         object_name = Some(value.clone());
                     }
+                    Meta::Path(path) if path.is_ident("hash") => {
+                        if hash {
+                            return Err(syn::Error::new(meta.span(), "duplicate hash attributes"));
+                        }
+
+                        hash = true;
+                    }
+                    Meta::Path(path) if path.is_ident("flatten_only") => {
+                        if flatten_only {
+                            return Err(syn::Error::new(
+                                meta.span(),
+                                "duplicate flatten_only attributes",
+                            ));
+                        }
+
+                        flatten_only = true;
+                    }
                     _ => {
                         return Err(syn::Error::new_spanned(
                             meta,
@@ -68,26 +117,47 @@ pub(crate) fn parse_container_attributes(input: &DeriveInput) -> syn::Result<Lit
         }
     }
 
-    let object_name = match object_name {
-        Some(object_name) => object_name,
-        None => {
-            return Err(syn::Error::new_spanned(input, "missing object attribute"));
-        }
-    };
+    if hash && object_name.is_some() {
+        return Err(syn::Error::new_spanned(
+            input,
+            "the object and hash attributes cannot be used together",
+        ));
+    }
+
+    if flatten_only && (hash || object_name.is_some()) {
+        return Err(syn::Error::new_spanned(
+            input,
+            "the flatten_only attribute cannot be used with the object or hash attributes",
+        ));
+    }
+
+    let is_named_struct = matches!(
+        &input.data,
+        syn::Data::Struct(data) if matches!(data.fields, Fields::Named(_))
+    );
+    if is_named_struct && object_name.is_none() && !hash && !flatten_only {
+        return Err(syn::Error::new_spanned(input, "missing object attribute"));
+    }
 
-    Ok(object_name)
+    Ok(ContainerAttributes {
+        object_name,
+        hash,
+        flatten_only,
+    })
 }
 
 pub(crate) struct FieldAttributes {
     pub name: Option<LitByteStr>,
     pub from_value: Option<syn::Path>,
     pub into_value: Option<syn::Path>,
+    pub flatten: bool,
 }
 
 pub(crate) fn parse_field_attributes(field: &Field) -> syn::Result<FieldAttributes> {
     let mut name = None;
     let mut from_value = None;
     let mut into_value = None;
+    let mut flatten = false;
     for attr in field.attrs.iter() {
         if attr.path().is_ident("ruby_marshal") {
             let nested = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
@@ -179,6 +249,16 @@ pub(crate) fn parse_field_attributes(field: &Field) -> syn::Result<FieldAttribut
 
                         into_value = Some(value.clone());
                     }
+                    Meta::Path(path) if path.is_ident("flatten") => {
+                        if flatten {
+                            return Err(syn::Error::new(
+                                meta.span(),
+                                "duplicate flatten attributes",
+                            ));
+                        }
+
+                        flatten = true;
+                    }
                     _ => {
                         return Err(syn::Error::new_spanned(
                             meta,
@@ -190,9 +270,124 @@ pub(crate) fn parse_field_attributes(field: &Field) -> syn::Result<FieldAttribut
         }
     }
 
+    if flatten && (name.is_some() || from_value.is_some() || into_value.is_some()) {
+        return Err(syn::Error::new_spanned(
+            field,
+            "flattened fields cannot have name, from_value, or into_value attributes",
+        ));
+    }
+
     Ok(FieldAttributes {
         name,
         from_value,
         into_value,
+        flatten,
     })
 }
+
+pub(crate) struct VariantAttributes {
+    pub name: Option<LitByteStr>,
+}
+
+pub(crate) fn parse_variant_attributes(variant: &Variant) -> syn::Result<VariantAttributes> {
+    let mut name = None;
+    for attr in variant.attrs.iter() {
+        if attr.path().is_ident("ruby_marshal") {
+            let nested = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+
+            for meta in nested.iter() {
+                match meta {
+                    Meta::NameValue(name_value) if name_value.path.is_ident("name") => {
+                        if name.is_some() {
+                            return Err(syn::Error::new(meta.span(), "duplicate name attributes"));
+                        }
+
+                        let value = match &name_value.value {
+                            Expr::Lit(value) => match &value.lit {
+                                Lit::ByteStr(value) => Some(value),
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+
+                        let value = match value {
+                            Some(value) => value,
+                            None => {
+                                return Err(syn::Error::new_spanned(
+                                    value,
+                                    "variant name must be a byte string literal",
+                                ));
+                            }
+                        };
+
+                        name = Some(value.clone());
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            meta,
+                            "unrecognized ruby_marshal attribute",
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(VariantAttributes { name })
+}
+
+/// A unit enum variant, mapped to a symbol.
+pub(crate) struct SymbolVariant<'a> {
+    pub name: &'a Ident,
+    pub name_str: LitByteStr,
+}
+
+/// Parse the variants of an enum where each variant maps to a symbol.
+///
+/// This validates that the enum is a unit-only enum without container attributes.
+pub(crate) fn parse_symbol_enum<'a>(
+    input: &DeriveInput,
+    data: &'a DataEnum,
+) -> syn::Result<Vec<SymbolVariant<'a>>> {
+    let container_attributes = parse_container_attributes(input)?;
+    if container_attributes.object_name.is_some() {
+        return Err(syn::Error::new_spanned(
+            input,
+            "enums do not support the object attribute",
+        ));
+    }
+    if container_attributes.hash {
+        return Err(syn::Error::new_spanned(
+            input,
+            "enums do not support the hash attribute",
+        ));
+    }
+    if container_attributes.flatten_only {
+        return Err(syn::Error::new_spanned(
+            input,
+            "enums do not support the flatten_only attribute",
+        ));
+    }
+
+    let mut variants = Vec::with_capacity(data.variants.len());
+    for variant in data.variants.iter() {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "only unit variants are supported",
+            ));
+        }
+
+        let variant_attributes = parse_variant_attributes(variant)?;
+
+        let name = &variant.ident;
+        let name_str = match variant_attributes.name {
+            Some(name) => name,
+            None => LitByteStr::new(name.to_string().as_bytes(), name.span()),
+        };
+
+        variants.push(SymbolVariant { name, name_str });
+    }
+
+    Ok(variants)
+}
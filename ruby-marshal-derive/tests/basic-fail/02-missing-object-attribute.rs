@@ -0,0 +1,6 @@
+#[derive(ruby_marshal_derive::FromValue)]
+pub struct MyObject {
+    field1: i32,
+}
+
+fn main() {}
@@ -0,0 +1,31 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::FromValueError;
+use ruby_marshal::IntoValue;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+pub enum Direction {
+    #[ruby_marshal(name = b"up")]
+    Up,
+
+    #[ruby_marshal(name = b"down")]
+    Down,
+
+    Left,
+}
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let up = Direction::Up.into_value(&mut arena).unwrap();
+    let left = Direction::Left.into_value(&mut arena).unwrap();
+    let right = arena.create_symbol(b"right".into()).into();
+
+    let ctx = FromValueContext::new(&arena);
+    assert_eq!(ctx.from_value::<Direction>(up).unwrap(), Direction::Up);
+    assert_eq!(ctx.from_value::<Direction>(left).unwrap(), Direction::Left);
+    assert!(matches!(
+        ctx.from_value::<Direction>(right),
+        Err(FromValueError::UnexpectedSymbol { name }) if name == b"right"
+    ));
+}
@@ -0,0 +1,47 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::IntoValue;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+#[ruby_marshal(flatten_only)]
+pub struct Position {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+#[ruby_marshal(object = b"Player")]
+pub struct Player {
+    health: i32,
+
+    #[ruby_marshal(flatten)]
+    position: Position,
+}
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let player = Player {
+        health: 10,
+        position: Position { x: 1, y: 2 },
+    };
+    let handle = player.into_value(&mut arena).unwrap();
+
+    let object = arena.get(handle).unwrap().as_object().unwrap();
+    let names: Vec<&[u8]> = object
+        .instance_variables()
+        .iter()
+        .map(|(name, _value)| arena.get_symbol(*name).unwrap().value())
+        .collect();
+    assert_eq!(names, [&b"@health"[..], b"@x", b"@y"]);
+
+    let ctx = FromValueContext::new(&arena);
+    let decoded: Player = ctx.from_value(handle).unwrap();
+    assert_eq!(
+        decoded,
+        Player {
+            health: 10,
+            position: Position { x: 1, y: 2 },
+        }
+    );
+}
@@ -0,0 +1,43 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::FromValueError;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, ruby_marshal_derive::FromValue)]
+#[ruby_marshal(object = b"Item")]
+pub struct Item {
+    name: i32,
+}
+
+#[derive(Debug, ruby_marshal_derive::FromValue)]
+#[ruby_marshal(object = b"Inventory")]
+pub struct Inventory {
+    items: Vec<Item>,
+}
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let item_name = arena.create_symbol("Item".into());
+    let name = arena.create_symbol("@name".into());
+    let value = arena.create_fixnum(1).into();
+    let item = arena.create_object(item_name, vec![(name, value)]).into();
+    let broken_item = arena.create_object(item_name, Vec::new()).into();
+    let items = arena.create_array(vec![item, broken_item]).into();
+
+    let inventory_name = arena.create_symbol("Inventory".into());
+    let items_name = arena.create_symbol("@items".into());
+    let inventory = arena
+        .create_object(inventory_name, vec![(items_name, items)])
+        .into();
+
+    let ctx = FromValueContext::new(&arena);
+    let error = ctx.from_value::<Inventory>(inventory).unwrap_err();
+    match &error {
+        FromValueError::At { path, .. } => assert_eq!(path, "@items[1]"),
+        error => panic!("unexpected error {error:?}"),
+    }
+    assert!(matches!(
+        error.without_path(),
+        FromValueError::MissingInstanceVariable { name } if name == b"@name"
+    ));
+}
@@ -0,0 +1,47 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::FromValueError;
+use ruby_marshal::IntoValue;
+use ruby_marshal::Value;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+pub struct Marker;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+#[ruby_marshal(object = b"Flag")]
+pub struct Flag;
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let marker = Marker.into_value(&mut arena).unwrap();
+    assert!(matches!(arena.get(marker), Some(Value::Array(value)) if value.is_empty()));
+
+    let flag = Flag.into_value(&mut arena).unwrap();
+    let object = arena.get(flag).unwrap().as_object().unwrap();
+    assert_eq!(arena.get_symbol(object.name()).unwrap().value(), b"Flag");
+    assert_eq!(object.instance_variable_count(), 0);
+
+    let fixnum = arena.create_fixnum(1).into();
+    let array = arena.create_array(vec![fixnum]).into();
+    let flag_name = arena.create_symbol("Flag".into());
+    let ivar_name = arena.create_symbol("@value".into());
+    let flag_with_ivar = arena
+        .create_object(flag_name, vec![(ivar_name, fixnum)])
+        .into();
+
+    let ctx = FromValueContext::new(&arena);
+    assert_eq!(ctx.from_value::<Marker>(marker).unwrap(), Marker);
+    assert_eq!(ctx.from_value::<Flag>(flag).unwrap(), Flag);
+    assert!(matches!(
+        ctx.from_value::<Marker>(array),
+        Err(FromValueError::UnexpectedArrayLength {
+            expected: 0,
+            actual: 1
+        })
+    ));
+    assert!(matches!(
+        ctx.from_value::<Flag>(flag_with_ivar),
+        Err(FromValueError::UnknownInstanceVariable { .. })
+    ));
+}
@@ -0,0 +1,80 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::FromValueError;
+use ruby_marshal::Value;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue)]
+#[ruby_marshal(flatten_only)]
+pub struct Position<'a> {
+    label: &'a str,
+    x: i32,
+}
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue)]
+#[ruby_marshal(object = b"Player")]
+pub struct Player<'a> {
+    name: &'a str,
+    data: &'a [u8],
+    tags: Vec<&'a str>,
+
+    #[ruby_marshal(from_value = "kind_from_value")]
+    kind: &'a [u8],
+
+    #[ruby_marshal(flatten)]
+    position: Position<'a>,
+}
+
+fn kind_from_value<'a>(
+    ctx: &FromValueContext<'a>,
+    value: &'a Value,
+) -> Result<&'a [u8], FromValueError> {
+    let value: &ruby_marshal::SymbolValue = ruby_marshal::FromValue::from_value(ctx, value)?;
+    Ok(value.value())
+}
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let object_name = arena.create_symbol("Player".into());
+    let name = arena.create_string("Alice".into());
+    let fields = [
+        ("@name", name.into()),
+        ("@data", arena.create_string(vec![0xFF, 0x00]).into()),
+        ("@tags", {
+            let a = arena.create_symbol("a".into()).into();
+            let b = arena.create_string("b".into()).into();
+            arena.create_array(vec![a, b]).into()
+        }),
+        ("@kind", arena.create_symbol("human".into()).into()),
+        ("@label", arena.create_string("start".into()).into()),
+        ("@x", arena.create_fixnum(1).into()),
+    ];
+    let instance_variables = fields
+        .into_iter()
+        .map(|(name, value)| (arena.create_symbol(name.into()), value))
+        .collect();
+    let handle = arena.create_object(object_name, instance_variables).into();
+
+    let ctx = FromValueContext::new(&arena);
+    let player: Player = ctx.from_value(handle).unwrap();
+    assert_eq!(
+        player,
+        Player {
+            name: "Alice",
+            data: &[0xFF, 0x00],
+            tags: vec!["a", "b"],
+            kind: b"human",
+            position: Position {
+                label: "start",
+                x: 1,
+            },
+        }
+    );
+
+    // The fields borrow from the arena.
+    let name = arena.get(name).unwrap().as_string().unwrap();
+    assert!(std::ptr::eq(player.name.as_ptr(), name.value().as_ptr()));
+
+    let error = ctx.from_value::<Player>(object_name.into()).unwrap_err();
+    assert!(matches!(error, FromValueError::UnexpectedValueKind { .. }));
+}
@@ -0,0 +1,95 @@
+use ruby_marshal::FromValueContext;
+use ruby_marshal::FromValueError;
+use ruby_marshal::IntoValue;
+use ruby_marshal::ValueArena;
+
+#[derive(Debug, PartialEq, ruby_marshal_derive::FromValue, ruby_marshal_derive::IntoValue)]
+#[ruby_marshal(hash)]
+pub struct Options {
+    width: i32,
+
+    #[ruby_marshal(name = b"full_screen")]
+    fullscreen: bool,
+}
+
+fn main() {
+    let mut arena = ValueArena::new();
+
+    let options = Options {
+        width: 640,
+        fullscreen: true,
+    };
+    let handle = options.into_value(&mut arena).unwrap();
+
+    let hash = match arena.get(handle).unwrap() {
+        ruby_marshal::Value::Hash(hash) => hash,
+        value => panic!("unexpected value {value:?}"),
+    };
+    let keys: Vec<&[u8]> = hash
+        .value()
+        .iter()
+        .map(|(key, _value)| arena.get(*key).unwrap().as_symbol().unwrap().value())
+        .collect();
+    assert_eq!(keys, [&b"width"[..], b"full_screen"]);
+
+    let ctx = FromValueContext::new(&arena);
+    let decoded: Options = ctx.from_value(handle).unwrap();
+    assert_eq!(
+        decoded,
+        Options {
+            width: 640,
+            fullscreen: true,
+        }
+    );
+
+    let width = arena.create_symbol("width".into()).into();
+    let full_screen = arena.create_symbol("full_screen".into()).into();
+    let extra = arena.create_symbol("extra".into()).into();
+    let string = arena.create_string("width".into()).into();
+    let value = arena.create_fixnum(1).into();
+    let true_value = arena.create_bool(true).into();
+
+    let missing = arena.create_hash(vec![(width, value)], None).into();
+    let unknown = arena
+        .create_hash(
+            vec![(width, value), (full_screen, true_value), (extra, value)],
+            None,
+        )
+        .into();
+    let duplicate = arena
+        .create_hash(vec![(width, value), (width, value)], None)
+        .into();
+    let wrong_value = arena.create_hash(vec![(width, true_value)], None).into();
+    let string_key = arena.create_hash(vec![(string, value)], None).into();
+
+    let ctx = FromValueContext::new(&arena);
+    let error = ctx.from_value::<Options>(missing).unwrap_err();
+    assert!(matches!(
+        error,
+        FromValueError::MissingInstanceVariable { name } if name == b"full_screen"
+    ));
+
+    let error = ctx.from_value::<Options>(unknown).unwrap_err();
+    assert!(matches!(
+        error,
+        FromValueError::UnknownInstanceVariable { name } if name == b"extra"
+    ));
+
+    let error = ctx.from_value::<Options>(duplicate).unwrap_err();
+    assert!(matches!(
+        error,
+        FromValueError::DuplicateHashKey { key } if key == width
+    ));
+
+    let error = ctx.from_value::<Options>(wrong_value).unwrap_err();
+    match &error {
+        FromValueError::At { path, .. } => assert_eq!(path, "[:width]"),
+        error => panic!("unexpected error {error:?}"),
+    }
+
+    let error = ctx.from_value::<Options>(string_key).unwrap_err();
+    assert!(matches!(
+        error.without_path(),
+        FromValueError::UnexpectedValueKind { .. }
+    ));
+}
@@ -0,0 +1,171 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::Criterion;
+use ruby_marshal::Cached;
+use ruby_marshal::FromValueContext;
+use ruby_marshal::ValueArena;
+use std::hint::black_box;
+use std::rc::Rc;
+
+/// The number of unique symbols in the fixture.
+const SYMBOL_COUNT: usize = 10_000;
+
+/// Make a symbol-heavy fixture.
+///
+/// This is an array of objects, each with a unique class name and a few instance variables.
+/// Instance variable names repeat, so the fixture has both new symbols and symbol links.
+fn symbol_heavy_fixture() -> Vec<u8> {
+    let mut arena = ValueArena::new();
+    let mut items = Vec::with_capacity(SYMBOL_COUNT);
+    for i in 0..SYMBOL_COUNT {
+        let name = arena.create_symbol(format!("Namespace::Class{i}").into());
+        let mut instance_variables = Vec::with_capacity(4);
+        for j in 0..4 {
+            let name = arena.create_symbol(format!("@field{j}").into());
+            let value = arena.create_symbol(format!("value_{i}_{j}").into());
+            instance_variables.push((name, value.into()));
+        }
+        items.push(arena.create_object(name, instance_variables).into());
+    }
+    let root = arena.create_array(items);
+    arena.replace_root(root);
+
+    let mut data = Vec::new();
+    ruby_marshal::dump(&mut data, &arena).expect("failed to dump");
+    data
+}
+
+fn load(c: &mut Criterion) {
+    let data = symbol_heavy_fixture();
+
+    c.bench_function("load symbol heavy", |b| {
+        b.iter(|| ruby_marshal::load(black_box(&data[..])).expect("failed to load"))
+    });
+}
+
+fn create_symbol(c: &mut Criterion) {
+    let names: Vec<Vec<u8>> = (0..SYMBOL_COUNT)
+        .map(|i| format!("symbol_{i}").into())
+        .collect();
+
+    c.bench_function("create symbol new", |b| {
+        b.iter_batched(
+            || names.clone(),
+            |names| {
+                let mut arena = ValueArena::new();
+                for name in names {
+                    arena.create_symbol(name);
+                }
+                arena
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("create symbol existing", |b| {
+        let mut arena = ValueArena::new();
+        for name in names.iter() {
+            arena.create_symbol(name.clone());
+        }
+
+        b.iter_batched(
+            || names.clone(),
+            |names| {
+                for name in names {
+                    black_box(arena.create_symbol(name));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Compare `create_symbol` and `create_symbol_static` for a few static names that repeat, like instance variable names.
+fn create_symbol_repeated(c: &mut Criterion) {
+    const NAMES: [&[u8]; 4] = [b"@id", b"@name", b"@position", b"@children"];
+
+    let mut group = c.benchmark_group("create symbol repeated");
+
+    group.bench_function("create_symbol", |b| {
+        b.iter(|| {
+            let mut arena = ValueArena::new();
+            for _ in 0..SYMBOL_COUNT {
+                for name in NAMES {
+                    black_box(arena.create_symbol(black_box(name).into()));
+                }
+            }
+            arena
+        })
+    });
+
+    group.bench_function("create_symbol_static", |b| {
+        b.iter(|| {
+            let mut arena = ValueArena::new();
+            for _ in 0..SYMBOL_COUNT {
+                for name in NAMES {
+                    black_box(arena.create_symbol_static(black_box(name)));
+                }
+            }
+            arena
+        })
+    });
+
+    group.finish();
+}
+
+/// Make a fixture where one large subtree is referenced many times.
+///
+/// The root is an array of 200 references to the same 50 by 50 array of integers.
+fn shared_fixture() -> ValueArena {
+    let mut arena = ValueArena::new();
+    let rows = (0..50)
+        .map(|i| {
+            let row = (0..50)
+                .map(|j| arena.create_fixnum(i * 50 + j).into())
+                .collect();
+            arena.create_array(row).into()
+        })
+        .collect();
+    let shared = arena.create_array(rows).into();
+    let root = arena.create_array(vec![shared; 200]);
+    arena.replace_root(root);
+
+    arena
+}
+
+/// Compare extracting a shared subtree at every reference with extracting it once through [`Cached`].
+fn from_value_shared(c: &mut Criterion) {
+    let arena = shared_fixture();
+
+    let mut group = c.benchmark_group("from value shared");
+
+    group.bench_function("uncached", |b| {
+        b.iter(|| {
+            let ctx = FromValueContext::new(&arena);
+            let value: Vec<Rc<Vec<Vec<i32>>>> =
+                ctx.from_value(arena.root()).expect("failed to extract");
+            value
+        })
+    });
+
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            let ctx = FromValueContext::new(&arena);
+            let value: Vec<Cached<Rc<Vec<Vec<i32>>>>> =
+                ctx.from_value(arena.root()).expect("failed to extract");
+            value
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    load,
+    create_symbol,
+    create_symbol_repeated,
+    from_value_shared
+);
+criterion_main!(benches);
@@ -0,0 +1,136 @@
+# Generate the round trip corpus in "test_data/corpus".
+#
+# Run this with `ruby scripts/generate-corpus.rb`.
+# Each value is dumped to "<name>.bin",
+# and the `corpus` test checks that loading and dumping each file reproduces the same bytes.
+
+require 'fileutils'
+require 'set'
+
+Point = Struct.new(:x, :y)
+
+class Pair
+  def initialize(first, second)
+    @first = first
+    @second = second
+  end
+end
+
+class UserDefined
+  def initialize(data)
+    @data = data
+  end
+
+  def _dump(_level)
+    @data
+  end
+
+  def self._load(data)
+    new(data)
+  end
+end
+
+class UserMarshal
+  def initialize(data)
+    @data = data
+  end
+
+  def marshal_dump
+    @data
+  end
+
+  def marshal_load(data)
+    @data = data
+  end
+end
+
+module Namespace
+  class Nested; end
+end
+
+recursive_array = []
+recursive_array << recursive_array
+
+recursive_hash = {}
+recursive_hash[:self] = recursive_hash
+
+hash_with_default = Hash.new(0)
+hash_with_default[:a] = 1
+
+self_default_hash = {}
+self_default_hash.default = self_default_hash
+self_default_hash[1] = self_default_hash
+
+shared_string = 'shared'
+
+shared_encoded_string = 'x'.encode(Encoding::Shift_JIS)
+shared_pair = Pair.new([shared_encoded_string], 1.5)
+shared_hash = { shared_pair => shared_encoded_string }
+object_links = [shared_encoded_string, shared_pair, shared_hash, shared_pair, shared_hash]
+object_links << object_links
+
+struct_with_instance_variables = Point.new(1, 2)
+struct_with_instance_variables.instance_variable_set(:@note, 'note')
+
+values = {
+  'nil' => nil,
+  'true' => true,
+  'false' => false,
+  'fixnum-zero' => 0,
+  'fixnum-small' => 100,
+  'fixnum-small-negative' => -100,
+  'fixnum-large' => 2**30 - 1,
+  'fixnum-large-negative' => -(2**30),
+  'bignum' => 2**64,
+  'bignum-odd-length' => 2**40,
+  'bignum-negative' => -(2**70),
+  'float' => 1.5,
+  'float-negative' => -2.25,
+  'float-integral' => 100.0,
+  'float-small' => 0.0001,
+  'float-tiny' => 1.0e-20,
+  'float-huge' => 1.0e20,
+  'float-inexact' => 0.1 + 0.2,
+  'float-third' => 1.0 / 3.0,
+  'float-max' => Float::MAX,
+  'float-min-normal' => Float::MIN,
+  'float-subnormal' => 5.0e-324,
+  'float-negative-subnormal' => -1.5e-323,
+  'float-zero' => 0.0,
+  'float-negative-zero' => -0.0,
+  'float-infinity' => Float::INFINITY,
+  'float-negative-infinity' => -Float::INFINITY,
+  'float-nan' => Float::NAN,
+  'symbol' => :symbol,
+  'symbol-utf8' => :"シンボル",
+  'string' => 'string',
+  'string-us-ascii' => 'string'.encode(Encoding::US_ASCII),
+  'string-iso-8859-1' => "caf\xE9".force_encoding(Encoding::ISO_8859_1),
+  'string-binary' => "\xFF\x00".b,
+  'array' => [1, :symbol, 'string', nil],
+  'array-links' => [shared_string, shared_string, :symbol, :symbol, 1.5],
+  'array-recursive' => recursive_array,
+  'object-links' => object_links,
+  'hash' => { a: 1, 'b' => [2] },
+  'hash-default' => hash_with_default,
+  'hash-recursive' => recursive_hash,
+  'hash-default-recursive' => self_default_hash,
+  'object' => Pair.new(1, 'two'),
+  'struct' => Point.new(1, 2),
+  'struct-instance-variables' => struct_with_instance_variables,
+  'user-defined' => UserDefined.new('data'),
+  'user-marshal' => UserMarshal.new([1, :symbol]),
+  'set' => Set[1, :symbol],
+  'regexp' => /ab+c/i,
+  'regexp-utf8' => /é+/m,
+  'class' => String,
+  'class-nested' => Namespace::Nested,
+  'module' => Kernel,
+}
+
+corpus_dir = File.join(__dir__, '..', 'test_data', 'corpus')
+FileUtils.mkdir_p(corpus_dir)
+
+values.each do |name, value|
+  File.binwrite(File.join(corpus_dir, "#{name}.bin"), Marshal.dump(value))
+end
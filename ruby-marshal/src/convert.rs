@@ -1,35 +1,139 @@
+mod binary;
+mod duration;
 mod from_value;
+mod nil_or_empty;
+mod one_or_many;
+#[cfg(feature = "std")]
+mod path;
+mod ruby_big_decimal;
+mod ruby_complex;
+mod ruby_key;
+#[cfg(feature = "std")]
+mod ruby_object_map;
+mod ruby_rational;
+mod ruby_set;
+mod ruby_time;
+mod user_defined_registry;
 
+pub use self::binary::Binary;
+pub use self::binary::BinaryRef;
+pub use self::duration::DurationFromValueError;
+pub use self::duration::DurationIntoValueError;
+pub use self::duration::Millis;
+pub use self::duration::Seconds;
 pub use self::from_value::BTreeMapFromValueError;
+pub use self::from_value::Cached;
+pub use self::from_value::FromInstanceVariables;
 pub use self::from_value::FromValue;
 pub use self::from_value::FromValueContext;
 pub use self::from_value::FromValueError;
 pub use self::from_value::HashMapFromValueError;
+pub use self::from_value::IndexMapFromValueError;
+pub use self::from_value::PathSegment;
+pub use self::nil_or_empty::NilOrEmpty;
+pub use self::one_or_many::OneOrMany;
+#[cfg(feature = "std")]
+pub use self::path::PathIntoValueError;
+pub use self::ruby_big_decimal::RubyBigDecimal;
+pub use self::ruby_big_decimal::RubyBigDecimalFromValueError;
+pub use self::ruby_big_decimal::RubyBigDecimalKind;
+pub use self::ruby_complex::RubyComplex;
+#[cfg(feature = "std")]
+pub use self::ruby_object_map::RubyObjectMap;
+pub use self::ruby_rational::RubyRational;
+pub use self::ruby_set::RubySet;
+pub use self::ruby_time::RubyTime;
+pub use self::ruby_time::RubyTimeFromValueError;
+pub use self::ruby_time::RubyTimeIntoValueError;
+pub use self::user_defined_registry::UserDefinedRegistry;
+pub use self::user_defined_registry::UserDefinedRegistryError;
+use crate::SymbolValue;
+use crate::TypedValueHandle;
 use crate::ValueArena;
 use crate::ValueHandle;
-use std::collections::BTreeMap;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use indexmap::IndexMap;
+#[cfg(feature = "std")]
 use std::collections::HashMap;
 
 /// A utility to display a byte sequence as a string if it is UTF8 or a slice otherwise.
 #[derive(Debug)]
 pub struct DisplayByteString<'a>(pub &'a [u8]);
 
-impl std::fmt::Display for DisplayByteString<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for DisplayByteString<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let string = self.0;
-        match std::str::from_utf8(string) {
+        match core::str::from_utf8(string) {
             Ok(string) => write!(f, "{string}"),
             Err(_error) => write!(f, "{string:?}"),
         }
     }
 }
 
+/// A utility like [`DisplayByteString`], that displays at most `max_len` bytes.
+///
+/// If bytes are cut off, a `…(N more)` suffix is written.
+/// This is used in error messages, so that a huge name from untrusted data cannot flood a log.
+#[derive(Debug)]
+pub struct DisplayByteStringTruncated<'a> {
+    value: &'a [u8],
+    max_len: usize,
+}
+
+impl<'a> DisplayByteStringTruncated<'a> {
+    /// The default maximum number of bytes to display.
+    pub const DEFAULT_MAX_LEN: usize = 256;
+
+    /// Make a new [`DisplayByteStringTruncated`] with the default maximum length.
+    pub fn new(value: &'a [u8]) -> Self {
+        Self::with_max_len(value, Self::DEFAULT_MAX_LEN)
+    }
+
+    /// Make a new [`DisplayByteStringTruncated`] with the given maximum length.
+    pub fn with_max_len(value: &'a [u8], max_len: usize) -> Self {
+        Self { value, max_len }
+    }
+}
+
+impl core::fmt::Display for DisplayByteStringTruncated<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let value = self.value;
+        let shown_len = match core::str::from_utf8(value) {
+            Ok(string) => {
+                // UTF-8 strings are cut at a char boundary, so they are still displayed as text.
+                let mut shown_len = value.len().min(self.max_len);
+                while !string.is_char_boundary(shown_len) {
+                    shown_len -= 1;
+                }
+                write!(f, "{}", &string[..shown_len])?;
+
+                shown_len
+            }
+            Err(_error) => {
+                let shown_len = value.len().min(self.max_len);
+                write!(f, "{:?}", &value[..shown_len])?;
+
+                shown_len
+            }
+        };
+
+        let remaining = value.len() - shown_len;
+        if remaining > 0 {
+            write!(f, "…({remaining} more)")?;
+        }
+
+        Ok(())
+    }
+}
+
 /// An error that may occur while transforming types into Ruby Values.
 #[derive(Debug)]
 pub enum IntoValueError {
     /// Another user-provided kind of error occured.
     Other {
-        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+        error: Box<dyn core::error::Error + Send + Sync + 'static>,
     },
 }
 
@@ -37,7 +141,7 @@ impl IntoValueError {
     /// Shorthand for creating a new `Other` error variant.
     pub fn new_other<E>(error: E) -> Self
     where
-        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+        E: Into<Box<dyn core::error::Error + Send + Sync + 'static>>,
     {
         Self::Other {
             error: error.into(),
@@ -45,16 +149,16 @@ impl IntoValueError {
     }
 }
 
-impl std::fmt::Display for IntoValueError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for IntoValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Other { .. } => write!(f, "a user-provided error was encountered"),
         }
     }
 }
 
-impl std::error::Error for IntoValueError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl core::error::Error for IntoValueError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             Self::Other { error } => Some(&**error),
             // _ => None,
@@ -68,6 +172,35 @@ pub trait IntoValue: Sized {
     fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError>;
 }
 
+/// Implemented for any type that can be converted into the instance variables of a Ruby object.
+///
+/// This is used to encode the fields of a struct that are stored in the same object as its parent.
+pub trait IntoInstanceVariables: Sized {
+    /// Turn this type into instance variables, appending them to `instance_variables`.
+    fn into_instance_variables(
+        self,
+        arena: &mut ValueArena,
+        instance_variables: &mut Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> Result<(), IntoValueError>;
+}
+
+/// Turn the items of an iterator into a Ruby Array.
+///
+/// This is useful for encoding borrowed or lazily computed items without collecting them into a `Vec` first.
+pub fn into_value_iter<I, T>(iter: I, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError>
+where
+    I: IntoIterator<Item = T>,
+    T: IntoValue,
+{
+    Ok(arena.create_array_from_iter(iter)?.into())
+}
+
+impl IntoValue for () {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        Ok(arena.create_nil().into())
+    }
+}
+
 impl IntoValue for bool {
     fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
         Ok(arena.create_bool(self).into())
@@ -80,19 +213,38 @@ impl IntoValue for i32 {
     }
 }
 
+impl IntoValue for f64 {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        Ok(arena.create_float(self).into())
+    }
+}
+
+/// Arrays are created item by item.
+///
+/// Use [`Binary`] for binary strings.
 impl<T> IntoValue for Vec<T>
 where
     T: IntoValue,
 {
     fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
-        let mut array = Vec::with_capacity(self.len());
-        for item in self.into_iter() {
-            array.push(item.into_value(arena)?);
-        }
-        Ok(arena.create_array(array).into())
+        Ok(arena.create_array_from_iter(self)?.into())
+    }
+}
+
+/// The items are cloned one at a time, so no intermediate `Vec` is allocated.
+impl<T> IntoValue for &[T]
+where
+    T: IntoValue + Clone,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        Ok(arena.create_array_from_iter(self.iter().cloned())?.into())
     }
 }
 
+/// The order of the resulting hash follows the iteration order of the HashMap, which is not deterministic.
+/// As a result, dumping the same HashMap twice may produce different bytes.
+/// Use a [`BTreeMap`] if reproducible output is required.
+#[cfg(feature = "std")]
 impl<K, V> IntoValue for HashMap<K, V>
 where
     K: IntoValue,
@@ -112,6 +264,8 @@ where
     }
 }
 
+/// The order of the resulting hash follows the order of the keys,
+/// so equal BTreeMaps always produce the same bytes when dumped.
 impl<K, V> IntoValue for BTreeMap<K, V>
 where
     K: IntoValue,
@@ -131,6 +285,26 @@ where
     }
 }
 
+/// The order of the resulting hash follows the insertion order of the IndexMap.
+impl<K, V, S> IntoValue for IndexMap<K, V, S>
+where
+    K: IntoValue,
+    V: IntoValue,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let mut items = Vec::with_capacity(self.len());
+
+        for (key, value) in self.into_iter() {
+            let key_handle = key.into_value(arena)?;
+            let value_handle = value.into_value(arena)?;
+
+            items.push((key_handle, value_handle));
+        }
+
+        Ok(arena.create_hash(items, None).into())
+    }
+}
+
 impl<T> IntoValue for Option<T>
 where
     T: IntoValue,
@@ -155,7 +329,14 @@ mod test {
     use crate::StringValue;
     use crate::SymbolValue;
     use crate::UserDefinedValue;
+    use crate::UserMarshalValue;
     use crate::Value;
+    use crate::ValueKind;
+    use alloc::collections::BTreeSet;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use core::time::Duration;
+    use std::thread_local;
 
     #[test]
     fn sanity() {
@@ -217,6 +398,18 @@ mod test {
             .from_value(user_defined_handle)
             .expect("failed exec &UserDefinedValue::from_value");
 
+        let _unit_value: () = ctx
+            .from_value(nil_handle)
+            .expect("failed exec ()::from_value");
+
+        assert!(matches!(
+            ctx.from_value::<()>(bool_handle),
+            Err(FromValueError::UnexpectedValueKind {
+                kind: ValueKind::Bool,
+                ..
+            })
+        ));
+
         let _bool_value: bool = ctx
             .from_value(bool_handle)
             .expect("failed exec bool::from_value");
@@ -237,6 +430,7 @@ mod test {
             .from_value(array_handle)
             .expect("failed exec <Vec<i32>>::from_value");
 
+        #[cfg(feature = "std")]
         let _hash_map_value: HashMap<i32, i32> = ctx
             .from_value(hash_handle)
             .expect("failed exec <HashMap<i32, i32>>::from_value");
@@ -245,6 +439,9 @@ mod test {
             .from_value(hash_handle)
             .expect("failed exec <BTreeMap<i32, i32>>::from_value");
 
+        ().into_value(&mut arena)
+            .expect("failed to exec ()::into_value");
+
         true.into_value(&mut arena)
             .expect("failed to exec bool::into_value");
 
@@ -256,6 +453,41 @@ mod test {
             .into_value(&mut arena)
             .expect("failed to exec Vec::<i32>::into_value");
 
+        let array = arena
+            .create_array_from_iter((0..3).map(|i| i * 2))
+            .expect("failed to exec ValueArena::create_array_from_iter");
+        let items: Vec<i32> = match &arena[array.into_raw()] {
+            Value::Array(array) => array
+                .value()
+                .iter()
+                .map(|handle| match &arena[*handle] {
+                    Value::Fixnum(value) => value.value(),
+                    value => panic!("expected a fixnum, got {value:?}"),
+                })
+                .collect(),
+            value => panic!("expected an array, got {value:?}"),
+        };
+        assert!(items == [0, 2, 4], "{items:?}");
+
+        // Errors from items are passed through.
+        struct Failing(i32);
+        impl IntoValue for Failing {
+            fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+                if self.0 == 1 {
+                    return Err(IntoValueError::new_other("failing item"));
+                }
+                self.0.into_value(arena)
+            }
+        }
+        let error = arena
+            .create_array_from_iter((0..3).map(Failing))
+            .expect_err("ValueArena::create_array_from_iter should fail");
+        assert!(
+            matches!(&error, IntoValueError::Other { error } if error.to_string() == "failing item"),
+            "{error:?}"
+        );
+
+        #[cfg(feature = "std")]
         HashMap::<i32, i32>::new()
             .into_value(&mut arena)
             .expect("failed to exec HashMap::<i32, i32>::into_value");
@@ -272,4 +504,872 @@ mod test {
             .into_value(&mut arena)
             .expect("failed to exec Option::<i32>::None::into_value");
     }
+
+    #[test]
+    fn display_byte_string_truncated() {
+        let value = DisplayByteStringTruncated::with_max_len(b"abcdef", 4).to_string();
+        assert!(value == "abcd…(2 more)", "{value}");
+
+        let value = DisplayByteStringTruncated::with_max_len(b"abcd", 4).to_string();
+        assert!(value == "abcd", "{value}");
+
+        // UTF-8 is cut at a char boundary.
+        let value = DisplayByteStringTruncated::with_max_len("aé".as_bytes(), 2).to_string();
+        assert!(value == "a…(2 more)", "{value}");
+
+        let value = DisplayByteStringTruncated::with_max_len(&[0xFF, 0, 1], 2).to_string();
+        assert!(value == "[255, 0]…(1 more)", "{value}");
+
+        let name = vec![b'a'; 1000];
+        let error = FromValueError::UnexpectedSymbol { name }.to_string();
+        assert!(error.ends_with("…(744 more)\""), "{error}");
+    }
+
+    #[test]
+    fn ruby_time() {
+        let mut arena = ValueArena::new();
+
+        let epoch = RubyTime::new_utc(0, 0)
+            .into_value(&mut arena)
+            .expect("failed to exec RubyTime::into_value");
+        let epoch_value: &UserDefinedValue = FromValueContext::new(&arena)
+            .from_value(epoch)
+            .expect("failed to exec &UserDefinedValue::from_value");
+        let p: u32 = (1 << 31) | (1 << 30) | (70 << 14) | (1 << 5);
+        assert!(epoch_value.value()[..4] == p.to_le_bytes());
+        assert!(epoch_value.value()[4..] == [0; 4]);
+
+        let time = RubyTime::new_local(1_700_000_000, 123_456, 3600);
+        let handle = time
+            .clone()
+            .into_value(&mut arena)
+            .expect("failed to exec RubyTime::into_value");
+        let decoded: RubyTime = FromValueContext::new(&arena)
+            .from_value(handle)
+            .expect("failed to exec RubyTime::from_value");
+        assert!(decoded == time);
+    }
+
+    #[test]
+    fn user_defined_registry() {
+        let mut arena = ValueArena::new();
+
+        let name = arena.create_symbol("Point".into());
+        let point = arena.create_user_defined(name, vec![1, 2]).into_raw();
+        let unknown_name = arena.create_symbol("Unknown".into());
+        let unknown = arena
+            .create_user_defined(unknown_name, Vec::new())
+            .into_raw();
+
+        let mut registry = UserDefinedRegistry::new();
+        registry.register(b"Point".into(), |value, _ctx| Ok((value[0], value[1])));
+
+        let ctx = FromValueContext::with_registry(&arena, &registry);
+
+        let point: &UserDefinedValue = ctx.from_value(point).expect("not a user defined value");
+        let point: (u8, u8) = ctx
+            .decode_user_defined(point)
+            .expect("failed to decode point");
+        assert!(point == (1, 2));
+
+        let unknown: &UserDefinedValue = ctx.from_value(unknown).expect("not a user defined value");
+        assert!(matches!(
+            ctx.decode_user_defined::<(u8, u8)>(unknown),
+            Err(FromValueError::UnexpectedUserDefinedName { .. })
+        ));
+    }
+
+    #[test]
+    fn ruby_big_decimal() {
+        let mut arena = ValueArena::new();
+
+        let name = arena.create_symbol("BigDecimal".into());
+        let payloads: [&[u8]; 5] = [
+            b"18:0.12345e2",
+            b"9:-0.1e-3",
+            b"9:0.0",
+            b"9:-Infinity",
+            b"9:NaN",
+        ];
+        for payload in payloads {
+            let handle = arena.create_user_defined(name, payload.into()).into_raw();
+
+            let ctx = FromValueContext::new(&arena);
+            let value: RubyBigDecimal = ctx
+                .from_value(handle)
+                .expect("failed to exec RubyBigDecimal::from_value");
+            let (_, number) =
+                payload.split_at(payload.iter().position(|b| *b == b':').unwrap() + 1);
+            assert!(value.to_string().as_bytes() == number);
+
+            let handle = value
+                .into_value(&mut arena)
+                .expect("failed to exec RubyBigDecimal::into_value");
+            let value: &UserDefinedValue = FromValueContext::new(&arena)
+                .from_value(handle)
+                .expect("failed to exec &UserDefinedValue::from_value");
+            assert!(value.value() == payload);
+        }
+
+        let value = RubyBigDecimal::new_finite(false, "12345", 2).expect("invalid digits");
+        assert!(value.kind() == RubyBigDecimalKind::Finite);
+        assert!(value.digits() == "12345");
+        assert!(value.exponent() == 2);
+
+        for payload in [&b"0.1e1"[..], b"x:0.1e1", b"9:1.5", b"9:0.1"] {
+            let handle = arena.create_user_defined(name, payload.into()).into_raw();
+            let ctx = FromValueContext::new(&arena);
+            assert!(ctx.from_value::<RubyBigDecimal>(handle).is_err());
+        }
+    }
+
+    #[test]
+    fn index_map() {
+        // The default hasher needs std.
+        type IndexMap<K, V> = indexmap::IndexMap<K, V, hashbrown::DefaultHashBuilder>;
+
+        let mut arena = ValueArena::new();
+
+        let mut map = IndexMap::<i32, i32>::default();
+        map.insert(3, 30);
+        map.insert(1, 10);
+        map.insert(2, 20);
+        let hash = map
+            .clone()
+            .into_value(&mut arena)
+            .expect("failed to exec IndexMap::into_value");
+
+        let pairs = [[3, 30], [1, 10], [2, 20]]
+            .into_iter()
+            .map(Vec::from)
+            .collect::<Vec<_>>()
+            .into_value(&mut arena)
+            .expect("failed to exec Vec::into_value");
+        let duplicate = vec![vec![1, 10], vec![1, 20]]
+            .into_value(&mut arena)
+            .expect("failed to exec Vec::into_value");
+        let triple = vec![vec![1, 10, 100]]
+            .into_value(&mut arena)
+            .expect("failed to exec Vec::into_value");
+
+        let ctx = FromValueContext::new(&arena);
+        let hash_value: &HashValue = ctx.from_value(hash).expect("not a hash");
+        let keys = hash_value
+            .value()
+            .iter()
+            .map(|(key, _)| ctx.from_value::<i32>(*key).expect("not a fixnum"))
+            .collect::<Vec<_>>();
+        assert!(keys == [3, 1, 2]);
+
+        for handle in [hash, pairs] {
+            let decoded: IndexMap<i32, i32> = ctx
+                .from_value(handle)
+                .expect("failed to exec IndexMap::from_value");
+            assert!(decoded.iter().eq(map.iter()));
+        }
+        assert!(matches!(
+            ctx.from_value::<IndexMap<i32, i32>>(duplicate),
+            Err(FromValueError::DuplicateHashKey { .. })
+        ));
+        assert!(matches!(
+            ctx.from_value::<IndexMap<i32, i32>>(triple),
+            Err(FromValueError::Other { .. })
+        ));
+    }
+
+    #[test]
+    fn ruby_key_equality() {
+        /// A key that is different for every value in the arena, even ones that Ruby considers equal.
+        #[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        struct Identity(ValueHandle);
+
+        impl<'a> FromValue<'a> for Identity {
+            fn from_value(
+                ctx: &FromValueContext<'a>,
+                value: &'a Value,
+            ) -> Result<Self, FromValueError> {
+                Ok(Self(ctx.current_handle(value).expect("missing handle")))
+            }
+        }
+
+        let mut arena = ValueArena::new();
+        let one = arena.create_fixnum(1).into();
+        let utf8 = arena.create_utf8_string("a").into();
+        let binary = arena.create_string("a".into()).into();
+        let equal_strings = arena.create_hash(vec![(utf8, one), (binary, one)], None);
+        let first_array = arena.create_array(vec![one, utf8]).into();
+        let second_array = arena.create_array(vec![one, binary]).into();
+        let equal_arrays = arena.create_hash(vec![(first_array, one), (second_array, one)], None);
+        let two = arena.create_fixnum(2).into();
+        let first_hash = arena
+            .create_hash(vec![(one, one), (two, utf8)], None)
+            .into();
+        let second_hash = arena
+            .create_hash(vec![(two, binary), (one, one)], None)
+            .into();
+        let equal_hashes = arena.create_hash(vec![(first_hash, one), (second_hash, one)], None);
+
+        // Ruby's eql? does not consider these equal.
+        let symbol = arena.create_symbol("a".into()).into();
+        let float = arena.create_float(1.0).into();
+        let non_ascii_utf8 = arena.create_utf8_string("\u{E9}").into();
+        let non_ascii_binary = arena.create_string("\u{E9}".into()).into();
+        let different = arena.create_hash(
+            vec![
+                (utf8, one),
+                (symbol, one),
+                (one, one),
+                (float, one),
+                (non_ascii_utf8, one),
+                (non_ascii_binary, one),
+            ],
+            None,
+        );
+
+        let ctx = FromValueContext::new(&arena);
+        for handle in [equal_strings, equal_arrays, equal_hashes] {
+            let map: BTreeMap<Identity, i32> = ctx
+                .from_value(handle.into())
+                .expect("failed to exec BTreeMap::from_value");
+            assert!(map.len() == 2);
+        }
+
+        let mut ctx = FromValueContext::new(&arena);
+        ctx.set_ruby_key_equality(true);
+        for (handle, duplicate) in [
+            (equal_strings, binary),
+            (equal_arrays, second_array),
+            (equal_hashes, second_hash),
+        ] {
+            let error = ctx
+                .from_value::<BTreeMap<Identity, i32>>(handle.into())
+                .expect_err("keys should be duplicates");
+            assert!(
+                matches!(error, FromValueError::DuplicateHashKey { key } if key == duplicate),
+                "{error:?}"
+            );
+            #[cfg(feature = "std")]
+            {
+                let error = ctx
+                    .from_value::<HashMap<Identity, i32>>(handle.into())
+                    .expect_err("keys should be duplicates");
+                assert!(
+                    matches!(error, FromValueError::DuplicateHashKey { .. }),
+                    "{error:?}"
+                );
+            }
+            let error = ctx
+                .from_value::<IndexMap<Identity, i32, hashbrown::DefaultHashBuilder>>(handle.into())
+                .expect_err("keys should be duplicates");
+            assert!(
+                matches!(error, FromValueError::DuplicateHashKey { .. }),
+                "{error:?}"
+            );
+        }
+
+        let map: BTreeMap<Identity, i32> = ctx
+            .from_value(different.into())
+            .expect("failed to exec BTreeMap::from_value");
+        assert!(map.len() == 6);
+    }
+
+    #[test]
+    fn duration() {
+        let mut arena = ValueArena::new();
+
+        let seconds = Seconds(Duration::from_secs(90))
+            .into_value(&mut arena)
+            .expect("failed to exec Seconds::into_value");
+        let millis = Millis(Duration::from_millis(1_500))
+            .into_value(&mut arena)
+            .expect("failed to exec Millis::into_value");
+        let negative = arena.create_fixnum(-1).into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+        assert!(ctx.from_value::<i32>(seconds).expect("not a fixnum") == 90);
+        assert!(ctx.from_value::<i32>(millis).expect("not a fixnum") == 1_500);
+        let Seconds(decoded) = ctx
+            .from_value(seconds)
+            .expect("failed to exec Seconds::from_value");
+        assert!(decoded == Duration::from_secs(90));
+        let Millis(decoded) = ctx
+            .from_value(millis)
+            .expect("failed to exec Millis::from_value");
+        assert!(decoded == Duration::from_millis(1_500));
+        assert!(ctx.from_value::<Seconds>(negative).is_err());
+        assert!(ctx.from_value::<Millis>(negative).is_err());
+
+        assert!(Seconds(Duration::from_millis(1_500))
+            .into_value(&mut arena)
+            .is_err());
+        assert!(Millis(Duration::from_micros(1_500))
+            .into_value(&mut arena)
+            .is_err());
+        assert!(Seconds(Duration::from_secs(1 << 31))
+            .into_value(&mut arena)
+            .is_err());
+        assert!(Millis(Duration::MAX).into_value(&mut arena).is_err());
+    }
+
+    #[test]
+    fn ruby_rational_complex() {
+        let mut arena = ValueArena::new();
+
+        let rational = RubyRational::new(-3, 4);
+        let rational_handle = rational
+            .into_value(&mut arena)
+            .expect("failed to exec RubyRational::into_value");
+        let complex = RubyComplex::new(1, -2);
+        let complex_handle = complex
+            .into_value(&mut arena)
+            .expect("failed to exec RubyComplex::into_value");
+
+        let ctx = FromValueContext::new(&arena);
+        let decoded: RubyRational = ctx
+            .from_value(rational_handle)
+            .expect("failed to exec RubyRational::from_value");
+        assert!(decoded == rational);
+        assert!(decoded.numerator() == -3 && decoded.denominator() == 4);
+        let decoded: RubyComplex = ctx
+            .from_value(complex_handle)
+            .expect("failed to exec RubyComplex::from_value");
+        assert!(decoded == complex);
+        assert!(decoded.real() == 1 && decoded.imaginary() == -2);
+        assert!(matches!(
+            ctx.from_value::<RubyRational>(complex_handle),
+            Err(FromValueError::UnexpectedObjectName { .. })
+        ));
+
+        let name = arena.create_symbol("Rational".into());
+        let numerator_name = arena.create_symbol("@numerator".into());
+        let numerator = arena.create_fixnum(1).into();
+        let missing = arena
+            .create_object(name, vec![(numerator_name, numerator)])
+            .into_raw();
+        let ctx = FromValueContext::new(&arena);
+        assert!(matches!(
+            ctx.from_value::<RubyRational>(missing),
+            Err(FromValueError::MissingInstanceVariable { name }) if name == b"@denominator"
+        ));
+    }
+
+    #[test]
+    fn ruby_set() {
+        let mut arena = ValueArena::new();
+
+        let set = RubySet(BTreeSet::from([3, 1, 2]));
+        let set_handle = set
+            .clone()
+            .into_value(&mut arena)
+            .expect("failed to exec RubySet::into_value");
+        #[cfg(feature = "std")]
+        let hash_set = RubySet(std::collections::HashSet::from([5]));
+        #[cfg(feature = "std")]
+        let hash_set_handle = hash_set
+            .clone()
+            .into_value(&mut arena)
+            .expect("failed to exec RubySet::into_value");
+
+        // Older versions of Ruby store the hash in an object.
+        let name = arena.create_symbol("Set".into());
+        let hash_name = arena.create_symbol("@hash".into());
+        let element = arena.create_fixnum(1).into();
+        let present = arena.create_bool(true).into();
+        let hash = arena.create_hash(vec![(element, present)], None).into();
+        let object_handle = arena
+            .create_object(name, vec![(hash_name, hash)])
+            .into_raw();
+
+        let name = arena.create_symbol("NotSet".into());
+        let wrong_name_handle = arena.create_user_marshal(name, hash).into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+        let value: &UserMarshalValue = ctx
+            .from_value(set_handle)
+            .expect("failed to exec from_value");
+        let value: BTreeMap<i32, bool> = ctx
+            .from_value(value.value())
+            .expect("failed to exec from_value");
+        assert!(value == BTreeMap::from([(1, true), (2, true), (3, true)]));
+
+        let decoded: RubySet<BTreeSet<i32>> = ctx
+            .from_value(set_handle)
+            .expect("failed to exec RubySet::from_value");
+        assert!(decoded == set);
+        #[cfg(feature = "std")]
+        {
+            let decoded: RubySet<std::collections::HashSet<i32>> = ctx
+                .from_value(hash_set_handle)
+                .expect("failed to exec RubySet::from_value");
+            assert!(decoded == hash_set);
+        }
+        let decoded: RubySet<BTreeSet<i32>> = ctx
+            .from_value(object_handle)
+            .expect("failed to exec RubySet::from_value");
+        assert!(decoded.0 == BTreeSet::from([1]));
+        assert!(matches!(
+            ctx.from_value::<RubySet<BTreeSet<i32>>>(wrong_name_handle),
+            Err(FromValueError::UnexpectedUserMarshalName { name }) if name == b"NotSet"
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn ruby_object_map() {
+        let mut arena = ValueArena::new();
+
+        let name = arena.create_symbol("Unknown".into());
+        let foo_name = arena.create_symbol("@foo".into());
+        let foo = arena.create_fixnum(1).into();
+        let bar_name = arena.create_symbol("@bar".into());
+        let bar = arena.create_string("bar".into()).into();
+        let object = arena
+            .create_object(name, vec![(foo_name, foo), (bar_name, bar)])
+            .into_raw();
+        let duplicate = arena
+            .create_object(name, vec![(foo_name, foo), (foo_name, bar)])
+            .into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+        let map: RubyObjectMap = ctx
+            .from_value(object)
+            .expect("failed to exec RubyObjectMap::from_value");
+        assert!(map.name() == b"Unknown");
+        assert!(map.instance_variables().len() == 2);
+        assert!(map.get(b"@foo") == Some(foo));
+        assert!(map.get(b"@bar") == Some(bar));
+        assert!(map.get(b"@baz").is_none());
+        assert!(ctx.from_value::<i32>(map.get(b"@foo").unwrap()).unwrap() == 1);
+
+        assert!(matches!(
+            ctx.from_value::<RubyObjectMap>(duplicate),
+            Err(FromValueError::DuplicateInstanceVariable { name }) if name == b"@foo"
+        ));
+        assert!(matches!(
+            ctx.from_value::<RubyObjectMap>(foo),
+            Err(FromValueError::UnexpectedValueKind { .. })
+        ));
+    }
+
+    #[test]
+    fn btree_map_deterministic() {
+        let mut data = Vec::new();
+        for keys in [[1, 2, 3], [3, 1, 2]] {
+            let mut map = BTreeMap::new();
+            for key in keys {
+                map.insert(key, vec![key; 2]);
+            }
+
+            let mut arena = ValueArena::new();
+            let handle = map
+                .into_value(&mut arena)
+                .expect("failed to exec BTreeMap::into_value");
+            arena.replace_root(handle);
+
+            let mut buffer = Vec::new();
+            crate::dump(&mut buffer, &arena).expect("failed to dump");
+            data.push(buffer);
+        }
+
+        assert!(data[0] == data[1]);
+    }
+
+    #[test]
+    fn arena_from_value() {
+        let arena = ValueArena::from_value(vec![1, 2]).expect("failed to create arena");
+
+        let ctx = FromValueContext::new(&arena);
+        let value: Vec<i32> = ctx
+            .from_value(arena.root())
+            .expect("failed to exec Vec::<i32>::from_value");
+        assert!(value == [1, 2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn path() {
+        use std::path::Path;
+        use std::path::PathBuf;
+
+        let mut arena = ValueArena::new();
+        let handle = Path::new("data/save.rxdata")
+            .into_value(&mut arena)
+            .expect("failed to exec Path::into_value");
+        let symbol = arena.create_symbol("data".into()).into_raw();
+
+        let string = match arena.get(handle).expect("missing value") {
+            Value::String(string) => string,
+            value => panic!("unexpected value {value:?}"),
+        };
+        assert!(string.as_str() == Ok("data/save.rxdata"));
+        assert!(string.instance_variables().is_some());
+
+        let ctx = FromValueContext::new(&arena);
+        let value: PathBuf = ctx
+            .from_value(handle)
+            .expect("failed to exec PathBuf::from_value");
+        assert!(value == Path::new("data/save.rxdata"));
+
+        let error = ctx
+            .from_value::<PathBuf>(symbol)
+            .expect_err("symbol should not be a path");
+        assert!(
+            matches!(error, FromValueError::UnexpectedValueKind { .. }),
+            "{error:?}"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let mut arena = ValueArena::new();
+            let path = OsStr::from_bytes(b"\xFF.rxdata");
+            let handle = path
+                .into_value(&mut arena)
+                .expect("failed to exec OsStr::into_value");
+            let string = match arena.get(handle).expect("missing value") {
+                Value::String(string) => string,
+                value => panic!("unexpected value {value:?}"),
+            };
+            assert!(string.value() == b"\xFF.rxdata");
+            assert!(string.instance_variables().is_none());
+
+            let ctx = FromValueContext::new(&arena);
+            let value: std::ffi::OsString = ctx
+                .from_value(handle)
+                .expect("failed to exec OsString::from_value");
+            assert!(value == path);
+        }
+    }
+
+    #[test]
+    fn binary() {
+        let mut arena = ValueArena::new();
+        let owned = Binary(b"\xFF".to_vec())
+            .into_value(&mut arena)
+            .expect("failed to exec Binary::into_value");
+        let borrowed = BinaryRef(b"\x00")
+            .into_value(&mut arena)
+            .expect("failed to exec BinaryRef::into_value");
+        let utf8 = arena.create_utf8_string("text").into_raw();
+        let symbol = arena.create_symbol("symbol".into()).into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+        let value: Binary = ctx
+            .from_value(owned)
+            .expect("failed to exec Binary::from_value");
+        assert!(value.0 == b"\xFF");
+
+        let value: BinaryRef = ctx
+            .from_value(borrowed)
+            .expect("failed to exec BinaryRef::from_value");
+        assert!(value.0 == b"\x00");
+
+        let value: BinaryRef = ctx
+            .from_value(utf8)
+            .expect("failed to exec BinaryRef::from_value");
+        assert!(value.0 == b"text");
+
+        let error = ctx
+            .from_value::<Binary>(symbol)
+            .expect_err("symbol should not be binary");
+        assert!(
+            matches!(error, FromValueError::UnexpectedValueKind { .. }),
+            "{error:?}"
+        );
+
+        for handle in [owned, borrowed] {
+            let string = match arena.get(handle).expect("missing value") {
+                Value::String(string) => string,
+                value => panic!("unexpected value {value:?}"),
+            };
+            assert!(string.instance_variables().is_none());
+        }
+    }
+
+    #[test]
+    fn smart_pointers() {
+        let mut arena = ValueArena::new();
+
+        let leaf = arena.create_fixnum(1).into();
+        let leaf = arena.create_array(vec![leaf]).into();
+        let nil = arena.create_nil().into();
+        let array = arena.create_array(vec![leaf, leaf, nil]).into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+
+        let value: Vec<Option<Box<Vec<i32>>>> = ctx
+            .from_value(array)
+            .expect("failed to exec Vec::<Option<Box<Vec<i32>>>>::from_value");
+        assert!(value == [Some(Box::new(vec![1])), Some(Box::new(vec![1])), None]);
+
+        let value: Vec<Option<alloc::rc::Rc<Vec<i32>>>> = ctx
+            .from_value(array)
+            .expect("failed to exec Vec::<Option<Rc<Vec<i32>>>>::from_value");
+        let (first, second) = (value[0].as_ref().unwrap(), value[1].as_ref().unwrap());
+        assert!(**first == [1]);
+        assert!(!alloc::rc::Rc::ptr_eq(first, second));
+
+        let value: Vec<Option<alloc::sync::Arc<Vec<i32>>>> = ctx
+            .from_value(array)
+            .expect("failed to exec Vec::<Option<Arc<Vec<i32>>>>::from_value");
+        let (first, second) = (value[0].as_ref().unwrap(), value[1].as_ref().unwrap());
+        assert!(**first == [1]);
+        assert!(!alloc::sync::Arc::ptr_eq(first, second));
+    }
+
+    #[test]
+    fn boxed_str() {
+        let mut arena = ValueArena::new();
+
+        let string = arena.create_utf8_string("string").into_raw();
+        let invalid = arena.create_string(vec![0xFF]).into_raw();
+        let array = arena.create_array(vec![string]).into_raw();
+
+        let ctx = FromValueContext::new(&arena);
+
+        let value: Box<str> = ctx
+            .from_value(string)
+            .expect("failed to exec Box::<str>::from_value");
+        assert!(&*value == "string");
+
+        let value: alloc::rc::Rc<str> = ctx
+            .from_value(string)
+            .expect("failed to exec Rc::<str>::from_value");
+        assert!(&*value == "string");
+
+        let value: alloc::sync::Arc<str> = ctx
+            .from_value(string)
+            .expect("failed to exec Arc::<str>::from_value");
+        assert!(&*value == "string");
+
+        let error = ctx
+            .from_value::<Box<str>>(invalid)
+            .expect_err("invalid UTF-8 should fail");
+        assert!(matches!(error, FromValueError::Other { .. }), "{error:?}");
+
+        let error = ctx
+            .from_value::<alloc::sync::Arc<str>>(array)
+            .expect_err("an array should fail");
+        assert!(
+            matches!(error, FromValueError::UnexpectedValueKind { .. }),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn cached() {
+        use alloc::rc::Rc;
+        use core::cell::Cell;
+
+        const DEPTH: usize = 20;
+
+        thread_local! {
+            static NUM_DECODES: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct Tree {
+            children: Vec<Cached<Rc<Tree>>>,
+        }
+
+        impl<'a> FromValue<'a> for Tree {
+            fn from_value(
+                ctx: &FromValueContext<'a>,
+                value: &'a Value,
+            ) -> Result<Self, FromValueError> {
+                NUM_DECODES.with(|num_decodes| num_decodes.set(num_decodes.get() + 1));
+
+                Ok(Self {
+                    children: FromValue::from_value(ctx, value)?,
+                })
+            }
+        }
+
+        // Each level references the level below it twice,
+        // so there are 2^DEPTH paths to the bottom.
+        let mut arena = ValueArena::new();
+        let mut handle = arena.create_array(Vec::new()).into();
+        for _ in 0..DEPTH {
+            handle = arena.create_array(vec![handle, handle]).into();
+        }
+        arena.replace_root(handle);
+
+        let ctx = FromValueContext::new(&arena);
+        let tree: Tree = ctx
+            .from_value(arena.root())
+            .expect("failed to exec Tree::from_value");
+        assert!(NUM_DECODES.with(Cell::get) == DEPTH + 1);
+        assert!(Rc::ptr_eq(&tree.children[0].0, &tree.children[1].0));
+
+        let ctx = FromValueContext::new(&arena);
+        let first: Rc<Tree> = ctx
+            .from_value_cached(arena.root())
+            .expect("failed to exec Rc::<Tree>::from_value");
+        let second: Rc<Tree> = ctx
+            .from_value_cached(arena.root())
+            .expect("failed to exec Rc::<Tree>::from_value");
+        assert!(Rc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn into_value_slice() {
+        let items = [1, 2, 3];
+
+        let mut arena = ValueArena::new();
+        let slice_handle = items
+            .as_slice()
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+        let iter_handle = into_value_iter(items.iter().map(|item| item * 2), &mut arena)
+            .expect("failed to exec into_value_iter");
+
+        let ctx = FromValueContext::new(&arena);
+        let slice_value: Vec<i32> = ctx
+            .from_value(slice_handle)
+            .expect("failed to exec from_value");
+        assert!(slice_value == items);
+        let iter_value: Vec<i32> = ctx
+            .from_value(iter_handle)
+            .expect("failed to exec from_value");
+        assert!(iter_value == [2, 4, 6]);
+    }
+
+    #[test]
+    fn nil_or_empty() {
+        let mut arena = ValueArena::new();
+        let nil_handle = arena.create_nil().into_raw();
+        let empty_handle = Vec::<i32>::new()
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+        let single_handle = vec![1]
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+
+        // Option only treats nil as absent.
+        let ctx = FromValueContext::new(&arena);
+        let value: Option<Vec<i32>> = ctx
+            .from_value(nil_handle)
+            .expect("failed to exec from_value");
+        assert!(value.is_none());
+        let value: Option<Vec<i32>> = ctx
+            .from_value(empty_handle)
+            .expect("failed to exec from_value");
+        assert!(value.is_some_and(|value| value.is_empty()));
+        let value: Option<Vec<i32>> = ctx
+            .from_value(single_handle)
+            .expect("failed to exec from_value");
+        assert!(value == Some(vec![1]));
+
+        let value: NilOrEmpty<i32> = ctx
+            .from_value(nil_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0.is_none());
+        let value: NilOrEmpty<i32> = ctx
+            .from_value(empty_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0.is_none());
+        let value: NilOrEmpty<i32> = ctx
+            .from_value(single_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0 == Some(vec![1]));
+
+        let none_handle = NilOrEmpty::<i32>(None)
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+        let ctx = FromValueContext::new(&arena);
+        let value: Vec<i32> = ctx
+            .from_value(none_handle)
+            .expect("failed to exec from_value");
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn one_or_many() {
+        let mut arena = ValueArena::new();
+        let nil_handle = arena.create_nil().into_raw();
+        let single_handle = arena.create_fixnum(1).into_raw();
+        let array_handle = vec![1, 2]
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+
+        let ctx = FromValueContext::new(&arena);
+        let value: OneOrMany<i32> = ctx
+            .from_value(nil_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0.is_empty());
+        let value: OneOrMany<i32> = ctx
+            .from_value(single_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0 == [1]);
+        let value: OneOrMany<i32> = ctx
+            .from_value(array_handle)
+            .expect("failed to exec from_value");
+        assert!(value.0 == [1, 2]);
+
+        let single_handle = OneOrMany(vec![3])
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+        let array_handle = OneOrMany(vec![3, 4])
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+        let empty_handle = OneOrMany::<i32>(Vec::new())
+            .into_value(&mut arena)
+            .expect("failed to exec into_value");
+
+        let ctx = FromValueContext::new(&arena);
+        let value: i32 = ctx
+            .from_value(single_handle)
+            .expect("failed to exec from_value");
+        assert!(value == 3);
+        let value: Vec<i32> = ctx
+            .from_value(array_handle)
+            .expect("failed to exec from_value");
+        assert!(value == [3, 4]);
+        let value: Vec<i32> = ctx
+            .from_value(empty_handle)
+            .expect("failed to exec from_value");
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn context_arena() {
+        /// The name of an object, resolved through the arena.
+        struct ObjectName<'a> {
+            arena: &'a ValueArena,
+            name: TypedValueHandle<SymbolValue>,
+        }
+
+        impl<'a> FromValue<'a> for ObjectName<'a> {
+            fn from_value(
+                ctx: &FromValueContext<'a>,
+                value: &'a Value,
+            ) -> Result<Self, FromValueError> {
+                let value: &ObjectValue = FromValue::from_value(ctx, value)?;
+
+                Ok(Self {
+                    arena: ctx.arena(),
+                    name: value.name(),
+                })
+            }
+        }
+
+        let mut arena = ValueArena::new();
+        let name = arena.create_symbol("Foo".into());
+        let object = arena.create_object(name, Vec::new());
+        arena.replace_root(object);
+
+        let ctx = FromValueContext::new(&arena);
+        assert!(core::ptr::eq(ctx.arena(), &arena));
+        let object_name: ObjectName = ctx
+            .from_value(arena.root())
+            .expect("failed to exec from_value");
+        drop(ctx);
+
+        let name = object_name
+            .arena
+            .get_symbol(object_name.name)
+            .expect("missing symbol");
+        assert!(name.value() == b"Foo");
+    }
 }
@@ -0,0 +1,60 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::StringValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec::Vec;
+
+/// A wrapper for the raw bytes of a Ruby String.
+///
+/// When extracting, the bytes of any String are taken as-is, without checking the encoding.
+/// Symbols and other values are rejected.
+///
+/// When converting into a value, a String without an encoding is written.
+/// Ruby reads this as an ASCII-8BIT String.
+///
+/// Use this for data that is not text, like compressed or serialized blobs.
+/// For text, extract a `&str`, which must be valid UTF-8,
+/// or create the String with [`ValueArena::create_utf8_string`] so that Ruby sees the encoding.
+///
+/// `Vec<u8>` has no conversions of its own, as it would be an array of integers like any other `Vec<T>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Binary(pub Vec<u8>);
+
+impl<'a> FromValue<'a> for Binary {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: BinaryRef<'a> = FromValue::from_value(ctx, value)?;
+
+        Ok(Self(value.0.into()))
+    }
+}
+
+impl IntoValue for Binary {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        Ok(arena.create_string(self.0).into())
+    }
+}
+
+/// A borrowed version of [`Binary`].
+///
+/// When converting into a value, the bytes are copied into the arena.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BinaryRef<'a>(pub &'a [u8]);
+
+impl<'a> FromValue<'a> for BinaryRef<'a> {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &StringValue = FromValue::from_value(ctx, value)?;
+
+        Ok(Self(value.value()))
+    }
+}
+
+impl IntoValue for BinaryRef<'_> {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        Ok(arena.create_string(self.0.into()).into())
+    }
+}
@@ -0,0 +1,130 @@
+use crate::FixnumValue;
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use core::time::Duration;
+
+/// An error that may occur while extracting a duration from a value.
+#[derive(Debug)]
+pub enum DurationFromValueError {
+    /// The Fixnum was negative.
+    Negative {
+        /// The Fixnum
+        value: i32,
+    },
+}
+
+impl core::fmt::Display for DurationFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Negative { value } => write!(f, "duration {value} is negative"),
+        }
+    }
+}
+
+impl core::error::Error for DurationFromValueError {}
+
+/// An error that may occur while turning a duration into a value.
+#[derive(Debug)]
+pub enum DurationIntoValueError {
+    /// The duration has a fractional part that cannot be represented in the unit.
+    Fractional {
+        /// The duration
+        duration: Duration,
+    },
+
+    /// The duration is too large to be a Fixnum.
+    TooLarge {
+        /// The duration
+        duration: Duration,
+    },
+}
+
+impl core::fmt::Display for DurationIntoValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Fractional { duration } => {
+                write!(f, "duration {duration:?} is not a whole number of units")
+            }
+            Self::TooLarge { duration } => {
+                write!(f, "duration {duration:?} is too large to be a Fixnum")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DurationIntoValueError {}
+
+/// A [`Duration`], stored as a Fixnum of whole seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Seconds(pub Duration);
+
+impl<'a> FromValue<'a> for Seconds {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value = fixnum_to_u64(ctx, value)?;
+        Ok(Self(Duration::from_secs(value)))
+    }
+}
+
+impl IntoValue for Seconds {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        if self.0.subsec_nanos() != 0 {
+            return Err(IntoValueError::new_other(
+                DurationIntoValueError::Fractional { duration: self.0 },
+            ));
+        }
+
+        u64_to_fixnum(arena, self.0.as_secs(), self.0)
+    }
+}
+
+/// A [`Duration`], stored as a Fixnum of whole milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Millis(pub Duration);
+
+impl<'a> FromValue<'a> for Millis {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value = fixnum_to_u64(ctx, value)?;
+        Ok(Self(Duration::from_millis(value)))
+    }
+}
+
+impl IntoValue for Millis {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        if !self.0.subsec_nanos().is_multiple_of(1_000_000) {
+            return Err(IntoValueError::new_other(
+                DurationIntoValueError::Fractional { duration: self.0 },
+            ));
+        }
+
+        // A u128 of millis is only larger than a u64 if the duration is far too large to be a Fixnum.
+        let millis = u64::try_from(self.0.as_millis()).unwrap_or(u64::MAX);
+        u64_to_fixnum(arena, millis, self.0)
+    }
+}
+
+/// Extract a non-negative Fixnum.
+fn fixnum_to_u64<'a>(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<u64, FromValueError> {
+    let value: &FixnumValue = FromValue::from_value(ctx, value)?;
+    let value = value.value();
+
+    u64::try_from(value)
+        .map_err(|_| FromValueError::new_other(DurationFromValueError::Negative { value }))
+}
+
+/// Create a Fixnum from a count of units of the given duration.
+fn u64_to_fixnum(
+    arena: &mut ValueArena,
+    value: u64,
+    duration: Duration,
+) -> Result<ValueHandle, IntoValueError> {
+    let value = i32::try_from(value)
+        .map_err(|_| IntoValueError::new_other(DurationIntoValueError::TooLarge { duration }))?;
+
+    Ok(arena.create_fixnum(value).into())
+}
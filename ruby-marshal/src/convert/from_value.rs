@@ -1,21 +1,44 @@
-use super::DisplayByteString;
+use super::ruby_key::RubyKeySet;
+use super::DisplayByteStringTruncated;
+use super::UserDefinedRegistry;
+use super::UserDefinedRegistryError;
 use crate::ArrayValue;
+use crate::BignumValue;
 use crate::BoolValue;
+use crate::ClassValue;
 use crate::FixnumValue;
+use crate::FloatValue;
 use crate::HashValue;
+use crate::ModuleValue;
 use crate::NilValue;
 use crate::ObjectValue;
+use crate::OldModuleValue;
+use crate::RegexpValue;
 use crate::StringValue;
+use crate::StructValue;
 use crate::SymbolValue;
+use crate::TypedValueHandle;
 use crate::UserDefinedValue;
+use crate::UserMarshalValue;
 use crate::Value;
 use crate::ValueArena;
 use crate::ValueHandle;
 use crate::ValueKind;
-use std::cell::RefCell;
-use std::collections::BTreeMap;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::any::TypeId;
+use core::cell::RefCell;
+use core::hash::BuildHasher;
+use core::hash::Hash;
+use indexmap::IndexMap;
+#[cfg(feature = "std")]
 use std::collections::HashMap;
-use std::hash::Hash;
 
 /// An error that may occur while creating a type from a Ruby Value.
 #[derive(Debug)]
@@ -57,6 +80,22 @@ pub enum FromValueError {
         name: Vec<u8>,
     },
 
+    /// A user marshal value name was unexpected.
+    UnexpectedUserMarshalName {
+        /// The user marshal name.
+        ///
+        /// This may or may not be UTF-8.
+        name: Vec<u8>,
+    },
+
+    /// A symbol was unexpected.
+    UnexpectedSymbol {
+        /// The symbol name.
+        ///
+        /// This may or may not be UTF-8.
+        name: Vec<u8>,
+    },
+
     /// An instance variable was duplicated
     DuplicateInstanceVariable {
         /// The instance variable name.
@@ -81,6 +120,15 @@ pub enum FromValueError {
         name: Vec<u8>,
     },
 
+    /// An array had an unexpected length.
+    UnexpectedArrayLength {
+        /// The expected length
+        expected: usize,
+
+        /// The actual length
+        actual: usize,
+    },
+
     /// A hash key was provided twice.
     DuplicateHashKey {
         /// The key that was provided twice.
@@ -89,17 +137,34 @@ pub enum FromValueError {
         key: ValueHandle,
     },
 
+    /// An error occured while extracting a nested value.
+    At {
+        /// The path from the outermost value to the nested value, like `[0].@name[:key]`.
+        path: String,
+
+        /// The error
+        error: Box<FromValueError>,
+    },
+
     /// Another user-provided kind of error occured.
     Other {
-        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+        error: Box<dyn core::error::Error + Send + Sync + 'static>,
     },
 }
 
 impl FromValueError {
+    /// Get the error without the path to where it occured.
+    pub fn without_path(&self) -> &Self {
+        match self {
+            Self::At { error, .. } => error,
+            _ => self,
+        }
+    }
+
     /// Shorthand for creating a new `Other` error variant.
     pub fn new_other<E>(error: E) -> Self
     where
-        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+        E: Into<Box<dyn core::error::Error + Send + Sync + 'static>>,
     {
         Self::Other {
             error: error.into(),
@@ -107,64 +172,149 @@ impl FromValueError {
     }
 }
 
-impl std::fmt::Display for FromValueError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for FromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Cycle { .. } => write!(f, "attempted to extract recursively"),
             Self::InvalidValueHandle { .. } => write!(f, "a handle was invalid"),
             Self::UnexpectedValueKind { kind, .. } => write!(f, "unexpected value kind {kind:?}"),
             Self::UnexpectedObjectName { name } => {
-                write!(f, "unexpected object name \"{}\"", DisplayByteString(name))
+                write!(
+                    f,
+                    "unexpected object name \"{}\"",
+                    DisplayByteStringTruncated::new(name)
+                )
             }
             Self::UnexpectedUserDefinedName { name } => {
                 write!(
                     f,
                     "unexpected user defined name \"{}\"",
-                    DisplayByteString(name)
+                    DisplayByteStringTruncated::new(name)
+                )
+            }
+            Self::UnexpectedUserMarshalName { name } => {
+                write!(
+                    f,
+                    "unexpected user marshal name \"{}\"",
+                    DisplayByteStringTruncated::new(name)
+                )
+            }
+            Self::UnexpectedSymbol { name } => {
+                write!(
+                    f,
+                    "unexpected symbol \"{}\"",
+                    DisplayByteStringTruncated::new(name)
                 )
             }
             Self::DuplicateInstanceVariable { name } => {
                 write!(
                     f,
                     "instance variable \"{}\" was encountered twice",
-                    DisplayByteString(name)
+                    DisplayByteStringTruncated::new(name)
                 )
             }
             Self::UnknownInstanceVariable { name } => {
                 write!(
                     f,
                     "instance variable \"{}\" is not known",
-                    DisplayByteString(name)
+                    DisplayByteStringTruncated::new(name)
                 )
             }
             Self::MissingInstanceVariable { name } => {
                 write!(
                     f,
                     "instance variable \"{}\" is missing",
-                    DisplayByteString(name)
+                    DisplayByteStringTruncated::new(name)
+                )
+            }
+            Self::UnexpectedArrayLength { expected, actual } => {
+                write!(
+                    f,
+                    "expected an array of length {expected}, but got length {actual}"
                 )
             }
             Self::DuplicateHashKey { .. } => {
                 write!(f, "duplicate hash key")
             }
+            Self::At { path, .. } => write!(f, "failed to extract the value at {path}"),
             Self::Other { .. } => write!(f, "a user-provided error was encountered"),
         }
     }
 }
 
-impl std::error::Error for FromValueError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl core::error::Error for FromValueError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
+            Self::At { error, .. } => Some(&**error),
             Self::Other { error } => Some(&**error),
             _ => None,
         }
     }
 }
 
+/// A step from a value to one of the values inside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment {
+    /// An array element at an index.
+    Index(usize),
+
+    /// A hash entry with the given key.
+    ///
+    /// This is used for both the key and the value of the entry.
+    HashKey(ValueHandle),
+
+    /// An instance variable with the given name.
+    InstanceVariable(TypedValueHandle<SymbolValue>),
+}
+
+/// A utility to display a path, rendering hash keys if they are simple.
+struct DisplayPath<'a> {
+    arena: &'a ValueArena,
+    path: &'a [PathSegment],
+}
+
+impl core::fmt::Display for DisplayPath<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, segment) in self.path.iter().enumerate() {
+            match segment {
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                PathSegment::HashKey(key) => match self.arena.get(*key) {
+                    Some(Value::Symbol(key)) => {
+                        write!(f, "[:{}]", DisplayByteStringTruncated::new(key.value()))?
+                    }
+                    Some(Value::String(key)) => {
+                        write!(f, "[\"{}\"]", DisplayByteStringTruncated::new(key.value()))?
+                    }
+                    Some(Value::Fixnum(key)) => write!(f, "[{}]", key.value())?,
+                    Some(key) => write!(f, "[#<{:?}>]", key.kind())?,
+                    None => write!(f, "[?]")?,
+                },
+                PathSegment::InstanceVariable(name) => {
+                    if i != 0 {
+                        write!(f, ".")?;
+                    }
+                    match self.arena.get(*name) {
+                        Some(Value::Symbol(name)) => {
+                            write!(f, "{}", DisplayByteStringTruncated::new(name.value()))?
+                        }
+                        _ => write!(f, "?")?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// A context to manage extracting values.
 pub struct FromValueContext<'a> {
     arena: &'a ValueArena,
+    registry: Option<&'a UserDefinedRegistry>,
+    ruby_key_equality: bool,
     stack: RefCell<Vec<ValueHandle>>,
+    path: RefCell<Vec<PathSegment>>,
+    cache: RefCell<hashbrown::HashMap<(ValueHandle, TypeId), Box<dyn Any>>>,
 }
 
 impl<'a> FromValueContext<'a> {
@@ -172,10 +322,63 @@ impl<'a> FromValueContext<'a> {
     pub fn new(arena: &'a ValueArena) -> Self {
         Self {
             arena,
+            registry: None,
+            ruby_key_equality: false,
             stack: RefCell::new(Vec::new()),
+            path: RefCell::new(Vec::new()),
+            cache: RefCell::new(hashbrown::HashMap::new()),
         }
     }
 
+    /// Create a new context from an arena and a registry of user defined value decoders.
+    pub fn with_registry(arena: &'a ValueArena, registry: &'a UserDefinedRegistry) -> Self {
+        Self {
+            registry: Some(registry),
+            ..Self::new(arena)
+        }
+    }
+
+    /// Set whether maps also check for duplicate keys the way Ruby's `eql?` compares them.
+    ///
+    /// By default, a duplicate key is only detected if the extracted keys are equal in Rust.
+    /// With this enabled, keys that Ruby considers equal fail with [`FromValueError::DuplicateHashKey`],
+    /// even if they extract to different Rust values.
+    /// This follows `eql?`, not `==`, so a symbol and a string with the same name are different keys,
+    /// as are a fixnum and a float with the same value.
+    /// Objects are compared by identity, and hashes used as keys are compared regardless of order.
+    ///
+    /// This applies to `HashMap`, `BTreeMap`, and `IndexMap`.
+    pub fn set_ruby_key_equality(&mut self, enabled: bool) {
+        self.ruby_key_equality = enabled;
+    }
+
+    /// Make a set to check map keys with, which accepts everything unless Ruby key equality is enabled.
+    fn new_ruby_key_set(&self) -> RubyKeySet<'a> {
+        RubyKeySet::new(self.arena, self.ruby_key_equality)
+    }
+
+    /// Get the arena that values are extracted from.
+    ///
+    /// Reading values from the arena directly does not track them for cycle detection,
+    /// so nested values should still be extracted with [`FromValueContext::from_value`].
+    pub fn arena(&self) -> &'a ValueArena {
+        self.arena
+    }
+
+    /// Decode a user defined value with the decoder registered for its name.
+    pub fn decode_user_defined<T>(&self, value: &UserDefinedValue) -> Result<T, FromValueError>
+    where
+        T: 'static,
+    {
+        let registry = self
+            .registry
+            .ok_or_else(|| FromValueError::new_other(UserDefinedRegistryError::MissingRegistry))?;
+
+        let name: &SymbolValue = self.from_value(value.name().into())?;
+
+        registry.decode(self, name.value(), value.value())
+    }
+
     fn begin_handle(&self, handle: ValueHandle) -> Result<(), FromValueError> {
         let mut stack = self.stack.borrow_mut();
 
@@ -215,6 +418,79 @@ impl<'a> FromValueContext<'a> {
         Ok(value)
     }
 
+    #[allow(clippy::wrong_self_convention)]
+    /// Extract a type from a value nested inside the current value.
+    ///
+    /// If this fails, the error is wrapped in a [`FromValueError::At`] with the path to the value,
+    /// unless an inner value already did so.
+    pub fn from_value_at<T>(
+        &self,
+        segment: PathSegment,
+        handle: ValueHandle,
+    ) -> Result<T, FromValueError>
+    where
+        T: FromValue<'a>,
+    {
+        self.path.borrow_mut().push(segment);
+        let result = self.from_value(handle).map_err(|error| match error {
+            FromValueError::At { .. } => error,
+            error => FromValueError::At {
+                path: DisplayPath {
+                    arena: self.arena,
+                    path: &self.path.borrow(),
+                }
+                .to_string(),
+                error: Box::new(error),
+            },
+        });
+        self.path.borrow_mut().pop();
+
+        result
+    }
+
+    /// Extract a type from a value, reusing the result if this handle was already extracted as this type.
+    ///
+    /// This is useful for values that are referenced more than once through object links,
+    /// as each one is only extracted once.
+    /// Results are cloned, so this works best with types that are cheap to clone, like [`Rc`] and [`Arc`].
+    /// Using [`Rc`] or [`Arc`] also makes values that are shared in Ruby shared in Rust.
+    pub fn from_value_cached<T>(&self, handle: ValueHandle) -> Result<T, FromValueError>
+    where
+        T: FromValue<'a> + Clone + 'static,
+    {
+        self.with_cache(handle, || self.from_value(handle))
+    }
+
+    /// Look up a cached value for a handle, or create and cache it.
+    fn with_cache<T, F>(&self, handle: ValueHandle, f: F) -> Result<T, FromValueError>
+    where
+        T: Clone + 'static,
+        F: FnOnce() -> Result<T, FromValueError>,
+    {
+        let key = (handle, TypeId::of::<T>());
+        if let Some(value) = self.cache.borrow().get(&key) {
+            // The type id is part of the key.
+            let value = value.downcast_ref::<T>().unwrap();
+            return Ok(value.clone());
+        }
+
+        let value = f()?;
+        self.cache.borrow_mut().insert(key, Box::new(value.clone()));
+
+        Ok(value)
+    }
+
+    /// Get the handle of the value currently being extracted, if it is the given value.
+    ///
+    /// This is useful in [`FromValue::from_value`] to identify the value by its handle.
+    /// Returns `None` if the value was not reached through this context.
+    pub fn current_handle(&self, value: &Value) -> Option<ValueHandle> {
+        let handle = *self.stack.borrow().last()?;
+        let current_value = self.arena.get(handle)?;
+
+        core::ptr::eq(current_value, value).then_some(handle)
+    }
+
     /// Create a new UnexpectedValueKind error
     pub fn new_unexpected_value_kind_error(&self, kind: ValueKind) -> FromValueError {
         FromValueError::UnexpectedValueKind {
@@ -246,6 +522,27 @@ impl Drop for FromValueGuard<'_, '_> {
     }
 }
 
+/// A wrapper that extracts a value at most once per handle, cloning it for later references.
+///
+/// See [`FromValueContext::from_value_cached`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cached<T>(pub T);
+
+impl<'a, T> FromValue<'a> for Cached<T>
+where
+    T: FromValue<'a> + Clone + 'static,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        // If this value was not reached through the context, its handle is unknown.
+        let value = match ctx.current_handle(value) {
+            Some(handle) => ctx.with_cache(handle, || T::from_value(ctx, value))?,
+            None => T::from_value(ctx, value)?,
+        };
+
+        Ok(Self(value))
+    }
+}
+
 /// Implemented for any type that can be created from a Ruby Value.
 pub trait FromValue<'a>: Sized {
     /// Create this type from the given value from the [`ValueArena`].
@@ -256,6 +553,21 @@ pub trait FromValue<'a>: Sized {
     fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError>;
 }
 
+/// Implemented for any type that can be created from the instance variables of a Ruby object.
+///
+/// This is used to decode the fields of a struct that are stored in the same object as its parent.
+pub trait FromInstanceVariables<'a>: Sized {
+    /// Create this type from the given instance variables.
+    ///
+    /// # Arguments
+    /// 1. `ctx`: The value extraction context.
+    /// 2. `instance_variables`: The instance variables to convert.
+    fn from_instance_variables(
+        ctx: &FromValueContext<'a>,
+        instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+    ) -> Result<Self, FromValueError>;
+}
+
 impl<'a> FromValue<'a> for &'a Value {
     fn from_value(_ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
         Ok(value)
@@ -343,6 +655,132 @@ impl<'a> FromValue<'a> for &'a UserDefinedValue {
     }
 }
 
+impl<'a> FromValue<'a> for &'a OldModuleValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::OldModule(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a FloatValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Float(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a BignumValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Bignum(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a RegexpValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Regexp(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a StructValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Struct(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a ClassValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Class(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a ModuleValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Module(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a UserMarshalValue {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::UserMarshal(value) => Ok(value),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a [u8] {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::String(value) => Ok(value.value()),
+            Value::Symbol(value) => Ok(value.value()),
+            value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for &'a str {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &[u8] = FromValue::from_value(ctx, value)?;
+        core::str::from_utf8(value).map_err(FromValueError::new_other)
+    }
+}
+
+/// An owned string, without the spare capacity of a [`String`].
+///
+/// This is extracted like `&str`, so the bytes must be UTF-8, or [`FromValueError::Other`] is returned.
+impl<'a> FromValue<'a> for Box<str> {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &str = FromValue::from_value(ctx, value)?;
+        Ok(value.into())
+    }
+}
+
+/// A shared string, extracted like [`Box<str>`].
+///
+/// Each decode allocates a new [`Rc`], like `Rc<T>`.
+impl<'a> FromValue<'a> for Rc<str> {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &str = FromValue::from_value(ctx, value)?;
+        Ok(value.into())
+    }
+}
+
+/// A shared string, extracted like [`Box<str>`].
+///
+/// Each decode allocates a new [`Arc`], like `Arc<T>`.
+impl<'a> FromValue<'a> for Arc<str> {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &str = FromValue::from_value(ctx, value)?;
+        Ok(value.into())
+    }
+}
+
+impl<'a> FromValue<'a> for () {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let _value: &NilValue = FromValue::from_value(ctx, value)?;
+        Ok(())
+    }
+}
+
 impl<'a> FromValue<'a> for bool {
     fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
         let value: &BoolValue = FromValue::from_value(ctx, value)?;
@@ -357,6 +795,16 @@ impl<'a> FromValue<'a> for i32 {
     }
 }
 
+impl<'a> FromValue<'a> for f64 {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &FloatValue = FromValue::from_value(ctx, value)?;
+        Ok(value.value())
+    }
+}
+
+/// Only nil becomes `None`.
+/// As a result, `Option<Vec<T>>` extracts an empty array as `Some` with an empty `Vec`.
+/// Use [`NilOrEmpty`](crate::NilOrEmpty) to treat empty arrays as absent.
 impl<'a, T> FromValue<'a> for Option<T>
 where
     T: FromValue<'a>,
@@ -369,6 +817,44 @@ where
     }
 }
 
+impl<'a, T> FromValue<'a> for Box<T>
+where
+    T: FromValue<'a>,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        T::from_value(ctx, value).map(Box::new)
+    }
+}
+
+/// Each decode allocates a new [`Rc`].
+///
+/// A value that is referenced more than once through object links is not deduplicated into a shared [`Rc`],
+/// unless it is extracted with [`FromValueContext::from_value_cached`] or [`Cached`].
+impl<'a, T> FromValue<'a> for Rc<T>
+where
+    T: FromValue<'a>,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        T::from_value(ctx, value).map(Rc::new)
+    }
+}
+
+/// Each decode allocates a new [`Arc`].
+///
+/// A value that is referenced more than once through object links is not deduplicated into a shared [`Arc`],
+/// unless it is extracted with [`FromValueContext::from_value_cached`] or [`Cached`].
+impl<'a, T> FromValue<'a> for Arc<T>
+where
+    T: FromValue<'a>,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        T::from_value(ctx, value).map(Arc::new)
+    }
+}
+
+/// Arrays are extracted item by item.
+///
+/// Use [`Binary`](crate::Binary) for the raw bytes of a string.
 impl<'a, T> FromValue<'a> for Vec<T>
 where
     T: FromValue<'a>,
@@ -378,8 +864,8 @@ where
         let array = array.value();
 
         let mut vec = Vec::with_capacity(array.len());
-        for handle in array.iter().copied() {
-            let value = ctx.from_value(handle)?;
+        for (index, handle) in array.iter().copied().enumerate() {
+            let value = ctx.from_value_at(PathSegment::Index(index), handle)?;
             vec.push(value);
         }
 
@@ -397,8 +883,8 @@ pub enum HashMapFromValueError {
     },
 }
 
-impl std::fmt::Display for HashMapFromValueError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for HashMapFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             Self::HasDefaultValue { .. } => {
                 write!(f, "HashValue has a default value")
@@ -407,8 +893,9 @@ impl std::fmt::Display for HashMapFromValueError {
     }
 }
 
-impl std::error::Error for HashMapFromValueError {}
+impl core::error::Error for HashMapFromValueError {}
 
+#[cfg(feature = "std")]
 impl<'a, K, V> FromValue<'a> for HashMap<K, V>
 where
     K: FromValue<'a> + Hash + Eq,
@@ -428,9 +915,11 @@ where
         let value = value.value();
 
         let mut map = HashMap::with_capacity(value.len());
+        let mut ruby_keys = ctx.new_ruby_key_set();
         for (key_handle, value_handle) in value.iter().copied() {
-            let key = ctx.from_value(key_handle)?;
-            let value = ctx.from_value(value_handle)?;
+            ruby_keys.insert(key_handle)?;
+            let key = ctx.from_value_at(PathSegment::HashKey(key_handle), key_handle)?;
+            let value = ctx.from_value_at(PathSegment::HashKey(key_handle), value_handle)?;
 
             let old_value = map.insert(key, value);
 
@@ -453,8 +942,8 @@ pub enum BTreeMapFromValueError {
     },
 }
 
-impl std::fmt::Display for BTreeMapFromValueError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for BTreeMapFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             Self::HasDefaultValue { .. } => {
                 write!(f, "HashValue has a default value")
@@ -463,7 +952,7 @@ impl std::fmt::Display for BTreeMapFromValueError {
     }
 }
 
-impl std::error::Error for BTreeMapFromValueError {}
+impl core::error::Error for BTreeMapFromValueError {}
 
 impl<'a, K, V> FromValue<'a> for BTreeMap<K, V>
 where
@@ -484,15 +973,109 @@ where
         let value = value.value();
 
         let mut map = BTreeMap::new();
+        let mut ruby_keys = ctx.new_ruby_key_set();
         for (key_handle, value_handle) in value.iter().copied() {
-            let key = ctx.from_value(key_handle)?;
-            let value = ctx.from_value(value_handle)?;
+            ruby_keys.insert(key_handle)?;
+            let key = ctx.from_value_at(PathSegment::HashKey(key_handle), key_handle)?;
+            let value = ctx.from_value_at(PathSegment::HashKey(key_handle), value_handle)?;
+
+            let old_value = map.insert(key, value);
+
+            if old_value.is_some() {
+                return Err(FromValueError::DuplicateHashKey { key: key_handle });
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+/// An error that may occur while extracting an IndexMap from a value.
+#[derive(Debug)]
+pub enum IndexMapFromValueError {
+    /// The IndexMap cannot be extracted since it has a default value.
+    HasDefaultValue {
+        /// The default value
+        value: ValueHandle,
+    },
+
+    /// An element of an array of pairs was not a pair.
+    InvalidPairLength {
+        /// The length of the element
+        len: usize,
+    },
+}
+
+impl core::fmt::Display for IndexMapFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::HasDefaultValue { .. } => {
+                write!(f, "HashValue has a default value")
+            }
+            Self::InvalidPairLength { len } => {
+                write!(f, "expected an array of length 2, but got length {len}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for IndexMapFromValueError {}
+
+/// This accepts either a hash or an array of `[key, value]` pairs,
+/// like the ones created by `Hash#to_a`.
+/// Either way, the order of the entries is preserved.
+/// A key that occurs more than once is a [`FromValueError::DuplicateHashKey`] error.
+impl<'a, K, V, S> FromValue<'a> for IndexMap<K, V, S>
+where
+    K: FromValue<'a> + Hash + Eq,
+    V: FromValue<'a>,
+    S: BuildHasher + Default,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let mut map = IndexMap::with_hasher(S::default());
+        let mut ruby_keys = ctx.new_ruby_key_set();
+        let mut insert = |key_handle, value_handle| {
+            ruby_keys.insert(key_handle)?;
+            let key = ctx.from_value_at(PathSegment::HashKey(key_handle), key_handle)?;
+            let value = ctx.from_value_at(PathSegment::HashKey(key_handle), value_handle)?;
 
             let old_value = map.insert(key, value);
 
             if old_value.is_some() {
                 return Err(FromValueError::DuplicateHashKey { key: key_handle });
             }
+
+            Ok(())
+        };
+
+        match value {
+            Value::Hash(value) => {
+                if let Some(default_value) = value.default_value() {
+                    return Err(FromValueError::new_other(
+                        IndexMapFromValueError::HasDefaultValue {
+                            value: default_value,
+                        },
+                    ));
+                }
+
+                for (key_handle, value_handle) in value.value().iter().copied() {
+                    insert(key_handle, value_handle)?;
+                }
+            }
+            Value::Array(value) => {
+                for (index, pair_handle) in value.value().iter().copied().enumerate() {
+                    let pair: &ArrayValue =
+                        ctx.from_value_at(PathSegment::Index(index), pair_handle)?;
+                    let &[key_handle, value_handle] = pair.value() else {
+                        return Err(FromValueError::new_other(
+                            IndexMapFromValueError::InvalidPairLength { len: pair.len() },
+                        ));
+                    };
+
+                    insert(key_handle, value_handle)?;
+                }
+            }
+            value => return Err(ctx.new_unexpected_value_kind_error(value.kind())),
         }
 
         Ok(map)
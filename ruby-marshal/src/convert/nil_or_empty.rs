@@ -0,0 +1,38 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec::Vec;
+
+/// A wrapper for arrays where nil and an empty array both mean the value is absent.
+///
+/// When extracting, nil and an empty array become `None`,
+/// and any other array becomes `Some` with its items.
+///
+/// When converting into a value, `None` and an empty `Vec` are both written as an empty array.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NilOrEmpty<T>(pub Option<Vec<T>>);
+
+impl<'a, T> FromValue<'a> for NilOrEmpty<T>
+where
+    T: FromValue<'a>,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let items: Option<Vec<T>> = FromValue::from_value(ctx, value)?;
+
+        Ok(Self(items.filter(|items| !items.is_empty())))
+    }
+}
+
+impl<T> IntoValue for NilOrEmpty<T>
+where
+    T: IntoValue,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        self.0.unwrap_or_default().into_value(arena)
+    }
+}
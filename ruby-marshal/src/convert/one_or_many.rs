@@ -0,0 +1,51 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A wrapper for values that may be a single item or an array of items.
+///
+/// When extracting:
+/// * nil becomes an empty `Vec`.
+/// * An array becomes a `Vec` of its items.
+/// * Anything else is extracted as a single item.
+///
+/// Arrays are always treated as many items, so `T` should not itself be extracted from an array.
+///
+/// When converting into a value, a single item is written on its own.
+/// Any other number of items, including zero, is written as an array.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OneOrMany<T>(pub Vec<T>);
+
+impl<'a, T> FromValue<'a> for OneOrMany<T>
+where
+    T: FromValue<'a>,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        match value {
+            Value::Nil(_) => Ok(Self(Vec::new())),
+            Value::Array(_) => Ok(Self(FromValue::from_value(ctx, value)?)),
+            _ => Ok(Self(vec![T::from_value(ctx, value)?])),
+        }
+    }
+}
+
+impl<T> IntoValue for OneOrMany<T>
+where
+    T: IntoValue,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let mut items = self.0;
+        if items.len() == 1 {
+            return items.remove(0).into_value(arena);
+        }
+
+        items.into_value(arena)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::StringValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An error that may occur while converting a path into a value.
+#[derive(Debug)]
+pub enum PathIntoValueError {
+    /// The path is not valid Unicode, and this platform cannot store it as bytes.
+    NotUnicode {
+        /// The path
+        path: PathBuf,
+    },
+}
+
+impl core::fmt::Display for PathIntoValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NotUnicode { path } => {
+                write!(f, "path \"{}\" is not valid Unicode", path.display())
+            }
+        }
+    }
+}
+
+impl core::error::Error for PathIntoValueError {}
+
+/// Make an [`OsString`] from the bytes of a Ruby String.
+///
+/// On Unix, any bytes are allowed.
+/// Elsewhere, the bytes must be UTF-8.
+fn os_string_from_bytes(bytes: &[u8]) -> Result<OsString, FromValueError> {
+    #[cfg(unix)]
+    {
+        Ok(OsStr::from_bytes(bytes).into())
+    }
+
+    #[cfg(not(unix))]
+    {
+        let value = core::str::from_utf8(bytes).map_err(FromValueError::new_other)?;
+        Ok(value.into())
+    }
+}
+
+/// Create a Ruby String from an [`OsStr`].
+///
+/// Unicode is written as a UTF-8 String.
+/// On Unix, anything else is written as a String of the raw bytes, without an encoding.
+/// Elsewhere, anything else is an error.
+fn os_str_into_value(value: &OsStr, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+    if let Some(value) = value.to_str() {
+        return Ok(arena.create_utf8_string(value).into());
+    }
+
+    #[cfg(unix)]
+    {
+        Ok(arena.create_string(value.as_bytes().into()).into())
+    }
+
+    #[cfg(not(unix))]
+    {
+        Err(IntoValueError::new_other(PathIntoValueError::NotUnicode {
+            path: value.into(),
+        }))
+    }
+}
+
+/// The bytes of a String, as an OS string.
+///
+/// On Unix, the bytes are used as-is, whatever their encoding.
+/// On other platforms, like Windows, the bytes must be UTF-8.
+/// Symbols and other values are rejected.
+impl<'a> FromValue<'a> for OsString {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &StringValue = FromValue::from_value(ctx, value)?;
+        os_string_from_bytes(value.value())
+    }
+}
+
+/// The bytes of a String, as a path.
+///
+/// This has the same platform differences as [`OsString`].
+impl<'a> FromValue<'a> for PathBuf {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: OsString = FromValue::from_value(ctx, value)?;
+        Ok(value.into())
+    }
+}
+
+/// A String, marked as UTF-8 if the OS string is valid Unicode.
+///
+/// On Unix, other OS strings are written as raw bytes, without an encoding.
+/// On other platforms, like Windows, they fail with [`PathIntoValueError::NotUnicode`].
+impl IntoValue for &OsStr {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        os_str_into_value(self, arena)
+    }
+}
+
+impl IntoValue for OsString {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        os_str_into_value(&self, arena)
+    }
+}
+
+/// A String, written the same way as [`OsStr`].
+impl IntoValue for &Path {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        os_str_into_value(self.as_os_str(), arena)
+    }
+}
+
+impl IntoValue for PathBuf {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        os_str_into_value(self.as_os_str(), arena)
+    }
+}
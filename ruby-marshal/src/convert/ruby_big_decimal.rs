@@ -0,0 +1,241 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::SymbolValue;
+use crate::UserDefinedValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::format;
+use alloc::string::String;
+
+const BIG_DECIMAL_NAME: &[u8] = b"BigDecimal";
+
+/// The number of decimal digits Ruby stores per internal word.
+const DIGITS_PER_WORD: usize = 9;
+
+/// An error that may occur while extracting a RubyBigDecimal from a value.
+#[derive(Debug)]
+pub enum RubyBigDecimalFromValueError {
+    /// The payload is missing the ':' between the precision and the number.
+    MissingSeparator,
+
+    /// The precision is not a valid number.
+    InvalidPrecision,
+
+    /// The number is malformed.
+    InvalidNumber,
+}
+
+impl core::fmt::Display for RubyBigDecimalFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::MissingSeparator => {
+                write!(f, "BigDecimal payload is missing the precision separator")
+            }
+            Self::InvalidPrecision => write!(f, "BigDecimal payload has an invalid precision"),
+            Self::InvalidNumber => write!(f, "BigDecimal payload has an invalid number"),
+        }
+    }
+}
+
+impl core::error::Error for RubyBigDecimalFromValueError {}
+
+/// The kind of a [`RubyBigDecimal`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RubyBigDecimalKind {
+    /// A finite number, including zero.
+    Finite,
+
+    /// Positive or negative infinity.
+    Infinite,
+
+    /// Not a number.
+    NaN,
+}
+
+/// A Ruby BigDecimal.
+///
+/// This is stored as a user defined value named "BigDecimal",
+/// with a payload like "18:0.12345e2".
+/// The part before the ':' is the precision,
+/// and the part after is the number as `0.<digits>e<exponent>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RubyBigDecimal {
+    precision: usize,
+    kind: RubyBigDecimalKind,
+    negative: bool,
+    digits: String,
+    exponent: i64,
+}
+
+impl RubyBigDecimal {
+    /// Create a new finite [`RubyBigDecimal`] with the value `0.<digits> * 10^exponent`.
+    ///
+    /// An empty digit string is zero.
+    ///
+    /// # Returns
+    /// Returns None if `digits` contains anything other than ASCII digits.
+    pub fn new_finite(negative: bool, digits: &str, exponent: i64) -> Option<Self> {
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(Self {
+            precision: default_precision(digits.len()),
+            kind: RubyBigDecimalKind::Finite,
+            negative,
+            digits: digits.into(),
+            exponent,
+        })
+    }
+
+    /// Create a new infinite [`RubyBigDecimal`].
+    pub fn new_infinity(negative: bool) -> Self {
+        Self {
+            precision: default_precision(0),
+            kind: RubyBigDecimalKind::Infinite,
+            negative,
+            digits: String::new(),
+            exponent: 0,
+        }
+    }
+
+    /// Create a new NaN [`RubyBigDecimal`].
+    pub fn new_nan() -> Self {
+        Self {
+            precision: default_precision(0),
+            kind: RubyBigDecimalKind::NaN,
+            negative: false,
+            digits: String::new(),
+            exponent: 0,
+        }
+    }
+
+    /// Get the precision, in digits.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    /// Set the precision, in digits.
+    pub fn set_precision(&mut self, precision: usize) {
+        self.precision = precision;
+    }
+
+    /// Get the kind of number.
+    pub fn kind(&self) -> RubyBigDecimalKind {
+        self.kind
+    }
+
+    /// Check if this number is negative.
+    ///
+    /// This is always false for NaN.
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Get the significant digits.
+    ///
+    /// This is empty for zero, infinity, and NaN.
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    /// Get the exponent.
+    ///
+    /// This is 0 for zero, infinity, and NaN.
+    pub fn exponent(&self) -> i64 {
+        self.exponent
+    }
+
+    /// Parse a payload.
+    fn from_payload(payload: &[u8]) -> Result<Self, RubyBigDecimalFromValueError> {
+        let payload = core::str::from_utf8(payload)
+            .map_err(|_| RubyBigDecimalFromValueError::InvalidNumber)?;
+        let (precision, number) = payload
+            .split_once(':')
+            .ok_or(RubyBigDecimalFromValueError::MissingSeparator)?;
+
+        let precision = precision
+            .parse()
+            .map_err(|_| RubyBigDecimalFromValueError::InvalidPrecision)?;
+
+        let (negative, unsigned_number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+
+        let mut value = match unsigned_number {
+            "NaN" if number == unsigned_number => Self::new_nan(),
+            "Infinity" => Self::new_infinity(negative),
+            "0.0" => Self::new_finite(negative, "", 0).unwrap(),
+            _ => {
+                let number = unsigned_number
+                    .strip_prefix("0.")
+                    .ok_or(RubyBigDecimalFromValueError::InvalidNumber)?;
+                let (digits, exponent) = number
+                    .split_once(['e', 'E'])
+                    .ok_or(RubyBigDecimalFromValueError::InvalidNumber)?;
+                let exponent = exponent
+                    .parse()
+                    .map_err(|_| RubyBigDecimalFromValueError::InvalidNumber)?;
+
+                if digits.is_empty() {
+                    return Err(RubyBigDecimalFromValueError::InvalidNumber);
+                }
+
+                Self::new_finite(negative, digits, exponent)
+                    .ok_or(RubyBigDecimalFromValueError::InvalidNumber)?
+            }
+        };
+        value.precision = precision;
+
+        Ok(value)
+    }
+}
+
+impl core::fmt::Display for RubyBigDecimal {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        match self.kind {
+            RubyBigDecimalKind::NaN => write!(f, "NaN"),
+            RubyBigDecimalKind::Infinite => write!(f, "{sign}Infinity"),
+            RubyBigDecimalKind::Finite if self.digits.is_empty() => write!(f, "{sign}0.0"),
+            RubyBigDecimalKind::Finite => {
+                write!(f, "{sign}0.{}e{}", self.digits, self.exponent)
+            }
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for RubyBigDecimal {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &UserDefinedValue = FromValue::from_value(ctx, value)?;
+
+        let name: &SymbolValue = ctx.from_value(value.name().into())?;
+        let name = name.value();
+        if name != BIG_DECIMAL_NAME {
+            return Err(FromValueError::UnexpectedUserDefinedName { name: name.into() });
+        }
+
+        Self::from_payload(value.value()).map_err(FromValueError::new_other)
+    }
+}
+
+impl IntoValue for RubyBigDecimal {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let payload = format!("{}:{self}", self.precision);
+
+        let name = arena.create_symbol_static(BIG_DECIMAL_NAME);
+        let handle = arena.create_user_defined(name, payload.into());
+
+        Ok(handle.into())
+    }
+}
+
+/// Get a default precision for a number with the given number of digits.
+fn default_precision(num_digits: usize) -> usize {
+    num_digits.div_ceil(DIGITS_PER_WORD).max(1) * DIGITS_PER_WORD
+}
@@ -0,0 +1,98 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::ObjectValue;
+use crate::PathSegment;
+use crate::SymbolValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec;
+
+const COMPLEX_NAME: &[u8] = b"Complex";
+const REAL_NAME: &[u8] = b"@real";
+const IMAGINARY_NAME: &[u8] = b"@imaginary";
+
+/// A Ruby Complex.
+///
+/// This is stored as an object named "Complex",
+/// with "@real" and "@imaginary" instance variables.
+/// Only Fixnum parts are supported; Float, Rational, and Bignum parts are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RubyComplex {
+    real: i32,
+    imaginary: i32,
+}
+
+impl RubyComplex {
+    /// Create a new [`RubyComplex`] from a real part and an imaginary part.
+    pub fn new(real: i32, imaginary: i32) -> Self {
+        Self { real, imaginary }
+    }
+
+    /// Get the real part.
+    pub fn real(&self) -> i32 {
+        self.real
+    }
+
+    /// Get the imaginary part.
+    pub fn imaginary(&self) -> i32 {
+        self.imaginary
+    }
+}
+
+impl<'a> FromValue<'a> for RubyComplex {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &ObjectValue = FromValue::from_value(ctx, value)?;
+
+        let name: &SymbolValue = ctx.from_value(value.name().into())?;
+        let name = name.value();
+        if name != COMPLEX_NAME {
+            return Err(FromValueError::UnexpectedObjectName { name: name.into() });
+        }
+
+        let mut real = None;
+        let mut imaginary = None;
+        for (key_handle, value) in value.instance_variables().iter().copied() {
+            let key: &SymbolValue = ctx.from_value(key_handle.into())?;
+            let key = key.value();
+            let slot = match key {
+                REAL_NAME => &mut real,
+                IMAGINARY_NAME => &mut imaginary,
+                _ => {
+                    return Err(FromValueError::UnknownInstanceVariable { name: key.into() });
+                }
+            };
+            if slot.is_some() {
+                return Err(FromValueError::DuplicateInstanceVariable { name: key.into() });
+            }
+            *slot = Some(ctx.from_value_at(PathSegment::InstanceVariable(key_handle), value)?);
+        }
+
+        let real = real.ok_or_else(|| FromValueError::MissingInstanceVariable {
+            name: REAL_NAME.into(),
+        })?;
+        let imaginary = imaginary.ok_or_else(|| FromValueError::MissingInstanceVariable {
+            name: IMAGINARY_NAME.into(),
+        })?;
+
+        Ok(Self { real, imaginary })
+    }
+}
+
+impl IntoValue for RubyComplex {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let real_name = arena.create_symbol_static(REAL_NAME);
+        let real = arena.create_fixnum(self.real).into();
+        let imaginary_name = arena.create_symbol_static(IMAGINARY_NAME);
+        let imaginary = arena.create_fixnum(self.imaginary).into();
+
+        let name = arena.create_symbol_static(COMPLEX_NAME);
+        let handle =
+            arena.create_object(name, vec![(real_name, real), (imaginary_name, imaginary)]);
+
+        Ok(handle.into())
+    }
+}
@@ -0,0 +1,234 @@
+use crate::FromValueError;
+use crate::SymbolValue;
+use crate::TypedValueHandle;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec::Vec;
+use hashbrown::HashSet;
+
+/// The deepest a key is compared by its contents.
+///
+/// Anything nested deeper is compared by identity, so that hostile input cannot exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+/// A hash key, in the form that Ruby's `eql?` compares it in.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum RubyKey<'a> {
+    /// A value that is only equal to itself, like an object.
+    Identity(ValueHandle),
+
+    Nil,
+
+    Bool(bool),
+
+    /// A fixnum or bignum.
+    ///
+    /// The magnitude is little endian, without trailing zeros, so equal values have equal keys.
+    Integer {
+        is_negative: bool,
+        magnitude: Vec<u8>,
+    },
+
+    /// The bits of a float, which is not NaN or negative zero.
+    Float(u64),
+
+    Symbol(&'a [u8]),
+
+    /// A string.
+    ///
+    /// The encoding is left out for ASCII strings, as Ruby considers those equal in any ASCII-compatible encoding.
+    String {
+        value: &'a [u8],
+        encoding: Option<&'a [u8]>,
+    },
+
+    Regexp {
+        source: &'a [u8],
+        options: u8,
+    },
+
+    Array(Vec<RubyKey<'a>>),
+
+    /// A hash, with its pairs sorted, as `eql?` ignores the order of hashes.
+    Hash(Vec<(RubyKey<'a>, RubyKey<'a>)>),
+
+    Struct {
+        name: &'a [u8],
+        members: Vec<RubyKey<'a>>,
+    },
+
+    Class(&'a [u8]),
+
+    Module(&'a [u8]),
+}
+
+/// A set of hash keys, compared the way Ruby's `eql?` compares them.
+pub(super) struct RubyKeySet<'a> {
+    arena: &'a ValueArena,
+
+    /// The keys so far, or `None` if keys are not checked.
+    keys: Option<HashSet<RubyKey<'a>>>,
+}
+
+impl<'a> RubyKeySet<'a> {
+    /// Make a new, empty [`RubyKeySet`].
+    ///
+    /// If `enabled` is false, every key is accepted.
+    pub(super) fn new(arena: &'a ValueArena, enabled: bool) -> Self {
+        Self {
+            arena,
+            keys: enabled.then(HashSet::new),
+        }
+    }
+
+    /// Add a key.
+    ///
+    /// # Errors
+    /// Returns [`FromValueError::DuplicateHashKey`] if an equal key was already added.
+    pub(super) fn insert(&mut self, handle: ValueHandle) -> Result<(), FromValueError> {
+        let Some(keys) = self.keys.as_mut() else {
+            return Ok(());
+        };
+
+        let key = ruby_key(self.arena, handle, &mut Vec::new());
+        if !keys.insert(key) {
+            return Err(FromValueError::DuplicateHashKey { key: handle });
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the [`RubyKey`] of a value.
+///
+/// `ancestors` holds the values that contain this one, to compare cyclic values by identity.
+fn ruby_key<'a>(
+    arena: &'a ValueArena,
+    handle: ValueHandle,
+    ancestors: &mut Vec<ValueHandle>,
+) -> RubyKey<'a> {
+    let Some(value) = arena.get(handle) else {
+        return RubyKey::Identity(handle);
+    };
+    if ancestors.len() >= MAX_DEPTH || ancestors.contains(&handle) {
+        return RubyKey::Identity(handle);
+    }
+
+    ancestors.push(handle);
+    let key = match value {
+        Value::Nil(_) => RubyKey::Nil,
+        Value::Bool(value) => RubyKey::Bool(value.value()),
+        Value::Fixnum(value) => {
+            let value = value.value();
+            integer_key(value < 0, &value.unsigned_abs().to_le_bytes())
+        }
+        Value::Bignum(value) => integer_key(value.is_negative(), value.value()),
+        Value::Float(value) => {
+            let value = value.value();
+            if value.is_nan() {
+                // NaN is not equal to anything, even itself.
+                RubyKey::Identity(handle)
+            } else {
+                // Adding zero turns negative zero into zero.
+                RubyKey::Float((value + 0.0).to_bits())
+            }
+        }
+        Value::Symbol(value) => RubyKey::Symbol(value.value()),
+        Value::String(value) => {
+            let encoding = if value.value().is_ascii() {
+                None
+            } else {
+                Some(string_encoding(
+                    arena,
+                    value.instance_variables().unwrap_or_default(),
+                ))
+            };
+
+            RubyKey::String {
+                value: value.value(),
+                encoding,
+            }
+        }
+        Value::Regexp(value) => RubyKey::Regexp {
+            source: value.source(),
+            options: value.options(),
+        },
+        Value::Array(value) => RubyKey::Array(
+            value
+                .value()
+                .iter()
+                .map(|handle| ruby_key(arena, *handle, ancestors))
+                .collect(),
+        ),
+        Value::Hash(value) if value.default_value().is_none() => {
+            let mut pairs: Vec<_> = value
+                .value()
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        ruby_key(arena, *key, ancestors),
+                        ruby_key(arena, *value, ancestors),
+                    )
+                })
+                .collect();
+            pairs.sort_unstable();
+
+            RubyKey::Hash(pairs)
+        }
+        Value::Struct(value) => match arena.get_symbol(value.name()) {
+            Some(name) => RubyKey::Struct {
+                name: name.value(),
+                members: value
+                    .members()
+                    .iter()
+                    .map(|(_name, handle)| ruby_key(arena, *handle, ancestors))
+                    .collect(),
+            },
+            None => RubyKey::Identity(handle),
+        },
+        Value::Class(value) => RubyKey::Class(value.name()),
+        Value::Module(value) => RubyKey::Module(value.name()),
+        Value::OldModule(value) => RubyKey::Module(value.name()),
+        Value::Hash(_) | Value::Object(_) | Value::UserDefined(_) | Value::UserMarshal(_) => {
+            RubyKey::Identity(handle)
+        }
+    };
+    ancestors.pop();
+
+    key
+}
+
+/// Make the key of an integer, from its little endian magnitude.
+fn integer_key(is_negative: bool, magnitude: &[u8]) -> RubyKey<'static> {
+    let len = magnitude
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |index| index + 1);
+
+    RubyKey::Integer {
+        is_negative: is_negative && len != 0,
+        magnitude: magnitude[..len].into(),
+    }
+}
+
+/// Get the name of the encoding of a string, from its instance variables.
+fn string_encoding<'a>(
+    arena: &'a ValueArena,
+    instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+) -> &'a [u8] {
+    for (name, value) in instance_variables.iter().copied() {
+        let Some(name) = arena.get_symbol(name) else {
+            continue;
+        };
+
+        match (name.value(), arena.get(value)) {
+            (b"E", Some(Value::Bool(value))) if value.value() => return b"UTF-8",
+            (b"E", Some(Value::Bool(_))) => return b"US-ASCII",
+            (b"encoding", Some(Value::String(value))) => return value.value(),
+            _ => {}
+        }
+    }
+
+    b"ASCII-8BIT"
+}
@@ -0,0 +1,74 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::ObjectValue;
+use crate::SymbolValue;
+use crate::Value;
+use crate::ValueHandle;
+use alloc::vec::Vec;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// A Ruby object of any class, with its instance variables in a map.
+///
+/// This is useful for inspecting objects without knowing their layout ahead of time.
+/// Instance variable values are left as handles, so they can be extracted as needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RubyObjectMap {
+    name: Vec<u8>,
+    instance_variables: HashMap<Vec<u8>, ValueHandle>,
+}
+
+impl RubyObjectMap {
+    /// Get the class name.
+    ///
+    /// This may or may not be UTF-8.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// Get the instance variables, keyed by name.
+    ///
+    /// Names include the leading `@`.
+    pub fn instance_variables(&self) -> &HashMap<Vec<u8>, ValueHandle> {
+        &self.instance_variables
+    }
+
+    /// Get the value of an instance variable by name.
+    pub fn get(&self, name: &[u8]) -> Option<ValueHandle> {
+        self.instance_variables.get(name).copied()
+    }
+
+    /// Split this into the class name and the instance variables.
+    pub fn into_parts(self) -> (Vec<u8>, HashMap<Vec<u8>, ValueHandle>) {
+        (self.name, self.instance_variables)
+    }
+}
+
+impl<'a> FromValue<'a> for RubyObjectMap {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &ObjectValue = FromValue::from_value(ctx, value)?;
+
+        let name: &SymbolValue = ctx.from_value(value.name().into())?;
+        let name = name.value().to_vec();
+
+        let mut instance_variables = HashMap::with_capacity(value.instance_variable_count());
+        for (key_handle, value) in value.instance_variables().iter().copied() {
+            let key: &SymbolValue = ctx.from_value(key_handle.into())?;
+            let key = key.value();
+            match instance_variables.entry(key.to_vec()) {
+                Entry::Occupied(_) => {
+                    return Err(FromValueError::DuplicateInstanceVariable { name: key.into() });
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+            }
+        }
+
+        Ok(Self {
+            name,
+            instance_variables,
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::ObjectValue;
+use crate::PathSegment;
+use crate::SymbolValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec;
+
+const RATIONAL_NAME: &[u8] = b"Rational";
+const NUMERATOR_NAME: &[u8] = b"@numerator";
+const DENOMINATOR_NAME: &[u8] = b"@denominator";
+
+/// A Ruby Rational.
+///
+/// This is stored as an object named "Rational",
+/// with "@numerator" and "@denominator" instance variables.
+/// Only Fixnum components are supported, as Bignums cannot be represented by a [`Value`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RubyRational {
+    numerator: i32,
+    denominator: i32,
+}
+
+impl RubyRational {
+    /// Create a new [`RubyRational`] from a numerator and denominator.
+    ///
+    /// The components are stored as given, without reducing them.
+    pub fn new(numerator: i32, denominator: i32) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Get the numerator.
+    pub fn numerator(&self) -> i32 {
+        self.numerator
+    }
+
+    /// Get the denominator.
+    pub fn denominator(&self) -> i32 {
+        self.denominator
+    }
+}
+
+impl<'a> FromValue<'a> for RubyRational {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &ObjectValue = FromValue::from_value(ctx, value)?;
+
+        let name: &SymbolValue = ctx.from_value(value.name().into())?;
+        let name = name.value();
+        if name != RATIONAL_NAME {
+            return Err(FromValueError::UnexpectedObjectName { name: name.into() });
+        }
+
+        let mut numerator = None;
+        let mut denominator = None;
+        for (key_handle, value) in value.instance_variables().iter().copied() {
+            let key: &SymbolValue = ctx.from_value(key_handle.into())?;
+            let key = key.value();
+            let slot = match key {
+                NUMERATOR_NAME => &mut numerator,
+                DENOMINATOR_NAME => &mut denominator,
+                _ => {
+                    return Err(FromValueError::UnknownInstanceVariable { name: key.into() });
+                }
+            };
+            if slot.is_some() {
+                return Err(FromValueError::DuplicateInstanceVariable { name: key.into() });
+            }
+            *slot = Some(ctx.from_value_at(PathSegment::InstanceVariable(key_handle), value)?);
+        }
+
+        let numerator = numerator.ok_or_else(|| FromValueError::MissingInstanceVariable {
+            name: NUMERATOR_NAME.into(),
+        })?;
+        let denominator = denominator.ok_or_else(|| FromValueError::MissingInstanceVariable {
+            name: DENOMINATOR_NAME.into(),
+        })?;
+
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+impl IntoValue for RubyRational {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let numerator_name = arena.create_symbol_static(NUMERATOR_NAME);
+        let numerator = arena.create_fixnum(self.numerator).into();
+        let denominator_name = arena.create_symbol_static(DENOMINATOR_NAME);
+        let denominator = arena.create_fixnum(self.denominator).into();
+
+        let name = arena.create_symbol_static(RATIONAL_NAME);
+        let handle = arena.create_object(
+            name,
+            vec![(numerator_name, numerator), (denominator_name, denominator)],
+        );
+
+        Ok(handle.into())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::HashValue;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::PathSegment;
+use crate::SymbolValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+
+const SET_NAME: &[u8] = b"Set";
+const HASH_NAME: &[u8] = b"@hash";
+
+/// A Ruby Set, wrapping a Rust set like a [`BTreeSet`] or a `HashSet`.
+///
+/// Ruby stores a Set as a hash with the elements as keys and `true` as values.
+/// This is wrapped in a user marshal value named "Set".
+/// Older versions of Ruby wrap it in an object named "Set" with a "@hash" instance variable instead,
+/// which is also accepted when extracting.
+///
+/// Converting into a value always produces the user marshal form.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RubySet<S>(pub S);
+
+/// Get the hash that holds the elements of a Ruby Set.
+fn set_hash<'a>(
+    ctx: &FromValueContext<'a>,
+    value: &'a Value,
+) -> Result<&'a HashValue, FromValueError> {
+    match value {
+        Value::UserMarshal(value) => {
+            let name: &SymbolValue = ctx.from_value(value.name().into())?;
+            let name = name.value();
+            if name != SET_NAME {
+                return Err(FromValueError::UnexpectedUserMarshalName { name: name.into() });
+            }
+
+            ctx.from_value(value.value())
+        }
+        Value::Object(value) => {
+            let name: &SymbolValue = ctx.from_value(value.name().into())?;
+            let name = name.value();
+            if name != SET_NAME {
+                return Err(FromValueError::UnexpectedObjectName { name: name.into() });
+            }
+
+            let mut hash = None;
+            for (key_handle, value) in value.instance_variables().iter().copied() {
+                let key: &SymbolValue = ctx.from_value(key_handle.into())?;
+                let key = key.value();
+                if key != HASH_NAME {
+                    return Err(FromValueError::UnknownInstanceVariable { name: key.into() });
+                }
+                if hash.is_some() {
+                    return Err(FromValueError::DuplicateInstanceVariable { name: key.into() });
+                }
+                hash = Some(ctx.from_value_at(PathSegment::InstanceVariable(key_handle), value)?);
+            }
+
+            hash.ok_or_else(|| FromValueError::MissingInstanceVariable {
+                name: HASH_NAME.into(),
+            })
+        }
+        value => Err(ctx.new_unexpected_value_kind_error(value.kind())),
+    }
+}
+
+/// Extract the elements of a Ruby Set.
+///
+/// `insert` returns false if the element was already present.
+fn extract_elements<'a, T, F>(
+    ctx: &FromValueContext<'a>,
+    value: &'a Value,
+    mut insert: F,
+) -> Result<(), FromValueError>
+where
+    T: FromValue<'a>,
+    F: FnMut(T) -> bool,
+{
+    let hash = set_hash(ctx, value)?;
+    for (key_handle, _value) in hash.value().iter().copied() {
+        let element = ctx.from_value_at(PathSegment::HashKey(key_handle), key_handle)?;
+        if !insert(element) {
+            return Err(FromValueError::DuplicateHashKey { key: key_handle });
+        }
+    }
+
+    Ok(())
+}
+
+/// Make a Ruby Set from elements.
+fn create_set<T, I>(arena: &mut ValueArena, elements: I) -> Result<ValueHandle, IntoValueError>
+where
+    T: IntoValue,
+    I: Iterator<Item = T>,
+{
+    let mut items = Vec::with_capacity(elements.size_hint().0);
+    for element in elements {
+        let element = element.into_value(arena)?;
+        let present = arena.create_bool(true).into();
+
+        items.push((element, present));
+    }
+    let hash = arena.create_hash(items, None).into();
+
+    let name = arena.create_symbol_static(SET_NAME);
+    Ok(arena.create_user_marshal(name, hash).into())
+}
+
+impl<'a, T> FromValue<'a> for RubySet<BTreeSet<T>>
+where
+    T: FromValue<'a> + Ord,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let mut set = BTreeSet::new();
+        extract_elements(ctx, value, |element| set.insert(element))?;
+
+        Ok(Self(set))
+    }
+}
+
+/// The elements are written in order, so equal sets always produce the same bytes when dumped.
+impl<T> IntoValue for RubySet<BTreeSet<T>>
+where
+    T: IntoValue,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        create_set(arena, self.0.into_iter())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> FromValue<'a> for RubySet<HashSet<T>>
+where
+    T: FromValue<'a> + Eq + core::hash::Hash,
+{
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let mut set = HashSet::new();
+        extract_elements(ctx, value, |element| set.insert(element))?;
+
+        Ok(Self(set))
+    }
+}
+
+/// The order of the elements follows the iteration order of the HashSet, which is not deterministic.
+/// Use a [`BTreeSet`] if reproducible output is required.
+#[cfg(feature = "std")]
+impl<T> IntoValue for RubySet<HashSet<T>>
+where
+    T: IntoValue,
+{
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        create_set(arena, self.0.into_iter())
+    }
+}
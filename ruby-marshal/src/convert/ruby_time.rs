@@ -0,0 +1,320 @@
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use crate::PathSegment;
+use crate::StringValue;
+use crate::SymbolValue;
+use crate::UserDefinedValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use alloc::vec::Vec;
+
+const TIME_NAME: &[u8] = b"Time";
+const OFFSET_NAME: &[u8] = b"offset";
+const ZONE_NAME: &[u8] = b"zone";
+
+const PAYLOAD_LEN: usize = 8;
+const SECONDS_PER_DAY: i64 = 86_400;
+const MICROSECONDS_PER_SECOND: u32 = 1_000_000;
+const MAX_YEAR: i64 = 1900 + 0xFFFF;
+
+/// An error that may occur while extracting a RubyTime from a value.
+#[derive(Debug)]
+pub enum RubyTimeFromValueError {
+    /// The payload was not the expected length.
+    InvalidPayloadLength {
+        /// The length of the payload
+        len: usize,
+    },
+
+    /// The payload uses the legacy pre-1.9 format, which is not supported.
+    LegacyFormat,
+
+    /// The payload contained an invalid date or time.
+    InvalidDateTime,
+}
+
+impl core::fmt::Display for RubyTimeFromValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::InvalidPayloadLength { len } => {
+                write!(
+                    f,
+                    "Time payload has length {len}, expected length {PAYLOAD_LEN}"
+                )
+            }
+            Self::LegacyFormat => write!(f, "Time payload uses the unsupported legacy format"),
+            Self::InvalidDateTime => write!(f, "Time payload contains an invalid date or time"),
+        }
+    }
+}
+
+impl core::error::Error for RubyTimeFromValueError {}
+
+/// An error that may occur while turning a RubyTime into a value.
+#[derive(Debug)]
+pub enum RubyTimeIntoValueError {
+    /// The year cannot be represented in the packed format.
+    YearOutOfRange {
+        /// The year
+        year: i64,
+    },
+}
+
+impl core::fmt::Display for RubyTimeIntoValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::YearOutOfRange { year } => {
+                write!(f, "year {year} cannot be represented as a Time payload")
+            }
+        }
+    }
+}
+
+impl core::error::Error for RubyTimeIntoValueError {}
+
+/// A Ruby Time.
+///
+/// This is stored as a user defined value named "Time",
+/// with a packed 8 byte payload and optional "offset" and "zone" instance variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RubyTime {
+    seconds: i64,
+    microseconds: u32,
+    is_utc: bool,
+    utc_offset: Option<i32>,
+    zone: Option<Vec<u8>>,
+}
+
+impl RubyTime {
+    /// Create a new UTC [`RubyTime`] from seconds and microseconds since the Unix epoch.
+    ///
+    /// Microseconds past one second are carried into the seconds.
+    pub fn new_utc(seconds: i64, microseconds: u32) -> Self {
+        Self {
+            seconds: seconds + i64::from(microseconds / MICROSECONDS_PER_SECOND),
+            microseconds: microseconds % MICROSECONDS_PER_SECOND,
+            is_utc: true,
+            utc_offset: None,
+            zone: None,
+        }
+    }
+
+    /// Create a new local [`RubyTime`] from seconds and microseconds since the Unix epoch and a UTC offset in seconds.
+    ///
+    /// Microseconds past one second are carried into the seconds.
+    pub fn new_local(seconds: i64, microseconds: u32, utc_offset: i32) -> Self {
+        Self {
+            is_utc: false,
+            utc_offset: Some(utc_offset),
+            ..Self::new_utc(seconds, microseconds)
+        }
+    }
+
+    /// Get the number of whole seconds since the Unix epoch.
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Get the number of microseconds past the whole seconds.
+    pub fn microseconds(&self) -> u32 {
+        self.microseconds
+    }
+
+    /// Check if this time is in UTC.
+    pub fn is_utc(&self) -> bool {
+        self.is_utc
+    }
+
+    /// Get the UTC offset in seconds, if it is known.
+    pub fn utc_offset(&self) -> Option<i32> {
+        self.utc_offset
+    }
+
+    /// Get the zone name, if it is known.
+    ///
+    /// This may or may not be UTF-8.
+    pub fn zone(&self) -> Option<&[u8]> {
+        self.zone.as_deref()
+    }
+
+    /// Set the zone name.
+    pub fn set_zone(&mut self, zone: Option<Vec<u8>>) {
+        self.zone = zone;
+    }
+
+    /// Parse the packed payload.
+    fn from_payload(payload: &[u8]) -> Result<Self, RubyTimeFromValueError> {
+        let payload: [u8; PAYLOAD_LEN] = payload
+            .try_into()
+            .map_err(|_| RubyTimeFromValueError::InvalidPayloadLength { len: payload.len() })?;
+        let p = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let s = u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+
+        if p & (1 << 31) == 0 {
+            return Err(RubyTimeFromValueError::LegacyFormat);
+        }
+
+        let is_utc = (p >> 30) & 0x1 == 1;
+        let year = i64::from((p >> 14) & 0xFFFF) + 1900;
+        let month = (p >> 10) & 0xF;
+        let day = (p >> 5) & 0x1F;
+        let hour = p & 0x1F;
+        let minute = (s >> 26) & 0x3F;
+        let second = (s >> 20) & 0x3F;
+        let microseconds = s & 0xFFFFF;
+
+        if month > 11
+            || day == 0
+            || hour > 23
+            || minute > 59
+            || second > 60
+            || microseconds >= MICROSECONDS_PER_SECOND
+        {
+            return Err(RubyTimeFromValueError::InvalidDateTime);
+        }
+
+        let days = days_from_civil(year, month + 1, day);
+        let seconds = days * SECONDS_PER_DAY
+            + i64::from(hour) * 3600
+            + i64::from(minute) * 60
+            + i64::from(second);
+
+        Ok(Self {
+            seconds,
+            microseconds,
+            is_utc,
+            utc_offset: None,
+            zone: None,
+        })
+    }
+
+    /// Create the packed payload.
+    fn to_payload(&self) -> Result<[u8; PAYLOAD_LEN], RubyTimeIntoValueError> {
+        let days = self.seconds.div_euclid(SECONDS_PER_DAY);
+        let seconds_of_day = self.seconds.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+
+        if !(1900..=MAX_YEAR).contains(&year) {
+            return Err(RubyTimeIntoValueError::YearOutOfRange { year });
+        }
+
+        // All of these values were range-checked above or are bounded by construction.
+        let year = u32::try_from(year - 1900).unwrap();
+        let seconds_of_day = u32::try_from(seconds_of_day).unwrap();
+        let hour = seconds_of_day / 3600;
+        let minute = (seconds_of_day / 60) % 60;
+        let second = seconds_of_day % 60;
+
+        let p = (1 << 31)
+            | (u32::from(self.is_utc) << 30)
+            | (year << 14)
+            | ((month - 1) << 10)
+            | (day << 5)
+            | hour;
+        let s = (minute << 26) | (second << 20) | self.microseconds;
+
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[..4].copy_from_slice(&p.to_le_bytes());
+        payload[4..].copy_from_slice(&s.to_le_bytes());
+
+        Ok(payload)
+    }
+}
+
+impl<'a> FromValue<'a> for RubyTime {
+    fn from_value(ctx: &FromValueContext<'a>, value: &'a Value) -> Result<Self, FromValueError> {
+        let value: &UserDefinedValue = FromValue::from_value(ctx, value)?;
+
+        let name: &SymbolValue = ctx.from_value(value.name().into())?;
+        let name = name.value();
+        if name != TIME_NAME {
+            return Err(FromValueError::UnexpectedUserDefinedName { name: name.into() });
+        }
+
+        let mut time = Self::from_payload(value.value()).map_err(FromValueError::new_other)?;
+
+        for (key_handle, value) in value.instance_variables().unwrap_or(&[]).iter().copied() {
+            let key: &SymbolValue = ctx.from_value(key_handle.into())?;
+            match key.value() {
+                OFFSET_NAME => {
+                    time.utc_offset =
+                        Some(ctx.from_value_at(PathSegment::InstanceVariable(key_handle), value)?);
+                }
+                ZONE_NAME => {
+                    let zone: Option<&StringValue> =
+                        ctx.from_value_at(PathSegment::InstanceVariable(key_handle), value)?;
+                    time.zone = zone.map(|zone| zone.value().to_vec());
+                }
+                // Other instance variables, like the sub-microsecond precision, are ignored.
+                _ => {}
+            }
+        }
+
+        Ok(time)
+    }
+}
+
+impl IntoValue for RubyTime {
+    fn into_value(self, arena: &mut ValueArena) -> Result<ValueHandle, IntoValueError> {
+        let payload = self.to_payload().map_err(IntoValueError::new_other)?;
+
+        let mut instance_variables = Vec::new();
+        if let Some(utc_offset) = self.utc_offset {
+            let key = arena.create_symbol_static(OFFSET_NAME);
+            let value = arena.create_fixnum(utc_offset).into();
+            instance_variables.push((key, value));
+        }
+        if let Some(zone) = self.zone {
+            let key = arena.create_symbol_static(ZONE_NAME);
+            let value = arena.create_string(zone).into();
+            instance_variables.push((key, value));
+        }
+
+        let name = arena.create_symbol_static(TIME_NAME);
+        let handle = arena.create_user_defined(name, payload.into());
+        if !instance_variables.is_empty() {
+            if let Some(Value::UserDefined(value)) = arena.get_mut(handle) {
+                value.set_instance_variables(Some(instance_variables));
+            }
+        }
+
+        Ok(handle.into())
+    }
+}
+
+/// Get the number of days since the Unix epoch for a date in the proleptic Gregorian calendar.
+///
+/// The month is 1-based.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Get the date in the proleptic Gregorian calendar for a number of days since the Unix epoch.
+///
+/// The month is 1-based.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = u32::try_from(day_of_year - (153 * mp + 2) / 5 + 1).unwrap();
+    let month = u32::try_from(if mp < 10 { mp + 3 } else { mp - 9 }).unwrap();
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
@@ -0,0 +1,108 @@
+use super::DisplayByteStringTruncated;
+use crate::FromValueContext;
+use crate::FromValueError;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::Any;
+use hashbrown::HashMap;
+
+/// A type-erased user defined value decoder.
+type Decoder = Box<dyn Fn(&[u8], &FromValueContext<'_>) -> Result<Box<dyn Any>, FromValueError>>;
+
+/// An error that may occur while decoding a user defined value with a [`UserDefinedRegistry`].
+#[derive(Debug)]
+pub enum UserDefinedRegistryError {
+    /// The context does not have a registry.
+    MissingRegistry,
+
+    /// The registered decoder produced a different type than the one requested.
+    TypeMismatch {
+        /// The user defined name.
+        ///
+        /// This may or may not be UTF-8.
+        name: Vec<u8>,
+    },
+}
+
+impl core::fmt::Display for UserDefinedRegistryError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::MissingRegistry => write!(f, "the context does not have a registry"),
+            Self::TypeMismatch { name } => write!(
+                f,
+                "the decoder for \"{}\" produced an unexpected type",
+                DisplayByteStringTruncated::new(name)
+            ),
+        }
+    }
+}
+
+impl core::error::Error for UserDefinedRegistryError {}
+
+/// A table of decoders for user defined values, keyed by name.
+///
+/// This allows decoding user defined payloads without this library needing to know every format.
+#[derive(Default)]
+pub struct UserDefinedRegistry {
+    decoders: HashMap<Vec<u8>, Decoder>,
+}
+
+impl UserDefinedRegistry {
+    /// Make a new empty [`UserDefinedRegistry`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a decoder for user defined values with the given name.
+    ///
+    /// # Returns
+    /// Returns true if a decoder for this name was replaced.
+    pub fn register<T, F>(&mut self, name: Vec<u8>, decoder: F) -> bool
+    where
+        T: 'static,
+        F: Fn(&[u8], &FromValueContext<'_>) -> Result<T, FromValueError> + 'static,
+    {
+        let decoder: Decoder = Box::new(move |value, ctx| {
+            let value = decoder(value, ctx)?;
+            Ok(Box::new(value))
+        });
+
+        self.decoders.insert(name, decoder).is_some()
+    }
+
+    /// Check if a decoder is registered for the given name.
+    pub fn contains(&self, name: &[u8]) -> bool {
+        self.decoders.contains_key(name)
+    }
+
+    /// Decode a user defined payload with the decoder registered for the given name.
+    pub(crate) fn decode<T>(
+        &self,
+        ctx: &FromValueContext<'_>,
+        name: &[u8],
+        value: &[u8],
+    ) -> Result<T, FromValueError>
+    where
+        T: 'static,
+    {
+        let decoder = self
+            .decoders
+            .get(name)
+            .ok_or_else(|| FromValueError::UnexpectedUserDefinedName { name: name.into() })?;
+
+        let value = decoder(value, ctx)?;
+        let value = value.downcast::<T>().map_err(|_| {
+            FromValueError::new_other(UserDefinedRegistryError::TypeMismatch { name: name.into() })
+        })?;
+
+        Ok(*value)
+    }
+}
+
+impl core::fmt::Debug for UserDefinedRegistry {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("UserDefinedRegistry")
+            .field("names", &self.decoders.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
@@ -1,4 +1,6 @@
+use crate::io::Write;
 use crate::Error;
+use crate::IntoValue;
 use crate::SymbolValue;
 use crate::TypedValueHandle;
 use crate::Value;
@@ -7,44 +9,211 @@ use crate::ValueHandle;
 use crate::MAJOR_VERSION;
 use crate::MINOR_VERSION;
 use crate::VALUE_KIND_ARRAY;
+use crate::VALUE_KIND_BIGNUM;
+use crate::VALUE_KIND_CLASS;
 use crate::VALUE_KIND_FALSE;
 use crate::VALUE_KIND_FIXNUM;
+use crate::VALUE_KIND_FLOAT;
 use crate::VALUE_KIND_HASH;
 use crate::VALUE_KIND_HASH_DEFAULT;
 use crate::VALUE_KIND_INSTANCE_VARIABLES;
+use crate::VALUE_KIND_MODULE;
 use crate::VALUE_KIND_NIL;
 use crate::VALUE_KIND_OBJECT;
 use crate::VALUE_KIND_OBJECT_LINK;
+use crate::VALUE_KIND_OLD_MODULE;
+use crate::VALUE_KIND_REGEXP;
 use crate::VALUE_KIND_STRING;
+use crate::VALUE_KIND_STRUCT;
 use crate::VALUE_KIND_SYMBOL;
 use crate::VALUE_KIND_SYMBOL_LINK;
 use crate::VALUE_KIND_TRUE;
 use crate::VALUE_KIND_USER_DEFINED;
+use crate::VALUE_KIND_USER_MARSHAL;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use hashbrown::DefaultHashBuilder;
+use indexmap::IndexMap;
 use indexmap::IndexSet;
-use std::io::Write;
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncWrite;
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncWriteExt;
+
+/// A writer that only counts the bytes written to it.
+struct LenCounter {
+    len: usize,
+}
+
+#[cfg(feature = "std")]
+impl Write for LenCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for LenCounter {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), crate::io::Error> {
+        self.len += buf.len();
+        Ok(())
+    }
+}
+
+/// A writer that fills a slice, and counts the bytes that did not fit.
+struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl SliceWriter<'_> {
+    /// Copy as much of the data as fits after the bytes already written.
+    fn write_slice(&mut self, buf: &[u8]) {
+        let start = self.len.min(self.buffer.len());
+        let end = self.len.saturating_add(buf.len()).min(self.buffer.len());
+        self.buffer[start..end].copy_from_slice(&buf[..end - start]);
+
+        self.len = self.len.saturating_add(buf.len());
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for SliceWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for SliceWriter<'_> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), crate::io::Error> {
+        self.write_slice(buf);
+        Ok(())
+    }
+}
+
+/// A composite value that is partially written.
+enum Frame<'a> {
+    /// An array, with the remaining elements.
+    Array {
+        items: core::slice::Iter<'a, ValueHandle>,
+    },
+
+    /// A hash, with the remaining pairs.
+    ///
+    /// If `value` is set, the key of the current pair was written.
+    Hash {
+        pairs: core::slice::Iter<'a, (ValueHandle, ValueHandle)>,
+        value: Option<ValueHandle>,
+        default_value: Option<ValueHandle>,
+    },
+
+    /// An object or struct.
+    ///
+    /// Struct members are written like instance variables,
+    /// and struct instance variables are written after them as `trailing_instance_variables`.
+    /// If `name` is not set, the name was written.
+    Object {
+        name: Option<TypedValueHandle<SymbolValue>>,
+        instance_variables: &'a [(TypedValueHandle<SymbolValue>, ValueHandle)],
+        trailing_instance_variables: Option<&'a [(TypedValueHandle<SymbolValue>, ValueHandle)]>,
+    },
+
+    /// A user defined value.
+    ///
+    /// If `name` is not set, the name was written.
+    UserDefined {
+        name: Option<TypedValueHandle<SymbolValue>>,
+        value: &'a [u8],
+        instance_variables: Option<&'a [(TypedValueHandle<SymbolValue>, ValueHandle)]>,
+    },
+
+    /// A user marshal value.
+    ///
+    /// If `name` is not set, the name was written.
+    /// If `value` is not set, the value was written.
+    UserMarshal {
+        name: Option<TypedValueHandle<SymbolValue>>,
+        value: Option<ValueHandle>,
+    },
+
+    /// Instance variables, with the remaining pairs.
+    ///
+    /// If `value` is set, the name of the current pair was written.
+    InstanceVariables {
+        instance_variables: core::slice::Iter<'a, (TypedValueHandle<SymbolValue>, ValueHandle)>,
+        value: Option<ValueHandle>,
+    },
+}
+
+/// Options for dumping.
+#[derive(Debug, Default, Clone)]
+pub struct DumperOptions {
+    /// Whether to write every value in full, instead of using object links.
+    ///
+    /// This is useful for readers that do not support object links.
+    /// Output may be larger, and values that were shared will be loaded as separate copies.
+    /// Values that contain themselves cannot be written without links,
+    /// so they fail with [`Error::CycleWithoutLinks`].
+    ///
+    /// Symbol links are still used.
+    pub disable_object_links: bool,
+}
+
+impl DumperOptions {
+    /// Make new [`DumperOptions`] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
 
 /// A dumper for ruby data
 pub struct Dumper<'a, W> {
     writer: W,
     arena: &'a ValueArena,
+    options: DumperOptions,
+    stack: Vec<Frame<'a>>,
 
-    symbol_links: IndexSet<TypedValueHandle<SymbolValue>>,
-    object_links: IndexSet<ValueHandle>,
+    symbol_links: IndexSet<TypedValueHandle<SymbolValue>, DefaultHashBuilder>,
+
+    /// Values that may be the target of an object link, by index.
+    ///
+    /// Values are added when they are started, before anything they hold, matching Ruby and the loader.
+    object_links: IndexSet<ValueHandle, DefaultHashBuilder>,
+
+    /// The values that are partially written, mapped to the stack length when they were started.
+    ///
+    /// This is only used if object links are disabled, to detect cycles.
+    ancestors: IndexMap<ValueHandle, usize, DefaultHashBuilder>,
 }
 
 impl<'a, W> Dumper<'a, W> {
     /// Create a new [`Dumper`] from a writer and entry arena.
-    fn new(writer: W, arena: &'a ValueArena) -> Self {
+    fn new(writer: W, arena: &'a ValueArena, options: DumperOptions) -> Self {
         Self {
             writer,
             arena,
-            symbol_links: IndexSet::new(),
-            object_links: IndexSet::new(),
+            options,
+            stack: Vec::new(),
+            symbol_links: IndexSet::default(),
+            object_links: IndexSet::default(),
+            ancestors: IndexMap::default(),
         }
     }
 }
 
-impl<W> Dumper<'_, W>
+impl<'a, W> Dumper<'a, W>
 where
     W: Write,
 {
@@ -56,7 +225,7 @@ where
 
     /// Write a byte
     fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
-        self.writer.write_all(std::slice::from_ref(&byte))?;
+        self.writer.write_all(core::slice::from_ref(&byte))?;
         Ok(())
     }
 
@@ -80,6 +249,7 @@ where
             return Ok(());
         }
 
+        // The unwraps below cannot fail, as each value is checked or masked to fit in a byte.
         if value > 0 && value < 123 {
             let value = u8::try_from(value).unwrap();
             self.writer.write_all(&[value + 5])?;
@@ -92,9 +262,9 @@ where
             return Ok(());
         }
 
-        let mut buffer = [0; std::mem::size_of::<i32>() + 1];
+        let mut buffer = [0; core::mem::size_of::<i32>() + 1];
         let mut buffer_size = 0;
-        for i in 1..(std::mem::size_of::<i32>() + 1) {
+        for i in 1..(core::mem::size_of::<i32>() + 1) {
             buffer[i] = u8::try_from(value & 0xFF).unwrap();
             buffer_size = i + 1;
 
@@ -113,12 +283,58 @@ where
         Ok(())
     }
 
+    /// Write a Fixnum with the given header byte.
+    ///
+    /// If the header cannot encode the value, the shortest form is written instead.
+    fn write_fixnum_with_header(&mut self, value: i32, header: u8) -> Result<(), Error> {
+        let header_i8 = header as i8;
+        let (fits, len) = match header_i8 {
+            0 => (value == 0, 0),
+            len @ 1..=4 => (len == 4 || (0..(1 << (8 * len))).contains(&value), len),
+            len @ -4..=-1 => (len == -4 || (-(1 << (8 * -len))..0).contains(&value), -len),
+            len if len > 4 => (i32::from(len) - 5 == value, 0),
+            len => (i32::from(len) + 5 == value, 0),
+        };
+
+        if !fits {
+            return self.write_fixnum(value);
+        }
+
+        // The length is in [0, 4].
+        let len = usize::try_from(len).unwrap();
+        self.write_byte(header)?;
+        self.writer.write_all(&value.to_le_bytes()[..len])?;
+
+        Ok(())
+    }
+
     /// Try to write a value object reference, if possible.
     /// If not successful, this entry is recorded and will be used for future resolutions.
     ///
+    /// If object links are disabled, this never writes a link,
+    /// and instead fails if the value is one of its own ancestors.
+    ///
     /// # Returns
     /// Returns true if successful.
     fn try_write_value_object_link(&mut self, handle: ValueHandle) -> Result<bool, Error> {
+        if self.options.disable_object_links {
+            // Values with frames at or above the current stack length are finished.
+            while self
+                .ancestors
+                .last()
+                .is_some_and(|(_handle, stack_len)| *stack_len >= self.stack.len())
+            {
+                self.ancestors.pop();
+            }
+
+            if self.ancestors.contains_key(&handle) {
+                return Err(Error::CycleWithoutLinks { handle });
+            }
+            self.ancestors.insert(handle, self.stack.len());
+
+            return Ok(false);
+        }
+
         let (index, inserted) = self.object_links.insert_full(handle);
         if !inserted {
             self.write_value_object_link(index)?;
@@ -132,7 +348,7 @@ where
     fn write_value_symbol_like(
         &mut self,
         handle: TypedValueHandle<SymbolValue>,
-        value: &SymbolValue,
+        value: &'a SymbolValue,
     ) -> Result<(), Error> {
         match self.symbol_links.get_index_of(&handle) {
             Some(index) => {
@@ -145,8 +361,20 @@ where
             None => {
                 self.symbol_links.insert(handle);
 
-                self.write_byte(VALUE_KIND_SYMBOL)?;
-                self.write_byte_string(value.value())?;
+                match value.instance_variables() {
+                    Some(instance_variables) => {
+                        self.write_byte(VALUE_KIND_INSTANCE_VARIABLES)?;
+
+                        self.write_byte(VALUE_KIND_SYMBOL)?;
+                        self.write_byte_string(value.value())?;
+
+                        self.start_instance_variables(instance_variables)?;
+                    }
+                    None => {
+                        self.write_byte(VALUE_KIND_SYMBOL)?;
+                        self.write_byte_string(value.value())?;
+                    }
+                }
             }
         }
 
@@ -163,25 +391,31 @@ where
         Ok(())
     }
 
-    /// Write instance variables
-    fn write_instance_variables(
+    /// Start writing instance variables.
+    ///
+    /// This writes the number of instance variables,
+    /// then pushes a frame to write the pairs.
+    fn start_instance_variables(
         &mut self,
-        instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+        instance_variables: &'a [(TypedValueHandle<SymbolValue>, ValueHandle)],
     ) -> Result<(), Error> {
         let num_vars = i32::try_from(instance_variables.len())
             .map_err(|error| Error::USizeInvalidFixnum { error })?;
         self.write_fixnum(num_vars)?;
 
-        for (name, value) in instance_variables.iter() {
-            self.write_value((*name).into())?;
-            self.write_value(*value)?;
-        }
+        self.stack.push(Frame::InstanceVariables {
+            instance_variables: instance_variables.iter(),
+            value: None,
+        });
 
         Ok(())
     }
 
-    /// Write a value
-    fn write_value(&mut self, handle: ValueHandle) -> Result<(), Error> {
+    /// Start writing a value.
+    ///
+    /// Simple values are written entirely.
+    /// Composite values are partially written, and push a frame to write the rest.
+    fn start_value(&mut self, handle: ValueHandle) -> Result<(), Error> {
         let value = self
             .arena
             .get(handle)
@@ -198,7 +432,10 @@ where
             }
             Value::Fixnum(value) => {
                 self.write_byte(VALUE_KIND_FIXNUM)?;
-                self.write_fixnum(value.value())?;
+                match value.header() {
+                    Some(header) => self.write_fixnum_with_header(value.value(), header)?,
+                    None => self.write_fixnum(value.value())?,
+                }
             }
             Value::Symbol(value) => {
                 let handle = TypedValueHandle::new_unchecked(handle);
@@ -214,9 +451,9 @@ where
 
                 self.write_byte(VALUE_KIND_ARRAY)?;
                 self.write_fixnum(len)?;
-                for value in value.value().iter() {
-                    self.write_value(*value)?;
-                }
+                self.stack.push(Frame::Array {
+                    items: value.value().iter(),
+                });
             }
             Value::Hash(value) => {
                 if self.try_write_value_object_link(handle)? {
@@ -236,14 +473,11 @@ where
                     .map_err(|error| Error::USizeInvalidFixnum { error })?;
                 self.write_fixnum(num_vars)?;
 
-                for (key, value) in value.iter() {
-                    self.write_value(*key)?;
-                    self.write_value(*value)?;
-                }
-
-                if let Some(default_value) = default_value {
-                    self.write_value(default_value)?;
-                }
+                self.stack.push(Frame::Hash {
+                    pairs: value.iter(),
+                    value: None,
+                    default_value,
+                });
             }
             Value::Object(value) => {
                 if self.try_write_value_object_link(handle)? {
@@ -251,8 +485,11 @@ where
                 }
 
                 self.write_byte(VALUE_KIND_OBJECT)?;
-                self.write_value(value.name().into())?;
-                self.write_instance_variables(value.instance_variables())?;
+                self.stack.push(Frame::Object {
+                    name: Some(value.name()),
+                    instance_variables: value.instance_variables(),
+                    trailing_instance_variables: None,
+                });
             }
             Value::String(value) => {
                 if self.try_write_value_object_link(handle)? {
@@ -266,7 +503,7 @@ where
                         self.write_byte(VALUE_KIND_STRING)?;
                         self.write_byte_string(value.value())?;
 
-                        self.write_instance_variables(instance_variables)?;
+                        self.start_instance_variables(instance_variables)?;
                     }
                     None => {
                         self.write_byte(VALUE_KIND_STRING)?;
@@ -279,20 +516,240 @@ where
                     return Ok(());
                 }
 
-                match value.instance_variables() {
-                    Some(instance_variables) => {
-                        self.write_byte(VALUE_KIND_INSTANCE_VARIABLES)?;
+                let instance_variables = value.instance_variables();
+                if instance_variables.is_some() {
+                    self.write_byte(VALUE_KIND_INSTANCE_VARIABLES)?;
+                }
 
-                        self.write_byte(VALUE_KIND_USER_DEFINED)?;
-                        self.write_value(value.name().into())?;
-                        self.write_byte_string(value.value())?;
+                self.write_byte(VALUE_KIND_USER_DEFINED)?;
+                self.stack.push(Frame::UserDefined {
+                    name: Some(value.name()),
+                    value: value.value(),
+                    instance_variables,
+                });
+            }
+            Value::OldModule(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                self.write_byte(VALUE_KIND_OLD_MODULE)?;
+                self.write_byte_string(value.name())?;
+            }
+            Value::Float(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                self.write_byte(VALUE_KIND_FLOAT)?;
+                self.write_byte_string(format_float(value.value()).as_bytes())?;
+            }
+            Value::Bignum(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                let sign = if value.is_negative() { b'-' } else { b'+' };
+                // The magnitude is always padded to a whole number of 16 bit words.
+                let bytes = value.value();
+                let len = i32::try_from(bytes.len() / 2)
+                    .map_err(|error| Error::USizeInvalidFixnum { error })?;
+
+                self.write_byte(VALUE_KIND_BIGNUM)?;
+                self.write_byte(sign)?;
+                self.write_fixnum(len)?;
+                self.writer.write_all(bytes)?;
+            }
+            Value::Regexp(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                let instance_variables = value.instance_variables();
+                if instance_variables.is_some() {
+                    self.write_byte(VALUE_KIND_INSTANCE_VARIABLES)?;
+                }
 
-                        self.write_instance_variables(instance_variables)?;
+                self.write_byte(VALUE_KIND_REGEXP)?;
+                self.write_byte_string(value.source())?;
+                self.write_byte(value.options())?;
+
+                if let Some(instance_variables) = instance_variables {
+                    self.start_instance_variables(instance_variables)?;
+                }
+            }
+            Value::Struct(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                let instance_variables = value.instance_variables();
+                if instance_variables.is_some() {
+                    self.write_byte(VALUE_KIND_INSTANCE_VARIABLES)?;
+                }
+
+                self.write_byte(VALUE_KIND_STRUCT)?;
+                self.stack.push(Frame::Object {
+                    name: Some(value.name()),
+                    instance_variables: value.members(),
+                    trailing_instance_variables: instance_variables,
+                });
+            }
+            Value::Class(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                self.write_byte(VALUE_KIND_CLASS)?;
+                self.write_byte_string(value.name())?;
+            }
+            Value::Module(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                self.write_byte(VALUE_KIND_MODULE)?;
+                self.write_byte_string(value.name())?;
+            }
+            Value::UserMarshal(value) => {
+                if self.try_write_value_object_link(handle)? {
+                    return Ok(());
+                }
+
+                self.write_byte(VALUE_KIND_USER_MARSHAL)?;
+                self.stack.push(Frame::UserMarshal {
+                    name: Some(value.name()),
+                    value: Some(value.value()),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a value, and everything it refers to.
+    ///
+    /// This does not recurse.
+    /// Instead, partially written values are tracked with an explicit stack.
+    fn write_value(&mut self, handle: ValueHandle) -> Result<(), Error> {
+        let stack_len = self.stack.len();
+        self.start_value(handle)?;
+
+        while self.stack.len() > stack_len {
+            // The stack is not empty, as it is longer than `stack_len`.
+            let frame = self.stack.pop().unwrap();
+            match frame {
+                Frame::Array { mut items } => {
+                    if let Some(item) = items.next() {
+                        self.stack.push(Frame::Array { items });
+                        self.start_value(*item)?;
+                    }
+                }
+                Frame::Hash {
+                    mut pairs,
+                    value,
+                    default_value,
+                } => {
+                    if let Some(value) = value {
+                        self.stack.push(Frame::Hash {
+                            pairs,
+                            value: None,
+                            default_value,
+                        });
+                        self.start_value(value)?;
+                    } else if let Some((key, value)) = pairs.next() {
+                        self.stack.push(Frame::Hash {
+                            pairs,
+                            value: Some(*value),
+                            default_value,
+                        });
+                        self.start_value(*key)?;
+                    } else if let Some(default_value) = default_value {
+                        // Keep the frame until the default value is written,
+                        // so the hash is still treated as partially written.
+                        self.stack.push(Frame::Hash {
+                            pairs,
+                            value: None,
+                            default_value: None,
+                        });
+                        self.start_value(default_value)?;
+                    }
+                }
+                Frame::Object {
+                    name,
+                    instance_variables,
+                    trailing_instance_variables,
+                } => match name {
+                    Some(name) => {
+                        self.stack.push(Frame::Object {
+                            name: None,
+                            instance_variables,
+                            trailing_instance_variables,
+                        });
+                        self.start_value(name.into())?;
                     }
                     None => {
-                        self.write_byte(VALUE_KIND_USER_DEFINED)?;
-                        self.write_value(value.name().into())?;
-                        self.write_byte_string(value.value())?;
+                        // Trailing instance variables are written once the current ones are done.
+                        if let Some(trailing_instance_variables) = trailing_instance_variables {
+                            self.stack.push(Frame::Object {
+                                name: None,
+                                instance_variables: trailing_instance_variables,
+                                trailing_instance_variables: None,
+                            });
+                        }
+                        self.start_instance_variables(instance_variables)?;
+                    }
+                },
+                Frame::UserDefined {
+                    name,
+                    value,
+                    instance_variables,
+                } => match name {
+                    Some(name) => {
+                        self.stack.push(Frame::UserDefined {
+                            name: None,
+                            value,
+                            instance_variables,
+                        });
+                        self.start_value(name.into())?;
+                    }
+                    None => {
+                        self.write_byte_string(value)?;
+                        if let Some(instance_variables) = instance_variables {
+                            self.start_instance_variables(instance_variables)?;
+                        }
+                    }
+                },
+                Frame::UserMarshal { name, value } => {
+                    if let Some(name) = name {
+                        self.stack.push(Frame::UserMarshal { name: None, value });
+                        self.start_value(name.into())?;
+                    } else if let Some(value) = value {
+                        // Keep the frame until the value is written,
+                        // so the user marshal value is still treated as partially written.
+                        self.stack.push(Frame::UserMarshal {
+                            name: None,
+                            value: None,
+                        });
+                        self.start_value(value)?;
+                    }
+                }
+                Frame::InstanceVariables {
+                    mut instance_variables,
+                    value,
+                } => {
+                    if let Some(value) = value {
+                        self.stack.push(Frame::InstanceVariables {
+                            instance_variables,
+                            value: None,
+                        });
+                        self.start_value(value)?;
+                    } else if let Some((name, value)) = instance_variables.next() {
+                        self.stack.push(Frame::InstanceVariables {
+                            instance_variables,
+                            value: Some(*value),
+                        });
+                        self.start_value((*name).into())?;
                     }
                 }
             }
@@ -302,9 +759,115 @@ where
     }
 
     /// Dump the root node to the writer.
-    fn dump(&mut self) -> Result<(), Error> {
+    fn dump(&mut self, handle: ValueHandle) -> Result<(), Error> {
         self.write_header()?;
-        self.write_value(self.arena.root())?;
+        self.write_value(handle)?;
+
+        Ok(())
+    }
+}
+
+/// Format a float the way Ruby does.
+///
+/// Ruby writes the shortest digits that round trip,
+/// using an exponent only if the number is very large or small.
+/// This includes subnormals, and the sign of negative zero.
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".into();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.into();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.into();
+    }
+
+    // Rust also picks the shortest digits that round trip.
+    let formatted = format!("{:e}", value.abs());
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("float should have an exponent");
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    // An f64 has at most 17 significant digits.
+    let num_digits = i32::try_from(digits.len()).unwrap();
+    let exponent: i32 = exponent.parse().expect("exponent should be an integer");
+
+    // The number of digits before the decimal point.
+    let point = exponent + 1;
+
+    let mut output = String::new();
+    if value.is_sign_negative() {
+        output.push('-');
+    }
+    if point < -3 || point > num_digits {
+        let (first, rest) = digits.split_at(1);
+        output.push_str(first);
+        if !rest.is_empty() {
+            output.push('.');
+            output.push_str(rest);
+        }
+        output.push_str(&format!("e{exponent}"));
+    } else if point > 0 {
+        // The point is in [1, num_digits] here.
+        let (before, after) = digits.split_at(usize::try_from(point).unwrap());
+        output.push_str(before);
+        if !after.is_empty() {
+            output.push('.');
+            output.push_str(after);
+        }
+    } else {
+        output.push_str("0.");
+        for _ in 0..-point {
+            output.push('0');
+        }
+        output.push_str(&digits);
+    }
+
+    output
+}
+
+/// A dumper that writes several values from one arena, one after another.
+///
+/// Symbol and object links are shared between values,
+/// so a value may link to symbols and objects written by an earlier value instead of writing them again.
+/// To share a vocabulary of symbols, dump an array of them first.
+///
+/// This is not standard Marshal data.
+/// Each value has a header, but only the first value can be loaded on its own.
+/// The rest must be loaded in order by a [`StatefulLoader`](crate::StatefulLoader).
+pub struct StatefulDumper<'a, W> {
+    dumper: Dumper<'a, W>,
+}
+
+impl<'a, W> StatefulDumper<'a, W> {
+    /// Make a new [`StatefulDumper`] from a writer and an arena.
+    pub fn new(writer: W, arena: &'a ValueArena, options: DumperOptions) -> Self {
+        Self {
+            dumper: Dumper::new(writer, arena, options),
+        }
+    }
+
+    /// Get the writer.
+    pub fn into_inner(self) -> W {
+        self.dumper.writer
+    }
+}
+
+impl<W> StatefulDumper<'_, W>
+where
+    W: Write,
+{
+    /// Dump a value, with a header.
+    ///
+    /// If this fails, the links of later values may be wrong, so the dumper should not be used again.
+    pub fn dump<H>(&mut self, handle: H) -> Result<(), Error>
+    where
+        H: Into<ValueHandle>,
+    {
+        self.dumper.stack.clear();
+        self.dumper.write_header()?;
+        self.dumper.write_value(handle.into())?;
 
         Ok(())
     }
@@ -315,7 +878,119 @@ pub fn dump<W>(writer: W, value_arena: &ValueArena) -> Result<(), Error>
 where
     W: Write,
 {
-    let mut dumper = Dumper::new(writer, value_arena);
-    dumper.dump()?;
+    dump_with_options(writer, value_arena, DumperOptions::default())
+}
+
+/// Dump to a writer, with options.
+pub fn dump_with_options<W>(
+    writer: W,
+    value_arena: &ValueArena,
+    options: DumperOptions,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut dumper = Dumper::new(writer, value_arena, options);
+    dumper.dump(value_arena.root())?;
+    Ok(())
+}
+
+/// Dump the value at the given handle to a writer, instead of the root.
+///
+/// Only the value and the values it holds are written, with their own symbol and object links,
+/// so the output can be loaded on its own.
+pub fn dump_from<W, H>(writer: W, value_arena: &ValueArena, handle: H) -> Result<(), Error>
+where
+    W: Write,
+    H: Into<ValueHandle>,
+{
+    let mut dumper = Dumper::new(writer, value_arena, DumperOptions::default());
+    dumper.dump(handle.into())?;
+    Ok(())
+}
+
+/// Dump into a slice, without allocating for the output.
+///
+/// The buffer can be sized exactly with [`ValueArena::serialized_len`].
+///
+/// # Returns
+/// Returns the number of bytes written, from the start of the buffer.
+///
+/// # Errors
+/// Returns [`Error::BufferTooSmall`] if the output does not fit.
+/// The buffer is still filled with as much of the output as fits.
+pub fn dump_into_slice(buffer: &mut [u8], value_arena: &ValueArena) -> Result<usize, Error> {
+    let mut writer = SliceWriter { buffer, len: 0 };
+    dump(&mut writer, value_arena)?;
+
+    if writer.len > writer.buffer.len() {
+        return Err(Error::BufferTooSmall {
+            len: writer.buffer.len(),
+            needed: writer.len,
+        });
+    }
+
+    Ok(writer.len)
+}
+
+/// Get the number of bytes that dumping would produce, without writing them.
+pub(crate) fn serialized_len(value_arena: &ValueArena) -> Result<usize, Error> {
+    let mut counter = LenCounter { len: 0 };
+    dump(&mut counter, value_arena)?;
+    Ok(counter.len)
+}
+
+/// Get the number of bytes that dumping the value at the given handle would produce, without the header.
+pub(crate) fn value_serialized_len(
+    value_arena: &ValueArena,
+    handle: ValueHandle,
+) -> Result<usize, Error> {
+    let mut counter = LenCounter { len: 0 };
+    let mut dumper = Dumper::new(&mut counter, value_arena, DumperOptions::default());
+    dumper.write_value(handle)?;
+    Ok(counter.len)
+}
+
+/// Convert a value into Ruby values, then dump it to bytes.
+pub fn to_bytes<T>(value: T) -> Result<Vec<u8>, Error>
+where
+    T: IntoValue,
+{
+    let value_arena = ValueArena::from_value(value)?;
+
+    let mut data = Vec::new();
+    dump(&mut data, &value_arena)?;
+
+    Ok(data)
+}
+
+/// Dump to an async writer.
+///
+/// This does not stream the output.
+/// The output is built in memory, then written all at once.
+#[cfg(feature = "tokio")]
+pub async fn dump_async<W>(writer: W, value_arena: &ValueArena) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    dump_async_with_options(writer, value_arena, DumperOptions::default()).await
+}
+
+/// Dump to an async writer, with options.
+///
+/// The output is built in memory, then written all at once.
+#[cfg(feature = "tokio")]
+pub async fn dump_async_with_options<W>(
+    mut writer: W,
+    value_arena: &ValueArena,
+    options: DumperOptions,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = Vec::new();
+    dump_with_options(&mut buffer, value_arena, options)?;
+    writer.write_all(&buffer).await?;
+
     Ok(())
 }
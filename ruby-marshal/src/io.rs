@@ -0,0 +1,112 @@
+//! The I/O traits used for loading and dumping.
+//!
+//! With the `std` feature, these are the traits from `std::io`.
+//! Without it, minimal replacements are provided instead.
+
+#[cfg(feature = "std")]
+pub use std::io::Error;
+#[cfg(feature = "std")]
+pub use std::io::Read;
+#[cfg(feature = "std")]
+pub use std::io::Write;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// An I/O error.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The reader ran out of data.
+    UnexpectedEof,
+
+    /// The writer could not accept all of the data.
+    WriteZero,
+
+    /// Another error from a user-provided reader or writer.
+    Other,
+}
+
+#[cfg(not(feature = "std"))]
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of file"),
+            Self::WriteZero => write!(f, "failed to write all data"),
+            Self::Other => write!(f, "other error"),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl core::error::Error for Error {}
+
+/// Check if an error is from a reader running out of data.
+#[cfg(feature = "std")]
+pub(crate) fn is_unexpected_eof(error: &Error) -> bool {
+    error.kind() == std::io::ErrorKind::UnexpectedEof
+}
+
+/// Check if an error is from a reader running out of data.
+#[cfg(not(feature = "std"))]
+pub(crate) fn is_unexpected_eof(error: &Error) -> bool {
+    *error == Error::UnexpectedEof
+}
+
+/// A source of bytes.
+#[cfg(not(feature = "std"))]
+pub trait Read {
+    /// Read exactly enough bytes to fill the buffer.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+}
+
+#[cfg(not(feature = "std"))]
+impl Read for &[u8] {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.len() > self.len() {
+            *self = &self[self.len()..];
+            return Err(Error::UnexpectedEof);
+        }
+
+        let (head, tail) = self.split_at(buf.len());
+        buf.copy_from_slice(head);
+        *self = tail;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<R> Read for &mut R
+where
+    R: Read + ?Sized,
+{
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        (**self).read_exact(buf)
+    }
+}
+
+/// A sink for bytes.
+#[cfg(not(feature = "std"))]
+pub trait Write {
+    /// Write all bytes in the buffer.
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+#[cfg(not(feature = "std"))]
+impl Write for Vec<u8> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<W> Write for &mut W
+where
+    W: Write + ?Sized,
+{
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        (**self).write_all(buf)
+    }
+}
@@ -0,0 +1,766 @@
+#[cfg(feature = "json")]
+use crate::Error;
+#[cfg(feature = "json")]
+use crate::SymbolValue;
+#[cfg(feature = "json")]
+use crate::TypedValueHandle;
+#[cfg(feature = "json")]
+use crate::Value;
+#[cfg(feature = "json")]
+use crate::ValueArena;
+#[cfg(feature = "json")]
+use crate::ValueHandle;
+use crate::ValueKind;
+#[cfg(feature = "json")]
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "json")]
+use alloc::string::ToString;
+#[cfg(feature = "json")]
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "json")]
+use base64::Engine;
+#[cfg(feature = "json")]
+use hashbrown::HashSet;
+
+/// How symbols are converted into JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum JsonSymbolMode {
+    /// Fail with [`JsonError::UnsupportedValue`].
+    #[default]
+    Error,
+
+    /// Convert into a JSON string of the symbol name.
+    ///
+    /// Names that are not valid UTF-8 fail with [`JsonError::InvalidUtf8Symbol`].
+    String,
+}
+
+/// How objects and user defined values are converted into JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum JsonObjectMode {
+    /// Fail with [`JsonError::UnsupportedValue`].
+    #[default]
+    Error,
+
+    /// Convert into a JSON object, with the class name in a `__class__` key.
+    ///
+    /// Instance variables become keys with their names, including the `@`.
+    /// The data of user defined values is base64 encoded, in a `__data__` key.
+    /// Names that are not valid UTF-8 fail with [`JsonError::InvalidUtf8Symbol`].
+    Tagged,
+}
+
+/// How hash keys are converted into JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum JsonKeyMode {
+    /// Convert keys like any other value.
+    ///
+    /// Hashes become JSON objects if every key converts into a unique JSON string,
+    /// and arrays of `[key, value]` pairs otherwise.
+    #[default]
+    Auto,
+
+    /// Convert symbol and string keys into JSON strings, so hashes always become JSON objects.
+    ///
+    /// Symbols become their names, whatever [`JsonSymbolMode`] is set to.
+    /// Strings become their contents if they are valid UTF-8, and are converted like other strings otherwise.
+    /// Other keys fail with [`JsonError::NonStringKey`],
+    /// and keys that become the same JSON string fail with [`JsonError::DuplicateKey`].
+    Normalize,
+
+    /// Like [`JsonKeyMode::Normalize`], but other keys become the JSON text they convert into.
+    ///
+    /// For example, the key `1` becomes `"1"`, and the key `[1, 2]` becomes `"[1,2]"`.
+    Stringify,
+}
+
+/// Options for converting into JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone)]
+pub struct JsonOptions {
+    /// How symbols are converted.
+    pub symbols: JsonSymbolMode,
+
+    /// How objects and user defined values are converted.
+    pub objects: JsonObjectMode,
+
+    /// How hash keys are converted.
+    pub keys: JsonKeyMode,
+
+    /// Whether to convert strings without an encoding into base64 JSON strings.
+    ///
+    /// By default, these fail with [`JsonError::BinaryString`].
+    pub binary_strings_to_base64: bool,
+
+    /// Arrays, hashes, and objects at or below this depth are replaced with a placeholder.
+    ///
+    /// The placeholder is an object like `{"__elided__": "Array(3)"}`,
+    /// with the number of items, pairs, or instance variables.
+    /// The root value is at depth 0.
+    pub max_depth: Option<usize>,
+
+    /// Array items past this index are replaced with a single marker.
+    ///
+    /// The marker is an object like `{"...": "3 more"}`.
+    pub max_array: Option<usize>,
+}
+
+#[cfg(feature = "json")]
+impl JsonOptions {
+    /// Make new [`JsonOptions`] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// An error that occurs while converting into JSON.
+#[derive(Debug)]
+pub enum JsonError {
+    /// This kind of value has no JSON equivalent.
+    UnsupportedValue { kind: ValueKind },
+
+    /// A string has no encoding, and binary strings are not converted to base64.
+    BinaryString,
+
+    /// A symbol name is not valid UTF-8.
+    InvalidUtf8Symbol {
+        /// The symbol name
+        name: Vec<u8>,
+    },
+
+    /// A float is infinite or NaN.
+    NonFiniteFloat { value: f64 },
+
+    /// A value contains itself.
+    Cycle,
+
+    /// A hash key is not a symbol or string, and keys must be strings.
+    NonStringKey { kind: ValueKind },
+
+    /// Two keys of a hash become the same JSON string.
+    DuplicateKey {
+        /// The key
+        key: String,
+    },
+}
+
+impl core::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnsupportedValue { kind } => write!(f, "cannot convert a {kind:?} into JSON"),
+            Self::BinaryString => write!(f, "cannot convert a binary String into JSON"),
+            Self::InvalidUtf8Symbol { name } => write!(
+                f,
+                "cannot convert the non-UTF-8 symbol \"{}\" into JSON",
+                crate::DisplayByteStringTruncated::new(name)
+            ),
+            Self::NonFiniteFloat { value } => {
+                write!(f, "cannot convert the float {value} into JSON")
+            }
+            Self::Cycle => write!(f, "cannot convert a value that contains itself into JSON"),
+            Self::NonStringKey { kind } => {
+                write!(f, "cannot convert a {kind:?} hash key into a JSON string")
+            }
+            Self::DuplicateKey { key } => {
+                write!(
+                    f,
+                    "more than one hash key converts into the JSON key \"{key}\""
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for JsonError {}
+
+/// Convert the value at the given handle into a [`serde_json::Value`].
+///
+/// Only values with a clear JSON equivalent are supported.
+/// Values that are shared are converted once for each place they are used.
+///
+/// Hash keys are converted as set by [`JsonOptions::keys`].
+/// Hashes with a default value fail with [`JsonError::UnsupportedValue`], as JSON has nowhere to put it.
+#[cfg(feature = "json")]
+pub fn to_json_value<H>(
+    arena: &ValueArena,
+    handle: H,
+    options: &JsonOptions,
+) -> Result<serde_json::Value, Error>
+where
+    H: Into<ValueHandle>,
+{
+    let mut converter = JsonConverter {
+        arena,
+        options,
+        containers: HashSet::new(),
+    };
+    converter.convert(handle.into(), 0)
+}
+
+/// The state of a conversion into JSON.
+#[cfg(feature = "json")]
+struct JsonConverter<'a> {
+    arena: &'a ValueArena,
+    options: &'a JsonOptions,
+
+    /// The containers that are currently being converted, used to detect cycles.
+    containers: HashSet<ValueHandle>,
+}
+
+#[cfg(feature = "json")]
+impl<'a> JsonConverter<'a> {
+    /// Convert a value at the given depth.
+    fn convert(&mut self, handle: ValueHandle, depth: usize) -> Result<serde_json::Value, Error> {
+        let value = self
+            .arena
+            .get(handle)
+            .ok_or(Error::InvalidValueHandle { handle })?;
+        match value {
+            Value::Nil(_) => Ok(serde_json::Value::Null),
+            Value::Bool(value) => Ok(serde_json::Value::Bool(value.value())),
+            Value::Fixnum(value) => Ok(serde_json::Value::Number(value.value().into())),
+            Value::Symbol(value) => match self.options.symbols {
+                JsonSymbolMode::Error => Err(JsonError::UnsupportedValue {
+                    kind: ValueKind::Symbol,
+                }
+                .into()),
+                JsonSymbolMode::String => {
+                    let name = core::str::from_utf8(value.value()).map_err(|_error| {
+                        JsonError::InvalidUtf8Symbol {
+                            name: value.value().into(),
+                        }
+                    })?;
+
+                    Ok(serde_json::Value::String(name.into()))
+                }
+            },
+            Value::Array(value) => {
+                let value = value.value();
+
+                if let Some(placeholder) = self.enter(handle, depth, "Array", value.len())? {
+                    return Ok(placeholder);
+                }
+
+                let len = self
+                    .options
+                    .max_array
+                    .map_or(value.len(), |max_array| value.len().min(max_array));
+                let mut array = Vec::with_capacity(len + 1);
+                for handle in &value[..len] {
+                    array.push(self.convert(*handle, depth + 1)?);
+                }
+
+                let remaining = value.len() - len;
+                if remaining > 0 {
+                    array.push(serde_json::json!({
+                        "...": format!("{remaining} more"),
+                    }));
+                }
+
+                self.containers.remove(&handle);
+
+                Ok(serde_json::Value::Array(array))
+            }
+            Value::Hash(value) => {
+                if value.default_value().is_some() {
+                    return Err(JsonError::UnsupportedValue {
+                        kind: ValueKind::Hash,
+                    }
+                    .into());
+                }
+
+                let value = value.value();
+
+                if let Some(placeholder) = self.enter(handle, depth, "Hash", value.len())? {
+                    return Ok(placeholder);
+                }
+
+                if self.options.keys != JsonKeyMode::Auto {
+                    let mut object = serde_json::Map::new();
+                    for (key, value) in value {
+                        let key = self.string_key(*key, depth + 1)?;
+                        if object.contains_key(&key) {
+                            return Err(JsonError::DuplicateKey { key }.into());
+                        }
+                        let value = self.convert(*value, depth + 1)?;
+                        object.insert(key, value);
+                    }
+
+                    self.containers.remove(&handle);
+
+                    return Ok(serde_json::Value::Object(object));
+                }
+
+                let mut pairs = Vec::with_capacity(value.len());
+                for (key, value) in value {
+                    let key = self.convert(*key, depth + 1)?;
+                    let value = self.convert(*value, depth + 1)?;
+                    pairs.push((key, value));
+                }
+
+                self.containers.remove(&handle);
+
+                let is_object = {
+                    let mut keys = HashSet::new();
+                    pairs.iter().all(|(key, _value)| {
+                        matches!(key, serde_json::Value::String(key) if keys.insert(key.as_str()))
+                    })
+                };
+                if is_object {
+                    let object = pairs
+                        .into_iter()
+                        .filter_map(|(key, value)| match key {
+                            serde_json::Value::String(key) => Some((key, value)),
+                            _ => None,
+                        })
+                        .collect();
+
+                    return Ok(serde_json::Value::Object(object));
+                }
+
+                Ok(serde_json::Value::Array(
+                    pairs
+                        .into_iter()
+                        .map(|(key, value)| serde_json::Value::Array(vec![key, value]))
+                        .collect(),
+                ))
+            }
+            Value::Object(value) if self.options.objects == JsonObjectMode::Tagged => {
+                let instance_variables = value.instance_variables();
+
+                if let Some(placeholder) =
+                    self.enter(handle, depth, "Object", instance_variables.len())?
+                {
+                    return Ok(placeholder);
+                }
+
+                let object = self.tagged_object(value.name(), instance_variables, depth)?;
+
+                self.containers.remove(&handle);
+
+                Ok(serde_json::Value::Object(object))
+            }
+            Value::UserDefined(value) if self.options.objects == JsonObjectMode::Tagged => {
+                let instance_variables = value.instance_variables().unwrap_or_default();
+
+                if let Some(placeholder) =
+                    self.enter(handle, depth, "UserDefined", instance_variables.len())?
+                {
+                    return Ok(placeholder);
+                }
+
+                let mut object = self.tagged_object(value.name(), instance_variables, depth)?;
+                object.insert(
+                    "__data__".into(),
+                    serde_json::Value::String(
+                        base64::engine::general_purpose::STANDARD.encode(value.value()),
+                    ),
+                );
+
+                self.containers.remove(&handle);
+
+                Ok(serde_json::Value::Object(object))
+            }
+            Value::String(value) => {
+                let instance_variables = value.instance_variables();
+                let has_encoding = instance_variables.is_some_and(|instance_variables| {
+                    instance_variables.iter().any(|(key, _value)| {
+                        self.arena
+                            .get_symbol(*key)
+                            .is_some_and(|key| key.value() == b"encoding" || key.value() == b"E")
+                    })
+                });
+
+                // TODO: Convert strings with a known encoding.
+                if has_encoding {
+                    return Err(JsonError::UnsupportedValue {
+                        kind: ValueKind::String,
+                    }
+                    .into());
+                }
+
+                if !self.options.binary_strings_to_base64 {
+                    return Err(JsonError::BinaryString.into());
+                }
+
+                Ok(serde_json::Value::String(
+                    base64::engine::general_purpose::STANDARD.encode(value.value()),
+                ))
+            }
+            Value::Float(value) => {
+                let value = value.value();
+                serde_json::Number::from_f64(value)
+                    .map(serde_json::Value::Number)
+                    .ok_or_else(|| JsonError::NonFiniteFloat { value }.into())
+            }
+            value => Err(JsonError::UnsupportedValue { kind: value.kind() }.into()),
+        }
+    }
+
+    /// Start converting a container at the given depth.
+    ///
+    /// # Returns
+    /// Returns a placeholder if the container is too deep, and `None` if it should be converted.
+    /// When `None` is returned, the handle must be removed from `containers` once the container is converted.
+    fn enter(
+        &mut self,
+        handle: ValueHandle,
+        depth: usize,
+        kind: &str,
+        len: usize,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            return Ok(Some(serde_json::json!({
+                "__elided__": format!("{kind}({len})"),
+            })));
+        }
+
+        if !self.containers.insert(handle) {
+            return Err(JsonError::Cycle.into());
+        }
+
+        Ok(None)
+    }
+
+    /// Convert a class name and instance variables into a tagged JSON object.
+    fn tagged_object(
+        &mut self,
+        name: TypedValueHandle<SymbolValue>,
+        instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+        depth: usize,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+        let mut object = serde_json::Map::new();
+        object.insert(
+            "__class__".into(),
+            serde_json::Value::String(self.symbol_name(name)?.into()),
+        );
+        for (key, value) in instance_variables {
+            let key = self.symbol_name(*key)?;
+            let value = self.convert(*value, depth + 1)?;
+            object.insert(key.into(), value);
+        }
+
+        Ok(object)
+    }
+
+    /// Convert a hash key into a JSON string, for [`JsonKeyMode::Normalize`] and [`JsonKeyMode::Stringify`].
+    fn string_key(&mut self, handle: ValueHandle, depth: usize) -> Result<String, Error> {
+        let value = self
+            .arena
+            .get(handle)
+            .ok_or(Error::InvalidValueHandle { handle })?;
+        match value {
+            Value::Symbol(value) => {
+                let name = core::str::from_utf8(value.value()).map_err(|_error| {
+                    JsonError::InvalidUtf8Symbol {
+                        name: value.value().into(),
+                    }
+                })?;
+
+                return Ok(name.into());
+            }
+            Value::String(value) => {
+                if let Ok(value) = core::str::from_utf8(value.value()) {
+                    return Ok(value.into());
+                }
+            }
+            value if self.options.keys != JsonKeyMode::Stringify => {
+                return Err(JsonError::NonStringKey { kind: value.kind() }.into());
+            }
+            _ => {}
+        }
+
+        match self.convert(handle, depth)? {
+            serde_json::Value::String(key) => Ok(key),
+            key => Ok(key.to_string()),
+        }
+    }
+
+    /// Get the name of a symbol as UTF-8.
+    fn symbol_name(&self, handle: TypedValueHandle<SymbolValue>) -> Result<&'a str, Error> {
+        let handle = handle.into_raw();
+        let value = self
+            .arena
+            .get(handle)
+            .and_then(Value::as_symbol)
+            .ok_or(Error::InvalidValueHandle { handle })?;
+
+        core::str::from_utf8(value.value()).map_err(|_error| {
+            JsonError::InvalidUtf8Symbol {
+                name: value.value().into(),
+            }
+            .into()
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn to_json_value() {
+        let mut arena = ValueArena::new();
+        let one = arena.create_fixnum(1).into();
+        let nil = arena.create_nil().into();
+        let float = arena.create_float(0.5).into();
+        let inner = arena.create_array(vec![one, nil]).into();
+        let root = arena.create_array(vec![inner, float, one]);
+
+        let value =
+            crate::to_json_value(&arena, root, &JsonOptions::new()).expect("failed to convert");
+        assert!(value == serde_json::json!([[1, null], 0.5, 1]), "{value}");
+
+        let options = JsonOptions {
+            max_depth: Some(1),
+            max_array: Some(2),
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, root, &options).expect("failed to convert");
+        assert!(
+            value
+                == serde_json::json!([
+                    {"__elided__": "Array(2)"},
+                    0.5,
+                    {"...": "1 more"},
+                ]),
+            "{value}"
+        );
+    }
+
+    #[test]
+    fn to_json_value_options() {
+        let mut arena = ValueArena::new();
+        let symbol = arena.create_symbol("symbol".into());
+        let error = crate::to_json_value(&arena, symbol, &JsonOptions::new())
+            .expect_err("symbol should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::UnsupportedValue {
+                        kind: ValueKind::Symbol
+                    }
+                }
+            ),
+            "{error:?}"
+        );
+
+        let options = JsonOptions {
+            symbols: JsonSymbolMode::String,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, symbol, &options).expect("failed to convert");
+        assert!(value == serde_json::json!("symbol"), "{value}");
+
+        let string = arena.create_string(b"\xFF\x00".into());
+        let error = crate::to_json_value(&arena, string, &JsonOptions::new())
+            .expect_err("binary string should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::BinaryString
+                }
+            ),
+            "{error:?}"
+        );
+
+        let options = JsonOptions {
+            binary_strings_to_base64: true,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, string, &options).expect("failed to convert");
+        assert!(value == serde_json::json!("/wA="), "{value}");
+    }
+
+    #[test]
+    fn to_json_value_hash() {
+        let mut arena = ValueArena::new();
+        let one = arena.create_fixnum(1).into();
+        let two = arena.create_fixnum(2).into();
+        let hash = arena.create_symbol_hash([(&b"b"[..], one), (b"a", two)]);
+        let array_key = arena.create_array(vec![one]).into();
+        let complex = arena.create_hash(vec![(array_key, two)], None);
+        let defaulted = arena.create_hash(Vec::new(), Some(one));
+
+        let options = JsonOptions {
+            symbols: JsonSymbolMode::String,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, hash, &options).expect("failed to convert");
+        assert!(value == serde_json::json!({"a": 2, "b": 1}), "{value}");
+
+        let value = crate::to_json_value(&arena, complex, &options).expect("failed to convert");
+        assert!(value == serde_json::json!([[[1], 2]]), "{value}");
+
+        let options = JsonOptions {
+            keys: JsonKeyMode::Stringify,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, complex, &options).expect("failed to convert");
+        assert!(value == serde_json::json!({"[1]": 2}), "{value}");
+
+        let options = JsonOptions {
+            symbols: JsonSymbolMode::String,
+            ..JsonOptions::new()
+        };
+        let error = crate::to_json_value(&arena, defaulted, &options)
+            .expect_err("hash with a default value should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::UnsupportedValue {
+                        kind: ValueKind::Hash
+                    }
+                }
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn to_json_value_normalized_keys() {
+        let mut arena = ValueArena::new();
+        let one = arena.create_fixnum(1).into();
+        let two = arena.create_fixnum(2).into();
+        let symbol_key = arena.create_symbol("a".into()).into();
+        let string_key = arena.create_utf8_string("b").into();
+        let same_string_key = arena.create_utf8_string("a").into();
+        let hash = arena.create_hash(vec![(symbol_key, one), (string_key, two)], None);
+        let duplicate = arena.create_hash(vec![(symbol_key, one), (same_string_key, two)], None);
+        let integer = arena.create_hash(vec![(one, two)], None);
+
+        // Symbol keys convert even though symbol values do not.
+        let options = JsonOptions {
+            keys: JsonKeyMode::Normalize,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, hash, &options).expect("failed to convert");
+        assert!(value == serde_json::json!({"a": 1, "b": 2}), "{value}");
+
+        let error = crate::to_json_value(&arena, duplicate, &options)
+            .expect_err("duplicate keys should not convert");
+        assert!(
+            matches!(
+                &error,
+                Error::Json {
+                    error: JsonError::DuplicateKey { key }
+                } if key == "a"
+            ),
+            "{error:?}"
+        );
+
+        let error = crate::to_json_value(&arena, integer, &options)
+            .expect_err("integer key should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::NonStringKey {
+                        kind: ValueKind::Fixnum
+                    }
+                }
+            ),
+            "{error:?}"
+        );
+
+        let options = JsonOptions {
+            keys: JsonKeyMode::Stringify,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, integer, &options).expect("failed to convert");
+        assert!(value == serde_json::json!({"1": 2}), "{value}");
+    }
+
+    #[test]
+    fn to_json_value_objects() {
+        let mut arena = ValueArena::new();
+        let name = arena.create_symbol("Point".into());
+        let x = arena.create_symbol("@x".into());
+        let one = arena.create_fixnum(1).into();
+        let object = arena.create_object(name, vec![(x, one)]);
+        let user_defined = arena.create_user_defined(name, b"\xFF\x00".into());
+
+        let error = crate::to_json_value(&arena, object, &JsonOptions::new())
+            .expect_err("object should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::UnsupportedValue {
+                        kind: ValueKind::Object
+                    }
+                }
+            ),
+            "{error:?}"
+        );
+
+        let options = JsonOptions {
+            objects: JsonObjectMode::Tagged,
+            ..JsonOptions::new()
+        };
+        let value = crate::to_json_value(&arena, object, &options).expect("failed to convert");
+        assert!(
+            value == serde_json::json!({"__class__": "Point", "@x": 1}),
+            "{value}"
+        );
+
+        let value =
+            crate::to_json_value(&arena, user_defined, &options).expect("failed to convert");
+        assert!(
+            value == serde_json::json!({"__class__": "Point", "__data__": "/wA="}),
+            "{value}"
+        );
+    }
+
+    #[test]
+    fn to_json_value_cycle() {
+        let mut arena = ValueArena::new();
+        let array = arena.create_array(Vec::new());
+        arena
+            .push_array_item(array, array.into())
+            .expect("failed to push");
+
+        let error = crate::to_json_value(&arena, array, &JsonOptions::new())
+            .expect_err("cycle should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::Cycle
+                }
+            ),
+            "{error:?}"
+        );
+
+        let hash = arena.create_hash(Vec::new(), None);
+        let one = arena.create_fixnum(1).into();
+        arena
+            .insert_hash_pair(hash, one, hash.into())
+            .expect("failed to insert");
+
+        let error = crate::to_json_value(&arena, hash, &JsonOptions::new())
+            .expect_err("cycle should not convert");
+        assert!(
+            matches!(
+                error,
+                Error::Json {
+                    error: JsonError::Cycle
+                }
+            ),
+            "{error:?}"
+        );
+    }
+}
@@ -1,32 +1,130 @@
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
 mod convert;
 mod dump;
+pub mod io;
+mod json;
 mod load;
 mod value_arena;
 
+pub use self::convert::into_value_iter;
 pub use self::convert::BTreeMapFromValueError;
+pub use self::convert::Binary;
+pub use self::convert::BinaryRef;
+pub use self::convert::Cached;
 pub use self::convert::DisplayByteString;
+pub use self::convert::DisplayByteStringTruncated;
+pub use self::convert::DurationFromValueError;
+pub use self::convert::DurationIntoValueError;
+pub use self::convert::FromInstanceVariables;
 pub use self::convert::FromValue;
 pub use self::convert::FromValueContext;
 pub use self::convert::FromValueError;
 pub use self::convert::HashMapFromValueError;
+pub use self::convert::IndexMapFromValueError;
+pub use self::convert::IntoInstanceVariables;
 pub use self::convert::IntoValue;
 pub use self::convert::IntoValueError;
+pub use self::convert::Millis;
+pub use self::convert::NilOrEmpty;
+pub use self::convert::OneOrMany;
+#[cfg(feature = "std")]
+pub use self::convert::PathIntoValueError;
+pub use self::convert::PathSegment;
+pub use self::convert::RubyBigDecimal;
+pub use self::convert::RubyBigDecimalFromValueError;
+pub use self::convert::RubyBigDecimalKind;
+pub use self::convert::RubyComplex;
+#[cfg(feature = "std")]
+pub use self::convert::RubyObjectMap;
+pub use self::convert::RubyRational;
+pub use self::convert::RubySet;
+pub use self::convert::RubyTime;
+pub use self::convert::RubyTimeFromValueError;
+pub use self::convert::RubyTimeIntoValueError;
+pub use self::convert::Seconds;
+pub use self::convert::UserDefinedRegistry;
+pub use self::convert::UserDefinedRegistryError;
 pub use self::dump::dump;
+#[cfg(feature = "tokio")]
+pub use self::dump::dump_async;
+#[cfg(feature = "tokio")]
+pub use self::dump::dump_async_with_options;
+pub use self::dump::dump_from;
+pub use self::dump::dump_into_slice;
+pub use self::dump::dump_with_options;
+pub use self::dump::to_bytes;
+pub use self::dump::DumperOptions;
+pub use self::dump::StatefulDumper;
+#[cfg(feature = "json")]
+pub use self::json::to_json_value;
+pub use self::json::JsonError;
+#[cfg(feature = "json")]
+pub use self::json::JsonKeyMode;
+#[cfg(feature = "json")]
+pub use self::json::JsonObjectMode;
+#[cfg(feature = "json")]
+pub use self::json::JsonOptions;
+#[cfg(feature = "json")]
+pub use self::json::JsonSymbolMode;
+pub use self::load::from_bytes;
 pub use self::load::load;
+#[cfg(feature = "tokio")]
+pub use self::load::load_async;
+#[cfg(feature = "tokio")]
+pub use self::load::load_async_with_options;
+pub use self::load::load_counted;
+#[cfg(feature = "std")]
+pub use self::load::load_hash;
+pub use self::load::load_with_options;
+pub use self::load::load_with_tokens;
+pub use self::load::FixnumUsage;
+pub use self::load::LoadWarning;
+pub use self::load::LoaderExtension;
+pub use self::load::LoaderOptions;
+pub use self::load::StatefulLoader;
+pub use self::load::Token;
+pub use self::load::TokenKind;
+pub use self::load::WarningHandler;
+pub use self::value_arena::ArenaStats;
 pub use self::value_arena::ArrayValue;
+pub use self::value_arena::BignumValue;
 pub use self::value_arena::BoolValue;
+pub use self::value_arena::ClassValue;
 pub use self::value_arena::FixnumValue;
+pub use self::value_arena::FloatValue;
 pub use self::value_arena::HashValue;
+pub use self::value_arena::ModuleValue;
 pub use self::value_arena::NilValue;
 pub use self::value_arena::ObjectValue;
+pub use self::value_arena::OldModuleValue;
+pub use self::value_arena::RegexpValue;
 pub use self::value_arena::StringValue;
+pub use self::value_arena::StructValue;
 pub use self::value_arena::SymbolValue;
+#[cfg(feature = "regex")]
+pub use self::value_arena::ToRegexError;
 pub use self::value_arena::TypedValueHandle;
 pub use self::value_arena::UserDefinedValue;
+pub use self::value_arena::UserMarshalValue;
+pub use self::value_arena::ValidationError;
 pub use self::value_arena::Value;
 pub use self::value_arena::ValueArena;
 pub use self::value_arena::ValueHandle;
 pub use self::value_arena::ValueKind;
+use alloc::vec::Vec;
+
+/// Items used by the derive macros.
+///
+/// This is not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec::Vec;
+}
 
 const MAJOR_VERSION: u8 = 4;
 const MINOR_VERSION: u8 = 8;
@@ -45,8 +143,23 @@ const VALUE_KIND_HASH_DEFAULT: u8 = b'}';
 const VALUE_KIND_OBJECT: u8 = b'o';
 const VALUE_KIND_STRING: u8 = b'"';
 const VALUE_KIND_USER_DEFINED: u8 = b'u';
+const VALUE_KIND_OLD_MODULE: u8 = b'M';
+const VALUE_KIND_FLOAT: u8 = b'f';
+const VALUE_KIND_BIGNUM: u8 = b'l';
+const VALUE_KIND_REGEXP: u8 = b'/';
+const VALUE_KIND_STRUCT: u8 = b'S';
+const VALUE_KIND_CLASS: u8 = b'c';
+const VALUE_KIND_MODULE: u8 = b'm';
+const VALUE_KIND_USER_MARSHAL: u8 = b'U';
+const VALUE_KIND_EXTENDED: u8 = b'e';
+const VALUE_KIND_USER_CLASS: u8 = b'C';
+const VALUE_KIND_DATA: u8 = b'd';
 
 /// The library error type
+///
+/// This covers loading, dumping, and conversions.
+/// [`FromValueError`], [`IntoValueError`], and [`JsonError`] convert into it,
+/// so `?` works across a whole pipeline, and the original error is available from `source`.
 #[derive(Debug)]
 pub enum Error {
     /// Invalid version
@@ -59,7 +172,7 @@ pub enum Error {
     },
 
     /// An I/O Error
-    Io { error: std::io::Error },
+    Io { error: crate::io::Error },
 
     /// An invalid value kind was encountered
     InvalidValueKind { kind: u8 },
@@ -73,11 +186,25 @@ pub enum Error {
     /// The fixnum size is too large
     InvalidFixnumSize { size: u8 },
 
+    /// A 4 byte fixnum does not fit in an i32.
+    ///
+    /// Values this large should be Bignums.
+    FixnumOutOfRange { value: i64 },
+
+    /// A float is not a valid number.
+    InvalidFloat {
+        /// The bytes of the float
+        value: Vec<u8>,
+    },
+
+    /// The sign of a bignum is not `+` or `-`.
+    InvalidBignumSign { sign: u8 },
+
     /// The Fixnum is not a valid usize
-    FixnumInvalidUSize { error: std::num::TryFromIntError },
+    FixnumInvalidUSize { error: core::num::TryFromIntError },
 
     /// The usize is not a valid Fixnum
-    USizeInvalidFixnum { error: std::num::TryFromIntError },
+    USizeInvalidFixnum { error: core::num::TryFromIntError },
 
     /// Missing a symbol link
     MissingSymbolLink { index: usize },
@@ -96,16 +223,58 @@ pub enum Error {
         /// The duplicated variable
         name: Vec<u8>,
     },
+
+    /// There was more data after the loaded value.
+    TrailingData {
+        /// The offset of the first byte after the value
+        offset: usize,
+    },
+
+    /// A value contains itself, but object links are disabled.
+    CycleWithoutLinks {
+        /// The value that contains itself
+        handle: ValueHandle,
+    },
+
+    /// The output does not fit in the buffer.
+    BufferTooSmall {
+        /// The length of the buffer
+        len: usize,
+
+        /// The length of the output
+        needed: usize,
+    },
+
+    /// A value could not be converted into a Ruby value
+    IntoValue { error: IntoValueError },
+
+    /// A Ruby value could not be converted into a value
+    FromValue { error: FromValueError },
+
+    /// A Ruby value could not be converted into JSON
+    Json { error: JsonError },
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::InvalidVersion { major, minor } => write!(f, "invalid version {major}.{minor}"),
             Self::Io { .. } => write!(f, "I/O error"),
             Self::InvalidValueKind { kind } => write!(f, "invalid value kind {kind}"),
             Self::InvalidValueHandle { .. } => write!(f, "invalid value handle"),
             Self::InvalidFixnumSize { size } => write!(f, "invalid fixnum size {size}"),
+            Self::FixnumOutOfRange { value } => write!(
+                f,
+                "fixnum {value} does not fit in an i32, and should have been a Bignum"
+            ),
+            Self::InvalidFloat { value } => {
+                write!(
+                    f,
+                    "invalid float \"{}\"",
+                    DisplayByteStringTruncated::new(value)
+                )
+            }
+            Self::InvalidBignumSign { sign } => write!(f, "invalid bignum sign {sign}"),
             Self::FixnumInvalidUSize { .. } => write!(f, "fixnum is not a valid usize"),
             Self::USizeInvalidFixnum { .. } => write!(f, "usize is not a valid Fixnum"),
             Self::MissingSymbolLink { index } => write!(f, "missing symbol link {index}"),
@@ -116,33 +285,80 @@ impl std::fmt::Display for Error {
             ),
             Self::NotAnObject => write!(f, "not an object"),
             Self::DuplicateInstanceVariable { name } => {
-                write!(f, "duplicate instance variable \"{name:?}\"")
+                write!(
+                    f,
+                    "duplicate instance variable \"{}\"",
+                    DisplayByteStringTruncated::new(name)
+                )
+            }
+            Self::TrailingData { offset } => {
+                write!(f, "unexpected data after the value at offset {offset}")
             }
+            Self::CycleWithoutLinks { .. } => {
+                write!(f, "a value contains itself, but object links are disabled")
+            }
+            Self::BufferTooSmall { len, needed } => {
+                write!(
+                    f,
+                    "the output is {needed} bytes, but the buffer is only {len} bytes"
+                )
+            }
+            Self::IntoValue { .. } => write!(f, "failed to convert into a Ruby value"),
+            Self::FromValue { .. } => write!(f, "failed to convert from a Ruby value"),
+            Self::Json { .. } => write!(f, "failed to convert into JSON"),
         }
     }
 }
 
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             Self::Io { error } => Some(error),
             Self::FixnumInvalidUSize { error } => Some(error),
             Self::USizeInvalidFixnum { error } => Some(error),
+            Self::IntoValue { error } => Some(error),
+            Self::FromValue { error } => Some(error),
+            Self::Json { error } => Some(error),
             _ => None,
         }
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(error: std::io::Error) -> Self {
+impl From<crate::io::Error> for Error {
+    fn from(error: crate::io::Error) -> Self {
         Error::Io { error }
     }
 }
 
+impl From<IntoValueError> for Error {
+    fn from(error: IntoValueError) -> Self {
+        Error::IntoValue { error }
+    }
+}
+
+impl From<FromValueError> for Error {
+    fn from(error: FromValueError) -> Self {
+        Error::FromValue { error }
+    }
+}
+
+impl From<JsonError> for Error {
+    fn from(error: JsonError) -> Self {
+        Error::Json { error }
+    }
+}
+
+/// The error for a whole pipeline of loading, converting, and dumping.
+///
+/// This is another name for [`Error`], which already wraps [`FromValueError`] and [`IntoValueError`].
+/// Use it where a function mixes these steps and returns any of their errors.
+pub type CodecError = Error;
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::io::Read;
+    use alloc::string::ToString;
+    use alloc::vec;
 
     #[test]
     fn kitchen_sink() {
@@ -155,20 +371,156 @@ mod test {
             let entry_path = entry.path();
 
             let data = std::fs::read(&entry_path).expect("failed to read entry");
-            let mut data_reader = std::io::Cursor::new(&data);
+            let mut data_reader = &data[..];
 
             let value_arena = load(&mut data_reader).expect("failed to load");
 
             let mut new_data = Vec::new();
             dump(&mut new_data, &value_arena).expect("failed to dump");
+            let len = value_arena
+                .serialized_len()
+                .expect("failed to get serialized len");
+            assert!(len == new_data.len());
 
-            let read_end_result = data_reader.read(&mut [0]);
-            let is_eof = matches!(read_end_result, Ok(0));
-            assert!(is_eof);
+            assert!(data_reader.is_empty());
             assert!(data == new_data, "{data:?} != {new_data:?}");
         }
     }
 
+    /// Load data, then dump it again and check that the bytes are the same.
+    fn round_trip(data: &[u8]) -> Result<(), std::string::String> {
+        let mut data_reader = data;
+        let value_arena = load(&mut data_reader).map_err(|error| std::format!("load: {error}"))?;
+        if !data_reader.is_empty() {
+            return Err("trailing data".into());
+        }
+        value_arena
+            .validate()
+            .map_err(|error| std::format!("validate: {error}"))?;
+
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &value_arena).map_err(|error| std::format!("dump: {error}"))?;
+        if data != new_data {
+            return Err(std::format!("{data:?} != {new_data:?}"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn corpus() {
+        // The corpus is generated by "scripts/generate-corpus.rb", which needs Ruby.
+        let entries = match std::fs::read_dir("test_data/corpus") {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                std::eprintln!("skipping, \"test_data/corpus\" has not been generated");
+                return;
+            }
+            Err(error) => panic!("failed to read \"test_data/corpus\": {error}"),
+        };
+
+        // Check every file before failing, so that all failures are reported at once.
+        let mut failures = Vec::new();
+        for entry in entries {
+            let entry_path = entry.expect("failed to read entry").path();
+            let data = std::fs::read(&entry_path).expect("failed to read entry");
+
+            if let Err(error) = round_trip(&data) {
+                failures.push(std::format!("{}: {error}", entry_path.display()));
+            }
+        }
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn core_types() {
+        // The output of Marshal.dump for values of each kind.
+        let cases: &[(&str, &[u8])] = &[
+            ("nil", b"\x04\x080"),
+            ("true", b"\x04\x08T"),
+            ("false", b"\x04\x08F"),
+            ("0", b"\x04\x08i\x00"),
+            ("2**30 - 1", b"\x04\x08i\x04\xFF\xFF\xFF\x3F"),
+            ("-(2**30)", b"\x04\x08i\xFC\x00\x00\x00\xC0"),
+            (
+                "2**64",
+                b"\x04\x08l+\x0A\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00",
+            ),
+            ("-(2**32)", b"\x04\x08l-\x08\x00\x00\x00\x00\x01\x00"),
+            ("1.5", b"\x04\x08f\x081.5"),
+            ("-2.25", b"\x04\x08f\x0A-2.25"),
+            ("100.0", b"\x04\x08f\x081e2"),
+            ("0.0001", b"\x04\x08f\x0B0.0001"),
+            ("1.0e-20", b"\x04\x08f\x0A1e-20"),
+            ("0.0", b"\x04\x08f\x060"),
+            ("-0.0", b"\x04\x08f\x07-0"),
+            ("Float::INFINITY", b"\x04\x08f\x08inf"),
+            ("-Float::INFINITY", b"\x04\x08f\x09-inf"),
+            ("Float::NAN", b"\x04\x08f\x08nan"),
+            ("[1.5, 1.5]", b"\x04\x08[\x07f\x081.5@\x06"),
+            (":symbol", b"\x04\x08:\x0Bsymbol"),
+            ("\"string\"", b"\x04\x08I\"\x0Bstring\x06:\x06ET"),
+            ("/ab+c/i", b"\x04\x08I/\x09ab+c\x01\x06:\x06EF"),
+            (
+                "Point.new(1, 2)",
+                b"\x04\x08S:\x0APoint\x07:\x06xi\x06:\x06yi\x07",
+            ),
+            ("String", b"\x04\x08c\x0BString"),
+            ("Kernel", b"\x04\x08m\x0BKernel"),
+        ];
+
+        for (name, data) in cases.iter() {
+            if let Err(error) = round_trip(data) {
+                panic!("failed to round trip {name}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn float_format() {
+        // The text of floats, as written by Marshal.dump.
+        let cases: &[(f64, &str)] = &[
+            (1.0, "1"),
+            (12345.0, "12345"),
+            (123.456, "123.456"),
+            (1.0e16, "1e16"),
+            (1.25e20, "1.25e20"),
+            (0.001, "0.001"),
+            (-0.00001, "-1e-5"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (-f64::MAX, "-1.7976931348623157e308"),
+            (1.0e300, "1e300"),
+            (-0.0, "-0"),
+            (1.0 / 3.0, "0.3333333333333333"),
+            (-2.0 / 3.0, "-0.6666666666666666"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            // Subnormals
+            (f64::from_bits(1), "5e-324"),
+            (-f64::from_bits(3), "-1.5e-323"),
+            (
+                f64::MIN_POSITIVE - f64::from_bits(1),
+                "2.225073858507201e-308",
+            ),
+        ];
+
+        for (value, expected) in cases.iter() {
+            let data = to_bytes(*value).expect("failed to dump");
+            let mut expected_data = vec![MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FLOAT];
+            expected_data.push(u8::try_from(expected.len() + 5).unwrap());
+            expected_data.extend(expected.as_bytes());
+            assert!(data == expected_data, "{value} was written as {data:?}");
+
+            // The text must load as exactly the same float, including the sign of zero.
+            let new_value: f64 = from_bytes(&data).expect("failed to load");
+            assert!(
+                new_value.to_bits() == value.to_bits(),
+                "{value} was loaded as {new_value}"
+            );
+        }
+    }
+
     #[test]
     fn duped_symbol() {
         let mut value_arena = ValueArena::new();
@@ -190,4 +542,1233 @@ mod test {
 
         assert!(deduped_data == duped_data);
     }
+
+    #[test]
+    fn serialized_len() {
+        let mut value_arena = ValueArena::new();
+
+        let name = value_arena.create_symbol("Object".into());
+        let ivar = value_arena.create_symbol("@value".into());
+        let string = value_arena.create_utf8_string("string").into();
+        let object = value_arena.create_object(name, vec![(ivar, string)]).into();
+        let array = value_arena.create_array(vec![object, object, name.into(), string]);
+        value_arena.replace_root(array);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        let len = value_arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+        assert!(len == data.len(), "{len} != {}", data.len());
+
+        // It fails wherever dumping does.
+        let dangling = ValueArena::new().create_nil().into();
+        value_arena
+            .get_array_mut(array)
+            .expect("missing array")
+            .push(dangling);
+        assert!(matches!(
+            value_arena.serialized_len(),
+            Err(Error::InvalidValueHandle { .. })
+        ));
+    }
+
+    #[test]
+    fn dump_into_slice() {
+        let mut value_arena = ValueArena::new();
+        let string = value_arena.create_utf8_string("string").into();
+        let array = value_arena.create_array(vec![string, string]);
+        value_arena.replace_root(array);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        let len = value_arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+
+        let mut buffer = vec![0; len + 1];
+        let written =
+            crate::dump_into_slice(&mut buffer, &value_arena).expect("failed to dump into slice");
+        assert!(written == len);
+        assert!(buffer[..written] == data);
+
+        let mut buffer = vec![0; len - 1];
+        let error =
+            crate::dump_into_slice(&mut buffer, &value_arena).expect_err("dump should fail");
+        assert!(
+            matches!(error, Error::BufferTooSmall { len: buffer_len, needed } if buffer_len == len - 1 && needed == len),
+            "{error:?}"
+        );
+        assert!(buffer == data[..len - 1]);
+    }
+
+    #[test]
+    fn value_serialized_len() {
+        let mut value_arena = ValueArena::new();
+
+        let name = value_arena.create_symbol("Object".into());
+        let ivar = value_arena.create_symbol("@value".into());
+        let string = value_arena.create_utf8_string("string").into();
+        let object = value_arena.create_object(name, vec![(ivar, string)]);
+        let array = value_arena.create_array(vec![string, object.into()]);
+        value_arena.replace_root(array);
+
+        let mut data = Vec::new();
+        crate::dump_from(&mut data, &value_arena, object).expect("failed to dump");
+        let len = value_arena
+            .value_serialized_len(object)
+            .expect("failed to get serialized len");
+        assert!(len + 2 == data.len(), "{len} != {}", data.len() - 2);
+
+        // Within the array, the string is a link, so the object takes up less space there.
+        let array_len = value_arena
+            .value_serialized_len(array)
+            .expect("failed to get serialized len");
+        let string_len = value_arena
+            .value_serialized_len(string)
+            .expect("failed to get serialized len");
+        assert!(array_len < 2 + string_len + len);
+
+        let dangling = ValueArena::new().create_nil();
+        assert!(matches!(
+            value_arena.value_serialized_len(dangling),
+            Err(Error::InvalidValueHandle { .. })
+        ));
+    }
+
+    #[test]
+    fn deeply_nested_dump() {
+        const DEPTH: usize = 100_000;
+
+        let mut value_arena = ValueArena::new();
+        let mut handle = value_arena.create_nil().into();
+        for _ in 0..DEPTH {
+            handle = value_arena.create_array(vec![handle]).into();
+        }
+        value_arena.replace_root(handle);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+
+        let mut expected = vec![MAJOR_VERSION, MINOR_VERSION];
+        for _ in 0..DEPTH {
+            expected.extend([VALUE_KIND_ARRAY, 0x06]);
+        }
+        expected.push(VALUE_KIND_NIL);
+        assert!(data == expected);
+    }
+
+    #[test]
+    fn to_from_bytes() {
+        let data = to_bytes(vec![1, 2]).expect("failed to convert to bytes");
+        assert!(data == b"\x04\x08[\x07i\x06i\x07", "{data:?}");
+
+        let value: Vec<i32> = from_bytes(&data).expect("failed to convert from bytes");
+        assert!(value == [1, 2]);
+
+        let error = from_bytes::<bool>(&data).expect_err("conversion should fail");
+        assert!(matches!(error, Error::FromValue { .. }), "{error:?}");
+        let source = core::error::Error::source(&error).expect("missing source");
+        assert!(source.is::<FromValueError>(), "{source:?}");
+
+        let error = from_bytes::<Vec<i32>>(&data[..3]).expect_err("load should fail");
+        assert!(matches!(error, Error::Io { .. }), "{error:?}");
+    }
+
+    #[test]
+    fn codec_error() {
+        fn increment(data: &[u8]) -> Result<Vec<u8>, CodecError> {
+            let value_arena = load(data)?;
+            let value: i32 = value_arena.root_value()?;
+
+            let mut value_arena = ValueArena::new();
+            let handle = (value + 1).into_value(&mut value_arena)?;
+            value_arena.replace_root(handle);
+
+            let mut data = Vec::new();
+            dump(&mut data, &value_arena)?;
+
+            Ok(data)
+        }
+
+        let data = increment(b"\x04\x08i\x06").expect("failed to increment");
+        assert!(data == b"\x04\x08i\x07", "{data:?}");
+
+        let error = increment(b"\x04\x08T").expect_err("conversion should fail");
+        assert!(matches!(error, CodecError::FromValue { .. }), "{error:?}");
+    }
+
+    #[test]
+    fn display_tree() {
+        let mut value_arena = ValueArena::new();
+
+        let name = value_arena.create_symbol("Object".into());
+        let ivar_name = value_arena.create_symbol("@value".into());
+        let ivar_value = value_arena.create_fixnum(1).into();
+        let object = value_arena
+            .create_object(name, vec![(ivar_name, ivar_value)])
+            .into();
+        let array = value_arena.create_array(vec![object, object]);
+        value_arena.replace_root(array);
+
+        let output = value_arena.display_tree().to_string();
+        assert!(
+            output == "[\n  #<Object\n    @value = 1\n  >,\n  #<link>,\n]",
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn utf8_string() {
+        let mut value_arena = ValueArena::new();
+
+        let string = value_arena.create_utf8_string("a");
+        value_arena.replace_root(string);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+
+        assert!(data == b"\x04\x08I\"\x06a\x06:\x06ET", "{data:?}");
+    }
+
+    #[test]
+    fn array_push() {
+        let mut value_arena = ValueArena::new();
+
+        let array = value_arena.create_array_with_capacity(2);
+        let element = value_arena.create_fixnum(1).into();
+
+        let array_value = value_arena.get_array_mut(array).expect("missing array");
+        assert!(array_value.is_empty());
+        array_value.reserve(2);
+        value_arena
+            .push_array_item(array, element)
+            .expect("failed to push");
+        value_arena
+            .push_array_item(array, element)
+            .expect("failed to push");
+        let array_value = value_arena.get_array_mut(array).expect("missing array");
+        assert!(array_value.len() == 2);
+
+        value_arena.replace_root(array);
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        assert!(data == b"\x04\x08[\x07i\x06i\x06", "{data:?}");
+    }
+
+    #[test]
+    fn hash_insert_remove() {
+        let mut value_arena = ValueArena::new();
+
+        let hash = value_arena.create_hash(Vec::new(), None);
+        let key_1 = value_arena.create_fixnum(1).into();
+        let key_2 = value_arena.create_fixnum(2).into();
+        let value_1 = value_arena.create_bool(true).into();
+        let value_2 = value_arena.create_bool(false).into();
+
+        let mut insert = |key, value| {
+            value_arena
+                .insert_hash_pair(hash, key, value)
+                .expect("failed to insert")
+        };
+        assert!(insert(key_1, value_1).is_none());
+        assert!(insert(key_2, value_1).is_none());
+        assert!(insert(key_1, value_2) == Some(value_1));
+
+        let hash_value = value_arena.get_hash_mut(hash).expect("missing hash");
+        assert!(hash_value.len() == 2);
+        assert!(hash_value.remove(key_2) == Some(value_1));
+        assert!(hash_value.len() == 1);
+        assert!(!hash_value.is_empty());
+
+        let old_default = value_arena
+            .set_hash_default(hash, Some(value_1))
+            .expect("failed to set default");
+        assert!(old_default.is_none());
+
+        value_arena.replace_root(hash);
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        assert!(data == b"\x04\x08}\x06i\x06FT", "{data:?}");
+    }
+
+    #[test]
+    fn symbol_encoding() {
+        let data = std::fs::read("test_data/symbol-utf8.bin").expect("failed to read");
+        let value_arena = load(&*data).expect("failed to load");
+
+        let symbol = value_arena[value_arena.root()]
+            .as_symbol()
+            .expect("not a symbol");
+        assert!(symbol.value() == "café".as_bytes());
+
+        let instance_variables = symbol
+            .instance_variables()
+            .expect("missing instance variables");
+        assert!(instance_variables.len() == 1);
+        let (name, value) = instance_variables[0];
+        assert!(value_arena.get_symbol(name).map(|name| name.value()) == Some(b"E"));
+        assert!(matches!(value_arena[value], Value::Bool(value) if value.value()));
+
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(data == new_data, "{data:?} != {new_data:?}");
+    }
+
+    #[test]
+    fn recursive_array() {
+        let data = std::fs::read("test_data/array-recursive.bin").expect("failed to read");
+        let value_arena = load(&*data).expect("failed to load");
+
+        // The link to the array resolves to the array, not a placeholder.
+        let root = value_arena.root();
+        match &value_arena[root] {
+            Value::Array(value) => assert!(value.value() == [root]),
+            value => panic!("{value:?} is not an array"),
+        }
+
+        let ctx = FromValueContext::new(&value_arena);
+        let error = ctx
+            .from_value::<Vec<Vec<i32>>>(root)
+            .expect_err("extraction should fail");
+        assert!(
+            matches!(&error, FromValueError::At { path, .. } if path == "[0]"),
+            "{error:?}"
+        );
+        assert!(
+            matches!(error.without_path(), FromValueError::Cycle { handle } if *handle == root),
+            "{error:?}"
+        );
+
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(data == new_data, "{data:?} != {new_data:?}");
+    }
+
+    #[test]
+    fn disable_object_links() {
+        let options = DumperOptions {
+            disable_object_links: true,
+        };
+
+        // A shared string is written in full each time.
+        let mut value_arena = ValueArena::new();
+        let string = value_arena.create_string("a".into()).into();
+        let array = value_arena.create_array(vec![string, string]);
+        value_arena.replace_root(array);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        assert!(data == [4, 8, b'[', 7, b'"', 6, b'a', b'@', 6], "{data:?}");
+
+        let mut data = Vec::new();
+        dump_with_options(&mut data, &value_arena, options.clone()).expect("failed to dump");
+        assert!(
+            data == [4, 8, b'[', 7, b'"', 6, b'a', b'"', 6, b'a'],
+            "{data:?}"
+        );
+
+        // A sibling is not a cycle.
+        let inner = value_arena.create_array(vec![string]).into();
+        let array = value_arena.create_array(vec![inner, inner]);
+        value_arena.replace_root(array);
+        let mut data = Vec::new();
+        dump_with_options(&mut data, &value_arena, options.clone()).expect("failed to dump");
+        assert!(
+            data == [4, 8, b'[', 7, b'[', 6, b'"', 6, b'a', b'[', 6, b'"', 6, b'a'],
+            "{data:?}"
+        );
+
+        // An array that contains itself.
+        let mut value_arena = ValueArena::new();
+        let array = value_arena.create_array(Vec::new());
+        value_arena
+            .push_array_item(array, array.into())
+            .expect("failed to push");
+        value_arena.replace_root(array);
+
+        let error = dump_with_options(Vec::new(), &value_arena, options.clone())
+            .expect_err("dump should fail");
+        assert!(
+            matches!(error, Error::CycleWithoutLinks { handle } if handle == array.into()),
+            "{error:?}"
+        );
+
+        // A hash that is its own default value.
+        let mut value_arena = ValueArena::new();
+        let hash = value_arena.create_hash(Vec::new(), None);
+        value_arena
+            .set_hash_default(hash, Some(hash.into()))
+            .expect("failed to set default");
+        value_arena.replace_root(hash);
+
+        let error =
+            dump_with_options(Vec::new(), &value_arena, options).expect_err("dump should fail");
+        assert!(
+            matches!(error, Error::CycleWithoutLinks { handle } if handle == hash.into()),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn canonicalize_symbols() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Foo".into());
+        let ivar = value_arena.create_symbol("@foo".into());
+        let duplicate_name = value_arena.create_new_symbol("Foo".into());
+        let duplicate_ivar = value_arena.create_new_symbol("@foo".into());
+        let fixnum = value_arena.create_fixnum(1).into();
+        let object = value_arena.create_object(duplicate_name, vec![(duplicate_ivar, fixnum)]);
+        let hash = value_arena.create_hash(vec![(duplicate_ivar.into(), object.into())], None);
+        let array = value_arena.create_array(vec![hash.into(), name.into(), ivar.into()]);
+        value_arena.replace_root(array);
+
+        assert!(value_arena.canonicalize_symbols() == 2);
+        assert!(value_arena.get(duplicate_name).is_none());
+        assert!(value_arena.get(duplicate_ivar).is_none());
+        assert!(value_arena.canonicalize_symbols() == 0);
+
+        let object = value_arena
+            .get(object)
+            .and_then(|value| value.as_object())
+            .expect("missing object");
+        assert!(object.name() == name);
+        assert!(object.instance_variables() == [(ivar, fixnum)]);
+        let Some(Value::Hash(hash)) = value_arena.get(hash) else {
+            panic!("missing hash");
+        };
+        assert!(hash.value()[0].0 == ivar.into());
+
+        // Every use after the first is a symbol link.
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        let expected = [
+            4, 8, b'[', 8, b'{', 6, b':', 9, b'@', b'f', b'o', b'o', b'o', b':', 8, b'F', b'o',
+            b'o', 6, b';', 0, b'i', 6, b';', 6, b';', 0,
+        ];
+        assert!(data == expected, "{data:?}");
+    }
+
+    #[test]
+    fn singletons() {
+        let mut value_arena = ValueArena::new();
+        assert!(value_arena.create_nil() == value_arena.create_nil());
+        assert!(value_arena.create_nil().into_raw() == value_arena.root());
+        assert!(value_arena.create_bool(true) == value_arena.create_bool(true));
+        assert!(value_arena.create_bool(false) == value_arena.create_bool(false));
+        assert!(value_arena.create_bool(true) != value_arena.create_bool(false));
+
+        // [nil, [], nil, true, true]
+        let data = [4, 8, b'[', 10, b'0', b'[', 0, b'0', b'T', b'T'];
+        let value_arena = load(&data[..]).expect("failed to load");
+        let Value::Array(array) = &value_arena[value_arena.root()] else {
+            panic!("root is not an array");
+        };
+        let items = array.value();
+        assert!(items[0] == items[2]);
+        assert!(items[3] == items[4]);
+        // Placeholders for composite values are not the nil singleton.
+        assert!(items[0] != items[1]);
+        assert!(matches!(value_arena[items[1]], Value::Array(_)));
+        assert!(matches!(value_arena[items[0]], Value::Nil(_)));
+
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+    }
+
+    #[test]
+    fn root_lifecycle() {
+        let mut value_arena = ValueArena::new();
+        let shared = value_arena.create_string("shared".into());
+        let symbol = value_arena.create_symbol("symbol".into());
+        let only_old = value_arena.create_fixnum(1);
+        let orphan = value_arena.create_fixnum(2);
+        let nil = value_arena.create_nil();
+        let old_root = value_arena.create_array(vec![
+            shared.into(),
+            symbol.into(),
+            only_old.into(),
+            nil.into(),
+        ]);
+        value_arena.replace_root(old_root);
+
+        let taken = value_arena.take_root();
+        assert!(taken == old_root.into_raw());
+        assert!(value_arena.root() == nil.into_raw());
+        assert!(value_arena.get(taken).is_some());
+
+        let new_root = value_arena.create_array(vec![shared.into()]);
+        value_arena.replace_root(old_root);
+        let removed = value_arena.set_root_and_gc(new_root);
+        assert!(removed == 3);
+        assert!(value_arena.root() == new_root.into_raw());
+        assert!(value_arena.get(old_root).is_none());
+        assert!(value_arena.get(symbol).is_none());
+        assert!(value_arena.get(only_old).is_none());
+        assert!(value_arena.get(shared).is_some());
+        assert!(value_arena.get(orphan).is_some());
+        assert!(value_arena.get(nil).is_some());
+        value_arena.validate().expect("arena should be valid");
+
+        // Removed symbols are no longer interned.
+        let new_symbol = value_arena.create_symbol("symbol".into());
+        assert!(new_symbol != symbol);
+    }
+
+    #[test]
+    fn clone_arena() {
+        let mut value_arena = ValueArena::new();
+        let string = value_arena.create_string("string".into());
+        let root = value_arena.create_array(vec![string.into()]);
+        value_arena.replace_root(root);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+
+        // Handles from the original are valid in the clone.
+        let mut cloned = value_arena.clone();
+        assert!(cloned.root() == root.into_raw());
+        assert!(cloned.get(string).is_some());
+
+        let fixnum = cloned.create_fixnum(1);
+        let removed = cloned.set_root_and_gc(fixnum);
+        assert!(removed == 2);
+        assert!(cloned.get(string).is_none());
+        cloned.validate().expect("clone should be valid");
+
+        assert!(value_arena.root() == root.into_raw());
+        assert!(value_arena.get(string).is_some());
+        value_arena.validate().expect("arena should be valid");
+
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data);
+    }
+
+    #[test]
+    fn iter_values() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Point".into());
+        let object = value_arena.create_object(name, Vec::new());
+        let string = value_arena.create_string("string".into());
+        let root = value_arena.create_array(vec![object.into()]);
+        value_arena.replace_root(root);
+
+        // Orphans are included.
+        let orphan = value_arena.create_object(name, Vec::new());
+
+        // nil, true, false, the symbol, both objects, the string, and the array.
+        assert!(value_arena.iter_values().count() == 8);
+
+        let objects: Vec<_> = value_arena
+            .iter_objects()
+            .map(|(handle, value)| {
+                assert!(value.name() == name);
+                handle
+            })
+            .collect();
+        assert!(objects.len() == 2);
+        assert!(objects.contains(&object));
+        assert!(objects.contains(&orphan));
+
+        let strings: Vec<_> = value_arena.iter_strings().collect();
+        assert!(strings.len() == 1);
+        assert!(strings[0].0 == string);
+        assert!(strings[0].1.value() == b"string");
+
+        let arrays: Vec<_> = value_arena
+            .iter_kind(ValueKind::Array)
+            .map(|(handle, _value)| handle)
+            .collect();
+        assert!(arrays == [root.into_raw()]);
+    }
+
+    #[test]
+    fn root_value() {
+        let mut value_arena = ValueArena::new();
+        let string = value_arena.create_string("abc".into());
+        let array = value_arena.create_array(vec![string.into(), string.into()]);
+        value_arena.replace_root(array);
+
+        let value: Vec<&str> = value_arena.root_value().expect("failed to get root value");
+        assert!(value == ["abc", "abc"]);
+
+        let error = value_arena
+            .root_value::<i32>()
+            .expect_err("root is not a fixnum");
+        assert!(
+            matches!(
+                error,
+                FromValueError::UnexpectedValueKind {
+                    kind: ValueKind::Array,
+                    ..
+                }
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn stateful() {
+        let mut value_arena = ValueArena::new();
+        let symbol = value_arena.create_symbol("a".into());
+        let string = value_arena.create_string("s".into());
+        let first = value_arena.create_array(vec![symbol.into(), string.into()]);
+        let second = value_arena.create_array(vec![string.into(), symbol.into()]);
+
+        let mut dumper = StatefulDumper::new(Vec::new(), &value_arena, DumperOptions::new());
+        dumper.dump(first).expect("failed to dump");
+        dumper.dump(second).expect("failed to dump");
+        let data = dumper.into_inner();
+
+        // The second value links to the string and symbol of the first.
+        let (first_data, second_data) = data.split_at(10);
+        assert!(first_data == b"\x04\x08[\x07:\x06a\"\x06s");
+        assert!(second_data == b"\x04\x08[\x07@\x06;\x00");
+
+        // Only the first value can be loaded on its own.
+        load(first_data).expect("failed to load");
+        let error = load(second_data).expect_err("load should fail");
+        assert!(
+            matches!(error, Error::MissingObjectLink { index: 1 }),
+            "{error:?}"
+        );
+
+        let mut reader = &data[..];
+        let mut loader = StatefulLoader::new(LoaderOptions::new());
+        let first = loader.load(&mut reader).expect("failed to load");
+        let second = loader.load(&mut reader).expect("failed to load");
+        assert!(reader.is_empty());
+        let value_arena = loader.into_arena();
+        assert!(value_arena.root() == second);
+
+        let ctx = FromValueContext::new(&value_arena);
+        let first: &ArrayValue = ctx.from_value(first).expect("failed to extract");
+        let second: &ArrayValue = ctx.from_value(second).expect("failed to extract");
+        assert!(first.value()[0] == second.value()[1]);
+        assert!(first.value()[1] == second.value()[0]);
+    }
+
+    #[test]
+    fn class_name_symbol_links() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Foo".into());
+        let object_1 = value_arena.create_object(name, Vec::new()).into();
+        let object_2 = value_arena.create_object(name, Vec::new()).into();
+        let user_defined_1 = value_arena.create_user_defined(name, Vec::new()).into();
+        let user_defined_2 = value_arena.create_user_defined(name, Vec::new()).into();
+        let array =
+            value_arena.create_array(vec![object_1, object_2, user_defined_1, user_defined_2]);
+        value_arena.replace_root(array);
+
+        // Only the first use of the class name is a symbol, the rest are symbol links.
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        let expected = [
+            4, 8, b'[', 9, b'o', b':', 8, b'F', b'o', b'o', 0, b'o', b';', 0, 0, b'u', b';', 0, 0,
+            b'u', b';', 0, 0,
+        ];
+        assert!(data == expected, "{data:?}");
+    }
+
+    #[test]
+    fn symbol_fixnum_ord() {
+        let mut value_arena = ValueArena::new();
+        let b = value_arena.create_symbol("b".into());
+        let a = value_arena.create_symbol("a".into());
+        let duplicate_a = value_arena.create_new_symbol("a".into());
+        let one = value_arena.create_fixnum(1).into();
+        let negative_one = value_arena.create_fixnum(-1).into();
+
+        let mut symbols: Vec<&SymbolValue> = [b, a]
+            .into_iter()
+            .map(|handle| value_arena.get_symbol(handle).expect("missing symbol"))
+            .collect();
+        symbols.sort();
+        assert!(symbols.iter().map(|symbol| symbol.value()).eq([b"a", b"b"]));
+        assert!(value_arena.get_symbol(a) == value_arena.get_symbol(duplicate_a));
+
+        let ctx = FromValueContext::new(&value_arena);
+        let one: &FixnumValue = ctx.from_value(one).expect("failed to extract");
+        let negative_one: &FixnumValue = ctx.from_value(negative_one).expect("failed to extract");
+        assert!(negative_one < one);
+
+        // Fixnums compare by value, not by how they were encoded.
+        let data = [4, 8, b'i', 1, 5];
+        let options = LoaderOptions {
+            preserve_fixnum_encoding: true,
+            ..LoaderOptions::default()
+        };
+        let other_value_arena = load_with_options(&data[..], options).expect("failed to load");
+        let other_ctx = FromValueContext::new(&other_value_arena);
+        let five: &FixnumValue = other_ctx
+            .from_value(other_value_arena.root())
+            .expect("failed to extract");
+        let mut value_arena = ValueArena::new();
+        let other_five = value_arena.create_fixnum(5).into();
+        let ctx = FromValueContext::new(&value_arena);
+        let other_five: &FixnumValue = ctx.from_value(other_five).expect("failed to extract");
+        assert!(five == other_five);
+    }
+
+    #[test]
+    fn child_handles() {
+        let mut value_arena = ValueArena::new();
+        let child_handles = |value_arena: &ValueArena, handle: ValueHandle| {
+            value_arena[handle].child_handles().collect::<Vec<_>>()
+        };
+
+        let nil = value_arena.create_nil().into();
+        let bool_value = value_arena.create_bool(true).into();
+        let fixnum = value_arena.create_fixnum(1).into();
+        let symbol = value_arena.create_symbol("foo".into());
+        assert!(child_handles(&value_arena, nil).is_empty());
+        assert!(child_handles(&value_arena, bool_value).is_empty());
+        assert!(child_handles(&value_arena, fixnum).is_empty());
+        assert!(child_handles(&value_arena, symbol.into()).is_empty());
+
+        let array = value_arena.create_array(vec![nil, fixnum, nil]).into();
+        assert!(child_handles(&value_arena, array) == [nil, fixnum, nil]);
+
+        let hash = value_arena
+            .create_hash(vec![(fixnum, nil)], Some(bool_value))
+            .into();
+        assert!(child_handles(&value_arena, hash) == [fixnum, nil, bool_value]);
+
+        let ivar = value_arena.create_symbol("@foo".into());
+        let object = value_arena
+            .create_object(symbol, vec![(ivar, array)])
+            .into();
+        assert!(child_handles(&value_arena, object) == [symbol.into(), ivar.into(), array]);
+
+        let string = value_arena.create_utf8_string("foo").into();
+        let encoding_name = value_arena.create_symbol("E".into());
+        assert!(child_handles(&value_arena, string)[0] == encoding_name.into());
+        assert!(child_handles(&value_arena, string).len() == 2);
+
+        let user_defined = value_arena.create_user_defined(symbol, vec![1, 2]).into();
+        assert!(child_handles(&value_arena, user_defined) == [symbol.into()]);
+    }
+
+    #[test]
+    fn cross_arena_handle() {
+        let mut value_arena = ValueArena::new();
+        let mut other_value_arena = ValueArena::new();
+
+        // Both handles refer to the same slot in their own arenas.
+        let handle = value_arena.create_fixnum(1);
+        let other_handle = other_value_arena.create_fixnum(2);
+
+        assert!(handle != other_handle);
+        assert!(value_arena.get(handle).is_some());
+        assert!(value_arena.get(other_handle).is_none());
+        assert!(other_value_arena.get(handle).is_none());
+
+        let ctx = FromValueContext::new(&value_arena);
+        assert!(matches!(
+            ctx.from_value::<i32>(other_handle.into()),
+            Err(FromValueError::InvalidValueHandle { .. })
+        ));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "is from another arena")]
+    fn create_with_cross_arena_handle() {
+        let mut value_arena = ValueArena::new();
+        let mut other_value_arena = ValueArena::new();
+        let other_handle = other_value_arena.create_fixnum(1).into();
+
+        value_arena.create_array(vec![other_handle]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "is from another arena")]
+    fn push_cross_arena_handle() {
+        let mut value_arena = ValueArena::new();
+        let mut other_value_arena = ValueArena::new();
+        let array = value_arena.create_array(Vec::new());
+        let other_handle = other_value_arena.create_fixnum(1).into();
+
+        let _ = value_arena.push_array_item(array, other_handle);
+    }
+
+    #[test]
+    fn validate() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Object".into());
+        let fixnum = value_arena.create_fixnum(1).into();
+        let object = value_arena.create_object(name, vec![(name, fixnum)]).into();
+        let array = value_arena.create_array(vec![object, object]);
+        value_arena.replace_root(array);
+        value_arena.validate().expect("arena should be valid");
+
+        // A handle from another arena will not be valid in this one.
+        let mut other_value_arena = ValueArena::new();
+        let dangling = other_value_arena.create_nil().into();
+        value_arena
+            .get_array_mut(array)
+            .expect("missing array")
+            .push(dangling);
+        let error = value_arena.validate().expect_err("arena should be invalid");
+        assert!(
+            matches!(
+                &error,
+                ValidationError::InvalidValueHandle { handle, path }
+                    if *handle == dangling && *path == [array.into()]
+            ),
+            "{error:?}"
+        );
+
+        // Object names must be symbols.
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_fixnum(1).into_raw();
+        let object = value_arena.create_object(TypedValueHandle::new_unchecked(name), Vec::new());
+        let array = value_arena.create_array(vec![object.into()]);
+        value_arena.replace_root(array);
+        let error = value_arena.validate().expect_err("arena should be invalid");
+        assert!(
+            matches!(
+                &error,
+                ValidationError::NotASymbol { handle, kind: ValueKind::Fixnum, path }
+                    if *handle == name && *path == [array.into(), object.into()]
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut arena = ValueArena::new();
+        let name = arena.create_symbol("Object".into());
+        let instance_variable = arena.create_symbol("@a".into());
+        let fixnum = arena.create_fixnum(1).into();
+        let object = arena
+            .create_object(name, vec![(instance_variable, fixnum)])
+            .into();
+        let string = arena.create_string("hi".into()).into();
+        let nested = arena.create_array(vec![string]).into();
+        let nil = arena.create_nil().into();
+        let root = arena.create_array(vec![object, object, nested, nil, nil]);
+        arena.replace_root(root);
+
+        // Not reachable from the root.
+        arena.create_string("orphan".into());
+
+        let stats = arena.stats();
+        assert!(stats.values == 8, "{stats}");
+        assert!(stats.kinds.get(&ValueKind::Array) == Some(&2), "{stats}");
+        assert!(stats.kinds.get(&ValueKind::Symbol) == Some(&2), "{stats}");
+        assert!(stats.kinds.get(&ValueKind::String) == Some(&1), "{stats}");
+        assert!(!stats.kinds.contains_key(&ValueKind::Hash), "{stats}");
+        assert!(stats.unique_symbols == 2, "{stats}");
+        assert!(stats.max_depth == 2, "{stats}");
+        assert!(stats.shared == 1, "{stats}");
+        assert!(stats.string_bytes == 2, "{stats}");
+        assert!(stats.symbol_bytes == 8, "{stats}");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regexp_to_regex() {
+        let mut arena = ValueArena::new();
+        let plain = arena.create_regexp("^b.c$".into(), 0);
+        let options = arena.create_regexp("^b . c$".into(), 1 | 2 | 4);
+        let lookbehind = arena.create_regexp("(?<=a)b".into(), 0);
+        let binary = arena.create_regexp(b"\xFF".into(), 0);
+
+        let to_regex = |handle: TypedValueHandle<RegexpValue>| match arena.get(handle) {
+            Some(Value::Regexp(value)) => value.to_regex(),
+            value => panic!("unexpected value {value:?}"),
+        };
+
+        let regex = to_regex(plain).expect("failed to compile");
+        assert!(regex.is_match("a\nbxc\nd"));
+        assert!(!regex.is_match("B\nc"));
+
+        let regex = to_regex(options).expect("failed to compile");
+        assert!(regex.is_match("B\nc"));
+
+        let error = to_regex(lookbehind).expect_err("lookbehind should not compile");
+        assert!(matches!(error, ToRegexError::Regex { .. }), "{error:?}");
+
+        let error = to_regex(binary).expect_err("binary source should not compile");
+        assert!(matches!(error, ToRegexError::InvalidUtf8), "{error:?}");
+    }
+
+    #[test]
+    fn take_instance_variables() {
+        let mut arena = ValueArena::new();
+        let string = arena.create_utf8_string("text");
+        arena.replace_root(string);
+        let old_len = arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+
+        let instance_variables = arena
+            .get_string_mut(string)
+            .expect("missing string")
+            .take_instance_variables()
+            .expect("missing instance variables");
+        assert!(instance_variables.len() == 1);
+        assert!(arena
+            .get_string_mut(string)
+            .expect("missing string")
+            .take_instance_variables()
+            .is_none());
+
+        let new_len = arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+        assert!(new_len < old_len, "{new_len} >= {old_len}");
+
+        let name = arena.create_symbol("Data".into());
+        let user_defined = arena.create_user_defined(name, Vec::new());
+        let user_defined_value = arena
+            .get_user_defined_mut(user_defined)
+            .expect("missing user defined value");
+        user_defined_value.clear_instance_variables();
+        assert!(user_defined_value.instance_variables().is_none());
+    }
+
+    #[test]
+    fn create_symbol_hash() {
+        let mut arena = ValueArena::new();
+        let existing = arena.create_symbol("a".into());
+        let one = arena.create_fixnum(1).into();
+        let two = arena.create_fixnum(2).into();
+        let hash = arena.create_symbol_hash([(&b"a"[..], one), (b"b", two)]);
+        arena.replace_root(hash);
+
+        let hash = match arena.get(hash).expect("missing hash") {
+            Value::Hash(hash) => hash,
+            value => panic!("unexpected value {value:?}"),
+        };
+        assert!(hash.default_value().is_none());
+        assert!(hash.value()[0] == (existing.into(), one));
+        assert!(hash.value()[1].1 == two);
+
+        let key = arena
+            .get(hash.value()[1].0)
+            .and_then(Value::as_symbol)
+            .expect("missing symbol");
+        assert!(key.value() == b"b");
+    }
+
+    #[test]
+    fn create_with_instance_variables() {
+        let mut arena = ValueArena::new();
+        let name = arena.create_symbol("Data".into());
+        let b = arena.create_symbol("@b".into());
+        let a = arena.create_symbol("@a".into());
+        let one = arena.create_fixnum(1).into();
+        let two = arena.create_fixnum(2).into();
+        let user_defined = arena
+            .create_user_defined_with_instance_variables(
+                name,
+                b"data".into(),
+                vec![(b, one), (a, two)],
+            )
+            .into();
+        let string = arena
+            .create_string_with_instance_variables(b"text".into(), vec![(a, one)])
+            .into();
+        let array = arena.create_array(vec![user_defined, string]);
+        arena.replace_root(array);
+
+        let mut data = Vec::new();
+        dump(&mut data, &arena).expect("failed to dump");
+        assert!(
+            data == b"\x04\x08[\x07Iu:\x09Data\x09data\x07:\x07@bi\x06:\x07@ai\x07I\"\x09text\x06;\x07i\x06",
+            "{data:?}"
+        );
+
+        let new_arena = load(&*data).expect("failed to load");
+        let mut new_data = Vec::new();
+        dump(&mut new_data, &new_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+    }
+
+    #[test]
+    fn deduplicate() {
+        let mut arena = ValueArena::new();
+        let first_string = arena.create_string("a".into()).into();
+        let second_string = arena.create_string("a".into()).into();
+        let utf8_string = arena.create_utf8_string("a").into();
+        let first_items = vec![arena.create_fixnum(1).into(), arena.create_fixnum(2).into()];
+        let first_array = arena.create_array(first_items).into();
+        let second_items = vec![arena.create_fixnum(1).into(), arena.create_fixnum(2).into()];
+        let second_array = arena.create_array(second_items).into();
+        let name = arena.create_symbol("Point".into());
+        let x = arena.create_symbol("@x".into());
+        let first_object = arena.create_object(name, vec![(x, first_string)]).into();
+        let second_object = arena.create_object(name, vec![(x, second_string)]).into();
+        let first_cycle = arena.create_array(Vec::new());
+        arena
+            .push_array_item(first_cycle, first_cycle.into())
+            .expect("failed to push");
+        let second_cycle = arena.create_array(Vec::new());
+        arena
+            .push_array_item(second_cycle, second_cycle.into())
+            .expect("failed to push");
+        let root = arena.create_array(vec![
+            first_string,
+            second_string,
+            utf8_string,
+            first_array,
+            second_array,
+            first_object,
+            second_object,
+            first_cycle.into(),
+            second_cycle.into(),
+        ]);
+        arena.replace_root(root);
+        let old_len = arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+
+        // The second string, the second array and its two fixnums, and the second object.
+        assert!(arena.deduplicate() == 5);
+        assert!(arena.deduplicate() == 0);
+
+        let items = match arena.get(arena.root()).expect("missing root") {
+            Value::Array(array) => array.value(),
+            value => panic!("unexpected value {value:?}"),
+        };
+        assert!(items[1] == items[0]);
+        assert!(items[2] != items[0]);
+        assert!(items[4] == items[3]);
+        assert!(items[6] == items[5]);
+        assert!(items[8] != items[7]);
+        assert!(arena.get(second_object).is_none());
+
+        let new_len = arena
+            .serialized_len()
+            .expect("failed to get serialized len");
+        assert!(new_len < old_len, "{new_len} >= {old_len}");
+    }
+
+    #[test]
+    fn create_symbol_full() {
+        let mut arena = ValueArena::new();
+        let (symbol, inserted) = arena.create_symbol_full("symbol".into());
+        assert!(inserted);
+
+        let (existing, inserted) = arena.create_symbol_full("symbol".into());
+        assert!(!inserted);
+        assert!(existing == symbol);
+        assert!(arena.create_symbol("symbol".into()) == symbol);
+    }
+
+    #[test]
+    fn create_symbol_static() {
+        static FIRST: &[u8] = b"symbol";
+        static SECOND: [u8; 6] = *b"symbol";
+
+        let mut arena = ValueArena::new();
+        let symbol = arena.create_symbol_static(FIRST);
+        assert!(arena.create_symbol_static(FIRST) == symbol);
+        assert!(arena.create_symbol_static(&SECOND) == symbol);
+        assert!(arena.create_symbol("symbol".into()) == symbol);
+    }
+
+    #[test]
+    fn dump_from() {
+        let mut arena = ValueArena::new();
+        let symbol = arena.create_symbol("sym".into());
+        let one = arena.create_fixnum(1);
+        let inner = arena.create_array(vec![one.into(), symbol.into()]);
+        let root = arena.create_array(vec![symbol.into(), inner.into()]);
+        arena.replace_root(root);
+
+        // The symbol was first written outside the subtree, but it is written in full here.
+        let mut data = Vec::new();
+        crate::dump_from(&mut data, &arena, inner).expect("failed to dump");
+        assert!(data == b"\x04\x08[\x07i\x06:\x08sym", "{data:?}");
+
+        let mut invalid_arena = ValueArena::new();
+        let invalid = invalid_arena.create_fixnum(1);
+        let error =
+            crate::dump_from(&mut Vec::new(), &arena, invalid).expect_err("dump should fail");
+        assert!(
+            matches!(error, Error::InvalidValueHandle { .. }),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn rename_symbol() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Foo".into());
+        let ivar = value_arena.create_symbol("@foo".into());
+        let fixnum = value_arena.create_fixnum(1).into();
+        let object = value_arena.create_object(name, vec![(ivar, fixnum)]);
+        value_arena.replace_root(object);
+
+        assert!(!value_arena.rename_symbol(b"Missing", b"Bar"));
+        assert!(value_arena.rename_symbol(b"Foo", b"Bar"));
+        assert!(
+            value_arena
+                .get_symbol(name)
+                .expect("missing symbol")
+                .value()
+                == b"Bar"
+        );
+        assert!(value_arena.create_symbol("Bar".into()) == name);
+        assert!(value_arena.create_symbol("Foo".into()) != name);
+
+        // Renaming onto an existing symbol merges the two.
+        let bar = value_arena.create_symbol("@bar".into());
+        let array = value_arena.create_array(vec![object.into(), ivar.into(), bar.into()]);
+        value_arena.replace_root(array);
+        assert!(value_arena.rename_symbol(b"@foo", b"@bar"));
+        let object = value_arena
+            .get(object)
+            .and_then(|value| value.as_object())
+            .expect("missing object");
+        assert!(object.instance_variable_count() == 1);
+        assert!(object.instance_variables()[0].0 == bar);
+
+        let mut data = Vec::new();
+        dump(&mut data, &value_arena).expect("failed to dump");
+        let expected = [
+            4, 8, b'[', 8, b'o', b':', 8, b'B', b'a', b'r', 6, b':', 9, b'@', b'b', b'a', b'r',
+            b'i', 6, b';', 6, b';', 6,
+        ];
+        assert!(data == expected, "{data:?}");
+    }
+
+    #[test]
+    fn replace_references() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Foo".into());
+        let ivar = value_arena.create_symbol("@foo".into());
+        let old = value_arena.create_string("old".into()).into();
+        let new = value_arena.create_string("new".into()).into();
+        let object = value_arena.create_object(name, vec![(ivar, old)]).into();
+        let hash = value_arena.create_hash(vec![(old, old)], Some(old)).into();
+        let array = value_arena.create_array(vec![old, object, hash]);
+        value_arena.replace_root(array);
+
+        assert!(value_arena.replace_references(old, new) == 5);
+        assert!(value_arena.replace_references(old, new) == 0);
+        let items = match value_arena.get(array) {
+            Some(Value::Array(value)) => value.value(),
+            _ => panic!("missing array"),
+        };
+        assert!(items[0] == new);
+        value_arena.validate().expect("arena should be valid");
+
+        // Names are only rewritten to symbols.
+        assert!(value_arena.replace_references(name, new) == 0);
+        let bar = value_arena.create_symbol("Bar".into());
+        assert!(value_arena.replace_references(name, bar) == 1);
+        let object = value_arena
+            .get(object)
+            .and_then(|value| value.as_object())
+            .expect("missing object");
+        assert!(object.name() == bar);
+
+        assert!(value_arena.replace_references(array, new) == 1);
+        assert!(value_arena.root() == new);
+    }
+
+    #[test]
+    fn leaf_value_keys() {
+        let mut value_arena = ValueArena::new();
+        let symbol = value_arena.create_symbol("a".into());
+        let bool_value = value_arena.create_bool(true).into();
+        let nil = value_arena.create_nil().into();
+
+        let mut other_value_arena = ValueArena::new();
+        let other_symbol = other_value_arena.create_symbol("a".into());
+        let other_bool_value = other_value_arena.create_bool(true).into();
+        let other_nil = other_value_arena.create_new_nil().into();
+
+        let mut symbols = std::collections::HashMap::new();
+        symbols.insert(
+            value_arena
+                .get_symbol(symbol)
+                .expect("missing symbol")
+                .clone(),
+            1,
+        );
+        let other_symbol = other_value_arena
+            .get_symbol(other_symbol)
+            .expect("missing symbol");
+        assert!(symbols.get(other_symbol) == Some(&1));
+
+        let get_bool = |value_arena: &ValueArena, handle: ValueHandle| match value_arena.get(handle)
+        {
+            Some(Value::Bool(value)) => *value,
+            value => panic!("expected a bool, got {value:?}"),
+        };
+        let mut bools = std::collections::HashSet::new();
+        bools.insert(get_bool(&value_arena, bool_value));
+        assert!(bools.contains(&get_bool(&other_value_arena, other_bool_value)));
+
+        let get_nil = |value_arena: &ValueArena, handle: ValueHandle| match value_arena.get(handle)
+        {
+            Some(Value::Nil(value)) => value.clone(),
+            value => panic!("expected nil, got {value:?}"),
+        };
+        assert!(get_nil(&value_arena, nil) == get_nil(&other_value_arena, other_nil));
+    }
+
+    #[test]
+    fn resolved_instance_variables() {
+        let mut value_arena = ValueArena::new();
+        let name = value_arena.create_symbol("Point".into());
+        let x_name = value_arena.create_symbol("@x".into());
+        let y_name = value_arena.create_symbol("@y".into());
+        let x = value_arena.create_fixnum(1).into();
+        let y = value_arena.create_fixnum(2).into();
+        let object = value_arena.create_object(name, vec![(x_name, x), (y_name, y)]);
+
+        let object = match value_arena.get(object) {
+            Some(Value::Object(object)) => object,
+            value => panic!("expected an object, got {value:?}"),
+        };
+        let instance_variables: Vec<_> = object.resolved_instance_variables(&value_arena).collect();
+        assert!(
+            instance_variables == [(&b"@x"[..], x), (&b"@y"[..], y)],
+            "{instance_variables:?}"
+        );
+
+        // Names from another arena are skipped.
+        let other_value_arena = ValueArena::new();
+        assert!(object
+            .resolved_instance_variables(&other_value_arena)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn as_str() {
+        let mut value_arena = ValueArena::new();
+        let symbol = value_arena.create_symbol("foo".into());
+        let invalid_symbol = value_arena.create_symbol(vec![0xFF]);
+        let string = value_arena.create_string("bar".into());
+        let invalid_string = value_arena.create_string(vec![0xC3]);
+
+        let symbol = value_arena.get_symbol(symbol).expect("missing symbol");
+        assert!(symbol.as_str() == Ok("foo"));
+        let invalid_symbol = value_arena
+            .get_symbol(invalid_symbol)
+            .expect("missing symbol");
+        assert!(invalid_symbol.as_str().is_err());
+
+        let string = value_arena
+            .get(string)
+            .and_then(|value| value.as_string())
+            .expect("missing string");
+        assert!(string.as_str() == Ok("bar"));
+        let invalid_string = value_arena
+            .get(invalid_string)
+            .and_then(|value| value.as_string())
+            .expect("missing string");
+        assert!(invalid_string.as_str().is_err());
+    }
 }
@@ -1,359 +1,1571 @@
-use crate::ArrayValue;
+mod state_machine;
+mod token;
+
+use self::state_machine::Need;
+use self::state_machine::StateMachine;
+pub use self::token::FixnumUsage;
+pub use self::token::Token;
+pub use self::token::TokenKind;
+use crate::io::Read;
 use crate::Error;
-use crate::FixnumValue;
+use crate::FromValue;
+#[cfg(feature = "std")]
+use crate::FromValueContext;
+#[cfg(feature = "std")]
+use crate::FromValueError;
+#[cfg(feature = "std")]
+use crate::HashMapFromValueError;
+#[cfg(feature = "std")]
 use crate::HashValue;
-use crate::ObjectValue;
-use crate::StringValue;
+#[cfg(feature = "std")]
+use crate::PathSegment;
 use crate::SymbolValue;
 use crate::TypedValueHandle;
-use crate::UserDefinedValue;
-use crate::Value;
 use crate::ValueArena;
 use crate::ValueHandle;
 use crate::MAJOR_VERSION;
 use crate::MINOR_VERSION;
-use crate::VALUE_KIND_ARRAY;
-use crate::VALUE_KIND_FALSE;
-use crate::VALUE_KIND_FIXNUM;
-use crate::VALUE_KIND_HASH;
-use crate::VALUE_KIND_HASH_DEFAULT;
-use crate::VALUE_KIND_INSTANCE_VARIABLES;
-use crate::VALUE_KIND_NIL;
-use crate::VALUE_KIND_OBJECT;
-use crate::VALUE_KIND_OBJECT_LINK;
-use crate::VALUE_KIND_STRING;
-use crate::VALUE_KIND_SYMBOL;
-use crate::VALUE_KIND_SYMBOL_LINK;
-use crate::VALUE_KIND_TRUE;
-use crate::VALUE_KIND_USER_DEFINED;
-use std::io::Read;
+#[cfg(feature = "std")]
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncRead;
+#[cfg(feature = "tokio")]
+use tokio::io::AsyncReadExt;
+
+/// The maximum number of elements to preallocate space for, based on a length read from the input.
+///
+/// Lengths come from untrusted input, so they cannot be used to size allocations directly.
+/// Larger collections will still load, but will grow as elements are read.
+const MAX_PREALLOCATED_LEN: usize = 1024;
+
+/// Grow a buffer that is being filled with `len` bytes by the next chunk.
+///
+/// The length comes from untrusted input, and may be far larger than the amount of remaining data.
+/// Instead of allocating it all up front, the buffer grows in bounded chunks as the data arrives.
+///
+/// # Returns
+/// Returns the new chunk, which must be filled before calling this again,
+/// or `None` if the buffer has all `len` bytes.
+fn next_read_chunk(buffer: &mut Vec<u8>, len: usize) -> Option<&mut [u8]> {
+    let start = buffer.len();
+    if start >= len {
+        return None;
+    }
+
+    let end = start + (len - start).min(MAX_PREALLOCATED_LEN);
+    buffer.resize(end, 0);
+
+    Some(&mut buffer[start..])
+}
+
+/// A non-fatal problem encountered while loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadWarning {
+    /// The version in the header is not supported, but loading continued anyway.
+    ///
+    /// This is only reported if [`LoaderOptions::allow_version_mismatch`] is enabled.
+    VersionMismatch {
+        /// The major version
+        major: u8,
+
+        /// The minor version
+        minor: u8,
+    },
+}
+
+impl core::fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::VersionMismatch { major, minor } => write!(
+                f,
+                "unsupported version {major}.{minor}, loading as {MAJOR_VERSION}.{MINOR_VERSION}"
+            ),
+        }
+    }
+}
+
+/// A callback that receives warnings while loading.
+pub type WarningHandler = Arc<dyn Fn(&LoadWarning) + Send + Sync>;
+
+/// A handler for value kinds that Ruby writes, but that this crate does not model.
+///
+/// These are `e`, a value extended with a module, `C`, an instance of a subclass of a builtin type,
+/// and `d`, a wrapped C data object.
+/// Each is written as a symbol naming the module or class, followed by a value.
+/// The loader reads both into the arena, then asks the extension to build the final value from them.
+///
+/// The returned value takes the place of the whole, so dumping it does not write the original kind.
+/// Later object links to the whole resolve to the returned value.
+///
+/// This is called after the value is read, so it cannot change how the bytes are parsed.
+/// Other kinds are not passed to the extension.
+pub trait LoaderExtension: Send + Sync {
+    /// Build a value from one of the unmodeled kinds.
+    ///
+    /// `name` is the module or class name, and `value` is the value that followed it.
+    /// The returned handle may be `value` itself, or a new value created in `arena`.
+    ///
+    /// Return `None` to fail loading with [`Error::InvalidValueKind`].
+    fn load(
+        &self,
+        kind: u8,
+        name: TypedValueHandle<SymbolValue>,
+        value: ValueHandle,
+        arena: &mut ValueArena,
+    ) -> Option<ValueHandle>;
+}
+
+/// Options for loading.
+#[derive(Default, Clone)]
+pub struct LoaderOptions {
+    /// Whether to preserve the original encoding of fixnum values.
+    ///
+    /// A fixnum may be encoded in more than one way.
+    /// Ruby always uses the shortest form, but other tools might not.
+    /// By default, fixnum values are dumped in the shortest form.
+    /// If this is enabled, fixnum values are dumped with the same encoding they were loaded with,
+    /// so that non-canonical inputs round-trip exactly.
+    ///
+    /// This only applies to fixnum values, not lengths or link indices.
+    pub preserve_fixnum_encoding: bool,
+
+    /// Whether to attempt to load data with an unsupported version.
+    ///
+    /// Only version 4.8 is supported, which every Ruby since 1.8 writes.
+    /// By default, any other version fails with [`Error::InvalidVersion`], including older minor versions.
+    /// If this is enabled, a [`LoadWarning::VersionMismatch`] is reported instead,
+    /// and the data is loaded as if it were the supported version.
+    /// This may still fail or produce incorrect values if the format differs.
+    pub allow_version_mismatch: bool,
+
+    /// Whether to require that the reader has no data after the loaded value.
+    ///
+    /// By default, loading stops after one value, and any data after it is left in the reader.
+    /// If this is enabled, loading fails with [`Error::TrailingData`] if there is more data.
+    /// This is useful for catching corrupt or accidentally concatenated input.
+    /// Checking reads one more byte from the reader, so this should not be used
+    /// when more values follow in the same reader, like with a [`StatefulLoader`].
+    pub require_eof: bool,
+
+    /// A callback that receives warnings while loading.
+    ///
+    /// If this is not set, warnings are ignored.
+    pub warning_handler: Option<WarningHandler>,
 
+    /// A handler for value kinds that this crate does not model.
+    ///
+    /// If this is not set, these kinds fail with [`Error::InvalidValueKind`].
+    /// Kinds that Ruby never writes always fail.
+    pub extension: Option<Arc<dyn LoaderExtension>>,
+}
+
+impl LoaderOptions {
+    /// Make new [`LoaderOptions`] with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report a warning to the warning handler, if there is one.
+    fn warn(&self, warning: LoadWarning) {
+        if let Some(warning_handler) = self.warning_handler.as_ref() {
+            warning_handler(&warning);
+        }
+    }
+}
+
+impl core::fmt::Debug for LoaderOptions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LoaderOptions")
+            .field("preserve_fixnum_encoding", &self.preserve_fixnum_encoding)
+            .field("allow_version_mismatch", &self.allow_version_mismatch)
+            .field("require_eof", &self.require_eof)
+            .field("warning_handler", &self.warning_handler.is_some())
+            .field("extension", &self.extension.is_some())
+            .finish()
+    }
+}
+
+/// A loader around a blocking reader.
 #[derive(Debug)]
-struct Loader<R> {
+struct Loader<'a, R> {
     reader: R,
+    state_machine: &'a mut StateMachine,
 
-    arena: ValueArena,
+    /// The number of bytes read so far.
+    offset: usize,
 
-    symbol_links: Vec<TypedValueHandle<SymbolValue>>,
-    object_links: Vec<ValueHandle>,
+    /// The offset of the start of the current token.
+    token_offset: usize,
 }
 
-impl<R> Loader<R> {
-    /// Make a new [`Loader`] around a reader.
-    fn new(reader: R) -> Self {
-        let arena = ValueArena::new();
-
+impl<'a, R> Loader<'a, R> {
+    /// Make a new [`Loader`] around a reader, that feeds a state machine.
+    fn new(reader: R, state_machine: &'a mut StateMachine) -> Self {
         Self {
             reader,
-            arena,
-            symbol_links: Vec::new(),
-            object_links: Vec::new(),
+            state_machine,
+            offset: 0,
+            token_offset: 0,
         }
     }
 }
 
-impl<R> Loader<R>
+impl<R> Loader<'_, R>
 where
     R: Read,
 {
     /// Read a byte
     fn read_byte(&mut self) -> Result<u8, Error> {
         let mut byte = 0;
-        self.reader.read_exact(std::slice::from_mut(&mut byte))?;
+        self.reader.read_exact(core::slice::from_mut(&mut byte))?;
         Ok(byte)
     }
 
-    /// Read a byte string.
-    ///
-    /// A byte string is a fixnum length, then that number of bytes.
-    fn read_byte_string(&mut self) -> Result<Vec<u8>, Error> {
-        let len = self.read_fixnum_value()?;
-        let len = usize::try_from(len).map_err(|error| Error::FixnumInvalidUSize { error })?;
-
-        let mut value = vec![0; len];
-        self.reader.read_exact(&mut value)?;
+    /// Read exactly `len` bytes.
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut value = Vec::new();
+        while let Some(chunk) = next_read_chunk(&mut value, len) {
+            self.reader.read_exact(chunk)?;
+        }
 
         Ok(value)
     }
 
-    /// Read and validate the header.
-    fn read_header(&mut self) -> Result<(), Error> {
-        let major_version = self.read_byte()?;
-        let minor_version = self.read_byte()?;
+    /// Load a value from the reader into the state machine.
+    ///
+    /// `on_token` is called with each token as it is completed.
+    ///
+    /// # Returns
+    /// Returns the number of bytes that the value took up.
+    fn load<F>(mut self, mut on_token: F) -> Result<usize, Error>
+    where
+        F: FnMut(Token),
+    {
+        loop {
+            match self.state_machine.need() {
+                Need::Byte => {
+                    let byte = self.read_byte()?;
+                    self.offset += 1;
+                    self.state_machine.feed_byte(byte)?;
+                }
+                Need::Bytes(len) => {
+                    let bytes = self.read_bytes(len)?;
+                    self.offset += len;
+                    self.state_machine.feed_bytes(bytes)?;
+                }
+                Need::Done => break,
+            }
+
+            if let Some(kind) = self.state_machine.take_token() {
+                on_token(Token {
+                    offset: self.token_offset,
+                    len: self.offset - self.token_offset,
+                    kind,
+                });
+                self.token_offset = self.offset;
+            }
+        }
 
-        if major_version != MAJOR_VERSION || minor_version > MINOR_VERSION {
-            return Err(Error::InvalidVersion {
-                major: major_version,
-                minor: minor_version,
-            });
+        if self.state_machine.options().require_eof {
+            match self.read_byte() {
+                Ok(_byte) => {
+                    return Err(Error::TrailingData {
+                        offset: self.offset,
+                    })
+                }
+                Err(Error::Io { error }) if crate::io::is_unexpected_eof(&error) => {}
+                Err(error) => return Err(error),
+            }
         }
 
-        Ok(())
+        Ok(self.offset)
     }
+}
 
-    /// Read a fixnum value
-    fn read_fixnum_value(&mut self) -> Result<i32, Error> {
-        let len = self.read_byte()?;
-        if len == 0 {
-            return Ok(0);
-        }
-        let positive = (len as i8) > 0;
-        let byte = len;
+/// Load from a reader.
+///
+/// This reads exactly one value, so more data may follow it in the reader.
+/// Values are loaded without recursion, so deeply nested input cannot overflow the stack.
+///
+/// Self-referential values contain their own handle.
+/// Extracting them with [`FromValue`] fails with a [`FromValueError::Cycle`](crate::FromValueError::Cycle) error.
+pub fn load<R>(reader: R) -> Result<ValueArena, Error>
+where
+    R: Read,
+{
+    load_with_options(reader, LoaderOptions::default())
+}
 
-        if positive {
-            if byte > 4 {
-                return Ok(i32::from(byte) - 5);
-            }
+/// Load from a reader, with options.
+pub fn load_with_options<R>(reader: R, options: LoaderOptions) -> Result<ValueArena, Error>
+where
+    R: Read,
+{
+    let mut state_machine = StateMachine::new(options);
+    Loader::new(reader, &mut state_machine).load(|_token| {})?;
 
-            if usize::from(byte) > std::mem::size_of::<i32>() {
-                return Err(Error::InvalidFixnumSize { size: byte });
-            }
+    Ok(state_machine.finish())
+}
 
-            let mut n: i32 = 0;
-            for i in 0..byte {
-                let byte = self.read_byte()?;
-                n |= i32::from(byte) << (i * 8);
-            }
+/// Load from a reader, and count the bytes that the value took up.
+///
+/// This is useful when the value is followed by other data,
+/// as the reader can be advanced past it, like [`ValueArena::serialized_len`] for dumping.
+pub fn load_counted<R>(reader: R) -> Result<(ValueArena, u64), Error>
+where
+    R: Read,
+{
+    let mut state_machine = StateMachine::new(LoaderOptions::default());
+    let len = Loader::new(reader, &mut state_machine).load(|_token| {})?;
 
-            Ok(n)
-        } else {
-            if (byte as i8) < -4 {
-                return Ok(i32::from(byte as i8) + 5);
-            }
+    // Lengths always fit in a u64 on supported targets.
+    Ok((state_machine.finish(), u64::try_from(len).unwrap()))
+}
 
-            let byte = -(byte as i8) as u8;
-            if usize::from(byte) > std::mem::size_of::<i32>() {
-                return Err(Error::InvalidFixnumSize { size: byte });
-            }
+/// Load from a reader, with options, reporting each token of the input as it is read.
+///
+/// This is useful for inspecting the layout of the input.
+/// If loading fails, the tokens before the failure will have been reported.
+pub fn load_with_tokens<R, F>(
+    reader: R,
+    options: LoaderOptions,
+    on_token: F,
+) -> Result<ValueArena, Error>
+where
+    R: Read,
+    F: FnMut(Token),
+{
+    let mut state_machine = StateMachine::new(options);
+    Loader::new(reader, &mut state_machine).load(on_token)?;
 
-            let mut n: i32 = -1;
-            for i in 0..byte {
-                n &= !(0xFF_i32 << (i * 8));
-                n |= i32::from(self.read_byte()?) << (i * 8);
-            }
+    Ok(state_machine.finish())
+}
 
-            Ok(n)
+/// A loader that reads several values into one arena, one after another.
+///
+/// Symbol and object links are shared between values,
+/// so a value may link to symbols and objects from an earlier value.
+/// This is how data written by a [`StatefulDumper`](crate::StatefulDumper) is loaded.
+/// Values must be loaded in the same order that they were dumped.
+#[derive(Debug)]
+pub struct StatefulLoader {
+    state_machine: StateMachine,
+}
+
+impl StatefulLoader {
+    /// Make a new [`StatefulLoader`] with an empty arena.
+    pub fn new(options: LoaderOptions) -> Self {
+        Self {
+            state_machine: StateMachine::new(options),
         }
     }
 
-    /// Read a fixnum.
-    fn read_fixnum(&mut self) -> Result<TypedValueHandle<FixnumValue>, Error> {
-        let value = self.read_fixnum_value()?;
-        Ok(self.arena.create_fixnum(value))
+    /// Load the next value from a reader.
+    ///
+    /// This reads exactly one value, so more data may follow it in the reader.
+    /// If this fails, the links of later values may be wrong, so the loader should not be used again.
+    ///
+    /// # Returns
+    /// Returns the handle of the value, which is also the new root of the arena.
+    pub fn load<R>(&mut self, reader: R) -> Result<ValueHandle, Error>
+    where
+        R: Read,
+    {
+        self.state_machine.restart();
+        Loader::new(reader, &mut self.state_machine).load(|_token| {})?;
+
+        Ok(self.state_machine.arena().root())
     }
 
-    /// Read a symbol.
-    fn read_symbol(&mut self) -> Result<TypedValueHandle<SymbolValue>, Error> {
-        let symbol = self.read_byte_string()?;
-        let handle = self.arena.create_symbol(symbol);
+    /// Get the arena that values are loaded into.
+    pub fn arena(&self) -> &ValueArena {
+        self.state_machine.arena()
+    }
 
-        self.symbol_links.push(handle);
+    /// Get the arena that values were loaded into.
+    pub fn into_arena(self) -> ValueArena {
+        self.state_machine.into_arena()
+    }
+}
 
-        Ok(handle)
+/// Load from bytes, then convert the root value.
+pub fn from_bytes<T>(data: &[u8]) -> Result<T, Error>
+where
+    T: for<'a> FromValue<'a>,
+{
+    let value_arena = load(data)?;
+    let value = value_arena.root_value()?;
+
+    Ok(value)
+}
+
+/// Load from a reader, where the root value is a hash with string or symbol keys.
+///
+/// Keys must be UTF-8.
+/// Values are left as handles into the returned arena, so they can be extracted as needed.
+/// Like extracting a [`HashMap`], this fails if the hash has a default value or two keys are the same.
+#[cfg(feature = "std")]
+pub fn load_hash<R>(reader: R) -> Result<(ValueArena, HashMap<String, ValueHandle>), Error>
+where
+    R: Read,
+{
+    let value_arena = load(reader)?;
+
+    let ctx = FromValueContext::new(&value_arena);
+    let root: &HashValue = ctx.from_value(value_arena.root())?;
+    if let Some(default_value) = root.default_value() {
+        return Err(
+            FromValueError::new_other(HashMapFromValueError::HasDefaultValue {
+                value: default_value,
+            })
+            .into(),
+        );
     }
 
-    /// Read a symbol link.
-    fn read_symbol_link(&mut self) -> Result<TypedValueHandle<SymbolValue>, Error> {
-        let index = self.read_fixnum_value()?;
-        let index = usize::try_from(index).map_err(|error| Error::FixnumInvalidUSize { error })?;
+    let mut map = HashMap::with_capacity(root.len());
+    for (key_handle, value_handle) in root.value().iter().copied() {
+        let key: &str = ctx.from_value_at(PathSegment::HashKey(key_handle), key_handle)?;
+        if map.insert(key.to_string(), value_handle).is_some() {
+            return Err(FromValueError::DuplicateHashKey { key: key_handle }.into());
+        }
+    }
+
+    Ok((value_arena, map))
+}
+
+/// Load from an async reader.
+///
+/// This reads exactly one value, so more data may follow it in the reader.
+/// The reader is read in small pieces, so it should be buffered.
+#[cfg(feature = "tokio")]
+pub async fn load_async<R>(reader: R) -> Result<ValueArena, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    load_async_with_options(reader, LoaderOptions::default()).await
+}
 
-        let value = self
-            .symbol_links
-            .get(index)
-            .ok_or(Error::MissingSymbolLink { index })?;
+/// Load from an async reader, with options.
+///
+/// This reads exactly one value, so more data may follow it in the reader.
+/// The reader is read in small pieces, so it should be buffered.
+#[cfg(feature = "tokio")]
+pub async fn load_async_with_options<R>(
+    mut reader: R,
+    options: LoaderOptions,
+) -> Result<ValueArena, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut state_machine = StateMachine::new(options);
+    let mut offset = 0;
+    loop {
+        match state_machine.need() {
+            Need::Byte => {
+                let byte = reader.read_u8().await?;
+                offset += 1;
+                state_machine.feed_byte(byte)?;
+            }
+            Need::Bytes(len) => {
+                let mut bytes = Vec::new();
+                while let Some(chunk) = next_read_chunk(&mut bytes, len) {
+                    reader.read_exact(chunk).await?;
+                }
+                offset += len;
 
-        Ok(*value)
+                state_machine.feed_bytes(bytes)?;
+            }
+            Need::Done => break,
+        }
     }
 
-    /// Read an object link
-    fn read_object_link(&mut self) -> Result<ValueHandle, Error> {
-        let index = self.read_fixnum_value()?;
-        let index = usize::try_from(index).map_err(|error| Error::FixnumInvalidUSize { error })?;
+    if state_machine.options().require_eof {
+        let mut byte = [0];
+        if reader.read(&mut byte).await? != 0 {
+            return Err(Error::TrailingData { offset });
+        }
+    }
 
-        let value = self
-            .object_links
-            .get(index)
-            .ok_or(Error::MissingObjectLink { index })?;
+    Ok(state_machine.finish())
+}
 
-        Ok(*value)
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FromValueContext;
+    use crate::FromValueError;
+    use crate::HashValue;
+    use crate::VALUE_KIND_ARRAY;
+    use crate::VALUE_KIND_FALSE;
+    use crate::VALUE_KIND_FIXNUM;
+    use crate::VALUE_KIND_HASH;
+    use crate::VALUE_KIND_HASH_DEFAULT;
+    use crate::VALUE_KIND_INSTANCE_VARIABLES;
+    use crate::VALUE_KIND_NIL;
+    use crate::VALUE_KIND_OBJECT;
+    use crate::VALUE_KIND_OBJECT_LINK;
+    use crate::VALUE_KIND_OLD_MODULE;
+    use crate::VALUE_KIND_STRING;
+    use crate::VALUE_KIND_SYMBOL;
+    use crate::VALUE_KIND_SYMBOL_LINK;
+    use crate::VALUE_KIND_TRUE;
+    use crate::VALUE_KIND_USER_DEFINED;
+    use alloc::vec;
+
+    /// A value that uses every kind of value.
+    const ROUND_TRIP_DATA: &[u8] = &[
+        MAJOR_VERSION,
+        MINOR_VERSION,
+        // An array with 9 elements.
+        VALUE_KIND_ARRAY,
+        0x0E,
+        // 300
+        VALUE_KIND_FIXNUM,
+        0x02,
+        0x2C,
+        0x01,
+        // -256
+        VALUE_KIND_FIXNUM,
+        0xFF,
+        0x00,
+        // "a", with E = true
+        VALUE_KIND_INSTANCE_VARIABLES,
+        VALUE_KIND_STRING,
+        0x06,
+        b'a',
+        0x06,
+        VALUE_KIND_SYMBOL,
+        0x06,
+        b'E',
+        VALUE_KIND_TRUE,
+        // :foo
+        VALUE_KIND_SYMBOL,
+        0x08,
+        b'f',
+        b'o',
+        b'o',
+        // :foo, as a link
+        VALUE_KIND_SYMBOL_LINK,
+        0x06,
+        // { 1 => nil }, with a default of false
+        VALUE_KIND_HASH_DEFAULT,
+        0x06,
+        VALUE_KIND_FIXNUM,
+        0x06,
+        VALUE_KIND_NIL,
+        VALUE_KIND_FALSE,
+        // #<Foo @a = "a">
+        VALUE_KIND_OBJECT,
+        VALUE_KIND_SYMBOL,
+        0x08,
+        b'F',
+        b'o',
+        b'o',
+        0x06,
+        VALUE_KIND_SYMBOL,
+        0x07,
+        b'@',
+        b'a',
+        VALUE_KIND_OBJECT_LINK,
+        0x06,
+        // A user defined value named B, with E = true on the name
+        VALUE_KIND_USER_DEFINED,
+        VALUE_KIND_INSTANCE_VARIABLES,
+        VALUE_KIND_SYMBOL,
+        0x06,
+        b'B',
+        0x06,
+        VALUE_KIND_SYMBOL_LINK,
+        0x00,
+        VALUE_KIND_TRUE,
+        0x07,
+        b'a',
+        b'b',
+        // []
+        VALUE_KIND_ARRAY,
+        0x00,
+    ];
+
+    /// Load a value from data that has the header prepended.
+    fn load_value(data: &[u8]) -> Result<ValueArena, Error> {
+        let mut input = vec![MAJOR_VERSION, MINOR_VERSION];
+        input.extend(data);
+
+        load(&*input)
     }
 
-    /// Read instance variables.
-    fn read_instance_variables(
-        &mut self,
-    ) -> Result<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>, Error> {
-        let num_pairs = self.read_fixnum_value()?;
-        let num_pairs =
-            usize::try_from(num_pairs).map_err(|error| Error::FixnumInvalidUSize { error })?;
+    #[test]
+    fn max_len() {
+        // A length of i32::MAX, with no data following it.
+        let max_len = [0x04, 0xFF, 0xFF, 0xFF, 0x7F];
 
-        // TODO: Consider making this a map.
-        let mut instance_variables = Vec::with_capacity(num_pairs);
-        for _ in 0..num_pairs {
-            let symbol = self.read_value_symbol_like()?;
-            let value = self.read_value()?;
+        for kind in [VALUE_KIND_ARRAY, VALUE_KIND_HASH, VALUE_KIND_STRING] {
+            let mut data = vec![kind];
+            data.extend(max_len);
 
-            instance_variables.push((symbol, value));
+            let error = load_value(&data).expect_err("load should fail");
+            assert!(
+                matches!(&error, Error::Io { error } if crate::io::is_unexpected_eof(error)),
+                "{error:?}"
+            );
         }
+    }
+
+    #[test]
+    fn preserve_fixnum_encoding() {
+        // 5, in a non-canonical 1 byte form.
+        let data = [MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FIXNUM, 0x01, 0x05];
 
-        Ok(instance_variables)
+        let arena = load(&data[..]).expect("failed to load");
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &arena).expect("failed to dump");
+        assert!(new_data == [MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FIXNUM, 0x0A]);
+
+        let options = LoaderOptions {
+            preserve_fixnum_encoding: true,
+            ..LoaderOptions::default()
+        };
+        let arena = load_with_options(&data[..], options).expect("failed to load");
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &arena).expect("failed to dump");
+        assert!(new_data == data);
     }
 
-    /// Read an array
-    fn read_array(&mut self) -> Result<TypedValueHandle<ArrayValue>, Error> {
-        let handle = self.arena.create_nil().into_raw();
-        self.object_links.push(handle);
+    #[test]
+    fn fixnum_lengths() {
+        let cases: [(&[u8], i32); 10] = [
+            (&[0x01, 0xFF], 0xFF),
+            (&[0x02, 0xFF, 0xFF], 0xFFFF),
+            (&[0x03, 0xFF, 0xFF, 0xFF], 0xFF_FFFF),
+            (&[0x04, 0xFF, 0xFF, 0xFF, 0x7F], i32::MAX),
+            (&[0x04, 0x00, 0x00, 0x00, 0x01], 1 << 24),
+            (&[0xFF, 0x00], -0x100),
+            (&[0xFE, 0x00, 0x00], -0x1_0000),
+            (&[0xFD, 0x00, 0x00, 0x00], -0x100_0000),
+            (&[0xFC, 0x00, 0x00, 0x00, 0x80], i32::MIN),
+            (&[0xFC, 0xFF, 0xFF, 0xFF, 0xFE], -(1 << 24) - 1),
+        ];
+        for (bytes, expected) in cases {
+            let mut data = vec![MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FIXNUM];
+            data.extend(bytes);
+
+            let arena = load(&data[..]).expect("failed to load");
+            let value = match &arena[arena.root()] {
+                crate::Value::Fixnum(value) => value.value(),
+                value => panic!("expected a fixnum, got {value:?}"),
+            };
+            assert!(value == expected, "{value} != {expected}");
+        }
 
-        let len = self.read_fixnum_value()?;
-        let len = usize::try_from(len).map_err(|error| Error::FixnumInvalidUSize { error })?;
-        let mut array_value = Vec::with_capacity(len);
+        let cases: [(&[u8], i64); 4] = [
+            (&[0x04, 0x00, 0x00, 0x00, 0x80], 1 << 31),
+            (&[0x04, 0xFF, 0xFF, 0xFF, 0xFF], (1 << 32) - 1),
+            (&[0xFC, 0xFF, 0xFF, 0xFF, 0x7F], -(1 << 31) - 1),
+            (&[0xFC, 0x00, 0x00, 0x00, 0x00], -(1 << 32)),
+        ];
+        for (bytes, expected) in cases {
+            let mut data = vec![MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FIXNUM];
+            data.extend(bytes);
+
+            let error = load(&data[..]).expect_err("load should fail");
+            assert!(
+                matches!(error, Error::FixnumOutOfRange { value } if value == expected),
+                "{error:?}"
+            );
+        }
+    }
 
-        for _ in 0..len {
-            let value = self.read_value()?;
-            array_value.push(value);
+    #[test]
+    fn negative_fixnums() {
+        // Encodings from Ruby's Marshal.dump, except where noted.
+        let cases: [(&[u8], i32); 10] = [
+            (&[0xFA], -1),
+            (&[0x80], -123),
+            (&[0xFF, 0x84], -124),
+            (&[0xFF, 0x00], -256),
+            (&[0xFE, 0xFF, 0xFE], -257),
+            (&[0xFD, 0x60, 0x79, 0xFE], -100_000),
+            (&[0xFD, 0x00, 0x00, 0x00], -16_777_216),
+            (&[0xFC, 0xFF, 0xFF, 0xFF, 0xFE], -16_777_217),
+            (&[0xFC, 0x00, 0x00, 0x00, 0xC0], -1_073_741_824),
+            // Hand-encoded, as Ruby dumps values below -2**30 as Bignums.
+            (&[0xFC, 0x01, 0x00, 0x00, 0x80], i32::MIN + 1),
+        ];
+        for (bytes, expected) in cases {
+            let mut data = vec![MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_FIXNUM];
+            data.extend(bytes);
+
+            let arena = load(&data[..]).expect("failed to load");
+            let value = match &arena[arena.root()] {
+                crate::Value::Fixnum(value) => value.value(),
+                value => panic!("expected a fixnum, got {value:?}"),
+            };
+            assert!(value == expected, "{value} != {expected}");
+
+            let mut new_data = Vec::new();
+            crate::dump(&mut new_data, &arena).expect("failed to dump");
+            assert!(new_data == data, "{new_data:?} != {data:?}");
         }
+    }
 
-        *self.arena.get_mut(handle).unwrap() = ArrayValue::new(array_value).into();
+    #[test]
+    fn fixnum_round_trip() {
+        // This samples the i32 range instead of checking every value, which takes too long.
+        // Each length boundary is checked closely, and everything else sparsely.
+        let boundaries = (0..32).flat_map(|shift| {
+            let power = 1_i64 << shift;
+            [power, -power, power + 122, -power - 123]
+        });
+        let values = boundaries
+            .flat_map(|boundary| (boundary - 2)..=(boundary + 2))
+            .chain((i64::from(i32::MIN)..=i64::from(i32::MAX)).step_by(65_521))
+            .chain([i64::from(i32::MIN), i64::from(i32::MAX)])
+            .filter_map(|value| i32::try_from(value).ok());
+
+        let mut data = Vec::new();
+        for value in values {
+            let mut arena = ValueArena::new();
+            let handle = arena.create_fixnum(value);
+            arena.replace_root(handle);
+
+            data.clear();
+            crate::dump(&mut data, &arena).expect("failed to dump");
+            let arena = load(&data[..]).expect("failed to load");
+            let new_value = match &arena[arena.root()] {
+                crate::Value::Fixnum(value) => value.value(),
+                value => panic!("expected a fixnum, got {value:?}"),
+            };
+            assert!(new_value == value, "{new_value} != {value}");
+        }
+    }
 
-        Ok(TypedValueHandle::new_unchecked(handle))
+    #[test]
+    fn round_trip() {
+        let data = ROUND_TRIP_DATA;
+        let mut input = data.to_vec();
+        // Trailing data, which should not be read.
+        input.push(0xFF);
+
+        let mut reader = &input[..];
+        let arena = load(&mut reader).expect("failed to load");
+        assert!(reader == [0xFF]);
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?} != {data:?}");
+
+        let error = load(&data[..data.len() - 1]).expect_err("load should fail");
+        assert!(
+            matches!(&error, Error::Io { error } if crate::io::is_unexpected_eof(error)),
+            "{error:?}"
+        );
     }
 
-    /// Read a hash.
-    fn read_hash(&mut self, has_default_value: bool) -> Result<TypedValueHandle<HashValue>, Error> {
-        let handle = self.arena.create_nil().into_raw();
-        self.object_links.push(handle);
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_round_trip() {
+        let data = ROUND_TRIP_DATA;
+        let mut input = data.to_vec();
+        // Trailing data, which should not be read.
+        input.push(0xFF);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("failed to build runtime");
+
+        let mut reader = &input[..];
+        let arena = runtime
+            .block_on(load_async(&mut reader))
+            .expect("failed to load");
+        assert!(reader == [0xFF]);
+
+        let mut new_data = Vec::new();
+        runtime
+            .block_on(crate::dump_async(&mut new_data, &arena))
+            .expect("failed to dump");
+        assert!(new_data == data, "{new_data:?} != {data:?}");
+
+        let error = runtime
+            .block_on(load_async(&data[..data.len() - 1]))
+            .expect_err("load should fail");
+        assert!(
+            matches!(&error, Error::Io { error } if crate::io::is_unexpected_eof(error)),
+            "{error:?}"
+        );
+    }
 
-        let num_pairs = self.read_fixnum_value()?;
-        let num_pairs =
-            usize::try_from(num_pairs).map_err(|error| Error::FixnumInvalidUSize { error })?;
+    #[test]
+    fn deeply_nested() {
+        const DEPTH: usize = 100_000;
 
-        // TODO: Consider making this a map.
-        let mut pairs = Vec::with_capacity(num_pairs);
-        for _ in 0..num_pairs {
-            let key = self.read_value()?;
-            let value = self.read_value()?;
+        let mut data = Vec::with_capacity(DEPTH * 2 + 1);
+        for _ in 0..DEPTH {
+            // An array with 1 element.
+            data.extend([VALUE_KIND_ARRAY, 0x06]);
+        }
+        data.push(VALUE_KIND_NIL);
+
+        let arena = load_value(&data).expect("failed to load");
+
+        let mut handle = arena.root();
+        for _ in 0..DEPTH {
+            let array = match &arena[handle] {
+                crate::Value::Array(array) => array,
+                value => panic!("{value:?} is not an array"),
+            };
+            assert!(array.len() == 1);
+            handle = array.value()[0];
+        }
+        assert!(matches!(arena[handle], crate::Value::Nil(_)));
+    }
 
-            pairs.push((key, value));
+    #[test]
+    fn negative_len() {
+        // A length of -1.
+        for kind in [VALUE_KIND_ARRAY, VALUE_KIND_HASH, VALUE_KIND_STRING] {
+            let data = [kind, 0xFA];
+
+            let error = load_value(&data).expect_err("load should fail");
+            assert!(
+                matches!(error, Error::FixnumInvalidUSize { .. }),
+                "{error:?}"
+            );
         }
+    }
 
-        let default_value = if has_default_value {
-            Some(self.read_value()?)
-        } else {
-            None
+    #[test]
+    fn version_mismatch() {
+        let data = [MAJOR_VERSION, MINOR_VERSION + 1, VALUE_KIND_NIL];
+
+        let error = load(&data[..]).expect_err("load should fail");
+        assert!(
+            matches!(
+                error,
+                Error::InvalidVersion {
+                    major: MAJOR_VERSION,
+                    minor
+                } if minor == MINOR_VERSION + 1
+            ),
+            "{error:?}"
+        );
+
+        let warnings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = LoaderOptions {
+            allow_version_mismatch: true,
+            warning_handler: Some({
+                let warnings = warnings.clone();
+                Arc::new(move |warning: &LoadWarning| {
+                    warnings.lock().unwrap().push(*warning);
+                })
+            }),
+            ..LoaderOptions::default()
         };
+        let value_arena = load_with_options(&data[..], options).expect("failed to load");
+        assert!(matches!(
+            value_arena.get(value_arena.root()),
+            Some(crate::Value::Nil(_))
+        ));
+        assert!(
+            *warnings.lock().unwrap()
+                == [LoadWarning::VersionMismatch {
+                    major: MAJOR_VERSION,
+                    minor: MINOR_VERSION + 1,
+                }]
+        );
+    }
 
-        *self.arena.get_mut(handle).unwrap() = HashValue::new(pairs, default_value).into();
+    #[test]
+    fn old_minor_version() {
+        let data = [MAJOR_VERSION, MINOR_VERSION - 1, VALUE_KIND_NIL];
+
+        let error = load(&data[..]).expect_err("load should fail");
+        assert!(
+            matches!(
+                error,
+                Error::InvalidVersion {
+                    major: MAJOR_VERSION,
+                    minor
+                } if minor == MINOR_VERSION - 1
+            ),
+            "{error:?}"
+        );
+
+        let options = LoaderOptions {
+            allow_version_mismatch: true,
+            ..LoaderOptions::default()
+        };
+        let value_arena = load_with_options(&data[..], options).expect("failed to load");
+        assert!(matches!(
+            value_arena.get(value_arena.root()),
+            Some(crate::Value::Nil(_))
+        ));
+    }
 
-        Ok(TypedValueHandle::new_unchecked(handle))
+    #[test]
+    fn old_module() {
+        // [Foo, Foo], where the second is a link to the first.
+        let data = [
+            MAJOR_VERSION,
+            MINOR_VERSION,
+            VALUE_KIND_ARRAY,
+            0x07,
+            VALUE_KIND_OLD_MODULE,
+            0x08,
+            b'F',
+            b'o',
+            b'o',
+            VALUE_KIND_OBJECT_LINK,
+            0x06,
+        ];
+        let value_arena = load(&data[..]).expect("failed to load");
+
+        let ctx = FromValueContext::new(&value_arena);
+        let value: Vec<&crate::OldModuleValue> = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        assert!(value.len() == 2);
+        assert!(value[0].name() == b"Foo");
+        assert!(core::ptr::eq(value[0], value[1]));
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+
+        // Old format classes and modules cannot have instance variables.
+        let data = [
+            VALUE_KIND_INSTANCE_VARIABLES,
+            VALUE_KIND_OLD_MODULE,
+            0x08,
+            b'F',
+            b'o',
+            b'o',
+            0x00,
+        ];
+        let error = load_value(&data).expect_err("load should fail");
+        assert!(matches!(error, Error::NotAnObject), "{error:?}");
     }
 
-    /// Read an object
-    fn read_object(&mut self) -> Result<TypedValueHandle<ObjectValue>, Error> {
-        let handle = self.arena.create_nil().into_raw();
-        self.object_links.push(handle);
+    #[test]
+    fn core_types() {
+        // [1.5, -0.0, Float::NAN, -(2**32), /a/i, Point.new(1, 2), String, Kernel]
+        let data = b"\x04\x08[\x0Df\x081.5f\x07-0f\x08nanl-\x08\x00\x00\x00\x00\x01\x00I/\x06a\x01\x06:\x06EFS:\x0APoint\x07:\x06xi\x06:\x06yi\x07c\x0BStringm\x0BKernel";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+
+        let value: f64 = ctx.from_value(items[0]).expect("failed to extract");
+        assert!(value == 1.5);
+        let value: f64 = ctx.from_value(items[1]).expect("failed to extract");
+        assert!(value == 0.0 && value.is_sign_negative());
+        let value: f64 = ctx.from_value(items[2]).expect("failed to extract");
+        assert!(value.is_nan());
+
+        let value: &crate::BignumValue = ctx.from_value(items[3]).expect("failed to extract");
+        assert!(value.is_negative());
+        assert!(value.value() == [0, 0, 0, 0, 1, 0]);
+
+        let value: &crate::RegexpValue = ctx.from_value(items[4]).expect("failed to extract");
+        assert!(value.source() == b"a");
+        assert!(value.options() == 1);
+        assert!(value
+            .instance_variables()
+            .is_some_and(|ivars| ivars.len() == 1));
+
+        let value: &crate::StructValue = ctx.from_value(items[5]).expect("failed to extract");
+        let name: &[u8] = ctx
+            .from_value(value.name().into())
+            .expect("failed to extract");
+        assert!(name == b"Point");
+        let members: Vec<(&[u8], i32)> = value
+            .members()
+            .iter()
+            .map(|(name, value)| {
+                let name: &[u8] = ctx.from_value((*name).into()).expect("failed to extract");
+                let value: i32 = ctx.from_value(*value).expect("failed to extract");
+                (name, value)
+            })
+            .collect();
+        assert!(members == [(&b"x"[..], 1), (&b"y"[..], 2)]);
+
+        let value: &crate::ClassValue = ctx.from_value(items[6]).expect("failed to extract");
+        assert!(value.name() == b"String");
+        let value: &crate::ModuleValue = ctx.from_value(items[7]).expect("failed to extract");
+        assert!(value.name() == b"Kernel");
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+
+        // Old versions of Ruby wrote mantissa bytes after the text.
+        let value_arena = load_value(b"f\x0A1.5\x00\x01\x02").expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let value: f64 = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        assert!(value == 1.5);
+
+        let error = load_value(b"f\x08abc").expect_err("load should fail");
+        assert!(
+            matches!(&error, Error::InvalidFloat { value } if value == b"abc"),
+            "{error:?}"
+        );
+
+        let error = load_value(b"l*\x06\x01\x00").expect_err("load should fail");
+        assert!(
+            matches!(error, Error::InvalidBignumSign { sign: b'*' }),
+            "{error:?}"
+        );
+    }
 
-        let name = self.read_value_symbol_like()?;
-        let instance_variables = self.read_instance_variables()?;
+    #[test]
+    fn wrapped_instance_variables() {
+        // An object wrapped in 'I', with trailing instance variables.
+        let data = b"\x04\x08Io:\x09Pair\x06:\x0B@firsti\x06\x06:\x0C@secondi\x07";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let value: &crate::ObjectValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let instance_variables: Vec<(&[u8], i32)> = value
+            .instance_variables()
+            .iter()
+            .map(|(name, value)| {
+                let name: &[u8] = ctx.from_value((*name).into()).expect("failed to extract");
+                let value: i32 = ctx.from_value(*value).expect("failed to extract");
+                (name, value)
+            })
+            .collect();
+        assert!(instance_variables == [(&b"@first"[..], 1), (&b"@second"[..], 2)]);
+
+        // The combined instance variables are written inside the object.
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(
+            new_data == b"\x04\x08o:\x09Pair\x07:\x0B@firsti\x06:\x0C@secondi\x07",
+            "{new_data:?}"
+        );
+
+        let error = load_value(b"Io:\x09Pair\x06:\x0B@firsti\x06\x06;\x06i\x07")
+            .expect_err("load should fail");
+        assert!(
+            matches!(&error, Error::DuplicateInstanceVariable { name } if name == b"@first"),
+            "{error:?}"
+        );
+
+        // Struct instance variables are kept apart from the members.
+        let data = b"\x04\x08IS:\x0APoint\x06:\x06xi\x06\x06:\x0A@notei\x07";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let value: &crate::StructValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        assert!(value.members().len() == 1);
+        assert!(value
+            .instance_variables()
+            .is_some_and(|ivars| ivars.len() == 1));
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+
+        // A user defined value wrapped in 'I' twice.
+        let error = load_value(b"IIu:\x09Name\x06a\x06:\x07@ai\x06\x06;\x06i\x07")
+            .expect_err("load should fail");
+        assert!(
+            matches!(&error, Error::DuplicateInstanceVariable { name } if name == b"@a"),
+            "{error:?}"
+        );
+    }
 
-        *self.arena.get_mut(handle).unwrap() = ObjectValue::new(name, instance_variables).into();
+    #[test]
+    fn instance_variable_order() {
+        // Instance variables in an order Ruby would not sort them in, on a string and a user defined value.
+        let data = b"\x04\x08[\x07I\"\x06x\x08:\x07@zi\x06:\x06ET:\x07@ai\x07Iu:\x09Name\x06a\x07;\x07i\x06;\x00i\x07";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+
+        let string: &crate::StringValue = ctx.from_value(items[0]).expect("failed to extract");
+        let names: Vec<&[u8]> = string
+            .instance_variables()
+            .expect("missing instance variables")
+            .iter()
+            .map(|(name, _value)| ctx.from_value((*name).into()).expect("failed to extract"))
+            .collect();
+        assert!(names == [&b"@z"[..], b"E", b"@a"], "{names:?}");
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+    }
 
-        Ok(TypedValueHandle::new_unchecked(handle))
+    #[test]
+    fn user_marshal() {
+        // Set[1], with a link to the set.
+        let data = b"\x04\x08[\x07U:\x08Set{\x06i\x06T@\x06";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+        assert!(items[0] == items[1]);
+
+        let value: &crate::UserMarshalValue = ctx.from_value(items[0]).expect("failed to extract");
+        let name: &[u8] = ctx
+            .from_value(value.name().into())
+            .expect("failed to extract");
+        assert!(name == b"Set");
+        let set: crate::RubySet<std::collections::BTreeSet<i32>> =
+            ctx.from_value(items[0]).expect("failed to extract");
+        assert!(set.0 == [1].into());
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
     }
 
-    /// Read a string
-    fn read_string(&mut self) -> Result<TypedValueHandle<StringValue>, Error> {
-        let data = self.read_byte_string()?;
+    #[test]
+    fn extension() {
+        /// Replace each unmodeled value with the value it wraps, except for data objects named `Skip`.
+        struct Unwrap;
+
+        impl LoaderExtension for Unwrap {
+            fn load(
+                &self,
+                _kind: u8,
+                name: TypedValueHandle<SymbolValue>,
+                value: ValueHandle,
+                arena: &mut ValueArena,
+            ) -> Option<ValueHandle> {
+                let name = arena.get_symbol(name)?;
+                if name.value() == b"Skip" {
+                    return None;
+                }
 
-        let handle = self.arena.create_string(data);
-        self.object_links.push(handle.into());
+                Some(value)
+            }
+        }
 
-        Ok(handle)
+        let options = LoaderOptions {
+            extension: Some(Arc::new(Unwrap)),
+            ..LoaderOptions::default()
+        };
+
+        // [MyString.new("a"), "b".extend(Comparable), Foo data wrapping 1, a link to the data]
+        let data = b"\x04\x08[\x09IC:\x0DMyString\"\x06a\x06:\x06ETIe:\x0FComparable\"\x06b\x06;\x07Td:\x08Fooi\x06@\x08";
+
+        let error = load(&data[..]).expect_err("load should fail without an extension");
+        assert!(
+            matches!(error, Error::InvalidValueKind { kind: b'C' }),
+            "{error:?}"
+        );
+
+        let value_arena = load_with_options(&data[..], options.clone()).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+        let string: &str = ctx.from_value(items[0]).expect("failed to extract");
+        assert!(string == "a");
+        let extended: &str = ctx.from_value(items[1]).expect("failed to extract");
+        assert!(extended == "b");
+        let data_value: i32 = ctx.from_value(items[2]).expect("failed to extract");
+        assert!(data_value == 1);
+        assert!(items[3] == items[2]);
+
+        let data = b"\x04\x08d:\x09Skip0";
+        let error = load_with_options(&data[..], options).expect_err("load should fail");
+        assert!(
+            matches!(error, Error::InvalidValueKind { kind: b'd' }),
+            "{error:?}"
+        );
     }
 
-    /// Read a user defined
-    fn read_user_defined(&mut self) -> Result<TypedValueHandle<UserDefinedValue>, Error> {
-        let name = self.read_value_symbol_like()?;
-        let value = self.read_byte_string()?;
+    #[test]
+    fn extension_links() {
+        /// Wrap each unmodeled value in an array.
+        struct Wrap;
+
+        impl LoaderExtension for Wrap {
+            fn load(
+                &self,
+                _kind: u8,
+                _name: TypedValueHandle<SymbolValue>,
+                value: ValueHandle,
+                arena: &mut ValueArena,
+            ) -> Option<ValueHandle> {
+                Some(arena.create_array(vec![value]).into())
+            }
+        }
 
-        let handle = self.arena.create_user_defined(name, value);
-        self.object_links.push(handle.into());
+        let options = LoaderOptions {
+            extension: Some(Arc::new(Wrap)),
+            ..LoaderOptions::default()
+        };
 
-        Ok(handle)
+        // [MyString.new("a"), a link to it, [].extend(A, B), a link to it]
+        let data = b"\x04\x08[\x09C:\x0DMyString\"\x06a@\x06e:\x06Ae:\x06B[\x00@\x07";
+        let value_arena = load_with_options(&data[..], options).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+        let string: Vec<&str> = ctx.from_value(items[0]).expect("failed to extract");
+        assert!(string == ["a"]);
+        assert!(items[1] == items[0]);
+        let extended: Vec<Vec<Vec<i32>>> = ctx.from_value(items[2]).expect("failed to extract");
+        assert!(extended == [[Vec::<i32>::new()]]);
+        assert!(items[3] == items[2]);
     }
 
-    /// Read the next value, failing if it is not a symbol-like value.
-    fn read_value_symbol_like(&mut self) -> Result<TypedValueHandle<SymbolValue>, Error> {
-        let kind = self.read_byte()?;
-        match kind {
-            VALUE_KIND_SYMBOL => self.read_symbol(),
-            VALUE_KIND_SYMBOL_LINK => self.read_symbol_link(),
-            _ => Err(Error::UnexpectedValueKind {
-                expected: VALUE_KIND_SYMBOL,
-                actual: kind,
-            }),
-        }
+    #[test]
+    fn object_link_order() {
+        // Links to strings with instance variables, objects, arrays, floats, hashes, and the root.
+        let data = b"\x04\x08[\x0EI\"\x06x\x06:\x0Dencoding\"\x0EShift_JIS@\x06o:\x09Pair\x07:\x0B@firsti\x06:\x0C@second[\x06@\x07@\x08f\x081.5@\x0A}\x06i\x06@\x09i\x07@\x0B@\x00";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+        assert!(items[1] == items[0]);
+        assert!(items[3] == items[2]);
+        assert!(items[5] == items[4]);
+        assert!(items[7] == items[6]);
+        assert!(items[8] == value_arena.root());
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
     }
 
-    /// Read the next value.
-    fn read_value(&mut self) -> Result<ValueHandle, Error> {
-        let kind = self.read_byte()?;
-        match kind {
-            VALUE_KIND_NIL => Ok(self.arena.create_nil().into()),
-            VALUE_KIND_TRUE => Ok(self.arena.create_bool(true).into()),
-            VALUE_KIND_FALSE => Ok(self.arena.create_bool(false).into()),
-            VALUE_KIND_FIXNUM => Ok(self.read_fixnum()?.into()),
-            VALUE_KIND_SYMBOL => Ok(self.read_symbol()?.into()),
-            VALUE_KIND_SYMBOL_LINK => Ok(self.read_symbol_link()?.into()),
-            VALUE_KIND_OBJECT_LINK => Ok(self.read_object_link()?),
-            VALUE_KIND_INSTANCE_VARIABLES => {
-                let value = self.read_value()?;
-
-                let instance_variables = self.read_instance_variables()?;
-
-                match self
-                    .arena
-                    .get_mut(value)
-                    .ok_or(Error::InvalidValueHandle { handle: value })?
-                {
-                    Value::String(value) => {
-                        value.set_instance_variables(Some(instance_variables));
-                    }
-                    Value::UserDefined(value) => {
-                        value.set_instance_variables(Some(instance_variables));
-                    }
-                    _ => return Err(Error::NotAnObject),
-                }
+    #[test]
+    fn require_eof() {
+        let data = b"\x04\x08i\x06i\x07";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let value: i32 = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        assert!(value == 1);
+
+        let options = LoaderOptions {
+            require_eof: true,
+            ..LoaderOptions::new()
+        };
+        let error = load_with_options(&data[..], options.clone()).expect_err("load should fail");
+        assert!(
+            matches!(error, Error::TrailingData { offset: 4 }),
+            "{error:?}"
+        );
 
-                Ok(value)
-            }
-            VALUE_KIND_ARRAY => Ok(self.read_array()?.into()),
-            VALUE_KIND_HASH => Ok(self.read_hash(false)?.into()),
-            VALUE_KIND_HASH_DEFAULT => Ok(self.read_hash(true)?.into()),
-            VALUE_KIND_OBJECT => Ok(self.read_object()?.into()),
-            VALUE_KIND_STRING => Ok(self.read_string()?.into()),
-            VALUE_KIND_USER_DEFINED => Ok(self.read_user_defined()?.into()),
-            _ => Err(Error::InvalidValueKind { kind }),
+        load_with_options(&data[..4], options).expect("failed to load");
+    }
+
+    #[test]
+    fn load_counted() {
+        let data = b"\x04\x08[\x07i\x06I\"\x06a\x06:\x06ETtrailing";
+        let (value_arena, len) = super::load_counted(&data[..]).expect("failed to load");
+        assert!(len == 15, "{len}");
+        assert!(data[usize::try_from(len).unwrap()..] == *b"trailing");
+
+        let mut expected = Vec::new();
+        crate::dump(&mut expected, &value_arena).expect("failed to dump");
+        assert!(data[..expected.len()] == expected);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_require_eof() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("failed to build runtime");
+        let options = LoaderOptions {
+            require_eof: true,
+            ..LoaderOptions::new()
+        };
+
+        let data = b"\x04\x08i\x06i\x07";
+        let error = runtime
+            .block_on(load_async_with_options(&data[..], options.clone()))
+            .expect_err("load should fail");
+        assert!(
+            matches!(error, Error::TrailingData { offset: 4 }),
+            "{error:?}"
+        );
+
+        runtime
+            .block_on(load_async_with_options(&data[..4], options))
+            .expect("failed to load");
+    }
+
+    #[test]
+    fn hash_default() {
+        // Hash.new(0).tap { |hash| hash[:a] = 1 }
+        let data = b"\x04\x08}\x06:\x06ai\x06i\x00";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+
+        let hash: &HashValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let default_value = hash.default_value().expect("missing default value");
+        let value: i32 = ctx.from_value(default_value).expect("failed to extract");
+        assert!(value == 0);
+
+        // Maps cannot hold the default value, so they refuse to drop it.
+        #[cfg(feature = "std")]
+        {
+            let error = ctx
+                .from_value::<std::collections::HashMap<&str, i32>>(value_arena.root())
+                .expect_err("HashMap should not extract");
+            assert!(
+                matches!(
+                    &error,
+                    FromValueError::Other { error }
+                        if matches!(
+                            error.downcast_ref(),
+                            Some(crate::HashMapFromValueError::HasDefaultValue { value })
+                                if *value == default_value
+                        )
+                ),
+                "{error:?}"
+            );
         }
+
+        let error = ctx
+            .from_value::<alloc::collections::BTreeMap<&str, i32>>(value_arena.root())
+            .expect_err("BTreeMap should not extract");
+        assert!(
+            matches!(
+                &error,
+                FromValueError::Other { error }
+                    if matches!(
+                        error.downcast_ref(),
+                        Some(crate::BTreeMapFromValueError::HasDefaultValue { value })
+                            if *value == default_value
+                    )
+            ),
+            "{error:?}"
+        );
+
+        // The pairs are still available through the hash itself.
+        let (key, value) = hash.value()[0];
+        let key: &str = ctx.from_value(key).expect("failed to extract");
+        let value: i32 = ctx.from_value(value).expect("failed to extract");
+        assert!(key == "a" && value == 1);
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
     }
 
-    /// Load from the reader and get the value.
-    fn load(mut self) -> Result<ValueArena, Error> {
-        self.read_header()?;
-        let root = self.read_value()?;
-        let _old_root = self.arena.replace_root(root);
+    #[test]
+    fn hash_default_self() {
+        // A hash that is its own default value, and also the value of one of its pairs.
+        let data = b"\x04\x08[\x07}\x06i\x06@\x06@\x06@\x06";
+        let value_arena = load(&data[..]).expect("failed to load");
+        let ctx = FromValueContext::new(&value_arena);
+        let items: &crate::ArrayValue = ctx
+            .from_value(value_arena.root())
+            .expect("failed to extract");
+        let items = items.value();
+        assert!(items[1] == items[0]);
+
+        let hash: &HashValue = ctx.from_value(items[0]).expect("failed to extract");
+        assert!(hash.default_value() == Some(items[0]));
+        assert!(hash.value()[0].1 == items[0]);
+
+        let mut new_data = Vec::new();
+        crate::dump(&mut new_data, &value_arena).expect("failed to dump");
+        assert!(new_data == data, "{new_data:?}");
+    }
 
-        // TODO: Delete old root.
+    #[test]
+    fn tokens() {
+        // [300, :a]
+        let data = [
+            MAJOR_VERSION,
+            MINOR_VERSION,
+            VALUE_KIND_ARRAY,
+            0x07,
+            VALUE_KIND_FIXNUM,
+            0x02,
+            0x2C,
+            0x01,
+            VALUE_KIND_SYMBOL,
+            0x06,
+            b'a',
+        ];
+        let mut tokens = Vec::new();
+        load_with_tokens(&data[..], LoaderOptions::default(), |token| {
+            tokens.push(token)
+        })
+        .expect("failed to load");
+
+        let expected = [
+            (
+                0,
+                2,
+                TokenKind::Header {
+                    major: MAJOR_VERSION,
+                    minor: MINOR_VERSION,
+                },
+            ),
+            (
+                2,
+                1,
+                TokenKind::ValueKind {
+                    kind: VALUE_KIND_ARRAY,
+                },
+            ),
+            (
+                3,
+                1,
+                TokenKind::Fixnum {
+                    value: 2,
+                    usage: FixnumUsage::Length,
+                },
+            ),
+            (
+                4,
+                1,
+                TokenKind::ValueKind {
+                    kind: VALUE_KIND_FIXNUM,
+                },
+            ),
+            (
+                5,
+                3,
+                TokenKind::Fixnum {
+                    value: 300,
+                    usage: FixnumUsage::Value,
+                },
+            ),
+            (
+                8,
+                1,
+                TokenKind::ValueKind {
+                    kind: VALUE_KIND_SYMBOL,
+                },
+            ),
+            (
+                9,
+                1,
+                TokenKind::Fixnum {
+                    value: 1,
+                    usage: FixnumUsage::Length,
+                },
+            ),
+            (10, 1, TokenKind::Bytes),
+        ]
+        .map(|(offset, len, kind)| Token { offset, len, kind });
+        assert!(tokens == expected, "{tokens:#?}");
+
+        // Tokens before an error are still reported.
+        let mut tokens = Vec::new();
+        load_with_tokens(&data[..6], LoaderOptions::default(), |token| {
+            tokens.push(token)
+        })
+        .expect_err("load should fail");
+        assert!(tokens == expected[..4]);
+    }
 
-        Ok(self.arena)
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_hash() {
+        // { "a" => 1, :b => nil }
+        let data = [
+            MAJOR_VERSION,
+            MINOR_VERSION,
+            VALUE_KIND_HASH,
+            0x07,
+            VALUE_KIND_INSTANCE_VARIABLES,
+            VALUE_KIND_STRING,
+            0x06,
+            b'a',
+            0x06,
+            VALUE_KIND_SYMBOL,
+            0x06,
+            b'E',
+            VALUE_KIND_TRUE,
+            VALUE_KIND_FIXNUM,
+            0x06,
+            VALUE_KIND_SYMBOL,
+            0x06,
+            b'b',
+            VALUE_KIND_NIL,
+        ];
+        let (value_arena, map) = super::load_hash(&data[..]).expect("failed to load");
+        assert!(map.len() == 2);
+        let ctx = FromValueContext::new(&value_arena);
+        assert!(ctx.from_value::<i32>(map["a"]).expect("failed to extract") == 1);
+        assert!(matches!(value_arena[map["b"]], crate::Value::Nil(_)));
+
+        // { 1 => nil }
+        let data = [
+            VALUE_KIND_HASH,
+            0x06,
+            VALUE_KIND_FIXNUM,
+            0x06,
+            VALUE_KIND_NIL,
+        ];
+        let mut input = vec![MAJOR_VERSION, MINOR_VERSION];
+        input.extend(data);
+        let error = super::load_hash(&*input).expect_err("load should fail");
+        assert!(matches!(error, Error::FromValue { .. }), "{error:?}");
+
+        // []
+        let data = [MAJOR_VERSION, MINOR_VERSION, VALUE_KIND_ARRAY, 0x00];
+        let error = super::load_hash(&data[..]).expect_err("load should fail");
+        assert!(
+            matches!(
+                error,
+                Error::FromValue {
+                    error: crate::FromValueError::UnexpectedValueKind { .. }
+                }
+            ),
+            "{error:?}"
+        );
     }
-}
 
-/// Load from a reader.
-pub fn load<R>(reader: R) -> Result<ValueArena, Error>
-where
-    R: Read,
-{
-    let loader = Loader::new(reader);
-    let value_arena = loader.load()?;
+    #[test]
+    fn malformed_input() {
+        // Every truncation and single byte change of these must fail or load, but never panic.
+        let samples: [&[u8]; 2] = [
+            b"\x04\x08[\x0Df\x081.5f\x07-0f\x08nanl-\x08\x00\x00\x00\x00\x01\x00I/\x06a\x01\x06:\x06EFS:\x0APoint\x07:\x06xi\x06:\x06yi\x07c\x0BStringm\x0BKernel",
+            b"\x04\x08[\x0EI\"\x06x\x06:\x0Dencoding\"\x0EShift_JIS@\x06o:\x09Pair\x07:\x0B@firsti\x06:\x0C@second[\x06@\x07@\x08f\x081.5@\x0A}\x06i\x06@\x09i\x07@\x0B@\x00",
+        ];
+        for sample in samples {
+            for len in 0..sample.len() {
+                let _ = load(&sample[..len]);
+            }
 
-    Ok(value_arena)
+            let mut data = sample.to_vec();
+            for index in 0..data.len() {
+                for byte in 0..=u8::MAX {
+                    data[index] = byte;
+                    if let Ok(value_arena) = load(&data[..]) {
+                        let _ = crate::dump(&mut Vec::new(), &value_arena);
+                    }
+                }
+                data[index] = sample[index];
+            }
+        }
+    }
 }
@@ -0,0 +1,1219 @@
+use super::FixnumUsage;
+use super::LoadWarning;
+use super::LoaderOptions;
+use super::TokenKind;
+use super::MAX_PREALLOCATED_LEN;
+use crate::ArrayValue;
+use crate::Error;
+use crate::FixnumValue;
+use crate::HashValue;
+use crate::ObjectValue;
+use crate::StructValue;
+use crate::SymbolValue;
+use crate::TypedValueHandle;
+use crate::UserMarshalValue;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use crate::MAJOR_VERSION;
+use crate::MINOR_VERSION;
+use crate::VALUE_KIND_ARRAY;
+use crate::VALUE_KIND_BIGNUM;
+use crate::VALUE_KIND_CLASS;
+use crate::VALUE_KIND_DATA;
+use crate::VALUE_KIND_EXTENDED;
+use crate::VALUE_KIND_FALSE;
+use crate::VALUE_KIND_FIXNUM;
+use crate::VALUE_KIND_FLOAT;
+use crate::VALUE_KIND_HASH;
+use crate::VALUE_KIND_HASH_DEFAULT;
+use crate::VALUE_KIND_INSTANCE_VARIABLES;
+use crate::VALUE_KIND_MODULE;
+use crate::VALUE_KIND_NIL;
+use crate::VALUE_KIND_OBJECT;
+use crate::VALUE_KIND_OBJECT_LINK;
+use crate::VALUE_KIND_OLD_MODULE;
+use crate::VALUE_KIND_REGEXP;
+use crate::VALUE_KIND_STRING;
+use crate::VALUE_KIND_STRUCT;
+use crate::VALUE_KIND_SYMBOL;
+use crate::VALUE_KIND_SYMBOL_LINK;
+use crate::VALUE_KIND_TRUE;
+use crate::VALUE_KIND_USER_CLASS;
+use crate::VALUE_KIND_USER_DEFINED;
+use crate::VALUE_KIND_USER_MARSHAL;
+use alloc::vec::Vec;
+
+/// The input that the state machine needs next.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Need {
+    /// A single byte.
+    Byte,
+
+    /// Exactly this many bytes.
+    Bytes(usize),
+
+    /// Nothing, the value is complete.
+    Done,
+}
+
+/// The owner of instance variables that are being read.
+#[derive(Debug)]
+enum InstanceVariablesOwner {
+    /// An object, which is filled in once its instance variables are read.
+    Object {
+        handle: ValueHandle,
+        name: TypedValueHandle<SymbolValue>,
+    },
+
+    /// A struct, which is filled in once its members are read.
+    ///
+    /// Members are stored like instance variables.
+    Struct {
+        handle: ValueHandle,
+        name: TypedValueHandle<SymbolValue>,
+    },
+
+    /// A value that was prefixed with instance variables.
+    Value(ValueHandle),
+}
+
+/// What a fixnum is used for, once it is read.
+#[derive(Debug)]
+enum FixnumTarget {
+    /// A fixnum value.
+    Value,
+
+    /// The index of a symbol link.
+    SymbolLink,
+
+    /// The index of an object link.
+    ObjectLink,
+
+    /// The length of an array.
+    ArrayLen { handle: ValueHandle },
+
+    /// The number of pairs in a hash.
+    HashLen {
+        handle: ValueHandle,
+        has_default_value: bool,
+    },
+
+    /// The number of instance variables.
+    InstanceVariablesLen { owner: InstanceVariablesOwner },
+
+    /// The length of a byte string.
+    ByteStringLen { target: ByteStringTarget },
+
+    /// The number of 16 bit words in a bignum.
+    BignumLen { is_negative: bool },
+}
+
+/// What a byte string is used for, once it is read.
+#[derive(Debug)]
+enum ByteStringTarget {
+    /// The name of a symbol.
+    Symbol,
+
+    /// The data of a string.
+    String,
+
+    /// The data of a user defined value.
+    UserDefined { name: TypedValueHandle<SymbolValue> },
+
+    /// The name of an old format class or module.
+    OldModule,
+
+    /// The text of a float.
+    Float,
+
+    /// The magnitude of a bignum.
+    Bignum { is_negative: bool },
+
+    /// The source of a regexp.
+    Regexp,
+
+    /// The name of a class.
+    Class,
+
+    /// The name of a module.
+    Module,
+}
+
+/// The current step of the state machine.
+#[derive(Debug)]
+enum Step {
+    /// Reading the header.
+    Header { major_version: Option<u8> },
+
+    /// Reading the kind of the next value.
+    ///
+    /// If `symbol_like` is true, the value must be a symbol or a symbol link.
+    Value { symbol_like: bool },
+
+    /// Reading the kind of a symbol-like value after an instance variables prefix.
+    InstanceVariablesSymbol,
+
+    /// Reading the first byte of a fixnum.
+    FixnumHeader { target: FixnumTarget },
+
+    /// Reading the remaining bytes of a fixnum.
+    FixnumBytes {
+        header: u8,
+        len: u8,
+        index: u8,
+        value: i32,
+        target: FixnumTarget,
+    },
+
+    /// Reading the bytes of a byte string.
+    ByteString {
+        len: usize,
+        target: ByteStringTarget,
+    },
+
+    /// Reading the sign of a bignum.
+    BignumSign,
+
+    /// Reading the option flags of a regexp.
+    RegexpOptions { source: Vec<u8> },
+
+    /// The value is complete.
+    Done,
+}
+
+/// A composite value that is waiting on child values.
+#[derive(Debug)]
+enum Frame {
+    /// An array, waiting on elements.
+    Array {
+        handle: ValueHandle,
+        len: usize,
+        items: Vec<ValueHandle>,
+    },
+
+    /// A hash, waiting on keys, values, and the default value.
+    Hash {
+        handle: ValueHandle,
+        len: usize,
+        has_default_value: bool,
+        pairs: Vec<(ValueHandle, ValueHandle)>,
+        key: Option<ValueHandle>,
+    },
+
+    /// An object, waiting on its name.
+    Object { handle: ValueHandle },
+
+    /// A struct, waiting on its name.
+    Struct { handle: ValueHandle },
+
+    /// A user defined value, waiting on its name.
+    UserDefined,
+
+    /// A user marshal value, waiting on its name and then its value.
+    UserMarshal {
+        handle: ValueHandle,
+        name: Option<TypedValueHandle<SymbolValue>>,
+    },
+
+    /// A kind handled by a [`LoaderExtension`](super::LoaderExtension), waiting on its name and then its value.
+    Extension {
+        kind: u8,
+
+        /// The index of the object link for this value.
+        ///
+        /// For data objects, this is a reserved placeholder.
+        /// For the others, this is the link of the value that follows, if that value takes one.
+        link: usize,
+
+        name: Option<TypedValueHandle<SymbolValue>>,
+    },
+
+    /// An instance variables prefix, waiting on the value it applies to.
+    InstanceVariablesValue,
+
+    /// Instance variables, waiting on names and values.
+    InstanceVariables {
+        owner: InstanceVariablesOwner,
+        len: usize,
+        items: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+        key: Option<TypedValueHandle<SymbolValue>>,
+    },
+}
+
+/// A loader that does not perform I/O or recurse.
+///
+/// The caller asks the state machine what input it needs with [`StateMachine::need`],
+/// and then provides it.
+/// Composite values push a frame onto an explicit stack,
+/// and are assembled as their children complete.
+#[derive(Debug)]
+pub(super) struct StateMachine {
+    options: LoaderOptions,
+    step: Step,
+
+    /// The kind of the token that was just completed, if any.
+    token: Option<TokenKind>,
+
+    stack: Vec<Frame>,
+
+    arena: ValueArena,
+
+    symbol_links: Vec<TypedValueHandle<SymbolValue>>,
+
+    /// Values that may be the target of an object link, by index.
+    ///
+    /// Containers are pushed as placeholders before their children are read.
+    /// Other values are pushed once their own bytes are read, which is still before any instance variables.
+    /// Either way, a value is pushed before anything it holds, which is the order Ruby and the dumper use,
+    /// so a load followed by a dump keeps every object link index.
+    object_links: Vec<ValueHandle>,
+}
+
+impl StateMachine {
+    /// Make a new [`StateMachine`].
+    pub(super) fn new(options: LoaderOptions) -> Self {
+        Self {
+            options,
+            step: Step::Header {
+                major_version: None,
+            },
+            token: None,
+            stack: Vec::new(),
+            arena: ValueArena::new(),
+            symbol_links: Vec::new(),
+            object_links: Vec::new(),
+        }
+    }
+
+    /// Get the options used for loading.
+    pub(super) fn options(&self) -> &LoaderOptions {
+        &self.options
+    }
+
+    /// Get the input that the state machine needs next.
+    pub(super) fn need(&self) -> Need {
+        match &self.step {
+            Step::ByteString { len, .. } => Need::Bytes(*len),
+            Step::Done => Need::Done,
+            _ => Need::Byte,
+        }
+    }
+
+    /// Take the kind of the token that the last input completed, if it completed one.
+    pub(super) fn take_token(&mut self) -> Option<TokenKind> {
+        self.token.take()
+    }
+
+    /// Provide a byte.
+    ///
+    /// # Panics
+    /// Panics if the state machine did not ask for a byte.
+    pub(super) fn feed_byte(&mut self, byte: u8) -> Result<(), Error> {
+        match core::mem::replace(&mut self.step, Step::Done) {
+            Step::Header {
+                major_version: None,
+            } => {
+                self.step = Step::Header {
+                    major_version: Some(byte),
+                };
+            }
+            Step::Header {
+                major_version: Some(major_version),
+            } => {
+                self.token = Some(TokenKind::Header {
+                    major: major_version,
+                    minor: byte,
+                });
+
+                // Older minor versions encoded some values differently, and those differences are not implemented.
+                // Loading them as the current version could silently produce wrong values, so they are rejected too.
+                if major_version != MAJOR_VERSION || byte != MINOR_VERSION {
+                    if !self.options.allow_version_mismatch {
+                        return Err(Error::InvalidVersion {
+                            major: major_version,
+                            minor: byte,
+                        });
+                    }
+
+                    self.options.warn(LoadWarning::VersionMismatch {
+                        major: major_version,
+                        minor: byte,
+                    });
+                }
+
+                self.step = Step::Value { symbol_like: false };
+            }
+            Step::Value { symbol_like: true } => {
+                self.token = Some(TokenKind::ValueKind { kind: byte });
+                self.start_symbol_like(byte)?
+            }
+            Step::Value { symbol_like: false } => {
+                self.token = Some(TokenKind::ValueKind { kind: byte });
+                self.start_value(byte)?
+            }
+            Step::InstanceVariablesSymbol => {
+                self.token = Some(TokenKind::ValueKind { kind: byte });
+                if byte != VALUE_KIND_SYMBOL {
+                    return Err(Error::UnexpectedValueKind {
+                        expected: VALUE_KIND_SYMBOL,
+                        actual: byte,
+                    });
+                }
+
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::Symbol,
+                    },
+                };
+            }
+            Step::FixnumHeader { target } => {
+                // A zero header is zero.
+                // Headers outside of [-4, 4] hold a small value directly.
+                // Otherwise, the magnitude of the header is the number of bytes that follow.
+                let header_i8 = header_as_i8(byte);
+                match header_i8 {
+                    0 => self.finish_fixnum(0, byte, target)?,
+                    1..=4 => {
+                        self.step = Step::FixnumBytes {
+                            header: byte,
+                            len: byte,
+                            index: 0,
+                            value: 0,
+                            target,
+                        };
+                    }
+                    -4..=-1 => {
+                        self.step = Step::FixnumBytes {
+                            header: byte,
+                            len: header_i8.unsigned_abs(),
+                            index: 0,
+                            value: -1,
+                            target,
+                        };
+                    }
+                    5.. => self.finish_fixnum(i32::from(header_i8) - 5, byte, target)?,
+                    _ => self.finish_fixnum(i32::from(header_i8) + 5, byte, target)?,
+                }
+            }
+            Step::FixnumBytes {
+                header,
+                len,
+                index,
+                mut value,
+                target,
+            } => {
+                let shift = index * 8;
+                value &= !(0xFF_i32 << shift);
+                value |= i32::from(byte) << shift;
+
+                let index = index + 1;
+                if index == len {
+                    // Only 4 byte fixnums can overflow.
+                    // A value that needs the sign bit flipped would have been written as a Bignum.
+                    let is_negative = header_as_i8(header) < 0;
+                    if len == 4 && (value < 0) != is_negative {
+                        let value = i64::from(value as u32);
+                        let value = if is_negative {
+                            value - (1 << 32)
+                        } else {
+                            value
+                        };
+                        return Err(Error::FixnumOutOfRange { value });
+                    }
+
+                    self.finish_fixnum(value, header, target)?;
+                } else {
+                    self.step = Step::FixnumBytes {
+                        header,
+                        len,
+                        index,
+                        value,
+                        target,
+                    };
+                }
+            }
+            Step::BignumSign => {
+                let is_negative = match byte {
+                    b'+' => false,
+                    b'-' => true,
+                    _ => return Err(Error::InvalidBignumSign { sign: byte }),
+                };
+                self.token = Some(TokenKind::BignumSign { is_negative });
+
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::BignumLen { is_negative },
+                };
+            }
+            Step::RegexpOptions { source } => {
+                self.token = Some(TokenKind::RegexpOptions { options: byte });
+
+                let handle = self.arena.create_regexp(source, byte).into();
+                self.object_links.push(handle);
+
+                self.finish_value(handle)?;
+            }
+            Step::ByteString { .. } | Step::Done => {
+                panic!("the state machine did not ask for a byte")
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Provide bytes.
+    ///
+    /// # Panics
+    /// Panics if the state machine did not ask for exactly this many bytes.
+    pub(super) fn feed_bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        let target = match core::mem::replace(&mut self.step, Step::Done) {
+            Step::ByteString { len, target } if len == bytes.len() => target,
+            _ => panic!("the state machine did not ask for {} bytes", bytes.len()),
+        };
+        self.token = Some(TokenKind::Bytes);
+
+        let handle = match target {
+            ByteStringTarget::Symbol => {
+                let handle = self.arena.create_symbol(bytes);
+                self.symbol_links.push(handle);
+
+                handle.into()
+            }
+            ByteStringTarget::String => {
+                let handle = self.arena.create_string(bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::UserDefined { name } => {
+                let handle = self.arena.create_user_defined(name, bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::OldModule => {
+                let handle = self.arena.create_old_module(bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::Float => {
+                let value = parse_float(&bytes).ok_or(Error::InvalidFloat { value: bytes })?;
+                let handle = self.arena.create_float(value).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::Bignum { is_negative } => {
+                let handle = self.arena.create_bignum(is_negative, bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::Regexp => {
+                // The regexp is complete once its options are read.
+                self.step = Step::RegexpOptions { source: bytes };
+                return Ok(());
+            }
+            ByteStringTarget::Class => {
+                let handle = self.arena.create_class(bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+            ByteStringTarget::Module => {
+                let handle = self.arena.create_module(bytes).into();
+                self.object_links.push(handle);
+
+                handle
+            }
+        };
+
+        self.finish_value(handle)
+    }
+
+    /// Get the loaded arena.
+    ///
+    /// # Panics
+    /// Panics if the value is not complete.
+    pub(super) fn finish(self) -> ValueArena {
+        assert!(
+            matches!(self.step, Step::Done) && self.stack.is_empty(),
+            "the value is not complete"
+        );
+
+        self.arena
+    }
+
+    /// Start reading another value into the same arena, starting with its header.
+    ///
+    /// Symbol and object links from earlier values are kept.
+    pub(super) fn restart(&mut self) {
+        self.step = Step::Header {
+            major_version: None,
+        };
+        self.token = None;
+        self.stack.clear();
+    }
+
+    /// Get the arena that values are loaded into.
+    pub(super) fn arena(&self) -> &ValueArena {
+        &self.arena
+    }
+
+    /// Get the arena that values were loaded into, even if the last value is not complete.
+    pub(super) fn into_arena(self) -> ValueArena {
+        self.arena
+    }
+
+    /// Start reading a symbol-like value, given its kind.
+    fn start_symbol_like(&mut self, kind: u8) -> Result<(), Error> {
+        match kind {
+            VALUE_KIND_SYMBOL => self.start_symbol(),
+            VALUE_KIND_SYMBOL_LINK => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::SymbolLink,
+                };
+            }
+            VALUE_KIND_INSTANCE_VARIABLES => {
+                self.stack.push(Frame::InstanceVariablesValue);
+                self.step = Step::InstanceVariablesSymbol;
+            }
+            _ => {
+                return Err(Error::UnexpectedValueKind {
+                    expected: VALUE_KIND_SYMBOL,
+                    actual: kind,
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start reading a value, given its kind.
+    fn start_value(&mut self, kind: u8) -> Result<(), Error> {
+        match kind {
+            VALUE_KIND_NIL => {
+                let handle = self.arena.create_nil().into();
+                self.finish_value(handle)?;
+            }
+            VALUE_KIND_TRUE => {
+                let handle = self.arena.create_bool(true).into();
+                self.finish_value(handle)?;
+            }
+            VALUE_KIND_FALSE => {
+                let handle = self.arena.create_bool(false).into();
+                self.finish_value(handle)?;
+            }
+            VALUE_KIND_FIXNUM => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::Value,
+                };
+            }
+            VALUE_KIND_SYMBOL | VALUE_KIND_SYMBOL_LINK => self.start_symbol_like(kind)?,
+            VALUE_KIND_OBJECT_LINK => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ObjectLink,
+                };
+            }
+            VALUE_KIND_INSTANCE_VARIABLES => {
+                self.stack.push(Frame::InstanceVariablesValue);
+                self.step = Step::Value { symbol_like: false };
+            }
+            VALUE_KIND_ARRAY => {
+                let handle = self.create_object_link_placeholder();
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ArrayLen { handle },
+                };
+            }
+            VALUE_KIND_HASH | VALUE_KIND_HASH_DEFAULT => {
+                let handle = self.create_object_link_placeholder();
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::HashLen {
+                        handle,
+                        has_default_value: kind == VALUE_KIND_HASH_DEFAULT,
+                    },
+                };
+            }
+            VALUE_KIND_OBJECT => {
+                let handle = self.create_object_link_placeholder();
+                self.stack.push(Frame::Object { handle });
+                self.step = Step::Value { symbol_like: true };
+            }
+            VALUE_KIND_STRING => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::String,
+                    },
+                };
+            }
+            VALUE_KIND_USER_DEFINED => {
+                self.stack.push(Frame::UserDefined);
+                self.step = Step::Value { symbol_like: true };
+            }
+            VALUE_KIND_OLD_MODULE => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::OldModule,
+                    },
+                };
+            }
+            VALUE_KIND_FLOAT => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::Float,
+                    },
+                };
+            }
+            VALUE_KIND_BIGNUM => {
+                self.step = Step::BignumSign;
+            }
+            VALUE_KIND_REGEXP => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::Regexp,
+                    },
+                };
+            }
+            VALUE_KIND_STRUCT => {
+                let handle = self.create_object_link_placeholder();
+                self.stack.push(Frame::Struct { handle });
+                self.step = Step::Value { symbol_like: true };
+            }
+            VALUE_KIND_CLASS => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::Class,
+                    },
+                };
+            }
+            VALUE_KIND_MODULE => {
+                self.step = Step::FixnumHeader {
+                    target: FixnumTarget::ByteStringLen {
+                        target: ByteStringTarget::Module,
+                    },
+                };
+            }
+            VALUE_KIND_USER_MARSHAL => {
+                let handle = self.create_object_link_placeholder();
+                self.stack.push(Frame::UserMarshal { handle, name: None });
+                self.step = Step::Value { symbol_like: true };
+            }
+            VALUE_KIND_EXTENDED | VALUE_KIND_USER_CLASS | VALUE_KIND_DATA
+                if self.options.extension.is_some() =>
+            {
+                // Ruby only reserves an object link for data objects.
+                // The others share the link of the value that follows, which is the next one.
+                let link = self.object_links.len();
+                if kind == VALUE_KIND_DATA {
+                    self.create_object_link_placeholder();
+                }
+                self.stack.push(Frame::Extension {
+                    kind,
+                    link,
+                    name: None,
+                });
+                self.step = Step::Value { symbol_like: true };
+            }
+            _ => return Err(Error::InvalidValueKind { kind }),
+        }
+
+        Ok(())
+    }
+
+    /// Build a value with the [`LoaderExtension`](super::LoaderExtension), once its name and value are read.
+    ///
+    /// # Returns
+    /// Returns the handle of the built value.
+    fn finish_extension(
+        &mut self,
+        kind: u8,
+        link: usize,
+        name: TypedValueHandle<SymbolValue>,
+        value: ValueHandle,
+    ) -> Result<ValueHandle, Error> {
+        let extension = self
+            .options
+            .extension
+            .as_ref()
+            .ok_or(Error::InvalidValueKind { kind })?;
+        let handle = extension
+            .load(kind, name, value, &mut self.arena)
+            .ok_or(Error::InvalidValueKind { kind })?;
+        if self.arena.get(handle).is_none() {
+            return Err(Error::InvalidValueHandle { handle });
+        }
+
+        // The built value replaces the placeholder or the wrapped value as the link target,
+        // so later links see the built value.
+        // Links from inside the value itself were already resolved, and still see the placeholder or the wrapped value.
+        if kind == VALUE_KIND_DATA || self.object_links.get(link) == Some(&value) {
+            self.object_links[link] = handle;
+        }
+
+        Ok(handle)
+    }
+
+    /// Start reading a symbol, after its kind.
+    fn start_symbol(&mut self) {
+        self.step = Step::FixnumHeader {
+            target: FixnumTarget::ByteStringLen {
+                target: ByteStringTarget::Symbol,
+            },
+        };
+    }
+
+    /// Reserve an object link for a composite value that is not yet complete.
+    ///
+    /// The value is a placeholder until it is filled in.
+    /// Children may link to it before then, which is how Ruby stores self-referential values.
+    /// Since links are handles, they refer to the complete value once loading finishes.
+    fn create_object_link_placeholder(&mut self) -> ValueHandle {
+        // The placeholder is overwritten, so it must not be the nil singleton.
+        let handle = self.arena.create_new_nil().into_raw();
+        self.object_links.push(handle);
+
+        handle
+    }
+
+    /// Use a fixnum that was just read.
+    fn finish_fixnum(&mut self, value: i32, header: u8, target: FixnumTarget) -> Result<(), Error> {
+        let usage = match target {
+            FixnumTarget::Value => FixnumUsage::Value,
+            FixnumTarget::SymbolLink => FixnumUsage::SymbolLink,
+            FixnumTarget::ObjectLink => FixnumUsage::ObjectLink,
+            FixnumTarget::ArrayLen { .. }
+            | FixnumTarget::HashLen { .. }
+            | FixnumTarget::InstanceVariablesLen { .. }
+            | FixnumTarget::ByteStringLen { .. }
+            | FixnumTarget::BignumLen { .. } => FixnumUsage::Length,
+        };
+        self.token = Some(TokenKind::Fixnum { value, usage });
+
+        match target {
+            FixnumTarget::Value => {
+                let handle = self.arena.create_fixnum(value);
+                if self.options.preserve_fixnum_encoding {
+                    *self.arena.get_mut(handle).unwrap() =
+                        FixnumValue::new_with_header(value, header).into();
+                }
+
+                self.finish_value(handle.into())
+            }
+            FixnumTarget::SymbolLink => {
+                let index = fixnum_to_usize(value)?;
+                let handle = *self
+                    .symbol_links
+                    .get(index)
+                    .ok_or(Error::MissingSymbolLink { index })?;
+
+                self.finish_value(handle.into())
+            }
+            FixnumTarget::ObjectLink => {
+                let index = fixnum_to_usize(value)?;
+                let handle = *self
+                    .object_links
+                    .get(index)
+                    .ok_or(Error::MissingObjectLink { index })?;
+
+                self.finish_value(handle)
+            }
+            FixnumTarget::ArrayLen { handle } => {
+                let len = fixnum_to_usize(value)?;
+                if len == 0 {
+                    *self.arena.get_mut(handle).unwrap() = ArrayValue::new(Vec::new()).into();
+                    return self.finish_value(handle);
+                }
+
+                self.stack.push(Frame::Array {
+                    handle,
+                    len,
+                    items: Vec::with_capacity(len.min(MAX_PREALLOCATED_LEN)),
+                });
+                self.step = Step::Value { symbol_like: false };
+
+                Ok(())
+            }
+            FixnumTarget::HashLen {
+                handle,
+                has_default_value,
+            } => {
+                let len = fixnum_to_usize(value)?;
+                if len == 0 && !has_default_value {
+                    *self.arena.get_mut(handle).unwrap() = HashValue::new(Vec::new(), None).into();
+                    return self.finish_value(handle);
+                }
+
+                self.stack.push(Frame::Hash {
+                    handle,
+                    len,
+                    has_default_value,
+                    pairs: Vec::with_capacity(len.min(MAX_PREALLOCATED_LEN)),
+                    key: None,
+                });
+                self.step = Step::Value { symbol_like: false };
+
+                Ok(())
+            }
+            FixnumTarget::InstanceVariablesLen { owner } => {
+                let len = fixnum_to_usize(value)?;
+                if len == 0 {
+                    let handle = self.finish_instance_variables(owner, Vec::new())?;
+                    return self.finish_value(handle);
+                }
+
+                // TODO: Consider making this a map.
+                // It must keep the order the instance variables were read in,
+                // or dumping will not write the same bytes.
+                self.stack.push(Frame::InstanceVariables {
+                    owner,
+                    len,
+                    items: Vec::with_capacity(len.min(MAX_PREALLOCATED_LEN)),
+                    key: None,
+                });
+                self.step = Step::Value { symbol_like: true };
+
+                Ok(())
+            }
+            FixnumTarget::ByteStringLen { target } => {
+                let len = fixnum_to_usize(value)?;
+                self.step = Step::ByteString { len, target };
+
+                Ok(())
+            }
+            FixnumTarget::BignumLen { is_negative } => {
+                // The length is in 16-bit words.
+                // This is done in an i64, as doubling it may not fit in a 32-bit usize.
+                let len = usize::try_from(i64::from(value) * 2)
+                    .map_err(|error| Error::FixnumInvalidUSize { error })?;
+                self.step = Step::ByteString {
+                    len,
+                    target: ByteStringTarget::Bignum { is_negative },
+                };
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Attach instance variables to their owner.
+    ///
+    /// # Returns
+    /// Returns the handle of the owner.
+    fn finish_instance_variables(
+        &mut self,
+        owner: InstanceVariablesOwner,
+        instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> Result<ValueHandle, Error> {
+        match owner {
+            InstanceVariablesOwner::Object { handle, name } => {
+                *self.arena.get_mut(handle).unwrap() =
+                    ObjectValue::new(name, instance_variables).into();
+
+                Ok(handle)
+            }
+            InstanceVariablesOwner::Struct { handle, name } => {
+                *self.arena.get_mut(handle).unwrap() =
+                    StructValue::new(name, instance_variables).into();
+
+                Ok(handle)
+            }
+            InstanceVariablesOwner::Value(handle) => {
+                let result = match self
+                    .arena
+                    .get_mut(handle)
+                    .ok_or(Error::InvalidValueHandle { handle })?
+                {
+                    Value::Symbol(value) => merge_optional_instance_variables(
+                        |instance_variables| value.set_instance_variables(instance_variables),
+                        instance_variables,
+                    ),
+                    Value::String(value) => merge_optional_instance_variables(
+                        |instance_variables| value.set_instance_variables(instance_variables),
+                        instance_variables,
+                    ),
+                    Value::Object(value) => {
+                        merge_instance_variables(value.instance_variables_mut(), instance_variables)
+                    }
+                    Value::Struct(value) => merge_optional_instance_variables(
+                        |instance_variables| value.set_instance_variables(instance_variables),
+                        instance_variables,
+                    ),
+                    Value::UserDefined(value) => merge_optional_instance_variables(
+                        |instance_variables| value.set_instance_variables(instance_variables),
+                        instance_variables,
+                    ),
+                    Value::Regexp(value) => merge_optional_instance_variables(
+                        |instance_variables| value.set_instance_variables(instance_variables),
+                        instance_variables,
+                    ),
+                    _ => return Err(Error::NotAnObject),
+                };
+
+                if let Err(name) = result {
+                    let name = self
+                        .arena
+                        .get_symbol(name)
+                        .map(|name| name.value().to_vec())
+                        .unwrap_or_default();
+                    return Err(Error::DuplicateInstanceVariable { name });
+                }
+
+                Ok(handle)
+            }
+        }
+    }
+
+    /// Hand a complete value to the frame waiting on it.
+    ///
+    /// This completes frames until one needs more input.
+    fn finish_value(&mut self, mut handle: ValueHandle) -> Result<(), Error> {
+        loop {
+            let frame = match self.stack.last_mut() {
+                Some(frame) => frame,
+                None => {
+                    // The old root is the nil singleton, or an earlier value of a stateful loader,
+                    // so nothing is left behind.
+                    let _old_root = self.arena.replace_root(handle);
+
+                    self.step = Step::Done;
+                    return Ok(());
+                }
+            };
+
+            match frame {
+                Frame::Array { len, items, .. } => {
+                    items.push(handle);
+                    if items.len() < *len {
+                        self.step = Step::Value { symbol_like: false };
+                        return Ok(());
+                    }
+
+                    let Some(Frame::Array {
+                        handle: array_handle,
+                        items,
+                        ..
+                    }) = self.stack.pop()
+                    else {
+                        unreachable!();
+                    };
+                    *self.arena.get_mut(array_handle).unwrap() = ArrayValue::new(items).into();
+
+                    handle = array_handle;
+                }
+                Frame::Hash {
+                    len,
+                    has_default_value,
+                    pairs,
+                    key,
+                    ..
+                } => {
+                    let default_value = if pairs.len() < *len {
+                        match key.take() {
+                            Some(key) => pairs.push((key, handle)),
+                            None => *key = Some(handle),
+                        }
+
+                        if key.is_some() || pairs.len() < *len || *has_default_value {
+                            self.step = Step::Value { symbol_like: false };
+                            return Ok(());
+                        }
+
+                        None
+                    } else {
+                        Some(handle)
+                    };
+
+                    let Some(Frame::Hash {
+                        handle: hash_handle,
+                        pairs,
+                        ..
+                    }) = self.stack.pop()
+                    else {
+                        unreachable!();
+                    };
+                    *self.arena.get_mut(hash_handle).unwrap() =
+                        HashValue::new(pairs, default_value).into();
+
+                    handle = hash_handle;
+                }
+                Frame::Object {
+                    handle: object_handle,
+                } => {
+                    let owner = InstanceVariablesOwner::Object {
+                        handle: *object_handle,
+                        name: TypedValueHandle::new_unchecked(handle),
+                    };
+                    self.stack.pop();
+                    self.step = Step::FixnumHeader {
+                        target: FixnumTarget::InstanceVariablesLen { owner },
+                    };
+
+                    return Ok(());
+                }
+                Frame::Struct {
+                    handle: struct_handle,
+                } => {
+                    let owner = InstanceVariablesOwner::Struct {
+                        handle: *struct_handle,
+                        name: TypedValueHandle::new_unchecked(handle),
+                    };
+                    self.stack.pop();
+                    self.step = Step::FixnumHeader {
+                        target: FixnumTarget::InstanceVariablesLen { owner },
+                    };
+
+                    return Ok(());
+                }
+                Frame::UserDefined => {
+                    let name = TypedValueHandle::new_unchecked(handle);
+                    self.stack.pop();
+                    self.step = Step::FixnumHeader {
+                        target: FixnumTarget::ByteStringLen {
+                            target: ByteStringTarget::UserDefined { name },
+                        },
+                    };
+
+                    return Ok(());
+                }
+                Frame::UserMarshal {
+                    name: name @ None, ..
+                } => {
+                    *name = Some(TypedValueHandle::new_unchecked(handle));
+                    self.step = Step::Value { symbol_like: false };
+
+                    return Ok(());
+                }
+                Frame::UserMarshal { name: Some(_), .. } => {
+                    let Some(Frame::UserMarshal {
+                        handle: user_marshal_handle,
+                        name: Some(name),
+                    }) = self.stack.pop()
+                    else {
+                        unreachable!();
+                    };
+                    *self.arena.get_mut(user_marshal_handle).unwrap() =
+                        UserMarshalValue::new(name, handle).into();
+
+                    handle = user_marshal_handle;
+                }
+                Frame::Extension {
+                    name: name @ None, ..
+                } => {
+                    *name = Some(TypedValueHandle::new_unchecked(handle));
+                    self.step = Step::Value { symbol_like: false };
+
+                    return Ok(());
+                }
+                Frame::Extension { name: Some(_), .. } => {
+                    let Some(Frame::Extension {
+                        kind,
+                        link,
+                        name: Some(name),
+                    }) = self.stack.pop()
+                    else {
+                        unreachable!();
+                    };
+
+                    handle = self.finish_extension(kind, link, name, handle)?;
+                }
+                Frame::InstanceVariablesValue => {
+                    let owner = InstanceVariablesOwner::Value(handle);
+                    self.stack.pop();
+                    self.step = Step::FixnumHeader {
+                        target: FixnumTarget::InstanceVariablesLen { owner },
+                    };
+
+                    return Ok(());
+                }
+                Frame::InstanceVariables {
+                    len, items, key, ..
+                } => {
+                    match key.take() {
+                        Some(key) => items.push((key, handle)),
+                        None => {
+                            *key = Some(TypedValueHandle::new_unchecked(handle));
+                            self.step = Step::Value { symbol_like: false };
+                            return Ok(());
+                        }
+                    }
+
+                    if items.len() < *len {
+                        self.step = Step::Value { symbol_like: true };
+                        return Ok(());
+                    }
+
+                    let Some(Frame::InstanceVariables { owner, items, .. }) = self.stack.pop()
+                    else {
+                        unreachable!();
+                    };
+
+                    handle = self.finish_instance_variables(owner, items)?;
+                }
+            }
+        }
+    }
+}
+
+/// Reinterpret a fixnum header byte as signed.
+fn header_as_i8(header: u8) -> i8 {
+    i8::from_le_bytes([header])
+}
+
+/// Convert a fixnum to a usize, for lengths and indices.
+fn fixnum_to_usize(value: i32) -> Result<usize, Error> {
+    usize::try_from(value).map_err(|error| Error::FixnumInvalidUSize { error })
+}
+
+/// Parse the text of a float.
+///
+/// Old versions of Ruby wrote extra mantissa bytes after a nul byte, which are ignored.
+fn parse_float(bytes: &[u8]) -> Option<f64> {
+    let bytes = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+    match bytes {
+        b"nan" => Some(f64::NAN),
+        b"inf" => Some(f64::INFINITY),
+        b"-inf" => Some(f64::NEG_INFINITY),
+        _ => core::str::from_utf8(bytes).ok()?.parse().ok(),
+    }
+}
+
+/// Add trailing instance variables to the ones a value already has.
+///
+/// Symbols are interned while loading, so names are compared by handle.
+///
+/// # Errors
+/// Returns the name of the first duplicated instance variable.
+fn merge_instance_variables(
+    existing: &mut Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+) -> Result<(), TypedValueHandle<SymbolValue>> {
+    for (name, _value) in instance_variables.iter() {
+        if existing
+            .iter()
+            .any(|(existing_name, _value)| existing_name == name)
+        {
+            return Err(*name);
+        }
+    }
+    existing.extend(instance_variables);
+
+    Ok(())
+}
+
+/// Add trailing instance variables to a value that may not have any yet.
+///
+/// `set_instance_variables` swaps in new instance variables and returns the old ones.
+fn merge_optional_instance_variables(
+    mut set_instance_variables: impl FnMut(
+        Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+    )
+        -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+    instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+) -> Result<(), TypedValueHandle<SymbolValue>> {
+    let mut existing = set_instance_variables(None).unwrap_or_default();
+    let result = merge_instance_variables(&mut existing, instance_variables);
+    set_instance_variables(Some(existing));
+
+    result
+}
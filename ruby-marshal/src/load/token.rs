@@ -0,0 +1,73 @@
+/// A span of the input, reported while loading with [`load_with_tokens`](crate::load_with_tokens).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    /// The offset of the first byte, from the start of the input.
+    pub offset: usize,
+
+    /// The number of bytes.
+    pub len: usize,
+
+    /// What the bytes are.
+    pub kind: TokenKind,
+}
+
+/// The kind of a [`Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// The version header.
+    Header {
+        /// The major version
+        major: u8,
+
+        /// The minor version
+        minor: u8,
+    },
+
+    /// A byte that says what kind of value follows.
+    ValueKind {
+        /// The kind byte
+        kind: u8,
+    },
+
+    /// A fixnum.
+    Fixnum {
+        /// The value
+        value: i32,
+
+        /// What the value is used for
+        usage: FixnumUsage,
+    },
+
+    /// The bytes of a symbol, string, user defined value, float, bignum, regexp, class, or module.
+    Bytes,
+
+    /// The sign of a bignum.
+    BignumSign {
+        /// Whether the bignum is negative
+        is_negative: bool,
+    },
+
+    /// The option flags of a regexp.
+    RegexpOptions {
+        /// The flags
+        options: u8,
+    },
+}
+
+/// What a fixnum in the input is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixnumUsage {
+    /// A fixnum value.
+    Value,
+
+    /// The index of a symbol link.
+    SymbolLink,
+
+    /// The index of an object link.
+    ObjectLink,
+
+    /// The length of an array, hash, instance variable list, struct member list, or byte string.
+    ///
+    /// The length of a bignum is the number of 16 bit words.
+    Length,
+}
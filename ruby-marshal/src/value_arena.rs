@@ -1,28 +1,78 @@
+mod deduplicate;
+mod display_tree;
+mod stats;
+mod symbol_table;
+mod validate;
 mod value;
 mod value_handle;
 
+use self::display_tree::DisplayTree;
+pub use self::stats::ArenaStats;
+use self::symbol_table::SymbolTable;
+pub use self::validate::ValidationError;
+use self::validate::Validator;
 pub use self::value::ArrayValue;
+pub use self::value::BignumValue;
 pub use self::value::BoolValue;
+pub use self::value::ClassValue;
 pub use self::value::FixnumValue;
+pub use self::value::FloatValue;
 pub use self::value::HashValue;
+pub use self::value::ModuleValue;
 pub use self::value::NilValue;
 pub use self::value::ObjectValue;
+pub use self::value::OldModuleValue;
+pub use self::value::RegexpValue;
 pub use self::value::StringValue;
+pub use self::value::StructValue;
 pub use self::value::SymbolValue;
+#[cfg(feature = "regex")]
+pub use self::value::ToRegexError;
 pub use self::value::UserDefinedValue;
+pub use self::value::UserMarshalValue;
 pub use self::value::Value;
 pub use self::value::ValueKind;
 pub use self::value_handle::TypedValueHandle;
 pub use self::value_handle::ValueHandle;
+use crate::Error;
+use crate::FromValue;
+use crate::FromValueContext;
+use crate::FromValueError;
+use crate::IntoValue;
+use crate::IntoValueError;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use hashbrown::HashMap;
+use hashbrown::HashSet;
 use slotmap::SlotMap;
-use std::collections::HashMap;
+
+/// The id of the next [`ValueArena`] to be created.
+///
+/// This is 64 bits even on 32-bit targets, so it never wraps around to the id of an existing arena.
+static NEXT_ARENA_ID: AtomicU64 = AtomicU64::new(0);
 
 /// An arena of Ruby values.
-#[derive(Debug)]
+///
+/// Each arena has a unique id, which is stored in every handle it creates.
+/// Handles from other arenas are never valid in this one.
+/// A clone keeps the id of the arena it was cloned from, so existing handles stay valid in both.
+/// Handles created after cloning should only be used with the arena that created them.
+/// In debug builds, creating or modifying a value to hold a handle from another arena panics.
+///
+/// Nil, true, and false are singletons, like in Ruby.
+/// Each arena holds one of each, and every handle to one of these values is the same handle.
+#[derive(Debug, Clone)]
 pub struct ValueArena {
+    id: u64,
     arena: SlotMap<slotmap::DefaultKey, Value>,
-    symbols: HashMap<Vec<u8>, TypedValueHandle<SymbolValue>>,
+    symbols: SymbolTable,
     root: ValueHandle,
+
+    nil: TypedValueHandle<NilValue>,
+    true_value: TypedValueHandle<BoolValue>,
+    false_value: TypedValueHandle<BoolValue>,
 }
 
 impl ValueArena {
@@ -30,17 +80,39 @@ impl ValueArena {
     ///
     /// The root node is nil.
     pub fn new() -> Self {
+        let id = NEXT_ARENA_ID.fetch_add(1, Ordering::Relaxed);
         let mut arena = SlotMap::new();
-        let symbols = HashMap::new();
-        let root = ValueHandle::new(arena.insert(Value::Nil(NilValue)));
+        let symbols = SymbolTable::new();
+
+        let nil = ValueHandle::new(id, arena.insert(Value::Nil(NilValue)));
+        let true_value = ValueHandle::new(id, arena.insert(Value::Bool(BoolValue::new(true))));
+        let false_value = ValueHandle::new(id, arena.insert(Value::Bool(BoolValue::new(false))));
 
         Self {
+            id,
             arena,
             symbols,
-            root,
+            root: nil,
+
+            nil: TypedValueHandle::new_unchecked(nil),
+            true_value: TypedValueHandle::new_unchecked(true_value),
+            false_value: TypedValueHandle::new_unchecked(false_value),
         }
     }
 
+    /// Make a new [`ValueArena`] with the given value as the root.
+    pub fn from_value<T>(value: T) -> Result<Self, IntoValueError>
+    where
+        T: IntoValue,
+    {
+        let mut arena = Self::new();
+        let root = value.into_value(&mut arena)?;
+        // The old root is the nil singleton, so nothing is left behind.
+        let _old_root = arena.replace_root(root);
+
+        Ok(arena)
+    }
+
     /// Get the root [`ValueHandle`].
     pub fn root(&self) -> ValueHandle {
         self.root
@@ -52,25 +124,226 @@ impl ValueArena {
         H: Into<ValueHandle>,
     {
         let mut new_root = new_root.into();
+        self.debug_assert_handles([new_root]);
 
-        std::mem::swap(&mut self.root, &mut new_root);
+        core::mem::swap(&mut self.root, &mut new_root);
         new_root
     }
 
+    /// Replace the current root with nil, returning the old root.
+    ///
+    /// The old root and the values it refers to stay in the arena, so they can be reused.
+    pub fn take_root(&mut self) -> ValueHandle {
+        let nil = self.create_nil();
+        self.replace_root(nil)
+    }
+
+    /// Replace the current root, then remove the values that were only reachable from the old root.
+    ///
+    /// Values that are reachable from the new root are kept,
+    /// as are values that were not reachable from the old root, like orphans that are not attached yet.
+    /// Handles to removed values become invalid.
+    ///
+    /// # Returns
+    /// Returns the number of values that were removed.
+    pub fn set_root_and_gc<H>(&mut self, new_root: H) -> usize
+    where
+        H: Into<ValueHandle>,
+    {
+        let old_root = self.replace_root(new_root);
+
+        let reachable = self.reachable_from(self.root);
+        let mut garbage = HashSet::new();
+        let mut stack = vec![old_root];
+        while let Some(handle) = stack.pop() {
+            if reachable.contains(&handle) || self.is_singleton(handle) {
+                continue;
+            }
+            let Some(value) = self.get(handle) else {
+                continue;
+            };
+            if garbage.insert(handle) {
+                stack.extend(value.child_handles());
+            }
+        }
+
+        for handle in garbage.iter() {
+            if let Some(Value::Symbol(_)) = self.arena.get(handle.index) {
+                self.symbols
+                    .remove_handle(&self.arena, TypedValueHandle::new_unchecked(*handle));
+            }
+            self.arena.remove(handle.index);
+        }
+
+        garbage.len()
+    }
+
+    /// Get the handles of every value reachable from the given handle, including itself.
+    fn reachable_from(&self, handle: ValueHandle) -> HashSet<ValueHandle> {
+        let mut reachable = HashSet::new();
+        let mut stack = vec![handle];
+        while let Some(handle) = stack.pop() {
+            let Some(value) = self.get(handle) else {
+                continue;
+            };
+            if reachable.insert(handle) {
+                stack.extend(value.child_handles());
+            }
+        }
+
+        reachable
+    }
+
+    /// Check that handles passed in by the user are from this arena.
+    ///
+    /// Mixing handles from two arenas is a common mistake that otherwise goes unnoticed until the arena is used.
+    /// This is only checked in debug builds, as it walks every handle.
+    ///
+    /// # Panics
+    /// Panics if a handle is from another arena.
+    fn debug_assert_handles<I>(&self, handles: I)
+    where
+        I: IntoIterator<Item = ValueHandle>,
+    {
+        if cfg!(debug_assertions) {
+            for handle in handles {
+                assert!(
+                    handle.arena_id == self.id,
+                    "{handle:?} is from another arena"
+                );
+            }
+        }
+    }
+
+    /// Check if a handle is the nil, true, or false singleton.
+    fn is_singleton(&self, handle: ValueHandle) -> bool {
+        handle == self.nil.into_raw()
+            || handle == self.true_value.into_raw()
+            || handle == self.false_value.into_raw()
+    }
+
+    /// Extract a type from the root value.
+    ///
+    /// This is shorthand for extracting the root with a new [`FromValueContext`].
+    pub fn root_value<'a, T>(&'a self) -> Result<T, FromValueError>
+    where
+        T: FromValue<'a>,
+    {
+        FromValueContext::new(self).from_value(self.root)
+    }
+
+    /// Get a utility to display the value graph starting at the root.
+    ///
+    /// Values that were already displayed are rendered as `#<link>`.
+    pub fn display_tree(&self) -> impl core::fmt::Display + '_ {
+        DisplayTree::new(self, self.root)
+    }
+
+    /// Check that every handle reachable from the root refers to a value in this arena,
+    /// and that every name refers to a symbol.
+    ///
+    /// This can be used to catch mistakes in a manually built arena before dumping it.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        Validator::new(self).validate()
+    }
+
+    /// Summarize the values reachable from the root.
+    ///
+    /// This is a single traversal, and does not modify the arena.
+    pub fn stats(&self) -> ArenaStats {
+        self::stats::arena_stats(self)
+    }
+
+    /// Get the exact number of bytes that dumping this arena would produce.
+    ///
+    /// This runs the dumper without writing anything,
+    /// so it fails in the same cases that dumping does.
+    pub fn serialized_len(&self) -> Result<usize, Error> {
+        crate::dump::serialized_len(self)
+    }
+
+    /// Get the number of bytes that the value at the given handle and the values it holds take up when dumped.
+    ///
+    /// The value is measured on its own, with its own symbol and object links, and without the header.
+    /// This is context-free, so the value may take up fewer bytes within the whole arena,
+    /// where symbols and objects it shares with earlier values are written as links.
+    pub fn value_serialized_len<H>(&self, handle: H) -> Result<usize, Error>
+    where
+        H: Into<ValueHandle>,
+    {
+        crate::dump::value_serialized_len(self, handle.into())
+    }
+
     /// Get a reference to the [`Value`] denoted by the given [`ValueHandle`].
+    ///
+    /// Returns `None` if the handle is from another arena.
     pub fn get<H>(&self, handle: H) -> Option<&Value>
     where
         H: Into<ValueHandle>,
     {
-        self.arena.get(handle.into().index)
+        let handle = handle.into();
+        if handle.arena_id != self.id {
+            return None;
+        }
+
+        self.arena.get(handle.index)
     }
 
     /// Get a mutable reference to the [`Value`] denoted by the given [`ValueHandle`].
+    ///
+    /// Returns `None` if the handle is from another arena.
     pub(crate) fn get_mut<H>(&mut self, handle: H) -> Option<&mut Value>
     where
         H: Into<ValueHandle>,
     {
-        self.arena.get_mut(handle.into().index)
+        let handle = handle.into();
+        if handle.arena_id != self.id {
+            return None;
+        }
+
+        self.arena.get_mut(handle.index)
+    }
+
+    /// Iterate over every value in the arena, in no particular order.
+    ///
+    /// This includes values that are not reachable from the root, like orphans.
+    pub fn iter_values(&self) -> impl Iterator<Item = (ValueHandle, &Value)> + '_ {
+        self.arena
+            .iter()
+            .map(|(index, value)| (ValueHandle::new(self.id, index), value))
+    }
+
+    /// Iterate over every value of the given kind in the arena, like [`ValueArena::iter_values`].
+    pub fn iter_kind(&self, kind: ValueKind) -> impl Iterator<Item = (ValueHandle, &Value)> + '_ {
+        self.iter_values()
+            .filter(move |(_handle, value)| value.kind() == kind)
+    }
+
+    /// Iterate over every `Object` value in the arena, like [`ValueArena::iter_values`].
+    pub fn iter_objects(
+        &self,
+    ) -> impl Iterator<Item = (TypedValueHandle<ObjectValue>, &ObjectValue)> + '_ {
+        self.iter_typed(Value::as_object)
+    }
+
+    /// Iterate over every `String` value in the arena, like [`ValueArena::iter_values`].
+    pub fn iter_strings(
+        &self,
+    ) -> impl Iterator<Item = (TypedValueHandle<StringValue>, &StringValue)> + '_ {
+        self.iter_typed(Value::as_string)
+    }
+
+    /// Iterate over every value that `get_value` accepts, with typed handles.
+    fn iter_typed<'a, T>(
+        &'a self,
+        get_value: fn(&Value) -> Option<&T>,
+    ) -> impl Iterator<Item = (TypedValueHandle<T>, &'a T)> + 'a
+    where
+        T: 'a,
+    {
+        self.iter_values().filter_map(move |(handle, value)| {
+            Some((TypedValueHandle::new_unchecked(handle), get_value(value)?))
+        })
     }
 
     /// Get a reference to the [`SymbolValue`] denoted by the given [`TypedValueHandle`].
@@ -81,26 +354,167 @@ impl ValueArena {
         Some(self.get(handle)?.as_symbol().expect("not a symbol"))
     }
 
-    /// Create an orphan `Nil` value and return the handle.
+    /// Get a mutable reference to the [`ArrayValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not an ArrayValue.
+    pub fn get_array_mut(
+        &mut self,
+        handle: TypedValueHandle<ArrayValue>,
+    ) -> Option<&mut ArrayValue> {
+        self.debug_assert_handles([handle.into_raw()]);
+
+        Some(self.get_mut(handle)?.as_array_mut().expect("not an array"))
+    }
+
+    /// Get a mutable reference to the [`HashValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not a HashValue.
+    pub fn get_hash_mut(&mut self, handle: TypedValueHandle<HashValue>) -> Option<&mut HashValue> {
+        self.debug_assert_handles([handle.into_raw()]);
+
+        Some(self.get_mut(handle)?.as_hash_mut().expect("not a hash"))
+    }
+
+    /// Append an item to the end of the array denoted by the given [`TypedValueHandle`].
+    pub fn push_array_item(
+        &mut self,
+        handle: TypedValueHandle<ArrayValue>,
+        item: ValueHandle,
+    ) -> Result<(), Error> {
+        self.debug_assert_handles([item]);
+
+        self.get_array_mut(handle)
+            .ok_or(Error::InvalidValueHandle {
+                handle: handle.into_raw(),
+            })?
+            .push(item);
+
+        Ok(())
+    }
+
+    /// Insert a key-value pair into the hash denoted by the given [`TypedValueHandle`].
+    ///
+    /// Keys are compared by handle, not by value.
+    /// If the key is already present, its value is replaced in place.
+    /// Otherwise, the pair is appended.
+    ///
+    /// # Returns
+    /// Returns the old value, if the key was present.
+    pub fn insert_hash_pair(
+        &mut self,
+        handle: TypedValueHandle<HashValue>,
+        key: ValueHandle,
+        value: ValueHandle,
+    ) -> Result<Option<ValueHandle>, Error> {
+        self.debug_assert_handles([key, value]);
+
+        let hash = self.get_hash_mut(handle).ok_or(Error::InvalidValueHandle {
+            handle: handle.into_raw(),
+        })?;
+
+        Ok(hash.insert(key, value))
+    }
+
+    /// Set the default value of the hash denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Returns
+    /// Returns the old default value.
+    pub fn set_hash_default(
+        &mut self,
+        handle: TypedValueHandle<HashValue>,
+        default_value: Option<ValueHandle>,
+    ) -> Result<Option<ValueHandle>, Error> {
+        self.debug_assert_handles(default_value);
+
+        let hash = self.get_hash_mut(handle).ok_or(Error::InvalidValueHandle {
+            handle: handle.into_raw(),
+        })?;
+
+        Ok(hash.set_default(default_value))
+    }
+
+    /// Get a mutable reference to the [`StringValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not a StringValue.
+    pub fn get_string_mut(
+        &mut self,
+        handle: TypedValueHandle<StringValue>,
+    ) -> Option<&mut StringValue> {
+        Some(self.get_mut(handle)?.as_string_mut().expect("not a string"))
+    }
+
+    /// Get a mutable reference to the [`UserDefinedValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not a UserDefinedValue.
+    pub fn get_user_defined_mut(
+        &mut self,
+        handle: TypedValueHandle<UserDefinedValue>,
+    ) -> Option<&mut UserDefinedValue> {
+        Some(
+            self.get_mut(handle)?
+                .as_user_defined_mut()
+                .expect("not a user defined value"),
+        )
+    }
+
+    /// Get a mutable reference to the [`RegexpValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not a RegexpValue.
+    pub fn get_regexp_mut(
+        &mut self,
+        handle: TypedValueHandle<RegexpValue>,
+    ) -> Option<&mut RegexpValue> {
+        Some(self.get_mut(handle)?.as_regexp_mut().expect("not a regexp"))
+    }
+
+    /// Get a mutable reference to the [`StructValue`] denoted by the given [`TypedValueHandle`].
+    ///
+    /// # Panics
+    /// Panics if the value is not a StructValue.
+    pub fn get_struct_mut(
+        &mut self,
+        handle: TypedValueHandle<StructValue>,
+    ) -> Option<&mut StructValue> {
+        Some(self.get_mut(handle)?.as_struct_mut().expect("not a struct"))
+    }
+
+    /// Get the handle of the `Nil` value.
+    ///
+    /// Nil is a singleton, so this always returns the same handle for this arena.
     pub fn create_nil(&mut self) -> TypedValueHandle<NilValue> {
+        self.nil
+    }
+
+    /// Create a new `Nil` value that is distinct from the singleton, and return the handle.
+    ///
+    /// This is used for placeholders that are replaced later.
+    pub(crate) fn create_new_nil(&mut self) -> TypedValueHandle<NilValue> {
         let index = self.arena.insert(Value::Nil(NilValue));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
 
-    /// Create an orphan `Bool` value and return the handle.
+    /// Get the handle of a `Bool` value.
+    ///
+    /// True and false are singletons, so this always returns the same handle for each value for this arena.
     pub fn create_bool(&mut self, value: bool) -> TypedValueHandle<BoolValue> {
-        let index = self.arena.insert(Value::Bool(BoolValue::new(value)));
-        let handle = ValueHandle::new(index);
-
-        TypedValueHandle::new_unchecked(handle)
+        if value {
+            self.true_value
+        } else {
+            self.false_value
+        }
     }
 
     /// Create an orphan `Fixnum` value and return the handle.
     pub fn create_fixnum(&mut self, value: i32) -> TypedValueHandle<FixnumValue> {
         let index = self.arena.insert(Value::Fixnum(FixnumValue::new(value)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
@@ -109,58 +523,282 @@ impl ValueArena {
     ///
     /// If a symbol with this name already exists in this arena, it is returned instead of creating a new symbol.
     pub fn create_symbol(&mut self, value: Vec<u8>) -> TypedValueHandle<SymbolValue> {
-        if let Some(handle) = self.symbols.get(&value) {
-            return *handle;
+        self.create_symbol_full(value).0
+    }
+
+    /// Create an orphan `Symbol` value and return the handle, like [`ValueArena::create_symbol`].
+    ///
+    /// # Returns
+    /// Returns the handle and true if a new symbol was created,
+    /// or the existing symbol with this name and false.
+    pub fn create_symbol_full(&mut self, value: Vec<u8>) -> (TypedValueHandle<SymbolValue>, bool) {
+        if let Some(handle) = self.symbols.get(&self.arena, &value) {
+            return (handle, false);
         }
 
-        self.create_new_symbol(value)
+        (self.create_new_symbol(value), true)
+    }
+
+    /// Create an orphan `Symbol` value from a static name and return the handle, like [`ValueArena::create_symbol`].
+    ///
+    /// The name is only copied if a new symbol is created,
+    /// so this is faster when the same names are used over and over, like the names of instance variables.
+    pub fn create_symbol_static(&mut self, value: &'static [u8]) -> TypedValueHandle<SymbolValue> {
+        self.create_symbol_from_slice(value)
+    }
+
+    /// Create an orphan `Symbol` value from a borrowed name, only copying it if a new symbol is created.
+    fn create_symbol_from_slice(&mut self, value: &[u8]) -> TypedValueHandle<SymbolValue> {
+        if let Some(handle) = self.symbols.get(&self.arena, value) {
+            return handle;
+        }
+
+        self.create_new_symbol(value.into())
     }
 
     /// Create a new orphan `Symbol` value and return the handle.
     pub fn create_new_symbol(&mut self, value: Vec<u8>) -> TypedValueHandle<SymbolValue> {
-        let index = self
-            .arena
-            .insert(Value::Symbol(SymbolValue::new(value.clone())));
-        let handle = ValueHandle::new(index);
+        let index = self.arena.insert(Value::Symbol(SymbolValue::new(value)));
+        let handle = ValueHandle::new(self.id, index);
         let handle = TypedValueHandle::new_unchecked(handle);
 
-        self.symbols.entry(value).or_insert(handle);
+        self.symbols.get_or_insert(&self.arena, handle);
 
         handle
     }
 
+    /// Rename the symbol named `old` to `new`, updating every reference to it.
+    ///
+    /// If a symbol named `new` already exists, references to the renamed symbol are redirected to it,
+    /// so the two symbols are merged.
+    ///
+    /// # Returns
+    /// Returns true if a symbol named `old` existed.
+    pub fn rename_symbol(&mut self, old: &[u8], new: &[u8]) -> bool {
+        let Some(old_handle) = self.symbols.remove(&self.arena, old) else {
+            return false;
+        };
+
+        // The symbol is renamed before it is interned again, since the table reads names from the arena.
+        match self.get_mut(old_handle) {
+            Some(Value::Symbol(value)) => {
+                value.set_value(new.into());
+            }
+            _ => unreachable!("handle should be a symbol"),
+        }
+
+        match self.symbols.get(&self.arena, new) {
+            Some(new_handle) => {
+                self.replace_references(old_handle, new_handle);
+            }
+            None => {
+                self.symbols.get_or_insert(&self.arena, old_handle);
+            }
+        }
+
+        true
+    }
+
+    /// Rewrite every reference to `old`, including the root, to point to `new` instead.
+    ///
+    /// References that must be symbols, like object names and instance variable names,
+    /// are only rewritten if `new` is a symbol.
+    /// `old` itself is left in the arena.
+    /// If `new` is not in this arena, nothing is rewritten.
+    ///
+    /// # Returns
+    /// Returns the number of references that were rewritten.
+    pub fn replace_references<O, N>(&mut self, old: O, new: N) -> usize
+    where
+        O: Into<ValueHandle>,
+        N: Into<ValueHandle>,
+    {
+        let old = old.into();
+        let new = new.into();
+        let replace_names = match self.get(new) {
+            Some(value) => matches!(value, Value::Symbol(_)),
+            None => return 0,
+        };
+
+        let get_replacement = |handle: ValueHandle| (handle == old).then_some(new);
+        let mut count = 0;
+        for (_, value) in self.arena.iter_mut() {
+            count += value.replace_handles(get_replacement, replace_names);
+        }
+        if self.root == old {
+            self.root = new;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Merge symbols with the same name, updating every reference to use one of them.
+    ///
+    /// Duplicate symbols can be made with [`ValueArena::create_new_symbol`].
+    /// Duplicates are removed from the arena, so handles to them become invalid.
+    /// Instance variables of duplicates, like their encoding, are discarded in favor of the kept symbol's.
+    ///
+    /// # Returns
+    /// Returns the number of symbols that were removed.
+    pub fn canonicalize_symbols(&mut self) -> usize {
+        let mut replacements = HashMap::new();
+        for (index, value) in self.arena.iter() {
+            if !matches!(value, Value::Symbol(_)) {
+                continue;
+            }
+
+            let handle = TypedValueHandle::new_unchecked(ValueHandle::new(self.id, index));
+            let canonical = self.symbols.get_or_insert(&self.arena, handle);
+            if canonical != handle {
+                replacements.insert(handle.into_raw(), canonical.into_raw());
+            }
+        }
+
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        let get_replacement = |handle: ValueHandle| replacements.get(&handle).copied();
+        for (_, value) in self.arena.iter_mut() {
+            value.replace_handles(get_replacement, true);
+        }
+        if let Some(new_root) = get_replacement(self.root) {
+            self.root = new_root;
+        }
+
+        for handle in replacements.keys() {
+            self.arena.remove(handle.index);
+        }
+
+        replacements.len()
+    }
+
+    /// Merge values reachable from the root that are structurally equal, updating every reference to use one of them.
+    ///
+    /// This makes dumped data smaller, as merged values are written once and then linked.
+    /// However, it changes identity: values that were separate objects in Ruby become the same object,
+    /// so mutating one through Ruby will change the others.
+    /// Only use this if the data does not depend on identity.
+    ///
+    /// Values are equal if they have the same kind and data, and their children are the same after merging.
+    /// Values in cycles are only merged if they refer to the exact same values.
+    /// Merged values are removed from the arena, so handles to them become invalid.
+    ///
+    /// # Returns
+    /// Returns the number of values that were removed.
+    pub fn deduplicate(&mut self) -> usize {
+        let replacements = self::deduplicate::find_duplicates(self);
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        let get_replacement = |handle: ValueHandle| replacements.get(&handle).copied();
+        for (_, value) in self.arena.iter_mut() {
+            value.replace_handles(get_replacement, false);
+        }
+        if let Some(new_root) = get_replacement(self.root) {
+            self.root = new_root;
+        }
+
+        for handle in replacements.keys() {
+            self.arena.remove(handle.index);
+        }
+
+        replacements.len()
+    }
+
     /// Create an orphan `Array` value and return the handle.
     pub fn create_array(&mut self, value: Vec<ValueHandle>) -> TypedValueHandle<ArrayValue> {
+        self.debug_assert_handles(value.iter().copied());
+
         let index = self.arena.insert(Value::Array(ArrayValue::new(value)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
 
+    /// Create an orphan empty `Array` value with the given capacity and return the handle.
+    pub fn create_array_with_capacity(&mut self, capacity: usize) -> TypedValueHandle<ArrayValue> {
+        self.create_array(Vec::with_capacity(capacity))
+    }
+
+    /// Create an orphan `Array` value from an iterator of items and return the handle.
+    ///
+    /// Each item is converted into a value as it is yielded.
+    pub fn create_array_from_iter<I, T>(
+        &mut self,
+        iter: I,
+    ) -> Result<TypedValueHandle<ArrayValue>, IntoValueError>
+    where
+        I: IntoIterator<Item = T>,
+        T: IntoValue,
+    {
+        let iter = iter.into_iter();
+
+        let mut array = Vec::with_capacity(iter.size_hint().0);
+        for item in iter {
+            array.push(item.into_value(self)?);
+        }
+
+        Ok(self.create_array(array))
+    }
+
     /// Create an orphan `Hash` value and return the handle.
     pub fn create_hash(
         &mut self,
         value: Vec<(ValueHandle, ValueHandle)>,
         default_value: Option<ValueHandle>,
     ) -> TypedValueHandle<HashValue> {
+        self.debug_assert_handles(
+            value
+                .iter()
+                .flat_map(|(key, value)| [*key, *value])
+                .chain(default_value),
+        );
+
         let index = self
             .arena
             .insert(Value::Hash(HashValue::new(value, default_value)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
 
+    /// Create an orphan `Hash` value with symbol keys and return the handle.
+    ///
+    /// Each key is interned, like [`ValueArena::create_symbol`].
+    /// The hash has no default value.
+    pub fn create_symbol_hash<'a, I>(&mut self, entries: I) -> TypedValueHandle<HashValue>
+    where
+        I: IntoIterator<Item = (&'a [u8], ValueHandle)>,
+    {
+        let pairs = entries
+            .into_iter()
+            .map(|(key, value)| (self.create_symbol_from_slice(key).into_raw(), value))
+            .collect();
+
+        self.create_hash(pairs, None)
+    }
+
     /// Create an orphan `Object` value and return the handle.
     pub fn create_object(
         &mut self,
         name: TypedValueHandle<SymbolValue>,
         instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
     ) -> TypedValueHandle<ObjectValue> {
+        self.debug_assert_handles(
+            core::iter::once(name.into_raw()).chain(
+                instance_variables
+                    .iter()
+                    .flat_map(|(key, value)| [key.into_raw(), *value]),
+            ),
+        );
+
         let index = self
             .arena
             .insert(Value::Object(ObjectValue::new(name, instance_variables)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
@@ -168,7 +806,57 @@ impl ValueArena {
     /// Create an orphan `String` value and return the handle.
     pub fn create_string(&mut self, value: Vec<u8>) -> TypedValueHandle<StringValue> {
         let index = self.arena.insert(Value::String(StringValue::new(value)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan UTF-8 `String` value and return the handle.
+    ///
+    /// This attaches the `E` instance variable set to `true`, which is how Ruby marks UTF-8 strings.
+    pub fn create_utf8_string(&mut self, value: &str) -> TypedValueHandle<StringValue> {
+        let encoding_name = self.create_symbol_static(b"E");
+        let encoding_value = self.create_bool(true).into();
+
+        self.create_string_with_instance_variables(
+            value.into(),
+            vec![(encoding_name, encoding_value)],
+        )
+    }
+
+    /// Create an orphan `String` value with the named encoding and return the handle.
+    ///
+    /// This attaches the `encoding` instance variable set to a string of the encoding name.
+    pub fn create_string_with_encoding(
+        &mut self,
+        value: Vec<u8>,
+        encoding: &[u8],
+    ) -> TypedValueHandle<StringValue> {
+        let encoding_name = self.create_symbol_static(b"encoding");
+        let encoding_value = self.create_string(encoding.into()).into();
+
+        self.create_string_with_instance_variables(value, vec![(encoding_name, encoding_value)])
+    }
+
+    /// Create an orphan `String` value with instance variables and return the handle.
+    ///
+    /// The instance variables are dumped in the given order, even if there are none.
+    pub fn create_string_with_instance_variables(
+        &mut self,
+        value: Vec<u8>,
+        instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> TypedValueHandle<StringValue> {
+        self.debug_assert_handles(
+            instance_variables
+                .iter()
+                .flat_map(|(key, value)| [key.into_raw(), *value]),
+        );
+
+        let mut string = StringValue::new(value);
+        string.set_instance_variables(Some(instance_variables));
+
+        let index = self.arena.insert(Value::String(string));
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
@@ -179,10 +867,140 @@ impl ValueArena {
         name: TypedValueHandle<SymbolValue>,
         value: Vec<u8>,
     ) -> TypedValueHandle<UserDefinedValue> {
+        self.debug_assert_handles([name.into_raw()]);
+
         let index = self
             .arena
             .insert(Value::UserDefined(UserDefinedValue::new(name, value)));
-        let handle = ValueHandle::new(index);
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `UserDefined` value with instance variables and return the handle.
+    ///
+    /// The instance variables are dumped in the given order, even if there are none.
+    pub fn create_user_defined_with_instance_variables(
+        &mut self,
+        name: TypedValueHandle<SymbolValue>,
+        value: Vec<u8>,
+        instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> TypedValueHandle<UserDefinedValue> {
+        self.debug_assert_handles(
+            core::iter::once(name.into_raw()).chain(
+                instance_variables
+                    .iter()
+                    .flat_map(|(key, value)| [key.into_raw(), *value]),
+            ),
+        );
+
+        let mut user_defined = UserDefinedValue::new(name, value);
+        user_defined.set_instance_variables(Some(instance_variables));
+
+        let index = self.arena.insert(Value::UserDefined(user_defined));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan old format class or module value and return the handle.
+    pub fn create_old_module(&mut self, name: Vec<u8>) -> TypedValueHandle<OldModuleValue> {
+        let index = self
+            .arena
+            .insert(Value::OldModule(OldModuleValue::new(name)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Float` value and return the handle.
+    pub fn create_float(&mut self, value: f64) -> TypedValueHandle<FloatValue> {
+        let index = self.arena.insert(Value::Float(FloatValue::new(value)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Bignum` value from a sign and little-endian magnitude, and return the handle.
+    ///
+    /// Ruby stores the magnitude in 16 bit words, so it is padded with a zero byte if its length is odd.
+    pub fn create_bignum(
+        &mut self,
+        is_negative: bool,
+        mut value: Vec<u8>,
+    ) -> TypedValueHandle<BignumValue> {
+        if !value.len().is_multiple_of(2) {
+            value.push(0);
+        }
+
+        let index = self
+            .arena
+            .insert(Value::Bignum(BignumValue::new(is_negative, value)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Regexp` value and return the handle.
+    pub fn create_regexp(&mut self, source: Vec<u8>, options: u8) -> TypedValueHandle<RegexpValue> {
+        let index = self
+            .arena
+            .insert(Value::Regexp(RegexpValue::new(source, options)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Struct` value and return the handle.
+    pub fn create_struct(
+        &mut self,
+        name: TypedValueHandle<SymbolValue>,
+        members: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> TypedValueHandle<StructValue> {
+        self.debug_assert_handles(
+            core::iter::once(name.into_raw()).chain(
+                members
+                    .iter()
+                    .flat_map(|(key, value)| [key.into_raw(), *value]),
+            ),
+        );
+
+        let index = self
+            .arena
+            .insert(Value::Struct(StructValue::new(name, members)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Class` value and return the handle.
+    pub fn create_class(&mut self, name: Vec<u8>) -> TypedValueHandle<ClassValue> {
+        let index = self.arena.insert(Value::Class(ClassValue::new(name)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `Module` value and return the handle.
+    pub fn create_module(&mut self, name: Vec<u8>) -> TypedValueHandle<ModuleValue> {
+        let index = self.arena.insert(Value::Module(ModuleValue::new(name)));
+        let handle = ValueHandle::new(self.id, index);
+
+        TypedValueHandle::new_unchecked(handle)
+    }
+
+    /// Create an orphan `UserMarshal` value and return the handle.
+    pub fn create_user_marshal(
+        &mut self,
+        name: TypedValueHandle<SymbolValue>,
+        value: ValueHandle,
+    ) -> TypedValueHandle<UserMarshalValue> {
+        self.debug_assert_handles([name.into_raw(), value]);
+
+        let index = self
+            .arena
+            .insert(Value::UserMarshal(UserMarshalValue::new(name, value)));
+        let handle = ValueHandle::new(self.id, index);
 
         TypedValueHandle::new_unchecked(handle)
     }
@@ -194,7 +1012,7 @@ impl Default for ValueArena {
     }
 }
 
-impl std::ops::Index<ValueHandle> for ValueArena {
+impl core::ops::Index<ValueHandle> for ValueArena {
     type Output = Value;
 
     fn index(&self, index: ValueHandle) -> &Self::Output {
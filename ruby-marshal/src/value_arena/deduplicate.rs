@@ -0,0 +1,147 @@
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use crate::ValueKind;
+use alloc::vec;
+use alloc::vec::Vec;
+use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
+use hashbrown::HashSet;
+
+/// The structure of a value, with its children replaced by their canonical handles.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Key {
+    kind: ValueKind,
+
+    /// The data that is not a handle, along with anything needed to tell apart where each child is held.
+    data: Vec<u8>,
+
+    /// The canonical handles of the children, in the order of [`Value::child_handles`].
+    children: Vec<ValueHandle>,
+}
+
+/// Find values reachable from the root that are equal to an earlier one.
+///
+/// # Returns
+/// Returns a map of each duplicate to the value it should be replaced with.
+pub(super) fn find_duplicates(arena: &ValueArena) -> HashMap<ValueHandle, ValueHandle> {
+    let mut replacements: HashMap<ValueHandle, ValueHandle> = HashMap::new();
+    let mut canonical: HashMap<Key, ValueHandle> = HashMap::new();
+
+    // Values are keyed in post-order, so children are usually canonicalized before their parents.
+    // A child that is still in progress is part of a cycle, and is keyed by its own handle.
+    for handle in post_order(arena) {
+        let Some(value) = arena.get(handle) else {
+            continue;
+        };
+        let Some(data) = data_key(value) else {
+            continue;
+        };
+
+        let key = Key {
+            kind: value.kind(),
+            data,
+            children: value
+                .child_handles()
+                .map(|child| replacements.get(&child).copied().unwrap_or(child))
+                .collect(),
+        };
+        match canonical.entry(key) {
+            Entry::Occupied(entry) => {
+                replacements.insert(handle, *entry.get());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(handle);
+            }
+        }
+    }
+
+    replacements
+}
+
+/// Get the values reachable from the root, with each value after the values it refers to, except in cycles.
+fn post_order(arena: &ValueArena) -> Vec<ValueHandle> {
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![(arena.root(), false)];
+    while let Some((handle, is_expanded)) = stack.pop() {
+        if is_expanded {
+            order.push(handle);
+            continue;
+        }
+
+        let Some(value) = arena.get(handle) else {
+            continue;
+        };
+        if !visited.insert(handle) {
+            continue;
+        }
+
+        // Children are pushed in reverse, so they are visited in the order they are dumped.
+        // This keeps the first of each set of equal values, like the dumper would write it.
+        let children: Vec<_> = value
+            .child_handles()
+            .filter(|child| !visited.contains(child))
+            .collect();
+        stack.push((handle, true));
+        stack.extend(children.into_iter().rev().map(|child| (child, false)));
+    }
+
+    order
+}
+
+/// Get the data of a value that is not a handle.
+///
+/// Returns `None` for values that should not be merged.
+/// Nil and bools are already singletons, and symbols are merged by [`ValueArena::canonicalize_symbols`].
+fn data_key(value: &Value) -> Option<Vec<u8>> {
+    fn push_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+        // Lengths always fit in a u64 on supported targets.
+        data.extend(u64::try_from(bytes.len()).unwrap().to_le_bytes());
+        data.extend(bytes);
+    }
+
+    let mut data = Vec::new();
+
+    match value {
+        Value::Nil(_) | Value::Bool(_) | Value::Symbol(_) => return None,
+        Value::Fixnum(value) => {
+            data.extend(value.value().to_le_bytes());
+            data.extend(value.header().map(|header| [1, header]).unwrap_or([0, 0]));
+        }
+        Value::Array(_) => {}
+        Value::Hash(value) => {
+            data.push(u8::from(value.default_value().is_some()));
+        }
+        Value::Object(_) => {}
+        Value::String(value) => {
+            push_bytes(&mut data, value.value());
+            data.push(u8::from(value.instance_variables().is_some()));
+        }
+        Value::UserDefined(value) => {
+            push_bytes(&mut data, value.value());
+            data.push(u8::from(value.instance_variables().is_some()));
+        }
+        Value::OldModule(value) => push_bytes(&mut data, value.name()),
+        Value::Float(value) => data.extend(value.value().to_bits().to_le_bytes()),
+        Value::Bignum(value) => {
+            data.push(u8::from(value.is_negative()));
+            push_bytes(&mut data, value.value());
+        }
+        Value::Regexp(value) => {
+            push_bytes(&mut data, value.source());
+            data.push(value.options());
+            data.push(u8::from(value.instance_variables().is_some()));
+        }
+        Value::Struct(value) => {
+            // The members and instance variables are both pairs, so the number of members marks where they split.
+            data.extend(u64::try_from(value.members().len()).unwrap().to_le_bytes());
+            data.push(u8::from(value.instance_variables().is_some()));
+        }
+        Value::Class(value) => push_bytes(&mut data, value.name()),
+        Value::Module(value) => push_bytes(&mut data, value.name()),
+        Value::UserMarshal(_) => {}
+    }
+
+    Some(data)
+}
@@ -0,0 +1,250 @@
+use crate::DisplayByteString;
+use crate::SymbolValue;
+use crate::TypedValueHandle;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use core::cell::RefCell;
+use core::fmt::Write;
+use hashbrown::HashSet;
+
+/// The indent used for each level of nesting.
+const INDENT: &str = "  ";
+
+/// A utility to display the value graph of a [`ValueArena`] from a given handle.
+pub(super) struct DisplayTree<'a> {
+    arena: &'a ValueArena,
+    handle: ValueHandle,
+    visited: RefCell<HashSet<ValueHandle>>,
+}
+
+impl<'a> DisplayTree<'a> {
+    /// Create a new [`DisplayTree`].
+    pub(super) fn new(arena: &'a ValueArena, handle: ValueHandle) -> Self {
+        Self {
+            arena,
+            handle,
+            visited: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Write the indent for the given depth.
+    fn write_indent(&self, f: &mut core::fmt::Formatter<'_>, depth: usize) -> core::fmt::Result {
+        for _ in 0..depth {
+            f.write_str(INDENT)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a symbol name, without the leading ':'.
+    fn write_symbol_name(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        handle: TypedValueHandle<SymbolValue>,
+    ) -> core::fmt::Result {
+        match self.arena.get(handle).and_then(|value| value.as_symbol()) {
+            Some(value) => write!(f, "{}", DisplayByteString(value.value())),
+            None => f.write_str("#<invalid>"),
+        }
+    }
+
+    /// Write instance variables, one per line.
+    fn write_instance_variables(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+        depth: usize,
+    ) -> core::fmt::Result {
+        for (name, value) in instance_variables.iter() {
+            self.write_indent(f, depth)?;
+            self.write_symbol_name(f, *name)?;
+            f.write_str(" = ")?;
+            self.write_value(f, *value, depth)?;
+            f.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a value.
+    fn write_value(
+        &self,
+        f: &mut core::fmt::Formatter<'_>,
+        handle: ValueHandle,
+        depth: usize,
+    ) -> core::fmt::Result {
+        let value = match self.arena.get(handle) {
+            Some(value) => value,
+            None => return f.write_str("#<invalid>"),
+        };
+
+        // Only values that can be the target of an object link are tracked.
+        let linkable = !matches!(
+            value,
+            Value::Nil(_) | Value::Bool(_) | Value::Fixnum(_) | Value::Symbol(_)
+        );
+        if linkable && !self.visited.borrow_mut().insert(handle) {
+            return f.write_str("#<link>");
+        }
+
+        match value {
+            Value::Nil(_) => f.write_str("nil"),
+            Value::Bool(value) => write!(f, "{}", value.value()),
+            Value::Fixnum(value) => write!(f, "{}", value.value()),
+            Value::Symbol(value) => write!(f, ":{}", DisplayByteString(value.value())),
+            Value::Array(value) => {
+                if value.is_empty() {
+                    return f.write_str("[]");
+                }
+
+                f.write_str("[\n")?;
+                for handle in value.value().iter() {
+                    self.write_indent(f, depth + 1)?;
+                    self.write_value(f, *handle, depth + 1)?;
+                    f.write_str(",\n")?;
+                }
+                self.write_indent(f, depth)?;
+                f.write_char(']')
+            }
+            Value::Hash(value) => {
+                if value.value().is_empty() && value.default_value().is_none() {
+                    return f.write_str("{}");
+                }
+
+                f.write_str("{\n")?;
+                for (key, value) in value.value().iter() {
+                    self.write_indent(f, depth + 1)?;
+                    self.write_value(f, *key, depth + 1)?;
+                    f.write_str(" => ")?;
+                    self.write_value(f, *value, depth + 1)?;
+                    f.write_str(",\n")?;
+                }
+                if let Some(default_value) = value.default_value() {
+                    self.write_indent(f, depth + 1)?;
+                    f.write_str("default => ")?;
+                    self.write_value(f, default_value, depth + 1)?;
+                    f.write_char('\n')?;
+                }
+                self.write_indent(f, depth)?;
+                f.write_char('}')
+            }
+            Value::Object(value) => {
+                f.write_str("#<")?;
+                self.write_symbol_name(f, value.name())?;
+
+                let instance_variables = value.instance_variables();
+                if instance_variables.is_empty() {
+                    return f.write_char('>');
+                }
+
+                f.write_char('\n')?;
+                self.write_instance_variables(f, instance_variables, depth + 1)?;
+                self.write_indent(f, depth)?;
+                f.write_char('>')
+            }
+            Value::String(value) => {
+                write!(f, "\"{}\"", DisplayByteString(value.value()))?;
+
+                if let Some(instance_variables) = value.instance_variables() {
+                    f.write_str(" (\n")?;
+                    self.write_instance_variables(f, instance_variables, depth + 1)?;
+                    self.write_indent(f, depth)?;
+                    f.write_char(')')?;
+                }
+
+                Ok(())
+            }
+            Value::UserDefined(value) => {
+                f.write_str("#<")?;
+                self.write_symbol_name(f, value.name())?;
+                write!(f, " \"{}\">", DisplayByteString(value.value()))?;
+
+                if let Some(instance_variables) = value.instance_variables() {
+                    f.write_str(" (\n")?;
+                    self.write_instance_variables(f, instance_variables, depth + 1)?;
+                    self.write_indent(f, depth)?;
+                    f.write_char(')')?;
+                }
+
+                Ok(())
+            }
+            Value::OldModule(value) => write!(f, "{}", DisplayByteString(value.name())),
+            Value::Float(value) => write!(f, "{:?}", value.value()),
+            Value::Bignum(value) => {
+                if value.is_negative() {
+                    f.write_char('-')?;
+                }
+                f.write_str("0x")?;
+
+                // The magnitude is little-endian, so it is written backwards without leading zeros.
+                let mut bytes = value.value().iter().rev().skip_while(|byte| **byte == 0);
+                match bytes.next() {
+                    Some(byte) => write!(f, "{byte:X}")?,
+                    None => f.write_char('0')?,
+                }
+                for byte in bytes {
+                    write!(f, "{byte:02X}")?;
+                }
+
+                Ok(())
+            }
+            Value::Regexp(value) => {
+                write!(f, "/{}/", DisplayByteString(value.source()))?;
+                for (flag, c) in [(4, 'm'), (1, 'i'), (2, 'x')] {
+                    if value.options() & flag != 0 {
+                        f.write_char(c)?;
+                    }
+                }
+
+                if let Some(instance_variables) = value.instance_variables() {
+                    f.write_str(" (\n")?;
+                    self.write_instance_variables(f, instance_variables, depth + 1)?;
+                    self.write_indent(f, depth)?;
+                    f.write_char(')')?;
+                }
+
+                Ok(())
+            }
+            Value::Struct(value) => {
+                f.write_str("#<struct ")?;
+                self.write_symbol_name(f, value.name())?;
+
+                let members = value.members();
+                if members.is_empty() {
+                    f.write_char('>')?;
+                } else {
+                    f.write_char('\n')?;
+                    self.write_instance_variables(f, members, depth + 1)?;
+                    self.write_indent(f, depth)?;
+                    f.write_char('>')?;
+                }
+
+                if let Some(instance_variables) = value.instance_variables() {
+                    f.write_str(" (\n")?;
+                    self.write_instance_variables(f, instance_variables, depth + 1)?;
+                    self.write_indent(f, depth)?;
+                    f.write_char(')')?;
+                }
+
+                Ok(())
+            }
+            Value::Class(value) => write!(f, "{}", DisplayByteString(value.name())),
+            Value::Module(value) => write!(f, "{}", DisplayByteString(value.name())),
+            Value::UserMarshal(value) => {
+                f.write_str("#<")?;
+                self.write_symbol_name(f, value.name())?;
+                f.write_char(' ')?;
+                self.write_value(f, value.value(), depth)?;
+                f.write_char('>')
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for DisplayTree<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.visited.borrow_mut().clear();
+        self.write_value(f, self.handle, 0)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use crate::ValueKind;
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
+use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
+use hashbrown::HashSet;
+
+/// A summary of the values reachable from the root of a [`ValueArena`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// The number of reachable values.
+    pub values: usize,
+
+    /// The number of reachable values of each kind.
+    ///
+    /// Kinds with no values are left out.
+    pub kinds: BTreeMap<ValueKind, usize>,
+
+    /// The number of distinct symbol names.
+    pub unique_symbols: usize,
+
+    /// The greatest depth of any value, where the root is at depth 0.
+    ///
+    /// The depth of a value is the length of the shortest path from the root to it.
+    pub max_depth: usize,
+
+    /// The number of values that are referenced more than once.
+    ///
+    /// Nil, bools, fixnums, and symbols are left out,
+    /// as they are never written as object links.
+    pub shared: usize,
+
+    /// The total length of every reachable string, in bytes.
+    pub string_bytes: usize,
+
+    /// The total length of every reachable symbol name, in bytes.
+    pub symbol_bytes: usize,
+}
+
+impl core::fmt::Display for ArenaStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "values: {}", self.values)?;
+        for (kind, count) in self.kinds.iter() {
+            writeln!(f, "  {kind:?}: {count}")?;
+        }
+        writeln!(f, "unique symbols: {}", self.unique_symbols)?;
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        writeln!(f, "shared values: {}", self.shared)?;
+        writeln!(f, "string bytes: {}", self.string_bytes)?;
+        write!(f, "symbol bytes: {}", self.symbol_bytes)
+    }
+}
+
+/// Compute the [`ArenaStats`] of the values reachable from the root.
+pub(super) fn arena_stats(arena: &ValueArena) -> ArenaStats {
+    let mut stats = ArenaStats::default();
+    let mut symbol_names = HashSet::new();
+
+    // The number of references to each value that was reached.
+    let mut references: HashMap<ValueHandle, usize> = HashMap::new();
+
+    // A breadth-first traversal reaches each value along its shortest path first.
+    let mut queue = VecDeque::new();
+    if arena.get(arena.root()).is_some() {
+        references.insert(arena.root(), 0);
+        queue.push_back((arena.root(), 0));
+    }
+
+    while let Some((handle, depth)) = queue.pop_front() {
+        let Some(value) = arena.get(handle) else {
+            continue;
+        };
+
+        stats.values += 1;
+        *stats.kinds.entry(value.kind()).or_default() += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+
+        match value {
+            Value::String(value) => {
+                stats.string_bytes += value.value().len();
+            }
+            Value::Symbol(value) => {
+                stats.symbol_bytes += value.value().len();
+                symbol_names.insert(value.value());
+            }
+            _ => {}
+        }
+
+        for child in value.child_handles() {
+            if arena.get(child).is_none() {
+                continue;
+            }
+
+            match references.entry(child) {
+                Entry::Occupied(mut entry) => {
+                    *entry.get_mut() += 1;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(1);
+                    queue.push_back((child, depth + 1));
+                }
+            }
+        }
+    }
+
+    stats.unique_symbols = symbol_names.len();
+    stats.shared = references
+        .iter()
+        .filter(|(handle, count)| {
+            **count > 1
+                && arena.get(**handle).is_some_and(|value| {
+                    !matches!(
+                        value,
+                        Value::Nil(_) | Value::Bool(_) | Value::Fixnum(_) | Value::Symbol(_)
+                    )
+                })
+        })
+        .count();
+
+    stats
+}
@@ -0,0 +1,113 @@
+use super::SymbolValue;
+use super::TypedValueHandle;
+use super::Value;
+use core::hash::BuildHasher;
+use hashbrown::hash_table::Entry;
+use hashbrown::DefaultHashBuilder;
+use hashbrown::HashTable;
+use slotmap::SlotMap;
+
+/// The interned symbols of an arena, keyed by name.
+///
+/// Only handles are stored here.
+/// Names are looked up in the arena, so the bytes of each symbol are only stored once.
+/// As a result, an interned symbol must be removed from this table before it is renamed or removed from the arena.
+#[derive(Debug, Clone)]
+pub(super) struct SymbolTable {
+    table: HashTable<TypedValueHandle<SymbolValue>>,
+    hash_builder: DefaultHashBuilder,
+}
+
+impl SymbolTable {
+    /// Make a new empty [`SymbolTable`].
+    pub(super) fn new() -> Self {
+        Self {
+            table: HashTable::new(),
+            hash_builder: DefaultHashBuilder::default(),
+        }
+    }
+
+    /// Get the interned symbol with the given name.
+    pub(super) fn get(
+        &self,
+        arena: &SlotMap<slotmap::DefaultKey, Value>,
+        name: &[u8],
+    ) -> Option<TypedValueHandle<SymbolValue>> {
+        let hash = self.hash_builder.hash_one(name);
+        self.table
+            .find(hash, |handle| symbol_name(arena, *handle) == name)
+            .copied()
+    }
+
+    /// Intern the given symbol, unless a symbol with the same name is already interned.
+    ///
+    /// # Returns
+    /// Returns the interned symbol.
+    pub(super) fn get_or_insert(
+        &mut self,
+        arena: &SlotMap<slotmap::DefaultKey, Value>,
+        handle: TypedValueHandle<SymbolValue>,
+    ) -> TypedValueHandle<SymbolValue> {
+        let name = symbol_name(arena, handle);
+        let hash = self.hash_builder.hash_one(name);
+        let entry = self.table.entry(
+            hash,
+            |handle| symbol_name(arena, *handle) == name,
+            |handle| self.hash_builder.hash_one(symbol_name(arena, *handle)),
+        );
+
+        match entry {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => *entry.insert(handle).get(),
+        }
+    }
+
+    /// Remove the interned symbol with the given name.
+    ///
+    /// # Returns
+    /// Returns the removed symbol.
+    pub(super) fn remove(
+        &mut self,
+        arena: &SlotMap<slotmap::DefaultKey, Value>,
+        name: &[u8],
+    ) -> Option<TypedValueHandle<SymbolValue>> {
+        let hash = self.hash_builder.hash_one(name);
+        let entry = self
+            .table
+            .find_entry(hash, |handle| symbol_name(arena, *handle) == name)
+            .ok()?;
+        let (handle, _entry) = entry.remove();
+
+        Some(handle)
+    }
+
+    /// Remove the given symbol if it is interned.
+    ///
+    /// # Returns
+    /// Returns true if the symbol was interned.
+    pub(super) fn remove_handle(
+        &mut self,
+        arena: &SlotMap<slotmap::DefaultKey, Value>,
+        handle: TypedValueHandle<SymbolValue>,
+    ) -> bool {
+        let hash = self.hash_builder.hash_one(symbol_name(arena, handle));
+        match self.table.find_entry(hash, |interned| *interned == handle) {
+            Ok(entry) => {
+                entry.remove();
+                true
+            }
+            Err(_entry) => false,
+        }
+    }
+}
+
+/// Get the name of a symbol in the arena.
+fn symbol_name(
+    arena: &SlotMap<slotmap::DefaultKey, Value>,
+    handle: TypedValueHandle<SymbolValue>,
+) -> &[u8] {
+    match arena.get(handle.into_raw().index) {
+        Some(Value::Symbol(value)) => value.value(),
+        _ => unreachable!("interned handle should be a symbol in the arena"),
+    }
+}
@@ -0,0 +1,222 @@
+use crate::SymbolValue;
+use crate::TypedValueHandle;
+use crate::Value;
+use crate::ValueArena;
+use crate::ValueHandle;
+use crate::ValueKind;
+use alloc::vec;
+use alloc::vec::Vec;
+use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
+
+/// An error that may occur while validating a [`ValueArena`].
+#[derive(Debug)]
+pub enum ValidationError {
+    /// A handle does not refer to a value in the arena.
+    InvalidValueHandle {
+        /// The invalid handle
+        handle: ValueHandle,
+
+        /// The path of handles from the root to the value holding the invalid handle.
+        ///
+        /// This is empty if the root is invalid.
+        path: Vec<ValueHandle>,
+    },
+
+    /// A handle that must refer to a symbol refers to another kind of value.
+    NotASymbol {
+        /// The handle
+        handle: ValueHandle,
+
+        /// The kind of value it refers to
+        kind: ValueKind,
+
+        /// The path of handles from the root to the value holding the handle.
+        path: Vec<ValueHandle>,
+    },
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidValueHandle { path, .. } => {
+                write!(f, "invalid value handle at depth {}", path.len())
+            }
+            Self::NotASymbol { kind, path, .. } => {
+                write!(
+                    f,
+                    "expected a symbol at depth {}, but got {kind:?}",
+                    path.len()
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+/// A utility to check that every handle reachable from the root of a [`ValueArena`] is valid.
+pub(super) struct Validator<'a> {
+    arena: &'a ValueArena,
+
+    /// The handle that each visited handle was first reached from.
+    parents: HashMap<ValueHandle, Option<ValueHandle>>,
+    stack: Vec<ValueHandle>,
+}
+
+impl<'a> Validator<'a> {
+    /// Create a new [`Validator`].
+    pub(super) fn new(arena: &'a ValueArena) -> Self {
+        Self {
+            arena,
+            parents: HashMap::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Get the path of handles from the root to the given handle.
+    fn path(&self, mut handle: ValueHandle) -> Vec<ValueHandle> {
+        let mut path = vec![handle];
+        while let Some(Some(parent)) = self.parents.get(&handle) {
+            path.push(*parent);
+            handle = *parent;
+        }
+        path.reverse();
+
+        path
+    }
+
+    /// Check a handle held by a visited value, and queue it to be visited if it is new.
+    fn visit(&mut self, parent: ValueHandle, handle: ValueHandle) -> Result<(), ValidationError> {
+        if self.arena.get(handle).is_none() {
+            return Err(ValidationError::InvalidValueHandle {
+                handle,
+                path: self.path(parent),
+            });
+        }
+
+        if let Entry::Vacant(entry) = self.parents.entry(handle) {
+            entry.insert(Some(parent));
+            self.stack.push(handle);
+        }
+
+        Ok(())
+    }
+
+    /// Check a handle held by a visited value that must refer to a symbol.
+    fn visit_symbol(
+        &mut self,
+        parent: ValueHandle,
+        handle: TypedValueHandle<SymbolValue>,
+    ) -> Result<(), ValidationError> {
+        let handle = handle.into_raw();
+        self.visit(parent, handle)?;
+
+        // The handle was checked above.
+        let kind = self.arena.get(handle).unwrap().kind();
+        if kind != ValueKind::Symbol {
+            return Err(ValidationError::NotASymbol {
+                handle,
+                kind,
+                path: self.path(parent),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check instance variables held by a visited value.
+    fn visit_instance_variables(
+        &mut self,
+        parent: ValueHandle,
+        instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)],
+    ) -> Result<(), ValidationError> {
+        for (name, value) in instance_variables.iter() {
+            self.visit_symbol(parent, *name)?;
+            self.visit(parent, *value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Validate the arena.
+    pub(super) fn validate(mut self) -> Result<(), ValidationError> {
+        let root = self.arena.root();
+        if self.arena.get(root).is_none() {
+            return Err(ValidationError::InvalidValueHandle {
+                handle: root,
+                path: Vec::new(),
+            });
+        }
+        self.parents.insert(root, None);
+        self.stack.push(root);
+
+        while let Some(handle) = self.stack.pop() {
+            // Only valid handles are queued.
+            let value = self.arena.get(handle).unwrap();
+
+            match value {
+                Value::Nil(_)
+                | Value::Bool(_)
+                | Value::Fixnum(_)
+                | Value::OldModule(_)
+                | Value::Float(_)
+                | Value::Bignum(_)
+                | Value::Class(_)
+                | Value::Module(_) => {}
+                Value::Symbol(value) => {
+                    if let Some(instance_variables) = value.instance_variables() {
+                        self.visit_instance_variables(handle, instance_variables)?;
+                    }
+                }
+                Value::UserMarshal(value) => {
+                    self.visit_symbol(handle, value.name())?;
+                    self.visit(handle, value.value())?;
+                }
+                Value::Array(value) => {
+                    for item in value.value().iter() {
+                        self.visit(handle, *item)?;
+                    }
+                }
+                Value::Hash(value) => {
+                    for (key, value) in value.value().iter() {
+                        self.visit(handle, *key)?;
+                        self.visit(handle, *value)?;
+                    }
+                    if let Some(default_value) = value.default_value() {
+                        self.visit(handle, default_value)?;
+                    }
+                }
+                Value::Object(value) => {
+                    self.visit_symbol(handle, value.name())?;
+                    self.visit_instance_variables(handle, value.instance_variables())?;
+                }
+                Value::String(value) => {
+                    if let Some(instance_variables) = value.instance_variables() {
+                        self.visit_instance_variables(handle, instance_variables)?;
+                    }
+                }
+                Value::UserDefined(value) => {
+                    self.visit_symbol(handle, value.name())?;
+                    if let Some(instance_variables) = value.instance_variables() {
+                        self.visit_instance_variables(handle, instance_variables)?;
+                    }
+                }
+                Value::Regexp(value) => {
+                    if let Some(instance_variables) = value.instance_variables() {
+                        self.visit_instance_variables(handle, instance_variables)?;
+                    }
+                }
+                Value::Struct(value) => {
+                    self.visit_symbol(handle, value.name())?;
+                    self.visit_instance_variables(handle, value.members())?;
+                    if let Some(instance_variables) = value.instance_variables() {
+                        self.visit_instance_variables(handle, instance_variables)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,8 +1,17 @@
 use crate::TypedValueHandle;
+use crate::ValueArena;
 use crate::ValueHandle;
+use alloc::vec::Vec;
+use core::cell::Cell;
 
 /// A Ruby Value
-#[derive(Debug)]
+///
+/// Leaf values that hold no handles, like [`NilValue`], [`BoolValue`], [`FixnumValue`], and [`SymbolValue`],
+/// are compared and hashed by their content, so they can be used as map keys.
+/// Values that hold handles deliberately do not implement these,
+/// as a handle is only meaningful with its arena.
+/// Compare those through the arena instead, like with [`ValueArena::deduplicate`](crate::ValueArena::deduplicate).
+#[derive(Debug, Clone)]
 pub enum Value {
     /// Nil
     Nil(NilValue),
@@ -30,6 +39,30 @@ pub enum Value {
 
     /// A User Defined Value
     UserDefined(UserDefinedValue),
+
+    /// A class or module, in the old format
+    OldModule(OldModuleValue),
+
+    /// A Float
+    Float(FloatValue),
+
+    /// A Bignum
+    Bignum(BignumValue),
+
+    /// A Regexp
+    Regexp(RegexpValue),
+
+    /// A Struct
+    Struct(StructValue),
+
+    /// A class
+    Class(ClassValue),
+
+    /// A module
+    Module(ModuleValue),
+
+    /// A user marshal value
+    UserMarshal(UserMarshalValue),
 }
 
 impl Value {
@@ -41,6 +74,22 @@ impl Value {
         }
     }
 
+    /// Get a mutable ref to the [`ArrayValue`], if it is an array.
+    pub fn as_array_mut(&mut self) -> Option<&mut ArrayValue> {
+        match self {
+            Self::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable ref to the [`HashValue`], if it is a hash.
+    pub fn as_hash_mut(&mut self) -> Option<&mut HashValue> {
+        match self {
+            Self::Hash(value) => Some(value),
+            _ => None,
+        }
+    }
+
     /// Get a ref to the [`ObjectValue`], if it is an object.
     pub fn as_object(&self) -> Option<&ObjectValue> {
         match self {
@@ -57,6 +106,38 @@ impl Value {
         }
     }
 
+    /// Get a mutable ref to the [`StringValue`], if it is a string.
+    pub fn as_string_mut(&mut self) -> Option<&mut StringValue> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable ref to the [`UserDefinedValue`], if it is a user defined value.
+    pub fn as_user_defined_mut(&mut self) -> Option<&mut UserDefinedValue> {
+        match self {
+            Self::UserDefined(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable ref to the [`RegexpValue`], if it is a regexp.
+    pub fn as_regexp_mut(&mut self) -> Option<&mut RegexpValue> {
+        match self {
+            Self::Regexp(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable ref to the [`StructValue`], if it is a struct.
+    pub fn as_struct_mut(&mut self) -> Option<&mut StructValue> {
+        match self {
+            Self::Struct(value) => Some(value),
+            _ => None,
+        }
+    }
+
     /// Get the kind of value.
     pub fn kind(&self) -> ValueKind {
         match self {
@@ -69,7 +150,186 @@ impl Value {
             Self::Object(_) => ValueKind::Object,
             Self::String(_) => ValueKind::String,
             Self::UserDefined(_) => ValueKind::UserDefined,
+            Self::OldModule(_) => ValueKind::OldModule,
+            Self::Float(_) => ValueKind::Float,
+            Self::Bignum(_) => ValueKind::Bignum,
+            Self::Regexp(_) => ValueKind::Regexp,
+            Self::Struct(_) => ValueKind::Struct,
+            Self::Class(_) => ValueKind::Class,
+            Self::Module(_) => ValueKind::Module,
+            Self::UserMarshal(_) => ValueKind::UserMarshal,
+        }
+    }
+
+    /// Iterate over the handles held by this value.
+    ///
+    /// This yields the name of objects, structs, user defined values, and user marshal values,
+    /// then the data of user marshal values, then array items, then hash keys and values, then the hash default value,
+    /// then struct member names and values, then instance variable names and values.
+    /// Handles are yielded once for each place they are held, so they may repeat.
+    pub fn child_handles(&self) -> impl Iterator<Item = ValueHandle> + '_ {
+        let mut name = None;
+        let mut data = None;
+        let mut items: &[ValueHandle] = &[];
+        let mut pairs: &[(ValueHandle, ValueHandle)] = &[];
+        let mut default_value = None;
+        let mut members: &[(TypedValueHandle<SymbolValue>, ValueHandle)] = &[];
+        let mut instance_variables: &[(TypedValueHandle<SymbolValue>, ValueHandle)] = &[];
+
+        match self {
+            Self::Nil(_)
+            | Self::Bool(_)
+            | Self::Fixnum(_)
+            | Self::OldModule(_)
+            | Self::Float(_)
+            | Self::Bignum(_)
+            | Self::Class(_)
+            | Self::Module(_) => {}
+            Self::Symbol(value) => {
+                instance_variables = value.instance_variables().unwrap_or_default();
+            }
+            Self::Array(value) => {
+                items = value.value();
+            }
+            Self::Hash(value) => {
+                pairs = value.value();
+                default_value = value.default_value();
+            }
+            Self::Object(value) => {
+                name = Some(value.name().into_raw());
+                instance_variables = value.instance_variables();
+            }
+            Self::String(value) => {
+                instance_variables = value.instance_variables().unwrap_or_default();
+            }
+            Self::UserDefined(value) => {
+                name = Some(value.name().into_raw());
+                instance_variables = value.instance_variables().unwrap_or_default();
+            }
+            Self::Regexp(value) => {
+                instance_variables = value.instance_variables().unwrap_or_default();
+            }
+            Self::Struct(value) => {
+                name = Some(value.name().into_raw());
+                members = value.members();
+                instance_variables = value.instance_variables().unwrap_or_default();
+            }
+            Self::UserMarshal(value) => {
+                name = Some(value.name().into_raw());
+                data = Some(value.value());
+            }
+        }
+
+        name.into_iter()
+            .chain(data)
+            .chain(items.iter().copied())
+            .chain(pairs.iter().flat_map(|(key, value)| [*key, *value]))
+            .chain(default_value)
+            .chain(
+                members
+                    .iter()
+                    .chain(instance_variables.iter())
+                    .flat_map(|(name, value)| [name.into_raw(), *value]),
+            )
+    }
+
+    /// Replace handles held by this value.
+    ///
+    /// `get_replacement` is called with every held handle,
+    /// and returns the handle to replace it with, if any.
+    /// Handles that must be symbols, like names, are only replaced if `replace_names` is true.
+    /// In that case, the caller must make sure that their replacements are symbols.
+    ///
+    /// # Returns
+    /// Returns the number of handles that were replaced.
+    pub(crate) fn replace_handles<F>(&mut self, get_replacement: F, replace_names: bool) -> usize
+    where
+        F: Fn(ValueHandle) -> Option<ValueHandle>,
+    {
+        let count = Cell::new(0);
+        let replace = |handle: &mut ValueHandle| {
+            if let Some(new) = get_replacement(*handle) {
+                *handle = new;
+                count.set(count.get() + 1);
+            }
+        };
+        let replace_name = |name: &mut TypedValueHandle<SymbolValue>| {
+            if !replace_names {
+                return;
+            }
+            if let Some(new) = get_replacement(name.into_raw()) {
+                *name = TypedValueHandle::new_unchecked(new);
+                count.set(count.get() + 1);
+            }
+        };
+        let replace_instance_variables =
+            |instance_variables: &mut [(TypedValueHandle<SymbolValue>, ValueHandle)]| {
+                for (name, value) in instance_variables.iter_mut() {
+                    replace_name(name);
+                    replace(value);
+                }
+            };
+
+        match self {
+            Self::Nil(_)
+            | Self::Bool(_)
+            | Self::Fixnum(_)
+            | Self::OldModule(_)
+            | Self::Float(_)
+            | Self::Bignum(_)
+            | Self::Class(_)
+            | Self::Module(_) => {}
+            Self::Symbol(value) => {
+                if let Some(instance_variables) = value.instance_variables.as_mut() {
+                    replace_instance_variables(instance_variables);
+                }
+            }
+            Self::Array(value) => {
+                value.value.iter_mut().for_each(replace);
+            }
+            Self::Hash(value) => {
+                for (key, value) in value.value.iter_mut() {
+                    replace(key);
+                    replace(value);
+                }
+                if let Some(default_value) = value.default_value.as_mut() {
+                    replace(default_value);
+                }
+            }
+            Self::Object(value) => {
+                replace_name(&mut value.name);
+                replace_instance_variables(&mut value.instance_variables);
+            }
+            Self::String(value) => {
+                if let Some(instance_variables) = value.instance_variables.as_mut() {
+                    replace_instance_variables(instance_variables);
+                }
+            }
+            Self::UserDefined(value) => {
+                replace_name(&mut value.name);
+                if let Some(instance_variables) = value.instance_variables.as_mut() {
+                    replace_instance_variables(instance_variables);
+                }
+            }
+            Self::Regexp(value) => {
+                if let Some(instance_variables) = value.instance_variables.as_mut() {
+                    replace_instance_variables(instance_variables);
+                }
+            }
+            Self::Struct(value) => {
+                replace_name(&mut value.name);
+                replace_instance_variables(&mut value.members);
+                if let Some(instance_variables) = value.instance_variables.as_mut() {
+                    replace_instance_variables(instance_variables);
+                }
+            }
+            Self::UserMarshal(value) => {
+                replace_name(&mut value.name);
+                replace(&mut value.value);
+            }
         }
+
+        count.get()
     }
 }
 
@@ -127,12 +387,60 @@ impl From<UserDefinedValue> for Value {
     }
 }
 
+impl From<OldModuleValue> for Value {
+    fn from(value: OldModuleValue) -> Self {
+        Self::OldModule(value)
+    }
+}
+
+impl From<FloatValue> for Value {
+    fn from(value: FloatValue) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<BignumValue> for Value {
+    fn from(value: BignumValue) -> Self {
+        Self::Bignum(value)
+    }
+}
+
+impl From<RegexpValue> for Value {
+    fn from(value: RegexpValue) -> Self {
+        Self::Regexp(value)
+    }
+}
+
+impl From<StructValue> for Value {
+    fn from(value: StructValue) -> Self {
+        Self::Struct(value)
+    }
+}
+
+impl From<ClassValue> for Value {
+    fn from(value: ClassValue) -> Self {
+        Self::Class(value)
+    }
+}
+
+impl From<ModuleValue> for Value {
+    fn from(value: ModuleValue) -> Self {
+        Self::Module(value)
+    }
+}
+
+impl From<UserMarshalValue> for Value {
+    fn from(value: UserMarshalValue) -> Self {
+        Self::UserMarshal(value)
+    }
+}
+
 /// A Nil value.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct NilValue;
 
 /// A bool value.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BoolValue {
     value: bool,
 }
@@ -150,43 +458,163 @@ impl BoolValue {
 }
 
 /// A Fixnum Value
+///
+/// Fixnums are compared and hashed by their numeric value, ignoring how they were encoded.
 #[derive(Debug, Copy, Clone)]
 pub struct FixnumValue {
     value: i32,
+
+    /// The header byte this value was originally encoded with, if it should be preserved.
+    header: Option<u8>,
 }
 
 impl FixnumValue {
     /// Create a new [`FixnumValue`].
     pub(super) fn new(value: i32) -> Self {
-        Self { value }
+        Self {
+            value,
+            header: None,
+        }
+    }
+
+    /// Create a new [`FixnumValue`] that should be encoded with the given header byte.
+    pub(crate) fn new_with_header(value: i32, header: u8) -> Self {
+        Self {
+            value,
+            header: Some(header),
+        }
     }
 
     /// Get the inner value
     pub fn value(self) -> i32 {
         self.value
     }
+
+    /// Get the header byte this value should be encoded with, if it should be preserved.
+    pub(crate) fn header(self) -> Option<u8> {
+        self.header
+    }
+}
+
+impl PartialEq for FixnumValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for FixnumValue {}
+
+impl PartialOrd for FixnumValue {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FixnumValue {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl core::hash::Hash for FixnumValue {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: core::hash::Hasher,
+    {
+        self.value.hash(state);
+    }
 }
 
 /// A Symbol
-#[derive(Debug)]
+///
+/// Symbols are compared and hashed by their bytes, ignoring their instance variables.
+#[derive(Debug, Clone)]
 pub struct SymbolValue {
     value: Vec<u8>,
+    instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
 }
 
 impl SymbolValue {
     /// Create a new [`SymbolValue`].
     pub(super) fn new(value: Vec<u8>) -> Self {
-        Self { value }
+        Self {
+            value,
+            instance_variables: None,
+        }
     }
 
     /// Get the inner value.
     pub fn value(&self) -> &[u8] {
         &self.value
     }
+
+    /// Get the inner value as a str.
+    ///
+    /// # Errors
+    /// Returns an error if the inner value is not valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(&self.value)
+    }
+
+    /// Set the inner value.
+    ///
+    /// # Returns
+    /// Returns the old value
+    pub(crate) fn set_value(&mut self, value: Vec<u8>) -> Vec<u8> {
+        core::mem::replace(&mut self.value, value)
+    }
+
+    /// Get the instance variables.
+    ///
+    /// Symbols use these to store their encoding, if they are not ASCII.
+    pub fn instance_variables(&self) -> Option<&[(TypedValueHandle<SymbolValue>, ValueHandle)]> {
+        self.instance_variables.as_deref()
+    }
+
+    /// Set the instance variables.
+    ///
+    /// # Returns
+    /// Returns the old instance variables
+    pub(crate) fn set_instance_variables(
+        &mut self,
+        mut instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        core::mem::swap(&mut self.instance_variables, &mut instance_variables);
+        instance_variables
+    }
+}
+
+impl PartialEq for SymbolValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for SymbolValue {}
+
+impl PartialOrd for SymbolValue {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SymbolValue {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl core::hash::Hash for SymbolValue {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: core::hash::Hasher,
+    {
+        self.value.hash(state);
+    }
 }
 
 /// An Array
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ArrayValue {
     value: Vec<ValueHandle>,
 }
@@ -211,10 +639,36 @@ impl ArrayValue {
     pub fn is_empty(&self) -> bool {
         self.value.is_empty()
     }
+
+    /// Append an element to the end of the array.
+    ///
+    /// Use [`ValueArena::push_array_item`](crate::ValueArena::push_array_item),
+    /// which checks that the handle is from the same arena.
+    pub(crate) fn push(&mut self, handle: ValueHandle) {
+        self.value.push(handle);
+    }
+
+    /// Reserve capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.value.reserve(additional);
+    }
+
+    /// Remove and return the element at the given index, shifting all elements after it.
+    ///
+    /// # Panics
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> ValueHandle {
+        self.value.remove(index)
+    }
+
+    /// Remove all elements.
+    pub fn clear(&mut self) {
+        self.value.clear();
+    }
 }
 
 /// A Hash
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HashValue {
     value: Vec<(ValueHandle, ValueHandle)>,
     default_value: Option<ValueHandle>,
@@ -237,14 +691,70 @@ impl HashValue {
         &self.value
     }
 
+    /// Get the number of pairs in the hash
+    pub fn len(&self) -> usize {
+        self.value.len()
+    }
+
+    /// Check if this is empty
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
     /// Get the default value.
     pub fn default_value(&self) -> Option<ValueHandle> {
         self.default_value
     }
+
+    /// Insert a key-value pair.
+    ///
+    /// Keys are compared by handle, not by value.
+    /// If the key is already present, its value is replaced in place.
+    /// Otherwise, the pair is appended.
+    ///
+    /// Use [`ValueArena::insert_hash_pair`](crate::ValueArena::insert_hash_pair),
+    /// which checks that the handles are from the same arena.
+    ///
+    /// # Returns
+    /// Returns the old value, if the key was present.
+    pub(crate) fn insert(&mut self, key: ValueHandle, value: ValueHandle) -> Option<ValueHandle> {
+        match self.value.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old_value)) => Some(core::mem::replace(old_value, value)),
+            None => {
+                self.value.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Remove a key-value pair, preserving the order of the remaining pairs.
+    ///
+    /// Keys are compared by handle, not by value.
+    ///
+    /// # Returns
+    /// Returns the removed value, if the key was present.
+    pub fn remove(&mut self, key: ValueHandle) -> Option<ValueHandle> {
+        let index = self.value.iter().position(|(k, _)| *k == key)?;
+        Some(self.value.remove(index).1)
+    }
+
+    /// Set the default value.
+    ///
+    /// Use [`ValueArena::set_hash_default`](crate::ValueArena::set_hash_default),
+    /// which checks that the handle is from the same arena.
+    ///
+    /// # Returns
+    /// Returns the old default value.
+    pub(crate) fn set_default(
+        &mut self,
+        default_value: Option<ValueHandle>,
+    ) -> Option<ValueHandle> {
+        core::mem::replace(&mut self.default_value, default_value)
+    }
 }
 
 /// An object
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ObjectValue {
     name: TypedValueHandle<SymbolValue>,
     instance_variables: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
@@ -271,10 +781,35 @@ impl ObjectValue {
     pub fn instance_variables(&self) -> &[(TypedValueHandle<SymbolValue>, ValueHandle)] {
         &self.instance_variables
     }
+
+    /// Get the number of instance variables
+    pub fn instance_variable_count(&self) -> usize {
+        self.instance_variables.len()
+    }
+
+    /// Iterate over the instance variables, with each name resolved to its bytes.
+    ///
+    /// Names include the leading `@`.
+    /// Instance variables whose name is not a symbol in the given arena are skipped.
+    pub fn resolved_instance_variables<'a>(
+        &'a self,
+        arena: &'a ValueArena,
+    ) -> impl Iterator<Item = (&'a [u8], ValueHandle)> + 'a {
+        self.instance_variables
+            .iter()
+            .filter_map(|(name, value)| Some((arena.get_symbol(*name)?.value(), *value)))
+    }
+
+    /// Get a mutable ref to the instance variables.
+    pub(crate) fn instance_variables_mut(
+        &mut self,
+    ) -> &mut Vec<(TypedValueHandle<SymbolValue>, ValueHandle)> {
+        &mut self.instance_variables
+    }
 }
 
 /// A String
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StringValue {
     value: Vec<u8>,
     instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
@@ -294,6 +829,14 @@ impl StringValue {
         &self.value
     }
 
+    /// Get the inner value as a str.
+    ///
+    /// # Errors
+    /// Returns an error if the inner value is not valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(&self.value)
+    }
+
     /// Get the instance variables
     pub fn instance_variables(&self) -> Option<&[(TypedValueHandle<SymbolValue>, ValueHandle)]> {
         self.instance_variables.as_deref()
@@ -307,13 +850,27 @@ impl StringValue {
         &mut self,
         mut instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
     ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
-        std::mem::swap(&mut self.instance_variables, &mut instance_variables);
+        core::mem::swap(&mut self.instance_variables, &mut instance_variables);
         instance_variables
     }
+
+    /// Remove the instance variables, returning them.
+    ///
+    /// The handles in them are not freed, and may become orphans.
+    pub fn take_instance_variables(
+        &mut self,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        self.set_instance_variables(None)
+    }
+
+    /// Remove the instance variables.
+    pub fn clear_instance_variables(&mut self) {
+        self.instance_variables = None;
+    }
 }
 
 /// A User Defined value
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UserDefinedValue {
     name: TypedValueHandle<SymbolValue>,
     value: Vec<u8>,
@@ -353,13 +910,341 @@ impl UserDefinedValue {
         &mut self,
         mut instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
     ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
-        std::mem::swap(&mut self.instance_variables, &mut instance_variables);
+        core::mem::swap(&mut self.instance_variables, &mut instance_variables);
+        instance_variables
+    }
+
+    /// Remove the instance variables, returning them.
+    ///
+    /// The handles in them are not freed, and may become orphans.
+    pub fn take_instance_variables(
+        &mut self,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        self.set_instance_variables(None)
+    }
+
+    /// Remove the instance variables.
+    pub fn clear_instance_variables(&mut self) {
+        self.instance_variables = None;
+    }
+}
+
+/// A class or module, in the old format.
+///
+/// Very old versions of Ruby wrote classes and modules with the same kind,
+/// so it is not known which one this is.
+#[derive(Debug, Clone)]
+pub struct OldModuleValue {
+    name: Vec<u8>,
+}
+
+impl OldModuleValue {
+    /// Create a new [`OldModuleValue`].
+    pub(crate) fn new(name: Vec<u8>) -> Self {
+        Self { name }
+    }
+
+    /// Get the name of the class or module.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+/// A Float
+#[derive(Debug, Copy, Clone)]
+pub struct FloatValue {
+    value: f64,
+}
+
+impl FloatValue {
+    /// Create a new [`FloatValue`].
+    pub(super) fn new(value: f64) -> Self {
+        Self { value }
+    }
+
+    /// Get the inner value
+    pub fn value(self) -> f64 {
+        self.value
+    }
+}
+
+/// A Bignum
+///
+/// Bignums are integers that do not fit in a Fixnum.
+/// The magnitude is stored as little-endian bytes, padded to an even length.
+#[derive(Debug, Clone)]
+pub struct BignumValue {
+    is_negative: bool,
+    value: Vec<u8>,
+}
+
+impl BignumValue {
+    /// Create a new [`BignumValue`].
+    pub(crate) fn new(is_negative: bool, value: Vec<u8>) -> Self {
+        Self { is_negative, value }
+    }
+
+    /// Check if this is negative.
+    pub fn is_negative(&self) -> bool {
+        self.is_negative
+    }
+
+    /// Get the magnitude, as little-endian bytes.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// A Regexp
+#[derive(Debug, Clone)]
+pub struct RegexpValue {
+    source: Vec<u8>,
+    options: u8,
+    instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+}
+
+impl RegexpValue {
+    /// Create a new [`RegexpValue`].
+    pub(crate) fn new(source: Vec<u8>, options: u8) -> Self {
+        Self {
+            source,
+            options,
+            instance_variables: None,
+        }
+    }
+
+    /// Get the source of the pattern.
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Get the option flags.
+    ///
+    /// These are `1` for ignore case, `2` for extended, and `4` for multiline.
+    pub fn options(&self) -> u8 {
+        self.options
+    }
+
+    /// Get the instance variables.
+    ///
+    /// Regexps use these to store their encoding, like strings.
+    pub fn instance_variables(&self) -> Option<&[(TypedValueHandle<SymbolValue>, ValueHandle)]> {
+        self.instance_variables.as_deref()
+    }
+
+    /// Set the instance variables.
+    ///
+    /// # Returns
+    /// Returns the old instance variables
+    pub(crate) fn set_instance_variables(
+        &mut self,
+        mut instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        core::mem::swap(&mut self.instance_variables, &mut instance_variables);
+        instance_variables
+    }
+
+    /// Remove the instance variables, returning them.
+    ///
+    /// The handles in them are not freed, and may become orphans.
+    pub fn take_instance_variables(
+        &mut self,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        self.set_instance_variables(None)
+    }
+
+    /// Remove the instance variables.
+    pub fn clear_instance_variables(&mut self) {
+        self.instance_variables = None;
+    }
+
+    /// Compile this pattern with the `regex` crate.
+    ///
+    /// Ruby's ignore case and extended options map to `case_insensitive` and `ignore_whitespace`.
+    /// Ruby's multiline option lets `.` match a newline, so it maps to `dot_matches_new_line`.
+    /// `^` and `$` always match at line boundaries in Ruby, so `multi_line` is always enabled.
+    ///
+    /// The source must be UTF-8.
+    /// Ruby-specific syntax, like lookbehind or possessive quantifiers, is not supported by the `regex` crate,
+    /// so patterns using it fail with [`ToRegexError::Regex`].
+    #[cfg(feature = "regex")]
+    pub fn to_regex(&self) -> Result<regex::Regex, ToRegexError> {
+        let source =
+            core::str::from_utf8(&self.source).map_err(|_error| ToRegexError::InvalidUtf8)?;
+
+        regex::RegexBuilder::new(source)
+            .case_insensitive(self.options & 1 != 0)
+            .ignore_whitespace(self.options & 2 != 0)
+            .dot_matches_new_line(self.options & 4 != 0)
+            .multi_line(true)
+            .build()
+            .map_err(|error| ToRegexError::Regex { error })
+    }
+}
+
+/// An error that may occur while compiling a [`RegexpValue`] with the `regex` crate.
+#[cfg(feature = "regex")]
+#[derive(Debug)]
+pub enum ToRegexError {
+    /// The source is not UTF-8.
+    InvalidUtf8,
+
+    /// The `regex` crate failed to compile the source.
+    Regex { error: regex::Error },
+}
+
+#[cfg(feature = "regex")]
+impl core::fmt::Display for ToRegexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidUtf8 => write!(f, "the Regexp source is not UTF-8"),
+            Self::Regex { .. } => write!(f, "failed to compile the Regexp"),
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+impl core::error::Error for ToRegexError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::InvalidUtf8 => None,
+            Self::Regex { error } => Some(error),
+        }
+    }
+}
+
+/// A Struct
+#[derive(Debug, Clone)]
+pub struct StructValue {
+    name: TypedValueHandle<SymbolValue>,
+    members: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+}
+
+impl StructValue {
+    /// Create a new [`StructValue`].
+    pub(crate) fn new(
+        name: TypedValueHandle<SymbolValue>,
+        members: Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>,
+    ) -> Self {
+        Self {
+            name,
+            members,
+            instance_variables: None,
+        }
+    }
+
+    /// Get the name.
+    pub fn name(&self) -> TypedValueHandle<SymbolValue> {
+        self.name
+    }
+
+    /// Get the members.
+    ///
+    /// Unlike instance variables, member names do not start with `@`.
+    pub fn members(&self) -> &[(TypedValueHandle<SymbolValue>, ValueHandle)] {
+        &self.members
+    }
+
+    /// Get the instance variables.
+    ///
+    /// These are stored separately from the members.
+    pub fn instance_variables(&self) -> Option<&[(TypedValueHandle<SymbolValue>, ValueHandle)]> {
+        self.instance_variables.as_deref()
+    }
+
+    /// Set the instance variables.
+    ///
+    /// # Returns
+    /// Returns the old instance variables
+    pub(crate) fn set_instance_variables(
+        &mut self,
+        mut instance_variables: Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>>,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        core::mem::swap(&mut self.instance_variables, &mut instance_variables);
         instance_variables
     }
+
+    /// Remove the instance variables, returning them.
+    ///
+    /// The handles in them are not freed, and may become orphans.
+    pub fn take_instance_variables(
+        &mut self,
+    ) -> Option<Vec<(TypedValueHandle<SymbolValue>, ValueHandle)>> {
+        self.set_instance_variables(None)
+    }
+
+    /// Remove the instance variables.
+    pub fn clear_instance_variables(&mut self) {
+        self.instance_variables = None;
+    }
+}
+
+/// A class.
+#[derive(Debug, Clone)]
+pub struct ClassValue {
+    name: Vec<u8>,
+}
+
+impl ClassValue {
+    /// Create a new [`ClassValue`].
+    pub(crate) fn new(name: Vec<u8>) -> Self {
+        Self { name }
+    }
+
+    /// Get the name of the class.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+/// A module.
+#[derive(Debug, Clone)]
+pub struct ModuleValue {
+    name: Vec<u8>,
+}
+
+impl ModuleValue {
+    /// Create a new [`ModuleValue`].
+    pub(crate) fn new(name: Vec<u8>) -> Self {
+        Self { name }
+    }
+
+    /// Get the name of the module.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+/// A user marshal value.
+///
+/// This is an object of a class with `marshal_dump` and `marshal_load`,
+/// stored as the class name and the value that `marshal_dump` returned.
+#[derive(Debug, Clone)]
+pub struct UserMarshalValue {
+    name: TypedValueHandle<SymbolValue>,
+    value: ValueHandle,
+}
+
+impl UserMarshalValue {
+    /// Create a new [`UserMarshalValue`].
+    pub(crate) fn new(name: TypedValueHandle<SymbolValue>, value: ValueHandle) -> Self {
+        Self { name, value }
+    }
+
+    /// Get the name of the class.
+    pub fn name(&self) -> TypedValueHandle<SymbolValue> {
+        self.name
+    }
+
+    /// Get the dumped value.
+    pub fn value(&self) -> ValueHandle {
+        self.value
+    }
 }
 
 /// The kind of value
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum ValueKind {
     Nil,
     Bool,
@@ -370,4 +1255,12 @@ pub enum ValueKind {
     Object,
     String,
     UserDefined,
+    OldModule,
+    Float,
+    Bignum,
+    Regexp,
+    Struct,
+    Class,
+    Module,
+    UserMarshal,
 }
@@ -1,19 +1,24 @@
-use std::hash::Hash;
-use std::hash::Hasher;
-use std::marker::PhantomData;
+use core::hash::Hash;
+use core::hash::Hasher;
+use core::marker::PhantomData;
 
 /// A handle around a Ruby Value.
-#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
+///
+/// Handles are ordered by arena and then by the order they were allocated in,
+/// which is arbitrary but stable for the life of the arena.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct ValueHandle {
+    /// The id of the arena that created this handle
+    pub(super) arena_id: u64,
+
     /// The arena index
     pub(super) index: slotmap::DefaultKey,
-    // TODO: Should we an a counter to the arena itself to prevent accidental accesses to other arenas?
 }
 
 impl ValueHandle {
-    /// Create a new [`ValueHandle`] from an Index
-    pub(super) fn new(index: slotmap::DefaultKey) -> Self {
-        Self { index }
+    /// Create a new [`ValueHandle`] from an arena id and an Index
+    pub(super) fn new(arena_id: u64, index: slotmap::DefaultKey) -> Self {
+        Self { arena_id, index }
     }
 }
 